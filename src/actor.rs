@@ -0,0 +1,665 @@
+use lightspeed_astro::devices::actions::DeviceActions;
+use lightspeed_astro::props::Property;
+use log::{debug, error};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot};
+
+/// Longest wait for a request to be answered, waiting in the queue
+/// included. Gotos and parks are answered once the slew started, the
+/// slowest requests wait for an axis to stop
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Requests waiting for the device, senders wait when it is full
+const QUEUE_SIZE: usize = 32;
+
+/// What the thread owning a device needs from it
+pub trait Device: Send + 'static {
+    fn update_property(&mut self, name: &str, value: &str) -> Result<(), DeviceActions>;
+    /// Updates every property or none of them
+    fn update_properties(&mut self, pairs: &[(&str, &str)]) -> Result<(), DeviceActions>;
+    fn fetch_props(&mut self);
+    /// Properties as sent to lightspeed clients
+    fn ls_props(&self) -> Vec<Property>;
+    /// Hands over the flag raised while urgent work waits, devices check
+    /// it to cut their long waits short
+    fn set_interrupt(&mut self, _interrupt: Interrupt) {}
+    /// When the device has timed work to do next, e.g. ending a guide
+    /// pulse, the thread calls `on_deadline` then
+    fn next_deadline(&self) -> Option<Instant> {
+        None
+    }
+    fn on_deadline(&mut self) {}
+}
+
+type Job<D> = Box<dyn FnOnce(&mut D) + Send>;
+
+/// Raised while urgent work waits for the device, see
+/// `DeviceHandle::urgent`
+#[derive(Clone, Debug, Default)]
+pub struct Interrupt(Arc<AtomicBool>);
+
+impl Interrupt {
+    pub fn is_pending(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+/// Jobs run before anything else in the queue
+struct Urgent<D> {
+    jobs: Mutex<VecDeque<Job<D>>>,
+    interrupt: Interrupt,
+}
+
+impl<D> Urgent<D> {
+    fn push(&self, job: Job<D>) {
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.push_back(job);
+        self.interrupt.0.store(true, Ordering::Release);
+    }
+
+    fn run_all(&self, device: &mut D) {
+        loop {
+            let job = {
+                let mut jobs = self.jobs.lock().unwrap();
+                let job = jobs.pop_front();
+                if jobs.is_empty() {
+                    self.interrupt.0.store(false, Ordering::Release);
+                }
+                job
+            };
+            match job {
+                Some(job) => job(device),
+                None => return,
+            }
+        }
+    }
+}
+
+enum Request<D> {
+    SetProperty {
+        name: String,
+        value: String,
+        reply: oneshot::Sender<Result<(), DeviceActions>>,
+    },
+    SetProperties {
+        pairs: Vec<(String, String)>,
+        reply: oneshot::Sender<Result<(), DeviceActions>>,
+    },
+    FetchProps {
+        reply: oneshot::Sender<()>,
+    },
+    GetSnapshot {
+        reply: oneshot::Sender<Vec<Property>>,
+    },
+    /// Driver specific work, reconnecting or guiding
+    Run(Job<D>),
+    /// Gets an idle device thread to the urgent jobs
+    Wake,
+}
+
+/// Sends requests to a device owned by its own thread, which runs them
+/// one at a time so the frames of two commands never mix on the port.
+/// The deadlines of the device are met in between, as soon as the
+/// request running is over.
+/// Gotos and tracking changes are property updates like in the drivers.
+/// A request whose caller gave up waiting is dropped rather than run
+/// late, a goto minutes after it was asked for is worse than none.
+pub struct DeviceHandle<D> {
+    sender: mpsc::Sender<Request<D>>,
+    urgent: Arc<Urgent<D>>,
+    timeout: Duration,
+}
+
+impl<D> Clone for DeviceHandle<D> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+            urgent: Arc::clone(&self.urgent),
+            timeout: self.timeout,
+        }
+    }
+}
+
+impl<D: Device> DeviceHandle<D> {
+    /// Moves the device to a new thread named `name`, it lives until
+    /// every handle is dropped
+    pub fn spawn(name: &str, mut device: D) -> Self {
+        let (sender, mut receiver) = mpsc::channel::<Request<D>>(QUEUE_SIZE);
+        let urgent = Arc::new(Urgent {
+            jobs: Mutex::new(VecDeque::new()),
+            interrupt: Interrupt::default(),
+        });
+        let thread_urgent = Arc::clone(&urgent);
+        let thread_name = name.to_owned();
+        thread::Builder::new()
+            .name(name.to_owned())
+            .spawn(move || {
+                let urgent = thread_urgent;
+                device.set_interrupt(urgent.interrupt.clone());
+                // Only there to wait for a request or the next deadline
+                let timer = tokio::runtime::Builder::new_current_thread()
+                    .enable_time()
+                    .build()
+                    .expect("cannot build the device timer");
+                loop {
+                    urgent.run_all(&mut device);
+                    let deadline = device.next_deadline();
+                    if deadline.is_some_and(|d| d <= Instant::now()) {
+                        device.on_deadline();
+                        continue;
+                    }
+                    let received = match deadline {
+                        Some(d) => timer
+                            .block_on(async {
+                                tokio::time::timeout_at(d.into(), receiver.recv()).await
+                            })
+                            .ok(),
+                        None => Some(receiver.blocking_recv()),
+                    };
+                    let request = match received {
+                        Some(Some(r)) => r,
+                        Some(None) => break,
+                        // Deadline reached, run at the top
+                        None => continue,
+                    };
+                    // Whatever came in while waiting goes first
+                    urgent.run_all(&mut device);
+                    match request {
+                        Request::SetProperty { reply, .. } if reply.is_closed() => {
+                            gave_up(&thread_name)
+                        }
+                        Request::SetProperty { name, value, reply } => {
+                            let _ = reply.send(device.update_property(&name, &value));
+                        }
+                        Request::SetProperties { reply, .. } if reply.is_closed() => {
+                            gave_up(&thread_name)
+                        }
+                        Request::SetProperties { pairs, reply } => {
+                            let pairs: Vec<(&str, &str)> = pairs
+                                .iter()
+                                .map(|(n, v)| (n.as_str(), v.as_str()))
+                                .collect();
+                            let _ = reply.send(device.update_properties(&pairs));
+                        }
+                        Request::FetchProps { reply } if reply.is_closed() => gave_up(&thread_name),
+                        Request::FetchProps { reply } => {
+                            device.fetch_props();
+                            let _ = reply.send(());
+                        }
+                        Request::GetSnapshot { reply } => {
+                            let _ = reply.send(device.ls_props());
+                        }
+                        // Jobs check their own reply, see job()
+                        Request::Run(job) => job(&mut device),
+                        Request::Wake => {}
+                    }
+                }
+                debug!("No handle left for {}, closing it", thread_name);
+            })
+            .expect("cannot spawn a device thread");
+
+        Self {
+            sender,
+            urgent,
+            timeout: DEFAULT_REQUEST_TIMEOUT,
+        }
+    }
+
+    /// Time after which a request is answered with a timeout, waiting in
+    /// the queue included. A request that times out before its turn is
+    /// never run, one already running goes on to the end.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    async fn request<R>(
+        &self,
+        request: Request<D>,
+        reply: oneshot::Receiver<R>,
+    ) -> Result<R, DeviceActions> {
+        let answer = async {
+            self.sender.send(request).await.map_err(|_| ())?;
+            reply.await.map_err(|_| ())
+        };
+        match tokio::time::timeout(self.timeout, answer).await {
+            Ok(Ok(r)) => Ok(r),
+            Ok(Err(())) => {
+                error!("The device thread is gone");
+                Err(DeviceActions::ComError)
+            }
+            Err(_) => Err(DeviceActions::Timeout),
+        }
+    }
+
+    pub async fn set_property(&self, name: &str, value: &str) -> Result<(), DeviceActions> {
+        let (reply, answer) = oneshot::channel();
+        let request = Request::SetProperty {
+            name: name.to_owned(),
+            value: value.to_owned(),
+            reply,
+        };
+        self.request(request, answer).await?
+    }
+
+    /// Sets the properties in one go, no other request runs in between
+    pub async fn set_properties(&self, pairs: Vec<(String, String)>) -> Result<(), DeviceActions> {
+        let (reply, answer) = oneshot::channel();
+        self.request(Request::SetProperties { pairs, reply }, answer)
+            .await?
+    }
+
+    pub async fn fetch_props(&self) -> Result<(), DeviceActions> {
+        let (reply, answer) = oneshot::channel();
+        self.request(Request::FetchProps { reply }, answer).await
+    }
+
+    /// Properties as they are between two commands
+    pub async fn snapshot(&self) -> Result<Vec<Property>, DeviceActions> {
+        let (reply, answer) = oneshot::channel();
+        self.request(Request::GetSnapshot { reply }, answer).await
+    }
+
+    /// Runs `f` on the device in its turn
+    pub async fn call<R, F>(&self, f: F) -> Result<R, DeviceActions>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut D) -> R + Send + 'static,
+    {
+        let (job, answer) = job(f);
+        self.request(Request::Run(job), answer).await
+    }
+
+    /// Runs `f` on the device ahead of every queued request, right after
+    /// the one running. Meant for stopping the mount, which can't wait
+    /// behind polls and gotos, devices see the Interrupt raised until
+    /// it ran.
+    pub async fn urgent<R, F>(&self, f: F) -> Result<R, DeviceActions>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut D) -> R + Send + 'static,
+    {
+        let (job, answer) = job(f);
+        self.urgent.push(job);
+        // A full queue means the thread is busy and checks the urgent
+        // jobs before the next request anyway
+        let _ = self.sender.try_send(Request::Wake);
+        match tokio::time::timeout(self.timeout, answer).await {
+            Ok(Ok(r)) => Ok(r),
+            Ok(Err(_)) => {
+                error!("The device thread is gone");
+                Err(DeviceActions::ComError)
+            }
+            Err(_) => Err(DeviceActions::Timeout),
+        }
+    }
+
+    /// Same as `urgent` for threads outside of the runtime, without a
+    /// timeout
+    pub fn urgent_blocking<R, F>(&self, f: F) -> Result<R, DeviceActions>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut D) -> R + Send + 'static,
+    {
+        let (job, answer) = job(f);
+        self.urgent.push(job);
+        let _ = self.sender.try_send(Request::Wake);
+        answer.blocking_recv().map_err(|_| DeviceActions::ComError)
+    }
+
+    /// Same as `call` for threads outside of the runtime, without a timeout
+    pub fn call_blocking<R, F>(&self, f: F) -> Result<R, DeviceActions>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut D) -> R + Send + 'static,
+    {
+        let (job, answer) = job(f);
+        self.sender
+            .blocking_send(Request::Run(job))
+            .map_err(|_| DeviceActions::ComError)?;
+        answer.blocking_recv().map_err(|_| DeviceActions::ComError)
+    }
+}
+
+/// Wraps `f` into a job answering on the returned receiver, skipped when
+/// nobody waits for the answer anymore
+fn job<D, R, F>(f: F) -> (Job<D>, oneshot::Receiver<R>)
+where
+    R: Send + 'static,
+    F: FnOnce(&mut D) -> R + Send + 'static,
+{
+    let (reply, answer) = oneshot::channel();
+    let job: Job<D> = Box::new(move |device| {
+        if reply.is_closed() {
+            debug!("Dropping a job, its caller gave up");
+            return;
+        }
+        let _ = reply.send(f(device));
+    });
+    (job, answer)
+}
+
+fn gave_up(device: &str) {
+    debug!("Dropping a request to {}, its caller gave up", device);
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Device, DeviceHandle, Interrupt};
+    use lightspeed_astro::devices::actions::DeviceActions;
+    use lightspeed_astro::props::Property;
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    /// Writes its frames to a transcript one byte at a time, as slowly
+    /// as a serial port would
+    #[derive(Default)]
+    struct Mount {
+        transcript: Arc<Mutex<Vec<u8>>>,
+        delay: Duration,
+        interrupt: Interrupt,
+        /// Writes ":end\r" then
+        deadline: Option<Instant>,
+    }
+
+    impl Mount {
+        fn write_frame(&self, frame: &str) {
+            for b in frame.bytes() {
+                self.transcript.lock().unwrap().push(b);
+                thread::sleep(self.delay);
+            }
+        }
+    }
+
+    impl Device for Mount {
+        fn update_property(&mut self, name: &str, value: &str) -> Result<(), DeviceActions> {
+            if value.is_empty() {
+                return Err(DeviceActions::InvalidValue);
+            }
+            self.write_frame(&format!(":{}={}\r", name, value));
+            Ok(())
+        }
+
+        fn update_properties(&mut self, pairs: &[(&str, &str)]) -> Result<(), DeviceActions> {
+            pairs
+                .iter()
+                .try_for_each(|(name, value)| self.update_property(name, value))
+        }
+
+        fn fetch_props(&mut self) {
+            self.write_frame(":j1\r");
+        }
+
+        fn ls_props(&self) -> Vec<Property> {
+            vec![Property {
+                name: String::from("FRAMES"),
+                value: self.transcript.lock().unwrap().len().to_string(),
+                kind: String::from("integer"),
+                permission: 0,
+            }]
+        }
+
+        fn set_interrupt(&mut self, interrupt: Interrupt) {
+            self.interrupt = interrupt;
+        }
+
+        fn next_deadline(&self) -> Option<Instant> {
+            self.deadline
+        }
+
+        fn on_deadline(&mut self) {
+            self.deadline = None;
+            self.write_frame(":end\r");
+        }
+    }
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(4)
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn concurrent_requests_are_not_interleaved() {
+        let transcript = Arc::new(Mutex::new(Vec::new()));
+        let handle = DeviceHandle::spawn(
+            "mount",
+            Mount {
+                transcript: Arc::clone(&transcript),
+                delay: Duration::from_micros(50),
+                ..Default::default()
+            },
+        );
+
+        let rt = runtime();
+        rt.block_on(async {
+            let mut tasks = Vec::new();
+            for i in 0..50 {
+                let handle = handle.clone();
+                tasks.push(tokio::spawn(async move {
+                    handle.set_property("TRACKING", &i.to_string()).await
+                }));
+            }
+            let poller = handle.clone();
+            tasks.push(tokio::spawn(async move { poller.fetch_props().await }));
+            for t in tasks {
+                assert_eq!(t.await.unwrap(), Ok(()));
+            }
+        });
+
+        let transcript = String::from_utf8(transcript.lock().unwrap().clone()).unwrap();
+        let frames: Vec<&str> = transcript.split_terminator('\r').collect();
+        assert_eq!(frames.len(), 51);
+        let mut values = Vec::new();
+        for frame in frames {
+            match frame.strip_prefix(":TRACKING=") {
+                Some(v) => values.push(v.parse::<u32>().unwrap()),
+                None => assert_eq!(frame, ":j1"),
+            }
+        }
+        values.sort();
+        assert_eq!(values, (0..50).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn requests_time_out() {
+        let handle = DeviceHandle::spawn(
+            "slow mount",
+            Mount {
+                delay: Duration::from_millis(100),
+                ..Default::default()
+            },
+        )
+        .with_timeout(Duration::from_millis(50));
+
+        let rt = runtime();
+        rt.block_on(async {
+            assert_eq!(
+                handle.set_property("TRACKING", "Sidereal").await,
+                Err(DeviceActions::Timeout)
+            );
+        });
+    }
+
+    #[test]
+    fn calls_and_snapshots() {
+        let handle = DeviceHandle::spawn("mount", Mount::default());
+        let rt = runtime();
+        rt.block_on(async {
+            let len = handle
+                .call(|m| {
+                    m.write_frame(":e1\r");
+                    m.transcript.lock().unwrap().len()
+                })
+                .await;
+            assert_eq!(len, Ok(4));
+            assert_eq!(
+                handle.set_property("TRACKING", "").await,
+                Err(DeviceActions::InvalidValue)
+            );
+            assert_eq!(handle.snapshot().await.unwrap()[0].value, "4");
+        });
+
+        let handle_ = handle.clone();
+        let outside = thread::spawn(move || handle_.call_blocking(|m| m.write_frame(":K1\r")));
+        assert_eq!(outside.join().unwrap(), Ok(()));
+        assert_eq!(rt.block_on(handle.snapshot()).unwrap()[0].value, "8");
+    }
+
+    #[test]
+    fn urgent_jobs_jump_the_queue() {
+        let transcript = Arc::new(Mutex::new(Vec::new()));
+        let handle = DeviceHandle::spawn(
+            "mount",
+            Mount {
+                transcript: Arc::clone(&transcript),
+                ..Default::default()
+            },
+        );
+        let (release, released) = mpsc::channel::<()>();
+
+        let rt = runtime();
+        rt.block_on(async {
+            // Keeps the device busy until released
+            let busy = handle.clone();
+            let busy = tokio::spawn(async move {
+                busy.call(move |_| released.recv_timeout(Duration::from_secs(5)))
+                    .await
+            });
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            let mut queued = Vec::new();
+            for i in 0..3 {
+                let handle = handle.clone();
+                queued.push(tokio::spawn(async move {
+                    handle.set_property("TRACKING", &i.to_string()).await
+                }));
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            let stop = handle.clone();
+            let stop = tokio::spawn(async move { stop.urgent(|m| m.write_frame(":L1\r")).await });
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            release.send(()).unwrap();
+
+            assert_eq!(busy.await.unwrap(), Ok(Ok(())));
+            assert_eq!(stop.await.unwrap(), Ok(()));
+            for q in queued {
+                assert_eq!(q.await.unwrap(), Ok(()));
+            }
+        });
+
+        let transcript = String::from_utf8(transcript.lock().unwrap().clone()).unwrap();
+        assert!(transcript.starts_with(":L1\r"), "{}", transcript);
+        assert_eq!(transcript.split_terminator('\r').count(), 4);
+
+        // An idle device gets to it as well
+        let handle_ = handle.clone();
+        let outside = thread::spawn(move || handle_.urgent_blocking(|m| m.write_frame(":L2\r")));
+        assert_eq!(outside.join().unwrap(), Ok(()));
+    }
+
+    #[test]
+    fn urgent_jobs_interrupt_long_waits() {
+        let handle = DeviceHandle::spawn("mount", Mount::default());
+        let rt = runtime();
+        rt.block_on(async {
+            let waiting = handle.clone();
+            let waiting = tokio::spawn(async move {
+                waiting
+                    .call(|m| {
+                        let started = Instant::now();
+                        while !m.interrupt.is_pending() {
+                            if started.elapsed() > Duration::from_secs(5) {
+                                return false;
+                            }
+                            thread::sleep(Duration::from_millis(1));
+                        }
+                        true
+                    })
+                    .await
+            });
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            assert_eq!(handle.urgent(|m| m.interrupt.is_pending()).await, Ok(false));
+            assert_eq!(waiting.await.unwrap(), Ok(true));
+        });
+    }
+
+    #[test]
+    fn timed_out_requests_are_not_run() {
+        let transcript = Arc::new(Mutex::new(Vec::new()));
+        let handle = DeviceHandle::spawn(
+            "slow mount",
+            Mount {
+                transcript: Arc::clone(&transcript),
+                ..Default::default()
+            },
+        )
+        .with_timeout(Duration::from_millis(50));
+
+        let rt = runtime();
+        rt.block_on(async {
+            let busy = handle.clone();
+            let busy = tokio::spawn(async move {
+                busy.call(|_| thread::sleep(Duration::from_millis(200)))
+                    .await
+            });
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            assert_eq!(
+                handle.set_property("GOTO", "10.0 20.0").await,
+                Err(DeviceActions::Timeout)
+            );
+            assert_eq!(busy.await.unwrap(), Err(DeviceActions::Timeout));
+            tokio::time::sleep(Duration::from_millis(250)).await;
+            assert_eq!(handle.snapshot().await.unwrap()[0].value, "0");
+        });
+        assert!(transcript.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn deadlines_are_met_between_requests() {
+        let transcript = Arc::new(Mutex::new(Vec::new()));
+        let handle = DeviceHandle::spawn(
+            "mount",
+            Mount {
+                transcript: Arc::clone(&transcript),
+                ..Default::default()
+            },
+        );
+        let rt = runtime();
+        rt.block_on(async {
+            let started = Instant::now();
+            handle
+                .call(|m| m.deadline = Some(Instant::now() + Duration::from_millis(100)))
+                .await
+                .unwrap();
+            // An idle device wakes up for it
+            while handle.snapshot().await.unwrap()[0].value == "0" {
+                assert!(started.elapsed() < Duration::from_secs(5));
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+            assert!(started.elapsed() >= Duration::from_millis(100));
+
+            // A busy one right after the request running
+            handle
+                .call(|m| m.deadline = Some(Instant::now() + Duration::from_millis(10)))
+                .await
+                .unwrap();
+            let busy = handle.clone();
+            let busy = tokio::spawn(async move {
+                busy.call(|_| thread::sleep(Duration::from_millis(50)))
+                    .await
+            });
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            handle.set_property("TRACKING", "Lunar").await.unwrap();
+            busy.await.unwrap().unwrap();
+        });
+        let transcript = String::from_utf8(transcript.lock().unwrap().clone()).unwrap();
+        assert_eq!(transcript, ":end\r:end\r:TRACKING=Lunar\r");
+    }
+}
@@ -1,91 +1,741 @@
 use astrotools::AstroSerialDevice;
 use hex::FromHex;
 use lightspeed_astro::devices::actions::DeviceActions;
-use lightspeed_astro::props::Property;
+use lightspeed_astro::props::{Permission, Property};
 use log::{debug, error, info, warn};
-#[cfg(windows)]
-use serialport::COMPort;
-#[cfg(unix)]
-use serialport::TTYPort;
-use serialport::{available_ports, SerialPortType, UsbPortInfo};
-use skywatcher_rs::str_24bits_to_u32;
+use serialport::ClearBuffer;
+use skywatcher_rs::actor::{self, DeviceHandle, Interrupt};
+use skywatcher_rs::eqmod::{
+    aux_encoder_payload, backlash_commands, command, custom_rate_rad_per_sec, goto_commands,
+    parse_24bits_response, parse_encoder_position, parse_motor_board_version,
+    parse_polar_led_brightness, polar_led_payload, ppec_payload, probe_aux_axis, slew_motion,
+    snap_port_command, split_response, start_tracking_commands, Axis, AxisStatus, Backlash,
+    ControllerError, Direction, EqModOp, GuidePulse, GuideQueue, MotionMode, MountCapabilities,
+    PpecCommand, RunningPulse, SlewMonitor, TrackingRate, DEFAULT_BREAK_STEPS, DEFAULT_GUIDE_RATE,
+    DEFAULT_POLL_INTERVAL, FEATURES_INQUIRY,
+};
+use skywatcher_rs::metrics::{CommandStats, Outcome};
+use skywatcher_rs::motor_simulator::SimulatedMotors;
+use skywatcher_rs::network::UdpTransport;
+use skywatcher_rs::park::{load_park_states, save_park_state, ParkState, DEFAULT_PARK_STATE_FILE};
+use skywatcher_rs::props::{check_writable, ls_props, update_all, CustomProp};
+use skywatcher_rs::serial::{
+    response_to_string, send_all, ReadError, SerialLink, SerialTransport, TransportError,
+};
+use skywatcher_rs::shared::{device_id, DeviceView};
+use skywatcher_rs::shutdown;
+use skywatcher_rs::simulator::SIMULATOR_ADDRESS;
+use skywatcher_rs::synscan::{GotoProgress, GotoWatch};
+use skywatcher_rs::traffic::TrafficLog;
+use skywatcher_rs::{
+    degrees_to_eqmod_steps, enum_kind, eqmod_steps_to_degrees, is_truthy, parse_coordinate_pair,
+    poll_interval_from, step_period_for_rate, str_24bits_to_u24, str_to_u16, u32_to_str_24bits,
+    EQMOD_HOME_POSITION, SIDEREAL_RATE,
+};
 use std::fmt::UpperHex;
-use std::io::{Read, Write};
-use std::time::Duration;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 
-const SIDEREAL_RATE: f64 = 2.0 * 3.14 / 86164.09065;
+/// Longest time an axis takes to decelerate from a high speed slew
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest time the slew to the park position may take
+const PARK_TIMEOUT: Duration = Duration::from_secs(300);
+const SLEWING_POLL_INTERVAL: Duration = Duration::from_millis(250);
 
-enum RaCommand {
-    Init = 0x3a4631,
-    MotorBoardVersion = 0x3a6531,
-    InquireGridPerRevolution = 0x3a6131,
-    GetAxisPosition = 0x3a6a31,
-    SetAxisPosition = 0x3a4531,
-    GetAxisStatus = 0x3a6631,
+/// Where the frames go, a motor controller or the built-in simulator
+pub enum MountPort {
+    /// Local or behind a TCP bridge
+    Serial(SerialLink),
+    /// An AZ-GTi or a Wi-Fi adapter, on LS_NETWORK_MOUNTS
+    Udp(UdpTransport),
+    Simulated(Box<SimulatedMotors>),
 }
 
-enum DecCommand {
-    Init = 0x3a4632,
-    InquireGridPerRevolution = 0x3a6132,
-    GetAxisPosition = 0x3a6a32,
-    SetAxisPosition = 0x3a4531,
-    GetAxisStatus = 0x3a6632,
+impl MountPort {
+    pub fn timeout(&self) -> Duration {
+        match self {
+            MountPort::Serial(p) => p.timeout(),
+            MountPort::Udp(u) => u.timeout(),
+            MountPort::Simulated(s) => s.timeout(),
+        }
+    }
+
+    pub fn clear(&mut self, buffer: ClearBuffer) -> serialport::Result<()> {
+        match self {
+            MountPort::Serial(p) => p.clear(buffer),
+            MountPort::Udp(u) => Ok(u.clear()?),
+            MountPort::Simulated(s) => {
+                s.clear();
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Read for MountPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            MountPort::Serial(p) => p.read(buf),
+            MountPort::Udp(u) => u.read(buf),
+            MountPort::Simulated(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for MountPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            MountPort::Serial(p) => p.write(buf),
+            MountPort::Udp(u) => u.write(buf),
+            MountPort::Simulated(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            MountPort::Serial(p) => p.flush(),
+            MountPort::Udp(u) => u.flush(),
+            MountPort::Simulated(s) => s.flush(),
+        }
+    }
+}
+
+impl SerialTransport for MountPort {
+    fn exchange(&mut self, frame: &[u8], terminator: u8) -> Result<Vec<u8>, TransportError> {
+        match self {
+            MountPort::Serial(p) => p.exchange(frame, terminator),
+            MountPort::Udp(u) => u.exchange(frame, terminator),
+            MountPort::Simulated(s) => s.exchange(frame, terminator),
+        }
+    }
 }
 
 pub struct MountDevice {
     id: Uuid,
     name: String,
-    pub properties: Vec<Property>,
+    pub properties: Vec<CustomProp>,
+    pub static_properties: Vec<Property>,
+    /// Every property with its value as of the last poll or update,
+    /// what get_properties hands out
+    exported: Vec<Property>,
+    view: DeviceView,
     address: String,
     pub baud: u32,
-    #[cfg(unix)]
-    pub port: TTYPort,
-    #[cfg(windows)]
-    pub port: COMPort,
+    pub port: MountPort,
+    motor_board_version: String,
+    ra_steps_per_rev: u32,
+    dec_steps_per_rev: u32,
+    timer_freq: u32,
+    ra_high_speed_ratio: u16,
+    dec_high_speed_ratio: u16,
+    capabilities: MountCapabilities,
+    tracking: Arc<RwLock<String>>,
+    ra_axis_position: Arc<RwLock<String>>,
+    dec_axis_position: Arc<RwLock<String>>,
+    force_reinit: Arc<RwLock<String>>,
+    ra_position: Arc<RwLock<String>>,
+    dec_position: Arc<RwLock<String>>,
+    ra_status: Arc<RwLock<String>>,
+    dec_status: Arc<RwLock<String>>,
+    goto_coordinates: Arc<RwLock<String>>,
+    slewing: Arc<RwLock<String>>,
+    snap_port_1: Arc<RwLock<String>>,
+    polar_led: Arc<RwLock<String>>,
+    /// Last brightness requested, re-applied each time the axes are
+    /// initialized again
+    polar_led_brightness: Option<u8>,
+    ppec_training: Arc<RwLock<String>>,
+    ppec: Arc<RwLock<String>>,
+    ppec_status: Arc<RwLock<String>>,
+    encoders_enabled: Arc<RwLock<String>>,
+    encoder_ra: Arc<RwLock<String>>,
+    encoder_dec: Arc<RwLock<String>>,
+    pulse_guide_ra: Arc<RwLock<String>>,
+    pulse_guide_dec: Arc<RwLock<String>>,
+    guide_pulses: GuideQueue,
+    /// Error answered to the last command, if any
+    last_controller_error: Option<ControllerError>,
+    stats: CommandStats,
+    traffic: TrafficLog,
+    /// Steps before the target where gotos slow down
+    break_steps: u32,
+    /// Time between two property fetches, from LS_POLL_INTERVAL_MS
+    poll_interval: Duration,
+    slew_monitor: SlewMonitor,
+    park: Arc<RwLock<String>>,
+    unpark: Arc<RwLock<String>>,
+    emergency_stop: Arc<RwLock<String>>,
+    /// Custom rates in arcseconds per second, 0 when not in use
+    custom_track_rate_ra: Arc<RwLock<String>>,
+    custom_track_rate_dec: Arc<RwLock<String>>,
+    parked: Arc<RwLock<String>>,
+    /// Slew to the park position in progress, finished by the polls
+    parking: Option<GotoWatch>,
+    tracking_before_park: Option<TrackingRate>,
+    park_state: ParkState,
+    park_state_file: PathBuf,
+    backlash_ra: Arc<RwLock<String>>,
+    backlash_dec: Arc<RwLock<String>>,
+    ra_backlash: Backlash,
+    dec_backlash: Backlash,
+    /// Third axis, only on some controllers
+    aux_present: bool,
+    aux_steps_per_rev: u32,
+    aux_high_speed_ratio: u16,
+    aux_backlash: Backlash,
+    aux_position: Arc<RwLock<String>>,
+    aux_move: Arc<RwLock<String>>,
+    /// Raised while an emergency stop waits for the device
+    interrupt: Interrupt,
 }
 
-impl AstroSerialDevice for MountDevice {
-    fn new(name: &str, address: &str, baud: u32, timeout_ms: u64) -> Option<Self> {
-        let builder = serialport::new(address, baud).timeout(Duration::from_millis(timeout_ms));
-
-        if let Ok(port_) = builder.open_native() {
-            let mut dev = Self {
-                id: Uuid::new_v4(),
-                name: name.to_owned(),
-                properties: Vec::new(),
-                address: address.to_owned(),
-                baud,
-                port: port_,
-            };
+impl MountDevice {
+    /// Sends an axis command built by the library command builder
+    fn send_axis_command(
+        &mut self,
+        op: EqModOp,
+        axis: Axis,
+        val: Option<String>,
+    ) -> Result<String, DeviceActions> {
+        self.send_eqmod_command(&command(op, axis), val.as_deref())
+    }
 
-            if let Err(_) = dev.send_command(DecCommand::Init as i32, None) {
-                debug!("{}", DeviceActions::CannotConnect as i32);
-                return None;
+    /// Sends `frame` followed by its payload as they are and reads the
+    /// answer up to the carriage return
+    fn send_eqmod_command(
+        &mut self,
+        frame: &[u8],
+        payload: Option<&str>,
+    ) -> Result<String, DeviceActions> {
+        let mut command = frame.to_vec();
+        if let Some(value) = payload {
+            command.extend_from_slice(value.as_bytes());
+        }
+
+        // append 13 at the end
+        command.push(0x0d);
+        self.last_controller_error = None;
+        debug!("COMMAND: {:?}", command);
+
+        debug!(
+            "Sending command: {}",
+            String::from_utf8_lossy(&command[..command.len() - 1])
+        );
+        let start = Instant::now();
+        let answer = self.port.exchange(&command, 0x0d);
+        let elapsed = start.elapsed();
+        self.stats.record(Outcome::from(&answer), elapsed);
+        self.traffic.record(
+            command.get(1).and_then(|op| EqModOp::try_from(*op).ok()),
+            &command,
+            answer.as_deref(),
+            elapsed,
+        );
+        let final_buf = answer.map_err(|e| {
+            error!("No valid response: {:?}", e);
+            e.device_action()
+        })?;
+
+        // Check if the response is OK (=) or there is an error (!)
+        match split_response(&final_buf) {
+            Ok(payload) => {
+                let response = response_to_string(payload.to_vec()).map_err(|e| {
+                    error!("Corrupted response: {:?}", e);
+                    self.stats.record_invalid_answer();
+                    DeviceActions::ComError
+                })?;
+                info!("RESPONSE: {}", response);
+                Ok(response)
             }
+            Err(e) => {
+                warn!("Motor controller error: {}", e);
+                self.last_controller_error = Some(e);
+                Err(e.device_action())
+            }
+        }
+    }
 
-            if let Err(_) = dev.send_command(RaCommand::Init as i32, None) {
-                debug!("{}", DeviceActions::CannotConnect as i32);
-                return None;
+    /// Re-applies the settings a reinitialized or power cycled board has
+    /// lost
+    fn restore_settings(&mut self) {
+        if let Some(brightness) = self.polar_led_brightness {
+            if let Err(e) = self.set_polar_led_brightness(brightness) {
+                warn!("Cannot restore the polar LED brightness: {:?}", e);
+            }
+        }
+    }
+
+    /// Rate the RA axis is tracking at, the custom one comes from
+    /// CUSTOM_TRACK_RATE_RA
+    fn tracking_rate(&self) -> Option<TrackingRate> {
+        let tracking = self.tracking.read().unwrap();
+        if tracking.eq_ignore_ascii_case("custom") {
+            let rate = self.custom_track_rate_ra.read().unwrap().parse().ok()?;
+            return TrackingRate::custom(rate);
+        }
+        tracking.parse().ok()
+    }
+
+    /// Sends a PPEC subcommand, checked against the mount capabilities
+    /// and the current tracking rate
+    fn send_ppec_command(&mut self, cmd: PpecCommand) -> Result<(), DeviceActions> {
+        let tracking = self.tracking_rate();
+        let payload = ppec_payload(&self.capabilities, cmd, tracking)?;
+        self.send_axis_command(EqModOp::ExtendedSetting, Axis::Ra, Some(payload))?;
+        info!("PPEC command {:?} sent", cmd);
+        Ok(())
+    }
+
+    /// Reads whether PPEC is training or running, the features found at
+    /// init are left as they are
+    fn update_ppec_status(&mut self) {
+        let resp = match self.send_axis_command(
+            EqModOp::ExtendedInquiry,
+            Axis::Ra,
+            Some(String::from(FEATURES_INQUIRY)),
+        ) {
+            Ok(r) => r,
+            Err(_) => return,
+        };
+        match MountCapabilities::from_response(&resp) {
+            Ok(caps) => {
+                self.capabilities.in_ppec_training = caps.in_ppec_training;
+                self.capabilities.in_ppec = caps.in_ppec;
+                *self.ppec_status.write().unwrap() = self.capabilities.ppec_status().to_owned();
             }
+            Err(e) => debug!("Cannot parse the PPEC status {:?}: {:?}", resp, e),
+        }
+    }
 
-            dev.init_device();
-            dev.fetch_props();
-            Some(dev)
-        } else {
+    /// Looks for a third axis and, when there is one, reads its
+    /// parameters and adds its properties
+    fn init_aux_axis(&mut self) {
+        // A late or partial answer is dropped by the transport
+        self.aux_present = probe_aux_axis(&mut self.port);
+        if !self.aux_present {
+            debug!("No auxiliary axis");
+            return;
+        }
+
+        self.aux_steps_per_rev = self
+            .send_axis_command(EqModOp::InquireGridPerRevolution, Axis::Aux, None)
+            .ok()
+            .and_then(|v| parse_24bits_response(&v).ok())
+            .unwrap_or(0);
+        self.aux_high_speed_ratio = self.get_high_speed_ratio(Axis::Aux);
+        info!(
+            "Auxiliary axis found, {} steps per revolution",
+            self.aux_steps_per_rev
+        );
+
+        for (name, kind, permission, value) in [
+            (
+                "AUX_POSITION",
+                "float",
+                Permission::ReadOnly,
+                &self.aux_position,
+            ),
+            ("AUX_MOVE", "float", Permission::ReadWrite, &self.aux_move),
+        ] {
+            self.properties.push(CustomProp {
+                name: String::from(name),
+                kind: String::from(kind),
+                permission,
+                value: value.clone(),
+            });
+        }
+    }
+
+    fn backlash(&mut self, axis: Axis) -> &mut Backlash {
+        match axis {
+            Axis::Ra => &mut self.ra_backlash,
+            Axis::Dec => &mut self.dec_backlash,
+            Axis::Aux => &mut self.aux_backlash,
+        }
+    }
+
+    /// Raw position of an axis
+    fn get_axis_steps(&mut self, axis: Axis) -> Result<u32, DeviceActions> {
+        self.send_axis_command(EqModOp::GetAxisPosition, axis, None)
+            .map(str_24bits_to_u24)?
+            .ok_or(DeviceActions::ComError)
+    }
+
+    /// Picks up the park state left by a previous run, warning when the
+    /// mount has been moved since it was parked
+    fn restore_park_state(&mut self) {
+        let state = match load_park_states(&self.park_state_file).remove(&self.name) {
+            Some(s) if s.parked => s,
+            _ => return,
+        };
+
+        match (
+            self.get_axis_steps(Axis::Ra),
+            self.get_axis_steps(Axis::Dec),
+        ) {
+            (Ok(ra), Ok(dec)) if state.moved(ra, dec) => {
+                warn!("The mount was moved while parked, its position may be wrong")
+            }
+            (Ok(_), Ok(_)) => info!("Mount still parked"),
+            _ => warn!("Cannot check whether the mount was moved while parked"),
+        }
+        *self.parked.write().unwrap() = String::from("true");
+        self.park_state = state;
+    }
+
+    fn save_park_state(&self) {
+        if let Err(e) = save_park_state(&self.park_state_file, &self.name, &self.park_state) {
+            warn!(
+                "Cannot save the park state to {:?}: {}",
+                self.park_state_file, e
+            );
+        }
+    }
+
+    /// Finishes a park once both axes stopped at the park position
+    fn update_parking(&mut self, slewing: bool) {
+        let progress = match self.parking.as_mut() {
+            Some(watch) => watch.update(slewing, Instant::now()),
+            None => return,
+        };
+        match progress {
+            GotoProgress::Slewing => return,
+            GotoProgress::Done => match (
+                self.get_axis_steps(Axis::Ra),
+                self.get_axis_steps(Axis::Dec),
+            ) {
+                (Ok(ra), Ok(dec)) => {
+                    self.park_state
+                        .park(self.tracking_before_park.take(), ra, dec);
+                    self.save_park_state();
+                    *self.parked.write().unwrap() = String::from("true");
+                    info!("Mount parked");
+                }
+                (Err(e), _) | (_, Err(e)) => {
+                    error!("Cannot read the park position: {:?}", e);
+                    self.tracking_before_park = None;
+                }
+            },
+            GotoProgress::TimedOut => {
+                error!("The mount didn't reach the park position in time");
+                self.tracking_before_park = None;
+            }
+        }
+        self.parking = None;
+    }
+
+    /// Forgets a park whose slew was stopped
+    fn cancel_parking(&mut self) {
+        if self.parking.take().is_some() {
+            warn!("Park interrupted");
+            self.tracking_before_park = None;
+        }
+    }
+
+    /// Starts the tracking that was running before parking again, along
+    /// with its custom rate
+    fn resume_tracking(&mut self, rate: TrackingRate) -> Result<(), DeviceActions> {
+        if let TrackingRate::Custom(arcsec_per_sec) = rate {
+            *self.custom_track_rate_ra.write().unwrap() = arcsec_per_sec.to_string();
+        }
+        self.start_tracking(rate)
+    }
+
+    fn send_axis_commands(
+        &mut self,
+        axis: Axis,
+        commands: &[(EqModOp, Option<String>)],
+    ) -> Result<(), DeviceActions> {
+        for (op, payload) in commands {
+            self.send_axis_command(*op, axis, payload.clone())?;
+        }
+        Ok(())
+    }
+
+    /// Ends the guide pulses that are over and starts the ones waiting
+    /// for their axis
+    fn run_guide_pulses(&mut self) {
+        for (axis, pulse) in self.guide_pulses.take_ended(Instant::now()) {
+            if let Err(e) = self.end_guide_pulse(axis, pulse.period_only) {
+                error!("Cannot end the {:?} guide pulse: {:?}", axis, e);
+            }
+            debug!("{:?} guide pulse done", axis);
+        }
+        while let Some((axis, pulse)) = self.guide_pulses.take_next() {
+            let offset = pulse.rate_offset(DEFAULT_GUIDE_RATE);
+            match self.start_guide_pulse(axis, offset) {
+                Ok(period_only) => self.guide_pulses.started(
+                    axis,
+                    RunningPulse {
+                        end: Instant::now() + Duration::from_millis(pulse.duration_ms as u64),
+                        period_only,
+                    },
+                ),
+                Err(e) => error!("Cannot start the {:?} guide pulse: {:?}", axis, e),
+            }
+        }
+    }
+
+    /// Waits for an axis to come to a standstill, giving up after
+    /// STOP_TIMEOUT
+    fn wait_for_stop(&mut self, axis: Axis) -> Result<(), DeviceActions> {
+        let started = Instant::now();
+        while started.elapsed() < STOP_TIMEOUT {
+            if self.interrupt.is_pending() {
+                warn!("Stopped waiting for the {:?} axis, urgent work waits", axis);
+                return Err(DeviceActions::Timeout);
+            }
+            let resp = self.send_axis_command(EqModOp::GetAxisStatus, axis, None)?;
+            if AxisStatus::from_response(&resp).is_ok_and(|s| !s.running) {
+                return Ok(());
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        error!("{:?} axis didn't stop within {:?}", axis, STOP_TIMEOUT);
+        Err(DeviceActions::Timeout)
+    }
+
+    /// What clients can read without locking the device
+    pub fn view(&self) -> DeviceView {
+        self.view.clone()
+    }
+
+    fn export_props(&mut self) {
+        self.exported = ls_props(&self.properties, &self.static_properties);
+    }
+
+    pub fn poll_interval(&self) -> Duration {
+        self.poll_interval
+    }
+
+    /// Ties the id to the USB serial number of the adapter, when it has
+    /// one, rather than to the port path
+    pub fn with_serial_number(mut self, serial_number: Option<&str>) -> Self {
+        self.id = device_id(serial_number, &self.address);
+        self
+    }
+
+    /// A mount answering EQMod frames over UDP at `address`, e.g. an
+    /// AZ-GTi in access point mode on "192.168.4.1:11880"
+    pub fn networked(address: &str, timeout_ms: u64) -> Option<Self> {
+        match UdpTransport::connect(address, Duration::from_millis(timeout_ms)) {
+            Ok(udp) => Self::with_port(
+                &format!("EQMod-{}", address),
+                address,
+                0,
+                MountPort::Udp(udp),
+            ),
+            Err(e) => {
+                debug!("Cannot reach {}: {}", address, e);
+                None
+            }
+        }
+    }
+
+    /// The built-in simulated motor board, for trying clients out and
+    /// for tests without any hardware
+    pub fn simulated() -> Option<Self> {
+        Self::with_simulator(SimulatedMotors::default())
+    }
+
+    /// A mount on a port the caller already opened, probed for instance
+    pub fn with_link(name: &str, address: &str, baud: u32, link: SerialLink) -> Option<Self> {
+        Self::with_port(name, address, baud, MountPort::Serial(link))
+    }
+
+    /// Same as `simulated` with a simulator set up by the caller
+    pub fn with_simulator(motors: SimulatedMotors) -> Option<Self> {
+        Self::with_port(
+            "EQMod-Simulator",
+            SIMULATOR_ADDRESS,
+            0,
+            MountPort::Simulated(Box::new(motors)),
+        )
+    }
+
+    /// Sets the mount on the other end of `port` up, None when it
+    /// doesn't answer
+    fn with_port(name: &str, address: &str, baud: u32, port: MountPort) -> Option<Self> {
+        let mut dev = Self {
+            id: device_id(None, address),
+            name: name.to_owned(),
+            properties: Vec::new(),
+            static_properties: Vec::new(),
+            exported: Vec::new(),
+            view: DeviceView::default(),
+            address: address.to_owned(),
+            baud,
+            port,
+            motor_board_version: String::from("unknown"),
+            ra_steps_per_rev: 0,
+            dec_steps_per_rev: 0,
+            timer_freq: 0,
+            ra_high_speed_ratio: 0,
+            dec_high_speed_ratio: 0,
+            capabilities: MountCapabilities::default(),
+            tracking: Arc::new(RwLock::new(String::from("Off"))),
+            ra_axis_position: Arc::new(RwLock::new(String::new())),
+            dec_axis_position: Arc::new(RwLock::new(String::new())),
+            force_reinit: Arc::new(RwLock::new(String::from("false"))),
+            ra_position: Arc::new(RwLock::new(String::new())),
+            dec_position: Arc::new(RwLock::new(String::new())),
+            ra_status: Arc::new(RwLock::new(String::new())),
+            dec_status: Arc::new(RwLock::new(String::new())),
+            goto_coordinates: Arc::new(RwLock::new(String::new())),
+            slewing: Arc::new(RwLock::new(String::from("false"))),
+            snap_port_1: Arc::new(RwLock::new(String::from("false"))),
+            polar_led: Arc::new(RwLock::new(String::new())),
+            polar_led_brightness: None,
+            ppec_training: Arc::new(RwLock::new(String::from("false"))),
+            ppec: Arc::new(RwLock::new(String::from("false"))),
+            ppec_status: Arc::new(RwLock::new(String::from("Idle"))),
+            encoders_enabled: Arc::new(RwLock::new(String::from("false"))),
+            encoder_ra: Arc::new(RwLock::new(String::new())),
+            encoder_dec: Arc::new(RwLock::new(String::new())),
+            pulse_guide_ra: Arc::new(RwLock::new(String::new())),
+            pulse_guide_dec: Arc::new(RwLock::new(String::new())),
+            guide_pulses: GuideQueue::default(),
+            last_controller_error: None,
+            stats: CommandStats::default(),
+            traffic: TrafficLog::default(),
+            break_steps: std::env::var("LS_BREAK_STEPS")
+                .ok()
+                .and_then(|v| v.parse::<u32>().ok())
+                .unwrap_or(DEFAULT_BREAK_STEPS),
+            poll_interval: poll_interval_from(
+                std::env::var("LS_POLL_INTERVAL_MS").ok().as_deref(),
+                DEFAULT_POLL_INTERVAL,
+            ),
+            slew_monitor: SlewMonitor::default(),
+            park: Arc::new(RwLock::new(String::new())),
+            unpark: Arc::new(RwLock::new(String::new())),
+            emergency_stop: Arc::new(RwLock::new(String::new())),
+            custom_track_rate_ra: Arc::new(RwLock::new(String::from("0"))),
+            custom_track_rate_dec: Arc::new(RwLock::new(String::from("0"))),
+            parked: Arc::new(RwLock::new(String::from("false"))),
+            parking: None,
+            tracking_before_park: None,
+            park_state: ParkState::default(),
+            park_state_file: std::env::var("LS_PARK_STATE_FILE")
+                .map(PathBuf::from)
+                .unwrap_or_else(|_| PathBuf::from(DEFAULT_PARK_STATE_FILE)),
+            backlash_ra: Arc::new(RwLock::new(String::from("0"))),
+            backlash_dec: Arc::new(RwLock::new(String::from("0"))),
+            ra_backlash: Backlash::default(),
+            dec_backlash: Backlash::default(),
+            aux_present: false,
+            aux_steps_per_rev: 0,
+            aux_high_speed_ratio: 0,
+            aux_backlash: Backlash::default(),
+            aux_position: Arc::new(RwLock::new(String::new())),
+            aux_move: Arc::new(RwLock::new(String::from("0"))),
+            interrupt: Interrupt::default(),
+        };
+
+        if dev.init_axes(false).is_err() {
             debug!("{}", DeviceActions::CannotConnect as i32);
-            None
+            return None;
+        }
+
+        dev.init_device();
+        dev.restore_park_state();
+        dev.fetch_props();
+        // What the forced ports task checks the ports against
+        dev.view.set_address(address, None);
+        Some(dev)
+    }
+}
+
+impl AstroSerialDevice for MountDevice {
+    fn new(name: &str, address: &str, baud: u32, timeout_ms: u64) -> Option<Self> {
+        match SerialLink::open(address, baud, Duration::from_millis(timeout_ms)) {
+            Ok(link) => Self::with_link(name, address, baud, link),
+            Err(_) => {
+                debug!("{}", DeviceActions::CannotConnect as i32);
+                None
+            }
         }
     }
 
     fn fetch_props(&mut self) {
         info!("Fetching props");
 
-        let axis_pos = self.get_axis_position();
-        println!("{}:{}", axis_pos.0, axis_pos.1);
+        let (ra, dec) = self.get_axis_position();
+        debug!("Axis positions RA: {:?} DEC: {:?}", ra, dec);
+        // Keep the last known position when a read fails
+        for (pos, prop) in [(ra, &self.ra_position), (dec, &self.dec_position)] {
+            if let Some(degrees) = pos {
+                *prop.write().unwrap() = format!("{:.6}", degrees);
+            }
+        }
+
+        if is_truthy(&self.encoders_enabled.read().unwrap()) {
+            for (axis, stepper, steps_per_rev, prop) in [
+                (Axis::Ra, ra, self.ra_steps_per_rev, self.encoder_ra.clone()),
+                (
+                    Axis::Dec,
+                    dec,
+                    self.dec_steps_per_rev,
+                    self.encoder_dec.clone(),
+                ),
+            ] {
+                let encoder = match self.get_encoder_position(axis) {
+                    Some(raw) if steps_per_rev > 0 => eqmod_steps_to_degrees(raw, steps_per_rev),
+                    _ => continue,
+                };
+                *prop.write().unwrap() = format!("{:.6}", encoder);
+                // A growing difference means the clutch is slipping
+                if let Some(stepper) = stepper {
+                    debug!(
+                        "{:?} axis encoder is {:.6} degrees off the stepper position",
+                        axis,
+                        encoder - stepper
+                    );
+                }
+            }
+        }
+
+        if self.aux_present && self.aux_steps_per_rev > 0 {
+            if let Ok(steps) = self.get_axis_steps(Axis::Aux) {
+                *self.aux_position.write().unwrap() = format!(
+                    "{:.6}",
+                    eqmod_steps_to_degrees(steps, self.aux_steps_per_rev)
+                );
+            }
+        }
+
+        let (ra_status, dec_status) = self.get_axis_status();
+        debug!("Axis status RA: {:?} DEC: {:?}", ra_status, dec_status);
+        for (status, prop) in [(ra_status, &self.ra_status), (dec_status, &self.dec_status)] {
+            *prop.write().unwrap() = match status {
+                Some(s) => s.to_string(),
+                None => String::from("Unknown"),
+            };
+        }
+        // A board turned off and on again comes back without its init
+        if [ra_status, dec_status]
+            .iter()
+            .any(|s| s.is_some_and(|s| !s.initialized))
+        {
+            warn!("Motor board lost its init, initializing again");
+            if let Err(e) = self.init_axes(true) {
+                error!("Cannot initialize the axes again: {:?}", e);
+            }
+        }
+        // Training ends on its own after a worm period
+        if self.capabilities.has_ppec {
+            self.update_ppec_status();
+        }
 
-        let axis_status = self.get_axis_status();
-        println!("{}:{}", axis_status.0, axis_status.1);
+        let slewing = self.slew_monitor.update(ra_status, dec_status);
+        *self.slewing.write().unwrap() = slewing.to_string();
+        self.update_parking(slewing);
+        self.stats.publish();
+        self.export_props();
     }
 
     fn get_id(&self) -> Uuid {
@@ -101,188 +751,1377 @@ impl AstroSerialDevice for MountDevice {
     }
 
     fn get_properties(&self) -> &Vec<Property> {
-        &self.properties
+        &self.exported
     }
 
     fn send_command<T>(&mut self, comm: T, val: Option<String>) -> Result<String, DeviceActions>
     where
         T: UpperHex,
     {
-        // First convert the command into an hex STRING
-        let mut hex_command = format!("{:X}", comm);
-
-        if let Some(value) = val {
-            hex_command += hex::encode(value).as_str();
-        }
-
-        // Cast the hex string to a sequence of bytes
-        let mut command: Vec<u8> = Vec::from_hex(hex_command).expect("Invalid Hex String");
+        // The frame bytes are the digits of the command
+        let frame = match Vec::from_hex(format!("{:X}", comm)) {
+            Ok(f) => f,
+            Err(e) => {
+                error!("Invalid EQMod command {:X}: {}", comm, e);
+                return Err(DeviceActions::InvalidValue);
+            }
+        };
+        self.send_eqmod_command(&frame, val.as_deref())
+    }
 
-        // append 13 at the end
-        command.push(0x0d);
-        debug!("COMMAND: {:?}", command);
+    fn update_property(&mut self, name: &str, value: &str) -> Result<(), DeviceActions> {
+        info!("EQMod updating property {} with {}", name, value);
+        check_writable(&self.properties, name)?;
+        let result = self.update_property_remote(name, value);
+        self.export_props();
+        result
+    }
 
-        match self.port.write(&command) {
-            Ok(_) => {
-                debug!(
-                    "Sent command: {}",
-                    std::str::from_utf8(&command[..command.len() - 1]).unwrap()
-                );
-                let mut final_buf: Vec<u8> = Vec::new();
-                debug!("Receiving data");
-
-                loop {
-                    let mut read_buf = [0; 1];
-
-                    match self.port.read(read_buf.as_mut_slice()) {
-                        Ok(_) => {
-                            let byte = read_buf[0];
-                            //debug!("Read byte: {}", byte);
-                            final_buf.push(byte);
-
-                            if byte == 0x0d as u8 {
-                                break;
-                            }
-                        }
-                        Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => {
-                            error!("Timeout");
-                            return Err(DeviceActions::Timeout);
-                        }
-                        Err(e) => error!("{:?}", e),
+    fn update_property_remote(&mut self, name: &str, value: &str) -> Result<(), DeviceActions> {
+        match name {
+            "FORCE_REINIT" if is_truthy(value) => {
+                self.init_axes(true)?;
+                Ok(())
+            }
+            "FORCE_REINIT" => Ok(()),
+            "PARK" if is_truthy(value) => self.park(),
+            "UNPARK" if is_truthy(value) => self.unpark(),
+            "PARK" | "UNPARK" => Ok(()),
+            // Clients go through emergency_stop(), this is for batches
+            "EMERGENCY_STOP" if is_truthy(value) => self.emergency_stop(),
+            "EMERGENCY_STOP" => Ok(()),
+            "TRACKING" if value.trim().eq_ignore_ascii_case("off") => self.stop_tracking(),
+            "TRACKING" if value.trim().eq_ignore_ascii_case("custom") => {
+                let rate = self
+                    .custom_track_rate_ra
+                    .read()
+                    .unwrap()
+                    .parse()
+                    .ok()
+                    .and_then(TrackingRate::custom)
+                    .ok_or(DeviceActions::InvalidValue)?;
+                self.start_tracking(rate)
+            }
+            "TRACKING" => match value.parse::<TrackingRate>() {
+                Ok(rate) => self.start_tracking(rate),
+                Err(_) => Err(DeviceActions::InvalidValue),
+            },
+            "CUSTOM_TRACK_RATE_RA" => {
+                let rate = value
+                    .trim()
+                    .parse::<f64>()
+                    .map_err(|_| DeviceActions::InvalidValue)?;
+                if rate == 0.0 {
+                    if matches!(self.tracking_rate(), Some(TrackingRate::Custom(_))) {
+                        self.stop_tracking()?;
                     }
+                } else {
+                    let custom = TrackingRate::custom(rate).ok_or_else(|| {
+                        error!("Invalid custom RA tracking rate {}\"/s", rate);
+                        DeviceActions::InvalidValue
+                    })?;
+                    self.start_tracking(custom)?;
                 }
-
-                // Use this to check if the response is OK (=) or there is an error (!)
-                if final_buf[0] == 0x3d {
-                    let response =
-                        std::str::from_utf8(&final_buf[1..&final_buf.len() - 1]).unwrap();
-                    info!("RESPONSE: {}", response);
-                    Ok(response.to_owned())
+                *self.custom_track_rate_ra.write().unwrap() = rate.to_string();
+                Ok(())
+            }
+            "CUSTOM_TRACK_RATE_DEC" => {
+                let rate = value
+                    .trim()
+                    .parse::<f64>()
+                    .map_err(|_| DeviceActions::InvalidValue)?;
+                if rate == 0.0 {
+                    self.stop_axis(Axis::Dec)?;
                 } else {
-                    Err(DeviceActions::InvalidValue)
+                    let rad_per_sec = custom_rate_rad_per_sec(rate).ok_or_else(|| {
+                        error!("Invalid custom DEC tracking rate {}\"/s", rate);
+                        DeviceActions::InvalidValue
+                    })?;
+                    self.slew_axis(Axis::Dec, rad_per_sec)?;
                 }
+                *self.custom_track_rate_dec.write().unwrap() = rate.to_string();
+                Ok(())
             }
-            Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => Err(DeviceActions::Timeout),
-            Err(e) => {
-                error!("{:?}", e);
-                Err(DeviceActions::ComError)
+            "GOTO_COORDINATES" => {
+                let (ra, dec) =
+                    parse_coordinate_pair(value).map_err(|_| DeviceActions::InvalidValue)?;
+                self.goto_ra_dec(ra, dec)?;
+                *self.goto_coordinates.write().unwrap() = value.to_owned();
+                Ok(())
+            }
+            "POLAR_LED" => {
+                let brightness = parse_polar_led_brightness(value)?;
+                self.set_polar_led_brightness(brightness)?;
+                *self.polar_led.write().unwrap() = brightness.to_string();
+                Ok(())
+            }
+            "PPEC_TRAINING" => {
+                let on = is_truthy(value);
+                if on {
+                    self.ppec_train_start()?;
+                } else {
+                    self.ppec_train_stop()?;
+                }
+                *self.ppec_training.write().unwrap() = on.to_string();
+                Ok(())
+            }
+            "PPEC" => {
+                let on = is_truthy(value);
+                self.ppec_enable(on)?;
+                *self.ppec.write().unwrap() = on.to_string();
+                Ok(())
+            }
+            "DEBUG_TRAFFIC" => {
+                self.traffic.set_enabled(is_truthy(value));
+                Ok(())
+            }
+            "ENCODERS_ENABLED" => {
+                let enabled = is_truthy(value);
+                self.set_aux_encoder(Axis::Ra, enabled)?;
+                self.set_aux_encoder(Axis::Dec, enabled)?;
+                *self.encoders_enabled.write().unwrap() = enabled.to_string();
+                Ok(())
+            }
+            "AUX_MOVE" => {
+                let rate = value
+                    .trim()
+                    .parse::<f64>()
+                    .map_err(|_| DeviceActions::InvalidValue)?;
+                if rate == 0.0 {
+                    self.stop_axis(Axis::Aux)?;
+                } else {
+                    self.slew_axis(Axis::Aux, rate * SIDEREAL_RATE)?;
+                }
+                *self.aux_move.write().unwrap() = rate.to_string();
+                Ok(())
+            }
+            "BACKLASH_RA" | "BACKLASH_DEC" => {
+                let steps = value
+                    .trim()
+                    .parse::<u32>()
+                    .map_err(|_| DeviceActions::InvalidValue)?;
+                if name == "BACKLASH_RA" {
+                    self.ra_backlash.steps = steps;
+                    *self.backlash_ra.write().unwrap() = steps.to_string();
+                } else {
+                    self.dec_backlash.steps = steps;
+                    *self.backlash_dec.write().unwrap() = steps.to_string();
+                }
+                Ok(())
+            }
+            // Pulses on the same axis run one after the other, the
+            // device thread ends them on time, see run_guide_pulses
+            "PULSE_GUIDE_RA" | "PULSE_GUIDE_DEC" => {
+                let pulse = value
+                    .parse::<GuidePulse>()
+                    .map_err(|_| DeviceActions::InvalidValue)?;
+                let (axis, prop) = if name == "PULSE_GUIDE_RA" {
+                    (Axis::Ra, &self.pulse_guide_ra)
+                } else {
+                    (Axis::Dec, &self.pulse_guide_dec)
+                };
+                *prop.write().unwrap() = value.trim().to_owned();
+                self.guide_pulses.push(axis, pulse);
+                self.run_guide_pulses();
+                Ok(())
+            }
+            "SNAP_PORT_1" => {
+                let on = is_truthy(value);
+                self.set_snap_port(1, on)?;
+                *self.snap_port_1.write().unwrap() = on.to_string();
+                Ok(())
+            }
+            "RA_AXIS_POSITION" | "DEC_AXIS_POSITION" => {
+                let degrees = value
+                    .trim()
+                    .parse::<f64>()
+                    .map_err(|_| DeviceActions::InvalidValue)?;
+                if name == "RA_AXIS_POSITION" {
+                    self.set_ra_axis_position(degrees)?;
+                    *self.ra_axis_position.write().unwrap() = degrees.to_string();
+                } else {
+                    self.set_dec_axis_position(degrees)?;
+                    *self.dec_axis_position.write().unwrap() = degrees.to_string();
+                }
+                Ok(())
             }
+            _ => Err(DeviceActions::UnknownProperty),
         }
     }
-
-    fn update_property(&mut self, _: &str, _: &str) -> Result<(), DeviceActions> {
-        todo!()
-    }
-
-    fn update_property_remote(&mut self, _: &str, _: &str) -> Result<(), DeviceActions> {
-        todo!()
-    }
-    fn find_property_index(&self, _: &str) -> Option<usize> {
-        todo!()
+    fn find_property_index(&self, name: &str) -> Option<usize> {
+        self.properties.iter().position(|p| p.name == name)
     }
 }
 
 trait EQModMount {
     fn init_device(&mut self);
-    fn get_motor_board_version(&mut self) -> u32;
-    fn get_grid_per_revolution(&mut self) -> (String, String);
-    fn get_axis_position(&mut self) -> (String, String);
-    fn set_ra_axis_position(&mut self, val: &str);
-    fn set_dec_axis_position(&mut self, val: &str);
-    fn get_axis_status(&mut self) -> (String, String);
+    fn init_axes(&mut self, force: bool) -> Result<(), DeviceActions>;
+    fn get_motor_board_version(&mut self) -> Result<String, DeviceActions>;
+    fn get_grid_per_revolution(&mut self) -> Result<(u32, u32), DeviceActions>;
+    fn get_timer_freq(&mut self) -> u32;
+    fn get_high_speed_ratio(&mut self, axis: Axis) -> u16;
+    fn get_capabilities(&mut self) -> MountCapabilities;
+    fn get_axis_position(&mut self) -> (Option<f64>, Option<f64>);
+    fn set_ra_axis_position(&mut self, degrees: f64) -> Result<(), DeviceActions>;
+    fn set_dec_axis_position(&mut self, degrees: f64) -> Result<(), DeviceActions>;
+    fn get_axis_status(&mut self) -> (Option<AxisStatus>, Option<AxisStatus>);
+    fn start_tracking(&mut self, rate: TrackingRate) -> Result<(), DeviceActions>;
+    fn stop_tracking(&mut self) -> Result<(), DeviceActions>;
+    fn move_axis(
+        &mut self,
+        axis: Axis,
+        mode: MotionMode,
+        step_period: u32,
+    ) -> Result<(), DeviceActions>;
+    fn slew_axis(&mut self, axis: Axis, rate_rad_per_sec: f64) -> Result<(), DeviceActions>;
+    fn stop_axis(&mut self, axis: Axis) -> Result<(), DeviceActions>;
+    fn emergency_stop(&mut self) -> Result<(), DeviceActions>;
+    fn goto_axis(&mut self, axis: Axis, target_steps: u32) -> Result<(), DeviceActions>;
+    fn goto_ra_dec(&mut self, ra_deg: f64, dec_deg: f64) -> Result<(), DeviceActions>;
+    fn set_snap_port(&mut self, port: u8, on: bool) -> Result<(), DeviceActions>;
+    fn set_polar_led_brightness(&mut self, value: u8) -> Result<(), DeviceActions>;
+    fn ppec_train_start(&mut self) -> Result<(), DeviceActions>;
+    fn ppec_train_stop(&mut self) -> Result<(), DeviceActions>;
+    fn ppec_enable(&mut self, on: bool) -> Result<(), DeviceActions>;
+    fn set_aux_encoder(&mut self, axis: Axis, enabled: bool) -> Result<(), DeviceActions>;
+    fn start_guide_pulse(&mut self, axis: Axis, rate_offset: f64) -> Result<bool, DeviceActions>;
+    fn end_guide_pulse(&mut self, axis: Axis, period_only: bool) -> Result<(), DeviceActions>;
+    fn get_encoder_position(&mut self, axis: Axis) -> Option<u32>;
+    fn park(&mut self) -> Result<(), DeviceActions>;
+    fn unpark(&mut self) -> Result<(), DeviceActions>;
 }
 
 impl EQModMount for MountDevice {
     fn init_device(&mut self) {
-        self.get_motor_board_version();
-        self.get_grid_per_revolution();
+        match self.get_motor_board_version() {
+            Ok(version) => self.motor_board_version = version,
+            Err(e) => warn!("Cannot read the motor board version: {:?}", e),
+        }
+        match self.get_grid_per_revolution() {
+            Ok((ra_grid, dec_grid)) => {
+                self.ra_steps_per_rev = ra_grid;
+                self.dec_steps_per_rev = dec_grid;
+            }
+            // Positions, gotos and tracking stay unavailable
+            Err(e) => error!("Cannot read the grids per revolution: {:?}", e),
+        }
+        self.timer_freq = self.get_timer_freq();
+        self.ra_high_speed_ratio = self.get_high_speed_ratio(Axis::Ra);
+        self.dec_high_speed_ratio = self.get_high_speed_ratio(Axis::Dec);
+        self.capabilities = self.get_capabilities();
+        self.init_aux_axis();
+
+        self.static_properties.push(Property {
+            name: String::from("BAUD_RATE"),
+            kind: String::from("integer"),
+            value: self.baud.to_string(),
+            permission: Permission::ReadOnly as i32,
+        });
+
+        for (name, value) in [
+            ("MOTOR_BOARD_VERSION", self.motor_board_version.clone()),
+            ("RA_GRID_PER_REV", self.ra_steps_per_rev.to_string()),
+            ("DEC_GRID_PER_REV", self.dec_steps_per_rev.to_string()),
+            ("TIMER_FREQ", self.timer_freq.to_string()),
+            (
+                "POLL_INTERVAL_MS",
+                self.poll_interval.as_millis().to_string(),
+            ),
+            ("RA_HIGH_SPEED_RATIO", self.ra_high_speed_ratio.to_string()),
+            (
+                "DEC_HIGH_SPEED_RATIO",
+                self.dec_high_speed_ratio.to_string(),
+            ),
+        ] {
+            self.static_properties.push(Property {
+                name: String::from(name),
+                kind: String::from("string"),
+                value,
+                permission: Permission::ReadOnly as i32,
+            });
+        }
+
+        let caps = self.capabilities;
+        for (name, value) in [
+            ("HAS_PPEC", caps.has_ppec),
+            ("HAS_ENCODER", caps.has_encoder),
+            ("HAS_SNAP_PORT", caps.has_snap_port),
+            ("IS_AZEQ", caps.is_az_eq),
+        ] {
+            self.static_properties.push(Property {
+                name: String::from(name),
+                kind: String::from("boolean"),
+                value: value.to_string(),
+                permission: Permission::ReadOnly as i32,
+            });
+        }
+
+        let mut tracking_values: Vec<String> =
+            TrackingRate::ALL.iter().map(|r| r.to_string()).collect();
+        tracking_values.push(String::from("Custom"));
+        tracking_values.push(String::from("Off"));
+        self.properties.push(CustomProp {
+            name: String::from("TRACKING"),
+            kind: enum_kind(&tracking_values),
+            permission: Permission::ReadWrite,
+            value: self.tracking.clone(),
+        });
+
+        for (name, kind, value) in [
+            ("FORCE_REINIT", "boolean", &self.force_reinit),
+            ("PARK", "boolean", &self.park),
+            ("UNPARK", "boolean", &self.unpark),
+            ("EMERGENCY_STOP", "boolean", &self.emergency_stop),
+            ("CUSTOM_TRACK_RATE_RA", "float", &self.custom_track_rate_ra),
+            (
+                "CUSTOM_TRACK_RATE_DEC",
+                "float",
+                &self.custom_track_rate_dec,
+            ),
+            ("RA_AXIS_POSITION", "float", &self.ra_axis_position),
+            ("DEC_AXIS_POSITION", "float", &self.dec_axis_position),
+            ("GOTO_COORDINATES", "string", &self.goto_coordinates),
+            ("SNAP_PORT_1", "boolean", &self.snap_port_1),
+            ("POLAR_LED", "integer", &self.polar_led),
+            ("PPEC_TRAINING", "boolean", &self.ppec_training),
+            ("PPEC", "boolean", &self.ppec),
+            ("ENCODERS_ENABLED", "boolean", &self.encoders_enabled),
+            ("BACKLASH_RA", "integer", &self.backlash_ra),
+            ("BACKLASH_DEC", "integer", &self.backlash_dec),
+            ("PULSE_GUIDE_RA", "integer", &self.pulse_guide_ra),
+            ("PULSE_GUIDE_DEC", "integer", &self.pulse_guide_dec),
+        ] {
+            self.properties.push(CustomProp {
+                name: String::from(name),
+                kind: String::from(kind),
+                permission: Permission::ReadWrite,
+                value: value.clone(),
+            });
+        }
+
+        for (name, kind, value) in [
+            ("RA_POSITION", "float", &self.ra_position),
+            ("DEC_POSITION", "float", &self.dec_position),
+            ("RA_STATUS", "string", &self.ra_status),
+            ("DEC_STATUS", "string", &self.dec_status),
+            ("SLEWING", "boolean", &self.slewing),
+            ("PARKED", "boolean", &self.parked),
+            ("PPEC_STATUS", "string", &self.ppec_status),
+            ("ENCODER_RA", "float", &self.encoder_ra),
+            ("ENCODER_DEC", "float", &self.encoder_dec),
+        ] {
+            self.properties.push(CustomProp {
+                name: String::from(name),
+                kind: String::from(kind),
+                permission: Permission::ReadOnly,
+                value: value.clone(),
+            });
+        }
+        self.properties.extend(self.stats.props());
+        self.properties.extend(self.traffic.props());
+        self.view
+            .publish(&self.name, &self.properties, &self.static_properties);
+        self.export_props();
     }
 
-    /// Returns the motor board version.
-    fn get_motor_board_version(&mut self) -> u32 {
-        let version = match self.send_command(RaCommand::MotorBoardVersion as i32, None) {
-            Ok(v) => str_24bits_to_u32(v),
-            Err(_) => 0x0,
-        };
-        version
+    /// Initializes both axes (:F), on some firmware sending it to an already
+    /// initialized board glitches tracking so it is skipped when the axis
+    /// status reports both axes as initialized, unless forced.
+    fn init_axes(&mut self, force: bool) -> Result<(), DeviceActions> {
+        if !force {
+            let (ra_status, dec_status) = self.get_axis_status();
+
+            if ra_status.is_some_and(|s| s.initialized) && dec_status.is_some_and(|s| s.initialized)
+            {
+                info!("Both axes already initialized, skipping init");
+                return Ok(());
+            }
+            info!("Axes not initialized, sending init");
+        } else {
+            info!("Forcing axes init");
+        }
+
+        self.send_axis_command(EqModOp::Init, Axis::Dec, None)?;
+        self.send_axis_command(EqModOp::Init, Axis::Ra, None)?;
+        self.ra_backlash.reset();
+        self.dec_backlash.reset();
+        self.restore_settings();
+        Ok(())
+    }
+
+    /// Returns the motor board version, e.g. "2.4.2"
+    fn get_motor_board_version(&mut self) -> Result<String, DeviceActions> {
+        let resp = self.send_axis_command(EqModOp::MotorBoardVersion, Axis::Ra, None)?;
+        parse_motor_board_version(&resp).map_err(|e| {
+            error!("Invalid motor board version {:?}: {:?}", resp, e);
+            DeviceActions::ComError
+        })
     }
 
     /// Returns (RA grid, DEC grid) grids per revolution.
-    fn get_grid_per_revolution(&mut self) -> (String, String) {
-        let ra_grid = match self.send_command(RaCommand::InquireGridPerRevolution as i32, None) {
-            Ok(v) => v,
-            Err(_) => String::from("UNKNOWN"),
+    fn get_grid_per_revolution(&mut self) -> Result<(u32, u32), DeviceActions> {
+        let mut grid = |axis: Axis| {
+            let resp = self.send_axis_command(EqModOp::InquireGridPerRevolution, axis, None)?;
+            parse_24bits_response(&resp).map_err(|e| {
+                error!("Invalid {:?} grid per revolution {:?}: {:?}", axis, resp, e);
+                DeviceActions::ComError
+            })
         };
 
-        let dec_grid = match self.send_command(DecCommand::InquireGridPerRevolution as i32, None) {
-            Ok(v) => v,
-            Err(_) => String::from("UNKNOWN"),
+        Ok((grid(Axis::Ra)?, grid(Axis::Dec)?))
+    }
+
+    /// Returns the frequency of the motor controller timer, 0 when unknown
+    fn get_timer_freq(&mut self) -> u32 {
+        match self.send_axis_command(EqModOp::InquireTimerInterruptFreq, Axis::Ra, None) {
+            Ok(v) => str_24bits_to_u24(v).unwrap_or(0),
+            Err(_) => 0,
+        }
+    }
+
+    /// Returns how many steps an axis moves per timer period at high
+    /// speed, 0 when unknown
+    fn get_high_speed_ratio(&mut self, axis: Axis) -> u16 {
+        match self.send_axis_command(EqModOp::InquireHighSpeedRatio, axis, None) {
+            Ok(v) => str_to_u16(v).unwrap_or(0),
+            Err(_) => 0,
+        }
+    }
+
+    /// Returns the features advertised by the motor board, older boards
+    /// don't understand the extended inquiry and support none of them
+    fn get_capabilities(&mut self) -> MountCapabilities {
+        let resp = match self.send_axis_command(
+            EqModOp::ExtendedInquiry,
+            Axis::Ra,
+            Some(String::from(FEATURES_INQUIRY)),
+        ) {
+            Ok(r) => r,
+            Err(_) => {
+                info!("No extended inquiry support, assuming no optional features");
+                return MountCapabilities::default();
+            }
         };
 
-        (ra_grid, dec_grid)
+        match MountCapabilities::from_response(&resp) {
+            Ok(caps) => {
+                info!("Mount capabilities: {:?}", caps);
+                caps
+            }
+            Err(e) => {
+                warn!("Cannot parse the mount capabilities {:?}: {:?}", resp, e);
+                MountCapabilities::default()
+            }
+        }
     }
 
-    fn get_axis_position(&mut self) -> (String, String) {
-        let ra_pos = match self.send_command(RaCommand::GetAxisPosition as i32, None) {
-            Ok(v) => v,
-            Err(_) => String::from("UNKNOWN"),
+    /// Returns (RA, DEC) axis positions in degrees from home.
+    fn get_axis_position(&mut self) -> (Option<f64>, Option<f64>) {
+        let ra_pos = match self.send_axis_command(EqModOp::GetAxisPosition, Axis::Ra, None) {
+            Ok(v) if self.ra_steps_per_rev > 0 => str_24bits_to_u24(v)
+                .map(|steps| eqmod_steps_to_degrees(steps, self.ra_steps_per_rev)),
+            _ => None,
         };
 
-        let dec_pos = match self.send_command(DecCommand::GetAxisPosition as i32, None) {
-            Ok(v) => v,
-            Err(_) => String::from("UNKNOWN"),
+        let dec_pos = match self.send_axis_command(EqModOp::GetAxisPosition, Axis::Dec, None) {
+            Ok(v) if self.dec_steps_per_rev > 0 => str_24bits_to_u24(v)
+                .map(|steps| eqmod_steps_to_degrees(steps, self.dec_steps_per_rev)),
+            _ => None,
         };
 
         (ra_pos, dec_pos)
     }
 
-    fn set_ra_axis_position(&mut self, val: &str) {
-        let ra_pos =
-            match self.send_command(RaCommand::SetAxisPosition as i32, Some(val.to_string())) {
-                Ok(v) => info!("Set RA Axis position to {}", v),
-                Err(e) => error!("Error while setting RA position: {}", e as i32),
-            };
+    /// Sets the RA axis position to the given degrees from home
+    fn set_ra_axis_position(&mut self, degrees: f64) -> Result<(), DeviceActions> {
+        if self.ra_steps_per_rev == 0 {
+            error!("Cannot set the RA axis position, unknown RA grid");
+            return Err(DeviceActions::InvalidValue);
+        }
+        let val = u32_to_str_24bits(degrees_to_eqmod_steps(degrees, self.ra_steps_per_rev));
+        match self.send_axis_command(EqModOp::SetAxisPosition, Axis::Ra, Some(val)) {
+            Ok(v) => {
+                info!("Set RA Axis position to {}", v);
+                Ok(())
+            }
+            Err(e) => {
+                error!("Error while setting RA position: {}", e as i32);
+                Err(e)
+            }
+        }
+    }
+
+    /// Sets the DEC axis position to the given degrees from home
+    fn set_dec_axis_position(&mut self, degrees: f64) -> Result<(), DeviceActions> {
+        if self.dec_steps_per_rev == 0 {
+            error!("Cannot set the DEC axis position, unknown DEC grid");
+            return Err(DeviceActions::InvalidValue);
+        }
+        let val = u32_to_str_24bits(degrees_to_eqmod_steps(degrees, self.dec_steps_per_rev));
+        match self.send_axis_command(EqModOp::SetAxisPosition, Axis::Dec, Some(val)) {
+            Ok(v) => {
+                info!("Set DEC Axis position to {}", v);
+                Ok(())
+            }
+            Err(e) => {
+                error!("Error while setting DEC position: {}", e as i32);
+                Err(e)
+            }
+        }
+    }
+
+    /// Returns the (RA, DEC) axis status flags, None when unreadable
+    fn get_axis_status(&mut self) -> (Option<AxisStatus>, Option<AxisStatus>) {
+        let mut status = |axis: Axis| {
+            let resp = self
+                .send_axis_command(EqModOp::GetAxisStatus, axis, None)
+                .ok()?;
+            match AxisStatus::from_response(&resp) {
+                Ok(s) => Some(s),
+                Err(e) => {
+                    warn!("Cannot parse {:?} axis status {:?}: {:?}", axis, resp, e);
+                    None
+                }
+            }
+        };
+
+        (status(Axis::Ra), status(Axis::Dec))
+    }
+
+    /// Starts tracking at the given rate on the RA axis
+    fn start_tracking(&mut self, rate: TrackingRate) -> Result<(), DeviceActions> {
+        if self.ra_steps_per_rev == 0 || self.timer_freq == 0 {
+            error!("Cannot compute the tracking rate, unknown RA grid or timer frequency");
+            return Err(DeviceActions::InvalidValue);
+        }
+
+        // A tracking axis can change rate without stopping
+        let current = self
+            .send_axis_command(EqModOp::GetAxisStatus, Axis::Ra, None)
+            .ok()
+            .and_then(|s| AxisStatus::from_response(&s).ok());
+        for (op, payload) in
+            start_tracking_commands(rate, self.ra_steps_per_rev, self.timer_freq, current)
+        {
+            self.send_axis_command(op, Axis::Ra, payload)?;
+        }
+        self.backlash(Axis::Ra).set_direction(Direction::Forward);
+        info!("{} tracking started", rate);
+        *self.tracking.write().unwrap() = rate.to_string();
+        Ok(())
+    }
+
+    /// Stops the RA axis, decelerating
+    fn stop_tracking(&mut self) -> Result<(), DeviceActions> {
+        self.stop_axis(Axis::Ra)?;
+        info!("Tracking stopped");
+        *self.tracking.write().unwrap() = String::from("Off");
+        Ok(())
+    }
+
+    /// Runs an axis in the given mode at `step_period` timer ticks per
+    /// step. The motion mode can only be changed while it stands still,
+    /// an axis already running in `mode` only gets the new period
+    fn move_axis(
+        &mut self,
+        axis: Axis,
+        mode: MotionMode,
+        step_period: u32,
+    ) -> Result<(), DeviceActions> {
+        let status = self
+            .send_axis_command(EqModOp::GetAxisStatus, axis, None)
+            .ok()
+            .and_then(|s| AxisStatus::from_response(&s).ok());
+        match status {
+            Some(s) if s.is_moving_in(mode) => {
+                self.send_axis_command(
+                    EqModOp::SetStepPeriod,
+                    axis,
+                    Some(u32_to_str_24bits(step_period)),
+                )?;
+                debug!("{:?} axis period changed to {}", axis, step_period);
+                return Ok(());
+            }
+            Some(s) if !s.running => {}
+            // Running otherwise, or unknown
+            _ => {
+                self.stop_axis(axis)?;
+                self.wait_for_stop(axis)?;
+            }
+        }
+
+        let compensation = self.backlash(axis).compensation(mode.direction);
+        if compensation > 0 {
+            debug!("Taking up {} steps of {:?} backlash", compensation, axis);
+            self.send_axis_commands(axis, &backlash_commands(mode.direction, compensation))?;
+            self.wait_for_stop(axis)?;
+        }
+
+        self.send_axis_command(EqModOp::SetMotionMode, axis, Some(mode.payload()))?;
+        self.send_axis_command(
+            EqModOp::SetStepPeriod,
+            axis,
+            Some(u32_to_str_24bits(step_period)),
+        )?;
+        self.send_axis_command(EqModOp::StartMotion, axis, None)?;
+        debug!("{:?} axis moving with {:?}", axis, mode);
+        Ok(())
+    }
+
+    /// Moves an axis at the given rate until stopped, negative rates
+    /// move it backwards
+    fn slew_axis(&mut self, axis: Axis, rate_rad_per_sec: f64) -> Result<(), DeviceActions> {
+        let (steps_per_rev, high_speed_ratio) = match axis {
+            Axis::Ra => (self.ra_steps_per_rev, self.ra_high_speed_ratio),
+            Axis::Dec => (self.dec_steps_per_rev, self.dec_high_speed_ratio),
+            Axis::Aux => (self.aux_steps_per_rev, self.aux_high_speed_ratio),
+        };
+        if steps_per_rev == 0 || self.timer_freq == 0 {
+            error!(
+                "Cannot compute the slew rate, unknown {:?} grid or timer frequency",
+                axis
+            );
+            return Err(DeviceActions::InvalidValue);
+        }
+
+        let (mode, period) = slew_motion(
+            rate_rad_per_sec,
+            steps_per_rev,
+            self.timer_freq,
+            high_speed_ratio,
+        );
+        self.move_axis(axis, mode, period)
     }
 
-    fn set_dec_axis_position(&mut self, val: &str) {
-        let ra_pos =
-            match self.send_command(DecCommand::SetAxisPosition as i32, Some(val.to_string())) {
-                Ok(v) => info!("Set DEC Axis position to {}", v),
-                Err(e) => error!("Error while setting DEC position: {}", e as i32),
+    /// Stops an axis, decelerating
+    fn stop_axis(&mut self, axis: Axis) -> Result<(), DeviceActions> {
+        self.send_axis_command(EqModOp::StopMotion, axis, None)?;
+        Ok(())
+    }
+
+    /// Stops every axis at once, without decelerating. All the stop
+    /// frames are written before any answer is read so an axis that
+    /// doesn't answer can't delay the others
+    fn emergency_stop(&mut self) -> Result<(), DeviceActions> {
+        warn!("Emergency stop");
+        let mut axes = vec![Axis::Ra, Axis::Dec];
+        if self.aux_present {
+            axes.push(Axis::Aux);
+        }
+        let frames: Vec<Vec<u8>> = axes
+            .iter()
+            .map(|axis| {
+                let mut frame = command(EqModOp::InstantStop, *axis);
+                frame.push(b'\r');
+                frame
+            })
+            .collect();
+        let timeout = self.port.timeout();
+        let answers = send_all(&mut self.port, &frames, b'\r', timeout);
+
+        // Whatever the answers, nothing is moving on purpose anymore
+        *self.tracking.write().unwrap() = String::from("Off");
+        *self.slewing.write().unwrap() = String::from("false");
+        self.slew_monitor.reset();
+        self.guide_pulses.clear();
+        self.cancel_parking();
+
+        let mut result = Ok(());
+        for answer in answers {
+            let error = match answer {
+                Ok(buf) => match split_response(&buf) {
+                    Ok(_) => continue,
+                    Err(e) => e.device_action(),
+                },
+                Err(ReadError::Timeout) => DeviceActions::Timeout,
+                Err(_) => DeviceActions::ComError,
             };
+            error!("Emergency stop not acknowledged: {:?}", error);
+            result = Err(error);
+        }
+        if result.is_err() {
+            // Drop late answers so they don't end up in the next one
+            let _ = self.port.clear(ClearBuffer::Input);
+        }
+        result
     }
 
-    fn get_axis_status(&mut self) -> (String, String) {
-        let ra_status = match self.send_command(RaCommand::GetAxisStatus as i32, None) {
-            Ok(v) => v,
-            Err(_) => String::from("UNKNOWN"),
+    /// Moves an axis to the given raw position, refused while the axis
+    /// is running
+    fn goto_axis(&mut self, axis: Axis, target_steps: u32) -> Result<(), DeviceActions> {
+        let steps_per_rev = match axis {
+            Axis::Ra => self.ra_steps_per_rev,
+            Axis::Dec => self.dec_steps_per_rev,
+            Axis::Aux => self.aux_steps_per_rev,
         };
+        if steps_per_rev == 0 {
+            error!("Cannot goto, unknown {:?} grid", axis);
+            return Err(DeviceActions::InvalidValue);
+        }
+
+        let status = self
+            .send_axis_command(EqModOp::GetAxisStatus, axis, None)
+            .and_then(|s| AxisStatus::from_response(&s).map_err(|_| DeviceActions::ComError))?;
+        let current = self.get_axis_steps(axis)?;
 
-        let dec_status = match self.send_command(DecCommand::GetAxisStatus as i32, None) {
-            Ok(v) => v,
-            Err(_) => String::from("UNKNOWN"),
+        let commands = match goto_commands(
+            &status,
+            current,
+            target_steps,
+            steps_per_rev,
+            self.break_steps,
+        ) {
+            Some(c) => c,
+            None => {
+                warn!("{:?} axis is running, stop it before a goto", axis);
+                return Err(DeviceActions::InvalidValue);
+            }
+        };
+        if let Err(e) = self.send_axis_commands(axis, &commands) {
+            match self.last_controller_error {
+                Some(ControllerError::NotInitialized) => {
+                    warn!("Motor board lost its init, initializing again");
+                    self.init_axes(true)?;
+                }
+                Some(ControllerError::MotorNotStopped) => {
+                    warn!("{:?} axis still moving, stopping it first", axis);
+                    self.stop_axis(axis)?;
+                    self.wait_for_stop(axis)?;
+                }
+                _ => return Err(e),
+            }
+            self.send_axis_commands(axis, &commands)?;
+        }
+        // Gotos aren't compensated but they leave the gears engaged the
+        // other way round
+        let direction = if target_steps < current {
+            Direction::Backward
+        } else {
+            Direction::Forward
         };
+        self.backlash(axis).set_direction(direction);
+        debug!("{:?} axis goto to {:06X}", axis, target_steps);
+        Ok(())
+    }
 
-        (ra_status, dec_status)
+    /// Moves both axes to the given angles, in degrees from home as
+    /// reported by RA_POSITION and DEC_POSITION
+    fn goto_ra_dec(&mut self, ra_deg: f64, dec_deg: f64) -> Result<(), DeviceActions> {
+        info!("GOTO to RA: {} DEC: {}", ra_deg, dec_deg);
+        let ra_target = degrees_to_eqmod_steps(ra_deg, self.ra_steps_per_rev);
+        let dec_target = degrees_to_eqmod_steps(dec_deg, self.dec_steps_per_rev);
+        self.goto_axis(Axis::Ra, ra_target)?;
+        if let Err(e) = self.goto_axis(Axis::Dec, dec_target) {
+            // Not left slewing towards half of the target
+            if let Err(stop) = self.stop_axis(Axis::Ra) {
+                error!("Cannot stop the RA axis after a failed goto: {:?}", stop);
+            }
+            return Err(e);
+        }
+        *self.slewing.write().unwrap() = String::from("true");
+        Ok(())
+    }
+
+    /// Switches a SNAP port on or off, e.g. to hold a DSLR shutter open
+    fn set_snap_port(&mut self, port: u8, on: bool) -> Result<(), DeviceActions> {
+        let (axis, payload) = snap_port_command(&self.capabilities, port, on)?;
+        self.send_axis_command(EqModOp::SetSnapPort, axis, Some(payload))?;
+        info!(
+            "SNAP port {} switched {}",
+            port,
+            if on { "on" } else { "off" }
+        );
+        Ok(())
+    }
+
+    /// Sets the polar scope illumination, 0 switches it off
+    fn set_polar_led_brightness(&mut self, value: u8) -> Result<(), DeviceActions> {
+        let payload = polar_led_payload(&self.capabilities, value)?;
+        self.send_axis_command(EqModOp::SetPolarLed, Axis::Ra, Some(payload))?;
+        self.polar_led_brightness = Some(value);
+        info!("Polar LED brightness set to {}", value);
+        Ok(())
+    }
+
+    /// Starts recording the periodic error, sidereal tracking must be running
+    fn ppec_train_start(&mut self) -> Result<(), DeviceActions> {
+        self.send_ppec_command(PpecCommand::StartTraining)
+    }
+
+    fn ppec_train_stop(&mut self) -> Result<(), DeviceActions> {
+        self.send_ppec_command(PpecCommand::StopTraining)
+    }
+
+    /// Turns the correction of the recorded periodic error on or off
+    fn ppec_enable(&mut self, on: bool) -> Result<(), DeviceActions> {
+        self.send_ppec_command(if on {
+            PpecCommand::Enable
+        } else {
+            PpecCommand::Disable
+        })
+    }
+
+    /// Turns the auxiliary encoder of an axis on or off
+    fn set_aux_encoder(&mut self, axis: Axis, enabled: bool) -> Result<(), DeviceActions> {
+        let payload = aux_encoder_payload(&self.capabilities, enabled)?;
+        self.send_axis_command(EqModOp::ExtendedSetting, axis, Some(payload))?;
+        info!(
+            "{:?} axis encoder {}",
+            axis,
+            if enabled { "enabled" } else { "disabled" }
+        );
+        Ok(())
+    }
+
+    /// Returns the raw position measured by the auxiliary encoder of an
+    /// axis, None when unreadable
+    fn get_encoder_position(&mut self, axis: Axis) -> Option<u32> {
+        let resp = self
+            .send_axis_command(EqModOp::InquireAuxEncoder, axis, None)
+            .ok()?;
+        match parse_encoder_position(&resp) {
+            Ok(pos) => Some(pos),
+            Err(e) => {
+                warn!(
+                    "Cannot parse {:?} encoder position {:?}: {:?}",
+                    axis, resp, e
+                );
+                None
+            }
+        }
+    }
+
+    /// Adds `rate_offset` to the current rate of an axis. Returns true
+    /// when only the step period of the running RA axis was changed,
+    /// otherwise the axis has been (re)started or stopped
+    fn start_guide_pulse(&mut self, axis: Axis, rate_offset: f64) -> Result<bool, DeviceActions> {
+        let tracking = match axis {
+            Axis::Ra => self.tracking_rate(),
+            Axis::Dec | Axis::Aux => None,
+        };
+        let rate = tracking.map_or(0.0, |r| r.rad_per_sec()) + rate_offset;
+
+        if tracking.is_some() && rate > 0.0 {
+            // Same direction, the period can be changed on the fly
+            if self.ra_steps_per_rev == 0 || self.timer_freq == 0 {
+                return Err(DeviceActions::InvalidValue);
+            }
+            let (_, period) = slew_motion(rate, self.ra_steps_per_rev, self.timer_freq, 0);
+            self.send_axis_command(
+                EqModOp::SetStepPeriod,
+                Axis::Ra,
+                Some(u32_to_str_24bits(period)),
+            )?;
+            Ok(true)
+        } else if rate == 0.0 {
+            self.stop_axis(axis)?;
+            Ok(false)
+        } else {
+            self.slew_axis(axis, rate)?;
+            Ok(false)
+        }
+    }
+
+    /// Brings an axis back to its rate before the pulse
+    fn end_guide_pulse(&mut self, axis: Axis, period_only: bool) -> Result<(), DeviceActions> {
+        let tracking = match axis {
+            Axis::Ra => self.tracking_rate(),
+            Axis::Dec | Axis::Aux => None,
+        };
+        match tracking {
+            Some(rate) if period_only => {
+                let period = step_period_for_rate(
+                    rate.rad_per_sec(),
+                    self.ra_steps_per_rev,
+                    self.timer_freq,
+                );
+                self.send_axis_command(
+                    EqModOp::SetStepPeriod,
+                    Axis::Ra,
+                    Some(u32_to_str_24bits(period)),
+                )?;
+                Ok(())
+            }
+            Some(rate) => self.start_tracking(rate),
+            None => self.stop_axis(axis),
+        }
     }
-}
 
-pub fn look_for_devices() -> Vec<(String, UsbPortInfo)> {
-    let ports = available_ports().unwrap();
-    let mut devices = Vec::new();
+    /// Stops tracking and starts the slew of both axes to the home
+    /// position, the polls set PARKED once the mount got there
+    fn park(&mut self) -> Result<(), DeviceActions> {
+        if self.parking.is_some() {
+            info!("Already parking");
+            return Ok(());
+        }
+        let tracking = self.tracking_rate();
+        if tracking.is_some() {
+            self.stop_tracking()?;
+            self.wait_for_stop(Axis::Ra)?;
+        }
 
-    for port in ports {
-        if let SerialPortType::UsbPort(info) = port.port_type {
-            if info.vid == 0x067b && info.pid == 0x2303 {
-                devices.push((port.port_name, info));
+        info!("Parking");
+        self.goto_axis(Axis::Ra, EQMOD_HOME_POSITION)?;
+        self.goto_axis(Axis::Dec, EQMOD_HOME_POSITION)?;
+        *self.slewing.write().unwrap() = String::from("true");
+        self.tracking_before_park = tracking;
+        self.parking = Some(GotoWatch::new(Instant::now(), PARK_TIMEOUT));
+        Ok(())
+    }
+
+    /// Clears the parked state and restores the tracking that was
+    /// running before parking, a park still on its way is called off
+    fn unpark(&mut self) -> Result<(), DeviceActions> {
+        if self.parking.is_some() {
+            info!("Park called off");
+            let tracking = self.tracking_before_park.take();
+            self.cancel_parking();
+            self.stop_axis(Axis::Ra)?;
+            self.stop_axis(Axis::Dec)?;
+            // The motion mode only changes once the goto stopped
+            if let Some(rate) = tracking {
+                self.wait_for_stop(Axis::Ra)?;
+                self.resume_tracking(rate)?;
             }
+            return Ok(());
+        }
+        if !self.park_state.parked {
+            return Ok(());
+        }
+
+        let tracking = self.park_state.unpark();
+        self.save_park_state();
+        *self.parked.write().unwrap() = String::from("false");
+        info!("Mount unparked");
+        if let Some(rate) = tracking {
+            self.resume_tracking(rate)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses `value` the way update_property_remote does without acting
+/// on it, so a batch is checked in full before anything is sent
+fn check_property_value(name: &str, value: &str) -> Result<(), DeviceActions> {
+    let valid = match name {
+        "CUSTOM_TRACK_RATE_RA" | "CUSTOM_TRACK_RATE_DEC" => value
+            .trim()
+            .parse::<f64>()
+            .is_ok_and(|rate| rate == 0.0 || custom_rate_rad_per_sec(rate).is_some()),
+        "GOTO_COORDINATES" => parse_coordinate_pair(value).is_ok(),
+        "POLAR_LED" => parse_polar_led_brightness(value).is_ok(),
+        "BACKLASH_RA" | "BACKLASH_DEC" => value.trim().parse::<u32>().is_ok(),
+        "PULSE_GUIDE_RA" | "PULSE_GUIDE_DEC" => value.parse::<GuidePulse>().is_ok(),
+        _ => true,
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(DeviceActions::InvalidValue)
+    }
+}
+
+impl actor::Device for MountDevice {
+    fn update_property(&mut self, name: &str, value: &str) -> Result<(), DeviceActions> {
+        AstroSerialDevice::update_property(self, name, value)
+    }
+
+    fn update_properties(&mut self, pairs: &[(&str, &str)]) -> Result<(), DeviceActions> {
+        let props = self.properties.clone();
+        update_all(&props, pairs, check_property_value, |name, value| {
+            AstroSerialDevice::update_property(self, name, value)
+        })
+    }
+
+    fn fetch_props(&mut self) {
+        AstroSerialDevice::fetch_props(self)
+    }
+
+    fn ls_props(&self) -> Vec<Property> {
+        ls_props(&self.properties, &self.static_properties)
+    }
+
+    fn set_interrupt(&mut self, interrupt: Interrupt) {
+        self.interrupt = interrupt;
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        self.guide_pulses.next_end()
+    }
+
+    fn on_deadline(&mut self) {
+        self.run_guide_pulses();
+    }
+}
+
+impl shutdown::Shutdown for MountDevice {
+    fn stop_tracking(&mut self) -> Result<(), DeviceActions> {
+        EQModMount::stop_tracking(self)
+    }
+
+    /// Waits for the mount to get to the park position, nothing else
+    /// needs the device anymore
+    fn park(&mut self) -> Result<(), DeviceActions> {
+        EQModMount::park(self)?;
+        while self.parking.is_some() {
+            thread::sleep(SLEWING_POLL_INTERVAL);
+            let (ra, dec) = self.get_axis_status();
+            let slewing = self.slew_monitor.update(ra, dec);
+            self.update_parking(slewing);
+        }
+        if self.park_state.parked {
+            Ok(())
+        } else {
+            Err(DeviceActions::Timeout)
+        }
+    }
+
+    fn close(&mut self) {
+        if let Err(e) = self.port.flush() {
+            warn!("Cannot flush the serial port: {}", e);
+        }
+    }
+}
+
+/// Stops every axis ahead of the requests waiting for the device
+pub async fn emergency_stop(device: &DeviceHandle<MountDevice>) -> Result<(), DeviceActions> {
+    device.urgent(EQModMount::emergency_stop).await?
+}
+
+#[cfg(test)]
+mod test {
+    use super::{EQModMount, MountDevice, MountPort};
+    use astrotools::AstroSerialDevice;
+    use lightspeed_astro::devices::actions::DeviceActions;
+    use skywatcher_rs::actor;
+    use skywatcher_rs::motor_simulator::SimulatedMotors;
+    use skywatcher_rs::park::load_park_states;
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::net::UdpSocket;
+    use std::path::PathBuf;
+    use std::thread;
+    use std::time::Duration;
+
+    fn state_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "skywatcher-eqmod-{}-{}.json",
+            name,
+            std::process::id()
+        ))
+    }
+
+    /// A device on a simulated board whose gotos take `goto_time`,
+    /// keeping its park state to itself
+    fn device(goto_time: Duration, name: &str) -> MountDevice {
+        let motors = SimulatedMotors::default().with_goto_time(goto_time);
+        let mut device = MountDevice::with_simulator(motors).unwrap();
+        device.park_state_file = state_file(name);
+        device
+    }
+
+    fn prop(device: &MountDevice, name: &str) -> String {
+        device
+            .view()
+            .ls_props()
+            .into_iter()
+            .find(|p| p.name == name)
+            .unwrap()
+            .value
+    }
+
+    /// Frames the device sent since the last call
+    fn sent(device: &mut MountDevice) -> Vec<String> {
+        match &mut device.port {
+            MountPort::Simulated(motors) => motors.take_frames(),
+            MountPort::Serial(_) | MountPort::Udp(_) => unreachable!(),
         }
     }
 
-    match devices.len() {
-        0 => warn!("No Sky-Watcher mount found"),
-        n => info!("Found {} Sky-Watcher mount(s)", n),
+    fn saved_tracking(device: &MountDevice) -> Option<String> {
+        load_park_states(&device.park_state_file)
+            .remove(&device.name)
+            .and_then(|s| s.tracking)
     }
 
-    devices
+    #[test]
+    fn park_is_finished_by_the_polls() {
+        let mut device = device(Duration::from_millis(300), "park");
+        device.update_property("TRACKING", "Sidereal").unwrap();
+
+        // Returns as soon as the slew started
+        device.update_property("PARK", "true").unwrap();
+        device.fetch_props();
+        assert_eq!(prop(&device, "SLEWING"), "true");
+        assert_eq!(prop(&device, "PARKED"), "false");
+        assert_eq!(prop(&device, "TRACKING"), "Off");
+
+        thread::sleep(Duration::from_millis(350));
+        device.fetch_props();
+        assert_eq!(prop(&device, "SLEWING"), "false");
+        assert_eq!(prop(&device, "PARKED"), "true");
+        assert_eq!(saved_tracking(&device), Some(String::from("Sidereal")));
+
+        device.update_property("UNPARK", "true").unwrap();
+        assert_eq!(prop(&device, "PARKED"), "false");
+        assert_eq!(prop(&device, "TRACKING"), "Sidereal");
+        fs::remove_file(&device.park_state_file).unwrap();
+    }
+
+    #[test]
+    fn custom_rates_survive_a_park() {
+        let mut device = device(Duration::from_millis(100), "custom-rate");
+        device
+            .update_property("CUSTOM_TRACK_RATE_RA", "20")
+            .unwrap();
+        assert_eq!(prop(&device, "TRACKING"), "Custom");
+
+        device.update_property("PARK", "true").unwrap();
+        device.fetch_props();
+        thread::sleep(Duration::from_millis(150));
+        device.fetch_props();
+        assert_eq!(prop(&device, "PARKED"), "true");
+        assert_eq!(saved_tracking(&device), Some(String::from("20")));
+
+        // As after a restart, the rate only comes from the park state
+        *device.custom_track_rate_ra.write().unwrap() = String::from("0");
+        device.update_property("UNPARK", "true").unwrap();
+        assert_eq!(prop(&device, "TRACKING"), "Custom");
+        assert_eq!(prop(&device, "CUSTOM_TRACK_RATE_RA"), "20");
+        fs::remove_file(&device.park_state_file).unwrap();
+    }
+
+    #[test]
+    fn unpark_calls_off_a_park() {
+        let mut device = device(Duration::from_secs(10), "called-off");
+        device.update_property("TRACKING", "Sidereal").unwrap();
+        device.update_property("PARK", "true").unwrap();
+        device.fetch_props();
+        assert_eq!(prop(&device, "SLEWING"), "true");
+
+        device.update_property("UNPARK", "true").unwrap();
+        device.fetch_props();
+        assert_eq!(prop(&device, "SLEWING"), "false");
+        assert_eq!(prop(&device, "PARKED"), "false");
+        assert!(!device.park_state.parked);
+        assert_eq!(saved_tracking(&device), None);
+    }
+
+    #[test]
+    fn unpark_mid_park_resumes_tracking() {
+        let mut device = device(Duration::from_secs(10), "resumed");
+        device.update_property("TRACKING", "Lunar").unwrap();
+        device.update_property("PARK", "true").unwrap();
+        assert_eq!(prop(&device, "TRACKING"), "Off");
+
+        device.update_property("UNPARK", "true").unwrap();
+        assert_eq!(prop(&device, "TRACKING"), "Lunar");
+        device.fetch_props();
+        assert_eq!(prop(&device, "SLEWING"), "false");
+        assert_eq!(prop(&device, "PARKED"), "false");
+    }
+
+    #[test]
+    fn ppec_status_follows_the_board() {
+        // PPEC and an encoder
+        let motors = SimulatedMotors::default().with_features(0x000003);
+        let mut device = MountDevice::with_simulator(motors).unwrap();
+        assert_eq!(prop(&device, "PPEC_STATUS"), "Idle");
+
+        device.update_property("TRACKING", "Sidereal").unwrap();
+        device.update_property("PPEC_TRAINING", "true").unwrap();
+        device.fetch_props();
+        assert_eq!(prop(&device, "PPEC_STATUS"), "Training");
+        device.update_property("PPEC_TRAINING", "false").unwrap();
+        device.update_property("PPEC", "true").unwrap();
+        device.fetch_props();
+        assert_eq!(prop(&device, "PPEC_STATUS"), "Active");
+        // The features read at init stay
+        assert!(device.capabilities.has_encoder && device.capabilities.has_ppec);
+    }
+
+    #[test]
+    fn reversing_takes_the_backlash_up() {
+        let motors = SimulatedMotors::default().with_goto_time(Duration::ZERO);
+        let mut device = MountDevice::with_simulator(motors).unwrap();
+        device.update_property("BACKLASH_DEC", "100").unwrap();
+        sent(&mut device);
+
+        // Standing still, nothing to stop nor to take up
+        device
+            .update_property("CUSTOM_TRACK_RATE_DEC", "10")
+            .unwrap();
+        assert_eq!(sent(&mut device), [":f2", ":G210", ":I2A50300", ":J2"]);
+
+        // Stopped first, then 100 (0x64) steps backwards before the slew
+        device
+            .update_property("CUSTOM_TRACK_RATE_DEC", "-10")
+            .unwrap();
+        assert_eq!(
+            sent(&mut device),
+            [
+                ":f2",
+                ":K2",
+                ":f2",
+                ":G221",
+                ":H2640000",
+                ":J2",
+                ":f2",
+                ":G211",
+                ":I2A50300",
+                ":J2"
+            ]
+        );
+
+        // Same direction, only the period changes
+        device
+            .update_property("CUSTOM_TRACK_RATE_DEC", "-5")
+            .unwrap();
+        assert_eq!(sent(&mut device), [":f2", ":I2490700"]);
+    }
+
+    #[test]
+    fn dec_position_is_set_on_the_dec_axis() {
+        let mut device = device(Duration::ZERO, "dec-position");
+        sent(&mut device);
+
+        device.set_dec_axis_position(10.0).unwrap();
+        let frames = sent(&mut device);
+        assert_eq!(frames.len(), 1, "{:?}", frames);
+        assert!(frames[0].starts_with(":E2"), "{:?}", frames);
+        assert!(!frames.iter().any(|f| f.starts_with(":E1")));
+
+        let (ra, dec) = device.get_axis_position();
+        assert!((dec.unwrap() - 10.0).abs() < 0.01, "{:?}", dec);
+        assert!(ra.unwrap().abs() < 0.01, "{:?}", ra);
+    }
+
+    #[test]
+    fn failed_dec_goto_stops_ra() {
+        let mut device = device(Duration::from_secs(10), "half-goto");
+        device.dec_steps_per_rev = 0;
+        sent(&mut device);
+
+        assert_eq!(
+            device.update_property("GOTO_COORDINATES", "30.0,20.0"),
+            Err(DeviceActions::InvalidValue)
+        );
+        let frames = sent(&mut device);
+        assert!(frames.iter().any(|f| f.starts_with(":J1")), "{:?}", frames);
+        assert_eq!(frames.last().map(String::as_str), Some(":K1"));
+        device.fetch_props();
+        assert_eq!(prop(&device, "SLEWING"), "false");
+    }
+
+    #[test]
+    fn axis_positions_need_the_grid() {
+        let mut device = device(Duration::ZERO, "unknown-grid");
+        device.ra_steps_per_rev = 0;
+        device.dec_steps_per_rev = 0;
+        sent(&mut device);
+
+        for name in ["RA_AXIS_POSITION", "DEC_AXIS_POSITION"] {
+            assert_eq!(
+                device.update_property(name, "10.0"),
+                Err(DeviceActions::InvalidValue)
+            );
+        }
+        assert!(sent(&mut device).is_empty());
+    }
+
+    #[test]
+    fn invalid_batches_send_nothing() {
+        let mut device = device(Duration::ZERO, "batch");
+        sent(&mut device);
+
+        for pairs in [
+            [("TRACKING", "Sidereal"), ("CUSTOM_TRACK_RATE_DEC", "x")],
+            [("TRACKING", "Sidereal"), ("PULSE_GUIDE_RA", "-0")],
+            [("TRACKING", "Sidereal"), ("TARGET_DEC", "10")],
+        ] {
+            assert!(actor::Device::update_properties(&mut device, &pairs).is_err());
+            assert!(sent(&mut device).is_empty(), "{:?}", pairs);
+        }
+        assert_eq!(prop(&device, "TRACKING"), "Off");
+
+        assert_eq!(
+            actor::Device::update_properties(
+                &mut device,
+                &[("BACKLASH_RA", "20"), ("TRACKING", "Sidereal")]
+            ),
+            Ok(())
+        );
+        assert_eq!(prop(&device, "TRACKING"), "Sidereal");
+    }
+
+    #[test]
+    fn every_property_is_handed_out() {
+        let mut device = device(Duration::ZERO, "props");
+        let value = |device: &MountDevice, name: &str| {
+            device
+                .get_properties()
+                .iter()
+                .find(|p| p.name == name)
+                .map(|p| p.value.clone())
+        };
+        assert_eq!(value(&device, "TRACKING").as_deref(), Some("Off"));
+        assert_eq!(
+            value(&device, "MOTOR_BOARD_VERSION").as_deref(),
+            Some("3.39.1")
+        );
+        // No serial line to the simulator
+        assert_eq!(value(&device, "BAUD_RATE").as_deref(), Some("0"));
+
+        device.update_property("TRACKING", "Sidereal").unwrap();
+        assert_eq!(value(&device, "TRACKING").as_deref(), Some("Sidereal"));
+        assert_eq!(
+            device.get_properties().len(),
+            device.view().ls_props().len()
+        );
+    }
+
+    #[test]
+    fn polar_led_comes_back_after_a_power_cycle() {
+        // A polar scope LED
+        let motors = SimulatedMotors::default().with_features(0x001000);
+        let mut device = MountDevice::with_simulator(motors).unwrap();
+        device.update_property("POLAR_LED", "128").unwrap();
+        let led = sent(&mut device).pop().unwrap();
+
+        match &mut device.port {
+            MountPort::Simulated(motors) => motors.power_cycle(),
+            MountPort::Serial(_) | MountPort::Udp(_) => unreachable!(),
+        }
+        device.fetch_props();
+        let frames = sent(&mut device);
+        let init = frames.iter().position(|f| f == ":F1").unwrap();
+        assert!(frames[init..].contains(&led));
+
+        device.update_property("FORCE_REINIT", "true").unwrap();
+        assert_eq!(sent(&mut device).last(), Some(&led));
+    }
+
+    #[test]
+    fn mount_behind_a_wifi_adapter() {
+        // The simulator answering datagrams like an AZ-GTi
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let mut motors = SimulatedMotors::default();
+            let mut frame = [0; 64];
+            while let Ok((len, from)) = socket.recv_from(&mut frame) {
+                motors.write_all(&frame[..len]).unwrap();
+                let mut answer = Vec::new();
+                let _ = motors.read_to_end(&mut answer);
+                if !answer.is_empty() {
+                    socket.send_to(&answer, from).unwrap();
+                }
+            }
+        });
+
+        let device = MountDevice::networked(&address, 500).unwrap();
+        assert_eq!(device.get_address(), &address);
+        assert!(matches!(device.port, MountPort::Udp(_)));
+        assert_eq!(prop(&device, "MOTOR_BOARD_VERSION"), "3.39.1");
+    }
 }
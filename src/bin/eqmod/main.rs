@@ -8,50 +8,219 @@ use lightspeed_astro::request::GetDevicesRequest;
 use lightspeed_astro::request::{CcdExposureRequest, CcdExposureResponse};
 use lightspeed_astro::response::GetDevicesResponse;
 use lightspeed_astro::server::astro_service_server::{AstroService, AstroServiceServer};
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
+use serialport::UsbPortInfo;
+use skywatcher_rs::actor::DeviceHandle;
+use skywatcher_rs::cache::{TtlCache, DEFAULT_CACHE_TTL};
+use skywatcher_rs::discovery::{look_for_devices, parse_network_mounts, PortSource};
+use skywatcher_rs::hotplug::{PortScanner, DEFAULT_RESCAN_INTERVAL};
+use skywatcher_rs::is_truthy;
+use skywatcher_rs::props::{parse_batch, BATCH_PROPERTY};
+use skywatcher_rs::serial::{detect_protocol, Protocol, SerialLink, PROBE_TIMEOUT};
+use skywatcher_rs::server::{bind_address, check_address_free, parse_args, DEFAULT_HOST};
+use skywatcher_rs::shared::SharedDevice;
+use skywatcher_rs::shutdown::{shutdown_device, ShutdownPolicy, SHUTDOWN_TIMEOUT};
+use skywatcher_rs::simulator::SimulatorMode;
+use tokio::task::JoinHandle;
 use tonic::{transport::Server, Request, Response, Status};
 
-use std::sync::{Arc, RwLock};
-use std::time::Duration;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
 
 mod device;
-use device::{look_for_devices, MountDevice};
+use device::{emergency_stop, MountDevice};
+
+type Devices = Arc<RwLock<Vec<SharedDevice<MountDevice>>>>;
 
 #[derive(Default, Clone)]
 struct EQmodDriver {
-    devices: Vec<Arc<RwLock<MountDevice>>>,
+    /// Shared with the forced ports task, which adds the mounts that
+    /// were missing or silent at startup
+    devices: Devices,
+    devices_cache: Arc<TtlCache<GetDevicesResponse>>,
+}
+
+/// Opens the motor controller on `port`, None when it doesn't answer as
+/// one
+fn open_device(port: &str, info: Option<UsbPortInfo>) -> Option<MountDevice> {
+    // Other adapters using the same bridges are left alone, the port is
+    // probed as it is opened for the device
+    let mut link = match SerialLink::open(port, 115200, PROBE_TIMEOUT) {
+        Ok(link) => link,
+        Err(e) => {
+            error!("Cannot open {}: {}", port, e);
+            return None;
+        }
+    };
+    match detect_protocol(&mut link, PROBE_TIMEOUT) {
+        Protocol::EqMod => {}
+        Protocol::SynScan => {
+            warn!(
+                "{} is connected to a hand controller, use the synscan driver",
+                port
+            );
+            return None;
+        }
+        Protocol::Unknown => {
+            info!("Nothing answering as a motor controller on {}", port);
+            return None;
+        }
+    }
+    let mut device_name = String::from("EQ6-r");
+    debug!("name: {}", port);
+    debug!("info: {:?}", info);
+
+    let serial_number = info.and_then(|i| i.serial_number);
+    if let Some(serial) = &serial_number {
+        device_name = device_name + "-" + serial
+    }
+    if let Err(e) = link.set_timeout(Duration::from_millis(5000)) {
+        warn!("Cannot set the timeout of {}: {}", port, e);
+    }
+    match MountDevice::with_link(&device_name, port, 115200, link) {
+        Some(device) => Some(device.with_serial_number(serial_number.as_deref())),
+        None => {
+            error!("Cannot start communication with {}", &device_name);
+            None
+        }
+    }
+}
+
+/// Polls the device until the driver stops
+fn spawn_fetch_task(
+    shared: SharedDevice<MountDevice>,
+    devices_cache: Arc<TtlCache<GetDevicesResponse>>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let device = &shared.device;
+        let interval = match device.call(|d| d.poll_interval()).await {
+            Ok(i) => i,
+            Err(e) => {
+                error!("Cannot read the polling interval: {:?}", e);
+                return;
+            }
+        };
+        loop {
+            tokio::time::sleep(interval).await;
+            if let Err(e) = device.fetch_props().await {
+                warn!("Cannot fetch the properties: {:?}", e);
+            }
+            devices_cache.invalidate();
+            shared.publish_changes();
+        }
+    })
+}
+
+/// Keeps trying the forced ports that are missing or didn't answer,
+/// with the hotplug backoff. Scanned USB ports are only opened at
+/// startup.
+fn spawn_forced_ports_task(
+    driver: EQmodDriver,
+    ports: PortSource,
+    fetch_tasks: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    interval: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut scanner = PortScanner::default();
+        loop {
+            tokio::time::sleep(interval).await;
+            let names: Vec<String> = ports.ports().into_iter().map(|(port, _)| port).collect();
+            let registered: Vec<String> = driver
+                .devices
+                .read()
+                .unwrap()
+                .iter()
+                .map(|d| d.view.address())
+                .collect();
+
+            for port in scanner.new_ports(&names, &registered, Instant::now()) {
+                // Probing the port blocks for up to a timeout
+                let opened = {
+                    let port = port.clone();
+                    tokio::task::spawn_blocking(move || open_device(&port, None)).await
+                };
+                match opened {
+                    Ok(Some(device)) => {
+                        info!("Mount found on {}", port);
+                        scanner.opened(&port);
+                        let device = SharedDevice::new(device.get_id(), device.view(), device);
+                        fetch_tasks.lock().unwrap().push(spawn_fetch_task(
+                            device.clone(),
+                            Arc::clone(&driver.devices_cache),
+                        ));
+                        driver.devices.write().unwrap().push(device);
+                        driver.devices_cache.invalidate();
+                    }
+                    Ok(None) => scanner.failed(&port, Instant::now()),
+                    Err(e) => {
+                        error!("Opening {} failed: {}", port, e);
+                        scanner.failed(&port, Instant::now());
+                    }
+                }
+            }
+        }
+    })
 }
 
 impl EQmodDriver {
-    fn new() -> Self {
-        let found = look_for_devices();
-        let mut devices: Vec<Arc<RwLock<MountDevice>>> = Vec::new();
-        for dev in found {
-            let mut device_name = String::from("EQ6-r");
-            debug!("name: {}", dev.0);
-            debug!("info: {:?}", dev.1);
-
-            if let Some(serial) = dev.1.serial_number {
-                device_name = device_name + "-" + &serial
+    fn new(ports: &PortSource) -> Self {
+        let simulator = SimulatorMode::from_env(std::env::var("LS_SIMULATOR").ok().as_deref());
+        // The simulator stands in for every mount, the ports are left alone
+        let found = match simulator {
+            SimulatorMode::On => Vec::new(),
+            _ => look_for_devices(ports),
+        };
+        let mut devices: Vec<SharedDevice<MountDevice>> = Vec::new();
+        for (port, info) in found {
+            if let Some(device) = open_device(&port, info) {
+                devices.push(SharedDevice::new(device.get_id(), device.view(), device));
             }
-            if let Some(device) = MountDevice::new(&device_name, &dev.0, 115200, 5000) {
-                devices.push(Arc::new(RwLock::new(device)));
-            } else {
-                error!("Cannot start communication with {}", &device_name);
+        }
+        if simulator != SimulatorMode::On {
+            let network_mounts = std::env::var("LS_NETWORK_MOUNTS")
+                .map(|v| parse_network_mounts(&v))
+                .unwrap_or_default();
+            for address in network_mounts {
+                match MountDevice::networked(&address, 5000) {
+                    Some(device) => {
+                        info!("Found a mount at {}", address);
+                        devices.push(SharedDevice::new(device.get_id(), device.view(), device));
+                    }
+                    None => warn!("No mount answering at {}", address),
+                }
             }
         }
-        Self { devices }
+        if simulator == SimulatorMode::On
+            || (simulator == SimulatorMode::Auto && devices.is_empty())
+        {
+            info!("Registering the simulated mount");
+            if let Some(device) = MountDevice::simulated() {
+                devices.push(SharedDevice::new(device.get_id(), device.view(), device));
+            }
+        }
+        // Bursts of GetDevices within the TTL are served the same response
+        let ttl = std::env::var("LS_DEVICES_CACHE_TTL_MS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_CACHE_TTL);
+
+        Self {
+            devices: Arc::new(RwLock::new(devices)),
+            devices_cache: Arc::new(TtlCache::new(ttl)),
+        }
     }
 }
 
 #[tonic::async_trait]
 impl AstroService for EQmodDriver {
+    /// Mounts take no pictures, answering with an empty image would
+    /// make sequencers believe an exposure was done
     async fn expose(
         &self,
-        request: Request<CcdExposureRequest>,
+        _request: Request<CcdExposureRequest>,
     ) -> Result<Response<CcdExposureResponse>, Status> {
-        let reply = CcdExposureResponse { data: vec![] };
-        Ok(Response::new(reply))
+        Err(Status::unimplemented("mount devices cannot expose"))
     }
 
     async fn get_devices(
@@ -63,24 +232,22 @@ impl AstroService for EQmodDriver {
             request.remote_addr()
         );
 
-        if self.devices.is_empty() {
-            let reply = GetDevicesResponse { devices: vec![] };
-            Ok(Response::new(reply))
-        } else {
+        // Only the views are read, a device busy on its port doesn't hold
+        // up the response
+        let reply = self.devices_cache.get_or_build(|| {
             let mut devices = Vec::new();
-            for dev in self.devices.iter() {
-                let device = dev.read().unwrap();
+            for dev in self.devices.read().unwrap().iter() {
                 let d = ProtoDevice {
-                    id: device.get_id().to_string(),
-                    name: device.get_name().to_owned(),
+                    id: dev.id.to_string(),
+                    name: dev.view.name(),
                     family: 0,
-                    properties: device.properties.to_owned(),
+                    properties: dev.view.ls_props(),
                 };
                 devices.push(d);
             }
-            let reply = GetDevicesResponse { devices };
-            Ok(Response::new(reply))
-        }
+            GetDevicesResponse { devices }
+        });
+        Ok(Response::new(reply))
     }
 
     async fn set_property(
@@ -94,24 +261,42 @@ impl AstroService for EQmodDriver {
         let message = request.get_ref();
         debug!("device_id: {:?}", message.device_id);
 
-        if message.device_id == "" || message.property_name == "" || message.property_value == "" {
+        if message.device_id.is_empty()
+            || message.property_name.is_empty()
+            || message.property_value.is_empty()
+        {
             return Ok(Response::new(SetPropertyResponse {
                 status: DeviceActions::InvalidValue as i32,
             }));
         };
 
-        // TODO: return case if no devices match
-        for d in self.devices.iter() {
-            let mut device = d.write().unwrap();
-            if device.get_id().to_string() == message.device_id {
+        // Not held across the awaits, the forced ports task may add a mount
+        let devices = self.devices.read().unwrap().clone();
+        let mut matched = false;
+        for d in devices.iter() {
+            if d.id.to_string() == message.device_id {
+                matched = true;
                 info!(
                     "Updating property {} for {} to {}",
                     message.property_name, message.device_id, message.property_value,
                 );
 
-                if let Err(e) =
-                    device.update_property(&message.property_name, &message.property_value)
-                {
+                let value = &message.property_value;
+                let result = match message.property_name.as_str() {
+                    // Doesn't wait behind the queued requests
+                    "EMERGENCY_STOP" if is_truthy(value) => emergency_stop(&d.device).await,
+                    // Applied in one go so no poll or other client gets in between
+                    BATCH_PROPERTY => match parse_batch(value) {
+                        Ok(pairs) => d.device.set_properties(pairs).await,
+                        Err(e) => Err(e),
+                    },
+                    name => d.device.set_property(name, value).await,
+                };
+                // A client reading back right after a set must see the new value
+                self.devices_cache.invalidate();
+                d.publish_changes();
+
+                if let Err(e) = result {
                     info!(
                         "Updating property {} for {} failed with reason: {:?}",
                         message.property_name, message.device_id, e
@@ -121,6 +306,15 @@ impl AstroService for EQmodDriver {
             }
         }
 
+        // lightspeed has no status for an unknown device, the id is
+        // reported as an invalid value like an empty one
+        if !matched {
+            warn!("No device with id {}", message.device_id);
+            return Ok(Response::new(SetPropertyResponse {
+                status: DeviceActions::InvalidValue as i32,
+            }));
+        }
+
         let reply = SetPropertyResponse {
             status: DeviceActions::Ok as i32,
         };
@@ -141,32 +335,195 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .build()
         .unwrap();
 
-    let host = "127.0.0.1";
-    let addr = build_server_address(host);
-    let driver = EQmodDriver::new();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args = parse_args(&args)?;
+    let addr = bind_address(
+        &args,
+        |name| std::env::var(name).ok(),
+        build_server_address(DEFAULT_HOST),
+    )?;
+    // Checked before the devices are opened, they would be left hanging
+    check_address_free(addr).map_err(|e| {
+        error!("Cannot listen on {}: {}", addr, e);
+        e
+    })?;
+    let ports =
+        PortSource::from_config(args.serial_port.as_deref(), |name| std::env::var(name).ok());
+    let driver = EQmodDriver::new(&ports);
 
-    let mut devices_for_fetching = Vec::new();
-    let mut devices_for_closing = Vec::new();
-    for d in &driver.devices {
-        devices_for_fetching.push(Arc::clone(d));
-        devices_for_closing.push(Arc::clone(d));
-    }
+    let shutdown_policy = ShutdownPolicy::from_env();
+    let devices_for_closing = Arc::clone(&driver.devices);
 
-    for d in &devices_for_fetching {
-        let device = Arc::clone(d);
-        tokio::spawn(async move {
-            loop {
-                tokio::time::sleep(Duration::from_secs(1)).await;
-                device.write().unwrap().fetch_props();
-            }
-        });
-    }
+    let fetch_tasks: Arc<Mutex<Vec<JoinHandle<()>>>> = Arc::new(Mutex::new(
+        driver
+            .devices
+            .read()
+            .unwrap()
+            .iter()
+            .map(|d| spawn_fetch_task(d.clone(), Arc::clone(&driver.devices_cache)))
+            .collect(),
+    ));
+
+    let simulator = SimulatorMode::from_env(std::env::var("LS_SIMULATOR").ok().as_deref());
+    let forced_ports = match ports {
+        PortSource::Forced(_) if simulator != SimulatorMode::On => {
+            let interval = std::env::var("LS_RESCAN_INTERVAL_SECS")
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_RESCAN_INTERVAL);
+            Some(spawn_forced_ports_task(
+                driver.clone(),
+                ports,
+                Arc::clone(&fetch_tasks),
+                interval,
+            ))
+        }
+        _ => None,
+    };
 
     info!("EQMOD driver process listening on {}", addr);
     Server::builder()
         .add_service(reflection_service)
         .add_service(AstroServiceServer::new(driver))
-        .serve(addr)
+        .serve_with_shutdown(addr, async {
+            if let Err(e) = tokio::signal::ctrl_c().await {
+                error!("Cannot listen for the interrupt signal: {}", e);
+                std::future::pending::<()>().await;
+            }
+            info!("Interrupted, shutting down");
+        })
         .await?;
+
+    // Nothing must poke the ports once they are being closed
+    if let Some(task) = forced_ports {
+        task.abort();
+    }
+    for task in fetch_tasks.lock().unwrap().iter() {
+        task.abort();
+    }
+    let devices: Vec<DeviceHandle<MountDevice>> = devices_for_closing
+        .read()
+        .unwrap()
+        .iter()
+        .map(|d| d.device.clone())
+        .collect();
+    for (i, device) in devices.iter().enumerate() {
+        let closed = device
+            .clone()
+            .with_timeout(SHUTDOWN_TIMEOUT)
+            .call(move |d| shutdown_device(i, d, shutdown_policy))
+            .await;
+        if let Err(e) = closed {
+            warn!("Device {} not closed: {:?}", i, e);
+        }
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::{spawn_forced_ports_task, EQmodDriver};
+    use lightspeed_astro::devices::actions::DeviceActions;
+    use lightspeed_astro::props::SetPropertyRequest;
+    use lightspeed_astro::request::CcdExposureRequest;
+    use lightspeed_astro::server::astro_service_server::AstroService;
+    use skywatcher_rs::discovery::PortSource;
+    use skywatcher_rs::motor_simulator::SimulatedMotors;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use tonic::{Code, Request};
+    use uuid::Uuid;
+
+    #[test]
+    fn set_property_on_unknown_device() {
+        let driver = EQmodDriver::default();
+        let request = Request::new(SetPropertyRequest {
+            device_id: Uuid::new_v4().to_string(),
+            property_name: String::from("TRACKING"),
+            property_value: String::from("Off"),
+        });
+        let response = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(driver.set_property(request))
+            .unwrap();
+        assert_eq!(
+            response.get_ref().status,
+            DeviceActions::InvalidValue as i32
+        );
+    }
+
+    #[test]
+    fn expose_is_unimplemented() {
+        let driver = EQmodDriver::default();
+        let request = Request::new(CcdExposureRequest::default());
+        let status = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(driver.expose(request))
+            .unwrap_err();
+        assert_eq!(status.code(), Code::Unimplemented);
+    }
+
+    /// Simulated motors behind a TCP bridge, fresh ones per connection
+    fn motors_bridge(listener: TcpListener) {
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { return };
+                let mut motors = SimulatedMotors::default();
+                let mut frame = [0; 64];
+                while let Ok(len) = stream.read(&mut frame) {
+                    if len == 0 {
+                        break;
+                    }
+                    motors.write_all(&frame[..len]).unwrap();
+                    let mut answer = Vec::new();
+                    let _ = motors.read_to_end(&mut answer);
+                    if stream.write_all(&answer).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn forced_ports_are_opened_once_they_answer() {
+        // Nothing answered on the port at startup
+        let address = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let driver = EQmodDriver::default();
+
+        motors_bridge(TcpListener::bind(address).unwrap());
+        let port = format!("tcp://{}", address);
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let fetch_tasks = Arc::new(Mutex::new(Vec::new()));
+        let task = {
+            let _runtime = rt.enter();
+            spawn_forced_ports_task(
+                driver.clone(),
+                PortSource::Forced(vec![port.clone()]),
+                Arc::clone(&fetch_tasks),
+                Duration::from_millis(20),
+            )
+        };
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while driver.devices.read().unwrap().is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(20));
+        }
+        let devices = driver.devices.read().unwrap().clone();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].view.address(), port);
+        assert_eq!(fetch_tasks.lock().unwrap().len(), 1);
+
+        // Registered, the port isn't opened a second time
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(driver.devices.read().unwrap().len(), 1);
+        task.abort();
+    }
+}
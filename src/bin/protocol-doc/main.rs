@@ -0,0 +1,33 @@
+use skywatcher_rs::protocol_doc::{
+    protocol_json, protocol_markdown, PROTOCOL_DOC_DIR, PROTOCOL_JSON, PROTOCOL_MARKDOWN,
+};
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+/// Writes the protocol documents into the directory given as argument,
+/// the checked in docs/ of the crate by default
+fn main() -> ExitCode {
+    let dir = std::env::args()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(PROTOCOL_DOC_DIR));
+
+    let written = fs::create_dir_all(&dir)
+        .and_then(|_| fs::write(dir.join(PROTOCOL_JSON), protocol_json()))
+        .and_then(|_| fs::write(dir.join(PROTOCOL_MARKDOWN), protocol_markdown()));
+    match written {
+        Ok(()) => {
+            println!("Protocol documents written to {}", dir.display());
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!(
+                "Cannot write the protocol documents to {}: {}",
+                dir.display(),
+                e
+            );
+            ExitCode::FAILURE
+        }
+    }
+}
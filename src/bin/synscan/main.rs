@@ -8,48 +8,264 @@ use lightspeed_astro::request::GetDevicesRequest;
 use lightspeed_astro::request::{CcdExposureRequest, CcdExposureResponse};
 use lightspeed_astro::response::GetDevicesResponse;
 use lightspeed_astro::server::astro_service_server::{AstroService, AstroServiceServer};
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
+use skywatcher_rs::cache::{TtlCache, DEFAULT_CACHE_TTL};
+use skywatcher_rs::discovery::{look_for_devices, parse_network_mounts, PortSource};
+use skywatcher_rs::hotplug::{PortScanner, DEFAULT_RESCAN_INTERVAL};
+use skywatcher_rs::is_truthy;
+use skywatcher_rs::props::{parse_batch, BATCH_PROPERTY};
+use skywatcher_rs::serial::{
+    baud_rates, detect_baud, probe_port, Protocol, DEFAULT_RETRY_ATTEMPTS,
+};
+use skywatcher_rs::server::{bind_address, check_address_free, parse_args, DEFAULT_HOST};
+use skywatcher_rs::shared::SharedDevice;
+use skywatcher_rs::shutdown::{shutdown_device, ShutdownPolicy, SHUTDOWN_TIMEOUT};
+use skywatcher_rs::simulator::SimulatorMode;
 use tonic::{transport::Server, Request, Response, Status};
 
-use std::sync::{Arc, RwLock};
+use serialport::UsbPortInfo;
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
 
 mod synscan;
-use synscan::{look_for_devices, MountDevice, SynScanMount};
+use synscan::{emergency_stop, MountDevice, SynScanMount};
+
+type Devices = Arc<RwLock<Vec<SharedDevice<MountDevice>>>>;
 
 #[derive(Default, Clone)]
 struct SynScanDriver {
-    devices: Vec<Arc<RwLock<MountDevice>>>,
+    /// Shared with the hotplug task, which adds the mounts plugged in later
+    devices: Devices,
+    devices_cache: Arc<TtlCache<GetDevicesResponse>>,
+    retry_attempts: u32,
+    /// Rates the hand controllers are probed at, in order
+    baud_rates: Vec<u32>,
+    ports: PortSource,
+}
+
+/// Opens the mount on `port`, None when it doesn't answer as a hand
+/// controller
+fn open_device(
+    port: &str,
+    info: Option<UsbPortInfo>,
+    retry_attempts: u32,
+    baud_rates: &[u32],
+) -> Option<MountDevice> {
+    // Other adapters using the same bridges are left alone, a wrong rate
+    // looks like nothing answering so each one is tried
+    let baud = match detect_baud(baud_rates, |baud| probe_port(port, baud)) {
+        Some((baud, Protocol::SynScan)) => {
+            info!("{} answers at {} baud", port, baud);
+            baud
+        }
+        Some((_, Protocol::EqMod)) => {
+            warn!(
+                "{} is connected straight to the motor controller, use the eqmod driver",
+                port
+            );
+            return None;
+        }
+        Some((_, Protocol::Unknown)) | None => {
+            info!("Nothing answering as a hand controller on {}", port);
+            return None;
+        }
+    };
+    let mut device_name = String::from("");
+
+    let serial_number = info.and_then(|i| i.serial_number);
+    if let Some(serial) = &serial_number {
+        device_name = device_name + "-" + serial
+    }
+    let device = MountDevice::with_retries(&device_name, port, baud, 5000, retry_attempts)
+        .map(|d| d.with_serial_number(serial_number.as_deref()));
+    if device.is_none() {
+        error!("Cannot start communication with {}", &device_name);
+    }
+    device
+}
+
+/// Polls the mount, more often while it is slewing. A disconnected
+/// mount isn't polled, it is reconnected with an increasing delay
+fn spawn_fetch_task(
+    shared: SharedDevice<MountDevice>,
+    devices_cache: Arc<TtlCache<GetDevicesResponse>>,
+    ports: PortSource,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let device = &shared.device;
+        loop {
+            let interval = device
+                .call(|d| {
+                    if d.is_online() {
+                        d.poll_interval()
+                    } else {
+                        d.reconnect_delay()
+                    }
+                })
+                .await;
+            let interval = match interval {
+                Ok(i) => i,
+                Err(e) => {
+                    error!("Stopped polling a mount: {:?}", e);
+                    return;
+                }
+            };
+            tokio::time::sleep(interval).await;
+            let ports = ports.clone();
+            let polled = device
+                .call(move |d| {
+                    if d.is_online() {
+                        d.fetch_props();
+                    } else {
+                        d.reconnect(&ports);
+                    }
+                })
+                .await;
+            if let Err(e) = polled {
+                warn!("Cannot poll the mount: {:?}", e);
+            }
+            devices_cache.invalidate();
+            shared.publish_changes();
+        }
+    })
+}
+
+/// Scans the serial ports every `interval` and registers the mounts
+/// plugged in since the start, each with its own fetch task
+fn spawn_hotplug_task(
+    driver: SynScanDriver,
+    fetch_tasks: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    interval: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut scanner = PortScanner::default();
+        loop {
+            tokio::time::sleep(interval).await;
+            let found = driver.ports.ports();
+            let names: Vec<String> = found.iter().map(|(port, _)| port.clone()).collect();
+            // Adapters of disconnected mounts are left to their fetch task.
+            // Only the views are read, a busy mount doesn't hold the scan up.
+            let mut registered = Vec::new();
+            for d in driver.devices.read().unwrap().iter() {
+                registered.push(d.view.address());
+                registered.extend(
+                    found
+                        .iter()
+                        .filter(|(_, info)| {
+                            d.view
+                                .waits_for(info.as_ref().and_then(|i| i.serial_number.as_deref()))
+                        })
+                        .map(|(port, _)| port.clone()),
+                );
+            }
+
+            for port in scanner.new_ports(&names, &registered, Instant::now()) {
+                let info = match found.iter().find(|(p, _)| *p == port) {
+                    Some((_, info)) => info.clone(),
+                    None => continue,
+                };
+                // Probing the port blocks for up to a timeout per baud rate
+                let opened = {
+                    let port = port.clone();
+                    let baud_rates = driver.baud_rates.clone();
+                    let retry_attempts = driver.retry_attempts;
+                    tokio::task::spawn_blocking(move || {
+                        open_device(&port, info, retry_attempts, &baud_rates)
+                    })
+                    .await
+                };
+                match opened {
+                    Ok(Some(device)) => {
+                        info!("New mount on {}", port);
+                        scanner.opened(&port);
+                        let device = SharedDevice::new(device.get_id(), device.view(), device);
+                        fetch_tasks.lock().unwrap().push(spawn_fetch_task(
+                            device.clone(),
+                            Arc::clone(&driver.devices_cache),
+                            driver.ports.clone(),
+                        ));
+                        driver.devices.write().unwrap().push(device);
+                        driver.devices_cache.invalidate();
+                    }
+                    Ok(None) => scanner.failed(&port, Instant::now()),
+                    Err(e) => {
+                        error!("Opening {} failed: {}", port, e);
+                        scanner.failed(&port, Instant::now());
+                    }
+                }
+            }
+        }
+    })
 }
 
 impl SynScanDriver {
-    fn new() -> Self {
-        let found = look_for_devices();
-        let mut devices: Vec<Arc<RwLock<MountDevice>>> = Vec::new();
-        for dev in found {
-            let mut device_name = String::from("");
-
-            if let Some(serial) = dev.1.serial_number {
-                device_name = device_name + "-" + &serial
+    fn new(ports: PortSource) -> Self {
+        let simulator = SimulatorMode::from_env(std::env::var("LS_SIMULATOR").ok().as_deref());
+        // The simulator stands in for every mount, the ports are left alone
+        let found = match simulator {
+            SimulatorMode::On => Vec::new(),
+            _ => look_for_devices(&ports),
+        };
+        let baud_rates = baud_rates(std::env::var("LS_BAUD").ok().as_deref());
+        let retry_attempts = std::env::var("LS_SERIAL_RETRIES")
+            .ok()
+            .and_then(|v| v.parse::<u32>().ok())
+            .unwrap_or(DEFAULT_RETRY_ATTEMPTS);
+        let mut devices: Vec<SharedDevice<MountDevice>> = Vec::new();
+        for (port, info) in found {
+            if let Some(device) = open_device(&port, info, retry_attempts, &baud_rates) {
+                devices.push(SharedDevice::new(device.get_id(), device.view(), device));
             }
-            if let Some(device) = MountDevice::new(&device_name, &dev.0, 9600, 5000) {
-                devices.push(Arc::new(RwLock::new(device)));
-            } else {
-                error!("Cannot start communication with {}", &device_name);
+        }
+        if simulator != SimulatorMode::On {
+            let network_mounts = std::env::var("LS_NETWORK_MOUNTS")
+                .map(|v| parse_network_mounts(&v))
+                .unwrap_or_default();
+            for address in network_mounts {
+                match MountDevice::networked(&address, 5000, retry_attempts) {
+                    Some(device) => {
+                        info!("Found a mount at {}", address);
+                        devices.push(SharedDevice::new(device.get_id(), device.view(), device));
+                    }
+                    None => warn!("No mount answering at {}", address),
+                }
             }
         }
-        Self { devices }
+        if simulator == SimulatorMode::On
+            || (simulator == SimulatorMode::Auto && devices.is_empty())
+        {
+            info!("Registering the simulated mount");
+            if let Some(device) = MountDevice::simulated(retry_attempts) {
+                devices.push(SharedDevice::new(device.get_id(), device.view(), device));
+            }
+        }
+        // Bursts of GetDevices within the TTL are served the same response
+        let ttl = std::env::var("LS_DEVICES_CACHE_TTL_MS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_CACHE_TTL);
+
+        Self {
+            devices: Arc::new(RwLock::new(devices)),
+            devices_cache: Arc::new(TtlCache::new(ttl)),
+            retry_attempts,
+            baud_rates,
+            ports,
+        }
     }
 }
 
 #[tonic::async_trait]
 impl AstroService for SynScanDriver {
+    /// Mounts take no pictures, answering with an empty image would
+    /// make sequencers believe an exposure was done
     async fn expose(
         &self,
-        request: Request<CcdExposureRequest>,
+        _request: Request<CcdExposureRequest>,
     ) -> Result<Response<CcdExposureResponse>, Status> {
-        let reply = CcdExposureResponse { data: vec![] };
-        Ok(Response::new(reply))
+        Err(Status::unimplemented("mount devices cannot expose"))
     }
 
     async fn get_devices(
@@ -62,28 +278,24 @@ impl AstroService for SynScanDriver {
             request.remote_addr()
         );
 
-        if self.devices.is_empty() {
-            let reply = GetDevicesResponse { devices: vec![] };
-            Ok(Response::new(reply))
-        } else {
+        let reply = self.devices_cache.get_or_build(|| {
             let mut devices = Vec::new();
-            for dev in self.devices.iter() {
-                let device = dev.read().unwrap();
+            for dev in self.devices.read().unwrap().iter() {
                 let d = ProtoDevice {
-                    id: device.get_id().to_string(),
-                    name: device.get_name().to_owned(),
+                    id: dev.id.to_string(),
+                    name: dev.view.name(),
                     family: 1,
-                    properties: device.get_ls_props(),
+                    properties: dev.view.ls_props(),
                 };
                 devices.push(d);
             }
-            let reply = GetDevicesResponse { devices };
-            println!(
-                "Returning devices status took {} ns",
-                now.elapsed().as_nanos()
-            );
-            Ok(Response::new(reply))
-        }
+            GetDevicesResponse { devices }
+        });
+        debug!(
+            "Returning devices status took {} ns",
+            now.elapsed().as_nanos()
+        );
+        Ok(Response::new(reply))
     }
 
     async fn set_property(
@@ -97,24 +309,41 @@ impl AstroService for SynScanDriver {
         let message = request.get_ref();
         debug!("device_id: {:?}", message.device_id);
 
-        if message.device_id == "" || message.property_name == "" || message.property_value == "" {
+        if message.device_id.is_empty()
+            || message.property_name.is_empty()
+            || message.property_value.is_empty()
+        {
             return Ok(Response::new(SetPropertyResponse {
                 status: DeviceActions::InvalidValue as i32,
             }));
         };
 
-        // TODO: return case if no devices match
-        for d in self.devices.iter() {
-            let mut device = d.write().unwrap();
-            if device.get_id().to_string() == message.device_id {
+        let mut matched = false;
+        let devices = self.devices.read().unwrap().clone();
+        for d in devices.iter() {
+            if d.id.to_string() == message.device_id {
+                matched = true;
                 info!(
                     "Updating property {} for {} to {}",
                     message.property_name, message.device_id, message.property_value,
                 );
 
-                if let Err(e) =
-                    device.update_property(&message.property_name, &message.property_value)
-                {
+                let value = &message.property_value;
+                let result = match message.property_name.as_str() {
+                    // Doesn't wait behind the queued requests
+                    "EMERGENCY_STOP" if is_truthy(value) => emergency_stop(&d.device).await,
+                    // Applied in one go so no poll or other client gets in between
+                    BATCH_PROPERTY => match parse_batch(&message.property_value) {
+                        Ok(pairs) => d.device.set_properties(pairs).await,
+                        Err(e) => Err(e),
+                    },
+                    name => d.device.set_property(name, &message.property_value).await,
+                };
+                // A client reading back right after a set must see the new value
+                self.devices_cache.invalidate();
+                d.publish_changes();
+
+                if let Err(e) = result {
                     info!(
                         "Updating property {} for {} failed with reason: {:?}",
                         message.property_name, message.device_id, e
@@ -124,6 +353,15 @@ impl AstroService for SynScanDriver {
             }
         }
 
+        // lightspeed has no status for an unknown device, the id is
+        // reported as an invalid value like an empty one
+        if !matched {
+            warn!("No device with id {}", message.device_id);
+            return Ok(Response::new(SetPropertyResponse {
+                status: DeviceActions::InvalidValue as i32,
+            }));
+        }
+
         let reply = SetPropertyResponse {
             status: DeviceActions::Ok as i32,
         };
@@ -144,32 +382,1033 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .build()
         .unwrap();
 
-    let host = "127.0.0.1";
-    let addr = build_server_address(host);
-    let driver = SynScanDriver::new();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args = parse_args(&args)?;
+    let addr = bind_address(
+        &args,
+        |name| std::env::var(name).ok(),
+        build_server_address(DEFAULT_HOST),
+    )?;
+    // Checked before the devices are opened, they would be left hanging
+    check_address_free(addr).map_err(|e| {
+        error!("Cannot listen on {}: {}", addr, e);
+        e
+    })?;
+    let ports =
+        PortSource::from_config(args.serial_port.as_deref(), |name| std::env::var(name).ok());
+    let driver = SynScanDriver::new(ports);
 
-    let mut devices_for_fetching = Vec::new();
-    let mut devices_for_closing = Vec::new();
-    for d in &driver.devices {
-        devices_for_fetching.push(Arc::clone(d));
-        devices_for_closing.push(Arc::clone(d));
-    }
+    let shutdown_policy = ShutdownPolicy::from_env();
+    let devices_for_closing = Arc::clone(&driver.devices);
 
-    for d in &devices_for_fetching {
-        let device = Arc::clone(d);
-        tokio::spawn(async move {
-            loop {
-                tokio::time::sleep(Duration::from_secs(1)).await;
-                device.write().unwrap().fetch_props();
-            }
-        });
-    }
+    let fetch_tasks: Arc<Mutex<Vec<JoinHandle<()>>>> = Arc::new(Mutex::new(
+        driver
+            .devices
+            .read()
+            .unwrap()
+            .iter()
+            .map(|d| {
+                spawn_fetch_task(
+                    d.clone(),
+                    Arc::clone(&driver.devices_cache),
+                    driver.ports.clone(),
+                )
+            })
+            .collect(),
+    ));
+
+    let rescan_interval = std::env::var("LS_RESCAN_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_RESCAN_INTERVAL);
+    let hotplug = spawn_hotplug_task(driver.clone(), Arc::clone(&fetch_tasks), rescan_interval);
 
     info!("SynScan driver process listening on {}", addr);
     Server::builder()
         .add_service(reflection_service)
         .add_service(AstroServiceServer::new(driver))
-        .serve(addr)
+        .serve_with_shutdown(addr, async {
+            if let Err(e) = tokio::signal::ctrl_c().await {
+                error!("Cannot listen for the interrupt signal: {}", e);
+                std::future::pending::<()>().await;
+            }
+            info!("Interrupted, shutting down");
+        })
         .await?;
+
+    // Nothing must poke the ports once they are being closed
+    hotplug.abort();
+    for task in fetch_tasks.lock().unwrap().iter() {
+        task.abort();
+    }
+    let devices = devices_for_closing.read().unwrap().clone();
+    for (i, d) in devices.iter().enumerate() {
+        let closed = d
+            .device
+            .clone()
+            .with_timeout(SHUTDOWN_TIMEOUT)
+            .call(move |m| shutdown_device(i, m, shutdown_policy))
+            .await;
+        if let Err(e) = closed {
+            warn!("Device {} not closed: {:?}", i, e);
+        }
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::synscan::MountPort;
+    use super::{MountDevice, SynScanDriver, SynScanMount};
+    use astrotools::AstroSerialDevice;
+    use lightspeed_astro::devices::actions::DeviceActions;
+    use lightspeed_astro::props::SetPropertyRequest;
+    use lightspeed_astro::request::{CcdExposureRequest, GetDevicesRequest};
+    use lightspeed_astro::server::astro_service_server::AstroService;
+    use skywatcher_rs::inflight::load_in_flight;
+    use skywatcher_rs::network::UDP_RESENDS;
+    use skywatcher_rs::shared::SharedDevice;
+    use skywatcher_rs::simulator::{SimulatedMount, SIMULATOR_ADDRESS};
+    use skywatcher_rs::slew_limits::{alt_az_to_ra_dec, local_sidereal_time_deg};
+    use skywatcher_rs::synscan::Command;
+    use skywatcher_rs::TrackingMode;
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::net::UdpSocket;
+    use std::thread;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use tonic::{Code, Request};
+    use uuid::Uuid;
+
+    #[test]
+    fn set_property_on_unknown_device() {
+        let driver = SynScanDriver::default();
+        let request = Request::new(SetPropertyRequest {
+            device_id: Uuid::new_v4().to_string(),
+            property_name: String::from("TRACKING"),
+            property_value: String::from("Off"),
+        });
+        let response = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(driver.set_property(request))
+            .unwrap();
+        assert_eq!(
+            response.get_ref().status,
+            DeviceActions::InvalidValue as i32
+        );
+    }
+
+    #[test]
+    fn expose_is_unimplemented() {
+        let driver = SynScanDriver::default();
+        let request = Request::new(CcdExposureRequest::default());
+        let status = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(driver.expose(request))
+            .unwrap_err();
+        assert_eq!(status.code(), Code::Unimplemented);
+    }
+
+    #[test]
+    fn grpc_against_the_simulator() {
+        let driver = SynScanDriver::default();
+        let device = MountDevice::simulated(1).unwrap();
+        assert_eq!(device.get_address(), SIMULATOR_ADDRESS);
+        driver.devices.write().unwrap().push(SharedDevice::new(
+            device.get_id(),
+            device.view(),
+            device,
+        ));
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let devices = rt
+            .block_on(driver.get_devices(Request::new(GetDevicesRequest::default())))
+            .unwrap()
+            .into_inner()
+            .devices;
+        assert_eq!(devices.len(), 1);
+        let value = |name: &str| {
+            devices[0]
+                .properties
+                .iter()
+                .find(|p| p.name == name)
+                .map(|p| p.value.clone())
+        };
+        assert_eq!(value("SYNSCAN_VERSION").as_deref(), Some("4.37.7"));
+        assert!(value("RA").is_some_and(|ra| !ra.is_empty()));
+
+        let request = Request::new(SetPropertyRequest {
+            device_id: devices[0].id.clone(),
+            property_name: String::from("TRACKING_MODE"),
+            property_value: TrackingMode::Eq.to_string(),
+        });
+        let response = rt.block_on(driver.set_property(request)).unwrap();
+        assert_eq!(response.get_ref().status, DeviceActions::Ok as i32);
+    }
+
+    #[test]
+    fn mount_behind_a_wifi_adapter() {
+        // The simulator answering datagrams like the adapter of a mount
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let mut mount = SimulatedMount::default();
+            let mut frame = [0; 64];
+            while let Ok((len, from)) = socket.recv_from(&mut frame) {
+                mount.write_all(&frame[..len]).unwrap();
+                let mut answer = Vec::new();
+                let _ = mount.read_to_end(&mut answer);
+                if !answer.is_empty() {
+                    socket.send_to(&answer, from).unwrap();
+                }
+            }
+        });
+
+        let device = MountDevice::networked(&address, 500, 1).unwrap();
+        assert_eq!(device.get_address(), &address);
+        assert!(device.is_online());
+        assert!(device
+            .view()
+            .ls_props()
+            .iter()
+            .any(|p| p.name == "SYNSCAN_VERSION" && p.value == "4.37.7"));
+    }
+
+    #[test]
+    fn subscribers_see_tracking_changes() {
+        let driver = SynScanDriver::default();
+        let device = MountDevice::simulated(1).unwrap();
+        let shared = SharedDevice::new(device.get_id(), device.view(), device);
+        driver.devices.write().unwrap().push(shared.clone());
+        let mut first = shared.events.subscribe();
+        let mut second = shared.events.subscribe();
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let request = Request::new(SetPropertyRequest {
+            device_id: shared.id.to_string(),
+            property_name: String::from("TRACKING_MODE"),
+            property_value: TrackingMode::Eq.to_string(),
+        });
+        let response = rt.block_on(driver.set_property(request)).unwrap();
+        assert_eq!(response.get_ref().status, DeviceActions::Ok as i32);
+
+        for subscription in [&mut first, &mut second] {
+            let tracking = rt.block_on(async {
+                // Past the snapshot, to the change
+                loop {
+                    let event = subscription.next().await.unwrap();
+                    if event.property_name == "TRACKING_MODE" && event.timestamp > 0 {
+                        return event;
+                    }
+                }
+            });
+            assert_eq!(tracking.device_id, shared.id.to_string());
+            assert_eq!(tracking.value, TrackingMode::Eq.to_string());
+        }
+    }
+
+    #[test]
+    fn batches_are_all_or_nothing() {
+        let driver = SynScanDriver::default();
+        let device = MountDevice::simulated(1).unwrap();
+        let shared = SharedDevice::new(device.get_id(), device.view(), device);
+        driver.devices.write().unwrap().push(shared.clone());
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let set = |name: &str, value: &str| {
+            let request = Request::new(SetPropertyRequest {
+                device_id: shared.id.to_string(),
+                property_name: String::from(name),
+                property_value: String::from(value),
+            });
+            rt.block_on(driver.set_property(request))
+                .unwrap()
+                .get_ref()
+                .status
+        };
+        let value = |name: &str| {
+            shared
+                .view
+                .ls_props()
+                .into_iter()
+                .find(|p| p.name == name)
+                .unwrap()
+                .value
+        };
+
+        assert_eq!(
+            set(
+                "__batch__",
+                r#"{"TARGET_RA": "83.82", "TARGET_DEC": "-5.39"}"#
+            ),
+            DeviceActions::Ok as i32
+        );
+        assert_eq!(value("TARGET_RA"), "83.82");
+        assert_eq!(value("TARGET_DEC"), "-5.39");
+
+        // The bad DEC fails the batch before anything is set, the RA is
+        // left alone and the goto never starts
+        assert_eq!(
+            set(
+                "__batch__",
+                r#"{"TARGET_RA": "120.5", "TARGET_DEC": "north", "DO_GOTO": "true"}"#
+            ),
+            DeviceActions::InvalidValue as i32
+        );
+        assert_eq!(value("TARGET_RA"), "83.82");
+        assert_eq!(value("TARGET_DEC"), "-5.39");
+        assert_eq!(value("GOTO_IN_PROGRESS"), "false");
+
+        // Out of range targets are refused up front, not at DO_GOTO
+        assert_eq!(
+            set(
+                "__batch__",
+                r#"{"TARGET_RA": "400", "TARGET_DEC": "10", "DO_GOTO": "true"}"#
+            ),
+            DeviceActions::InvalidValue as i32
+        );
+        assert_eq!(value("TARGET_RA"), "83.82");
+        assert_eq!(value("TARGET_DEC"), "-5.39");
+        assert_eq!(value("GOTO_IN_PROGRESS"), "false");
+
+        // A malformed target list is caught before the goto is sent
+        assert_eq!(
+            set(
+                "__batch__",
+                r#"{"TARGET_DEC": "10", "DO_GOTO": "true", "TARGET_LIST": "[{\"name\": \"M81\"}]"}"#
+            ),
+            DeviceActions::InvalidValue as i32
+        );
+        assert_eq!(value("TARGET_DEC"), "-5.39");
+        assert_eq!(value("GOTO_IN_PROGRESS"), "false");
+
+        // The longitude is not sent ahead of a latitude off the globe
+        let longitude = value("SITE_LONGITUDE");
+        assert_eq!(
+            set(
+                "__batch__",
+                r#"{"SITE_LONGITUDE": "12.5", "SITE_LATITUDE": "95"}"#
+            ),
+            DeviceActions::InvalidValue as i32
+        );
+        assert_eq!(value("SITE_LONGITUDE"), longitude);
+
+        // Nothing is applied when one of the properties is read only
+        assert_eq!(
+            set("__batch__", r#"{"TARGET_RA": "120.5", "RA": "0"}"#),
+            DeviceActions::CannotUpdateReadOnlyProperty as i32
+        );
+        assert_eq!(value("TARGET_RA"), "83.82");
+
+        assert_eq!(
+            set("__batch__", "TARGET_RA=120.5"),
+            DeviceActions::InvalidValue as i32
+        );
+        // Single updates are unchanged
+        assert_eq!(set("TARGET_RA", "120.5"), DeviceActions::Ok as i32);
+        assert_eq!(value("TARGET_RA"), "120.5");
+    }
+
+    fn prop(device: &MountDevice, name: &str) -> String {
+        device
+            .view()
+            .ls_props()
+            .into_iter()
+            .find(|p| p.name == name)
+            .unwrap()
+            .value
+    }
+
+    #[test]
+    fn every_property_is_handed_out() {
+        let mut device = MountDevice::simulated(1).unwrap();
+        let value = |device: &MountDevice, name: &str| {
+            device
+                .get_properties()
+                .iter()
+                .find(|p| p.name == name)
+                .map(|p| p.value.clone())
+        };
+        assert_eq!(
+            value(&device, "TRACKING_MODE"),
+            Some(TrackingMode::Off.to_string())
+        );
+
+        device
+            .update_property("TRACKING_MODE", &TrackingMode::Eq.to_string())
+            .unwrap();
+        assert_eq!(
+            value(&device, "TRACKING_MODE"),
+            Some(TrackingMode::Eq.to_string())
+        );
+        assert_eq!(
+            device.get_properties().len(),
+            device.view().ls_props().len()
+        );
+    }
+
+    #[test]
+    fn park_is_finished_by_the_polls() {
+        let mount = SimulatedMount::default().with_slew_time(Duration::from_millis(300));
+        let mut device = MountDevice::with_simulator(mount, 1).unwrap();
+        device
+            .update_property("TRACKING_MODE", &TrackingMode::Eq.to_string())
+            .unwrap();
+
+        // Returns as soon as the slew started
+        device.update_property("PARK", "true").unwrap();
+        // The mount answers '1#' twice, then '0#'
+        for _ in 0..2 {
+            device.fetch_props();
+            assert_eq!(prop(&device, "GOTO_IN_PROGRESS"), "true");
+            assert_eq!(prop(&device, "PARKED"), "false");
+        }
+        thread::sleep(Duration::from_millis(350));
+        device.fetch_props();
+        assert_eq!(prop(&device, "GOTO_IN_PROGRESS"), "false");
+        assert_eq!(prop(&device, "PARKED"), "true");
+        assert_eq!(
+            prop(&device, "TRACKING_MODE"),
+            TrackingMode::Off.to_string()
+        );
+
+        device.update_property("UNPARK", "true").unwrap();
+        assert_eq!(prop(&device, "PARKED"), "false");
+        assert_eq!(prop(&device, "TRACKING_MODE"), TrackingMode::Eq.to_string());
+    }
+
+    #[test]
+    fn interrupted_park_forgets_the_tracking() {
+        let mount = SimulatedMount::default().with_slew_time(Duration::from_secs(10));
+        let mut device = MountDevice::with_simulator(mount, 1).unwrap();
+        device
+            .update_property("TRACKING_MODE", &TrackingMode::Eq.to_string())
+            .unwrap();
+        device.update_property("PARK", "true").unwrap();
+        device.update_property("EMERGENCY_STOP", "true").unwrap();
+        device.fetch_props();
+        assert_eq!(prop(&device, "PARKED"), "false");
+
+        // Nothing to restore, the mount was never parked
+        device
+            .update_property("TRACKING_MODE", &TrackingMode::Off.to_string())
+            .unwrap();
+        device.update_property("UNPARK", "true").unwrap();
+        assert_eq!(
+            prop(&device, "TRACKING_MODE"),
+            TrackingMode::Off.to_string()
+        );
+    }
+
+    #[test]
+    fn tracking_stops_at_home() {
+        let mount = SimulatedMount::default().with_slew_time(Duration::from_millis(300));
+        let mut device = MountDevice::with_simulator(mount, 1).unwrap();
+        device
+            .update_property("TRACKING_MODE", &TrackingMode::Eq.to_string())
+            .unwrap();
+        device.update_property("GO_HOME", "true").unwrap();
+
+        device.fetch_props();
+        assert_eq!(prop(&device, "GOTO_IN_PROGRESS"), "true");
+        assert_eq!(prop(&device, "TRACKING_MODE"), TrackingMode::Eq.to_string());
+        thread::sleep(Duration::from_millis(350));
+        device.fetch_props();
+        assert_eq!(
+            prop(&device, "TRACKING_MODE"),
+            TrackingMode::Off.to_string()
+        );
+    }
+
+    #[test]
+    fn goto_error_is_measured_after_the_slew() {
+        let mount = SimulatedMount::default().with_slew_time(Duration::from_millis(300));
+        let mut device = MountDevice::with_simulator(mount, 1).unwrap();
+        device
+            .update_property("TRACKING_MODE", &TrackingMode::Eq.to_string())
+            .unwrap();
+        device.update_property("TARGET_RA", "10.0").unwrap();
+        device.update_property("TARGET_DEC", "80.0").unwrap();
+        device.update_property("DO_GOTO", "true").unwrap();
+
+        // Two polls on the way don't count as settled
+        device.fetch_props();
+        device.fetch_props();
+        assert_eq!(prop(&device, "GOTO_ERROR_ARCSEC"), "");
+        thread::sleep(Duration::from_millis(350));
+        device.fetch_props();
+        device.fetch_props();
+        let error: f64 = prop(&device, "GOTO_ERROR_ARCSEC").parse().unwrap();
+        assert!(error < 1.0, "{}", error);
+    }
+
+    /// Sets the target to 45 degrees up at azimuth `az` and starts the
+    /// goto, the mount stays there with tracking off
+    fn goto_azimuth(device: &mut MountDevice, az: f64) -> Result<(), DeviceActions> {
+        let site = |name| prop(device, name).parse::<f64>().unwrap();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs_f64();
+        let lst = local_sidereal_time_deg(now, site("SITE_LONGITUDE"));
+        let (ra, dec) = alt_az_to_ra_dec(45.0, az, site("SITE_LATITUDE"), lst);
+        device.update_property("TARGET_RA", &ra.to_string())?;
+        device.update_property("TARGET_DEC", &dec.to_string())?;
+        device.update_property("DO_GOTO", "true")?;
+        thread::sleep(Duration::from_millis(80));
+        device.fetch_props();
+        Ok(())
+    }
+
+    #[test]
+    fn gotos_wrapping_the_cable_are_refused() {
+        // An AZ mount, whatever it tracks
+        let mount = SimulatedMount::default()
+            .with_slew_time(Duration::from_millis(50))
+            .with_model(128);
+        let mut device = MountDevice::with_simulator(mount, 1).unwrap();
+        device.fetch_props();
+
+        // Clockwise a bit less than half a turn at a time
+        for az in [170.0, 340.0] {
+            goto_azimuth(&mut device, az).unwrap();
+        }
+        let wrap: f64 = prop(&device, "AZ_CABLE_WRAP_DEG").parse().unwrap();
+        assert!((wrap - 340.0).abs() < 1.0, "{}", wrap);
+        assert_eq!(
+            goto_azimuth(&mut device, 150.0),
+            Err(DeviceActions::InvalidValue)
+        );
+        // Back the other way is fine
+        goto_azimuth(&mut device, 170.0).unwrap();
+        goto_azimuth(&mut device, 340.0).unwrap();
+
+        device.update_property("UNWIND_CABLE", "true").unwrap();
+        device.update_property("ABORT_MOTION", "true").unwrap();
+
+        device.update_property("RESET_CABLE_WRAP", "true").unwrap();
+        assert_eq!(prop(&device, "AZ_CABLE_WRAP_DEG"), "0.0");
+        device.fetch_props();
+        goto_azimuth(&mut device, 150.0).unwrap();
+    }
+
+    #[test]
+    fn equatorial_mounts_have_no_cable_wrap() {
+        let mount = SimulatedMount::default().with_slew_time(Duration::from_millis(50));
+        let mut device = MountDevice::with_simulator(mount, 1).unwrap();
+        device
+            .update_property("TRACKING_MODE", &TrackingMode::Eq.to_string())
+            .unwrap();
+        device.fetch_props();
+
+        for az in [170.0, 340.0, 150.0, 320.0] {
+            goto_azimuth(&mut device, az).unwrap();
+        }
+        assert_eq!(prop(&device, "AZ_CABLE_WRAP_DEG"), "0");
+        assert_eq!(
+            device.update_property("UNWIND_CABLE", "true"),
+            Err(DeviceActions::InvalidValue)
+        );
+
+        // Counted once the mount is set up Alt-Az
+        device
+            .update_property("TRACKING_MODE", &TrackingMode::AltAz.to_string())
+            .unwrap();
+        device.fetch_props();
+        // Counter clockwise from 320
+        for az in [150.0, 340.0] {
+            goto_azimuth(&mut device, az).unwrap();
+        }
+        assert_eq!(
+            goto_azimuth(&mut device, 170.0),
+            Err(DeviceActions::InvalidValue)
+        );
+    }
+
+    /// Address of a Wi-Fi adapter in front of the simulator answering
+    /// `command` with `answers` in turn, `delay` late, None leaving one
+    /// unanswered, then like the simulator
+    fn scripted_adapter(
+        command: Command,
+        answers: Vec<Option<&'static [u8]>>,
+        delay: Duration,
+    ) -> String {
+        // Keeps the gotos and target lists of these mounts out of the
+        // working directory
+        std::env::set_var(
+            "LS_INFLIGHT_FILE",
+            std::env::temp_dir().join(format!(
+                "skywatcher-synscan-inflight-{}.json",
+                std::process::id()
+            )),
+        );
+        std::env::set_var(
+            "LS_TARGET_LIST_FILE",
+            std::env::temp_dir().join(format!(
+                "skywatcher-synscan-targets-{}.json",
+                std::process::id()
+            )),
+        );
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let mut mount = SimulatedMount::default();
+            let mut answers = answers.into_iter();
+            let mut frame = [0; 64];
+            while let Ok((len, from)) = socket.recv_from(&mut frame) {
+                let mut answer = Vec::new();
+                let scripted = if frame[0] == command.as_byte() {
+                    answers.next()
+                } else {
+                    None
+                };
+                match scripted {
+                    Some(scripted) => {
+                        thread::sleep(delay);
+                        answer.extend(scripted.unwrap_or_default());
+                    }
+                    None => {
+                        mount.write_all(&frame[..len]).unwrap();
+                        let _ = mount.read_to_end(&mut answer);
+                    }
+                }
+                if !answer.is_empty() {
+                    socket.send_to(&answer, from).unwrap();
+                }
+            }
+        });
+        address
+    }
+
+    fn mount_answering(command: Command, answers: Vec<Option<&'static [u8]>>) -> MountDevice {
+        MountDevice::networked(&scripted_adapter(command, answers, Duration::ZERO), 300, 1).unwrap()
+    }
+
+    #[test]
+    fn gotos_need_the_acknowledgement() {
+        let mut device = mount_answering(
+            Command::GoToPreciseRaDec,
+            vec![Some(b"#"), Some(b"!2#"), Some(b"1#"), None],
+        );
+        device.update_property("TARGET_RA", "10.0").unwrap();
+        device.update_property("TARGET_DEC", "80.0").unwrap();
+
+        for expected in [
+            Ok(()),
+            // Rejected by the hand controller
+            Err(DeviceActions::InvalidValue),
+            Err(DeviceActions::ComError),
+            Err(DeviceActions::Timeout),
+        ] {
+            assert_eq!(device.update_property("DO_GOTO", "true"), expected);
+        }
+        assert!(device.is_online());
+    }
+
+    #[test]
+    fn bad_position_frames_are_survived() {
+        // Both answered while the device is set up
+        let mut device = mount_answering(
+            Command::GetPreciseRaDec,
+            vec![Some(b"12AB0500,4#"), Some(b"+2AB0500,40000000#")],
+        );
+        assert_eq!(prop(&device, "RA"), "");
+        assert_eq!(prop(&device, "POSITION_STALE"), "true");
+
+        device.fetch_props();
+        assert_eq!(prop(&device, "DEC"), "90.000000");
+        assert_eq!(prop(&device, "POSITION_STALE"), "false");
+        device
+            .update_property("TRACKING_MODE", &TrackingMode::Eq.to_string())
+            .unwrap();
+    }
+
+    #[test]
+    fn gotos_stay_within_the_slew_limits() {
+        let mut device = MountDevice::with_simulator(SimulatedMount::default(), 1).unwrap();
+        device.update_property("MIN_ALTITUDE", "10").unwrap();
+        device.update_property("MAX_HOUR_ANGLE", "3").unwrap();
+
+        // On the meridian, then almost four hours west of it
+        assert_eq!(goto_azimuth(&mut device, 180.0), Ok(()));
+        assert_eq!(
+            goto_azimuth(&mut device, 270.0),
+            Err(DeviceActions::InvalidValue)
+        );
+        assert_eq!(
+            device.goto_precise_alt_az(270.0, 45.0),
+            Err(DeviceActions::InvalidValue)
+        );
+        assert_eq!(device.goto_precise_alt_az(180.0, 45.0), Ok(()));
+        // The park position on the horizon is still reachable
+        assert_eq!(device.update_property("PARK", "true"), Ok(()));
+        device.update_property("EMERGENCY_STOP", "true").unwrap();
+
+        device.update_property("ENABLE_LIMITS", "false").unwrap();
+        assert_eq!(goto_azimuth(&mut device, 270.0), Ok(()));
+    }
+
+    #[test]
+    fn gotos_need_the_site_to_check_the_limits() {
+        let mut device = mount_answering(Command::GetLocation, vec![None; 4]);
+        assert_eq!(prop(&device, "SITE_LATITUDE"), "");
+        device.update_property("TARGET_RA", "10.0").unwrap();
+        device.update_property("TARGET_DEC", "80.0").unwrap();
+
+        assert_eq!(
+            device.update_property("DO_GOTO", "true"),
+            Err(DeviceActions::InvalidValue)
+        );
+        device.update_property("ENABLE_LIMITS", "false").unwrap();
+        assert_eq!(device.update_property("DO_GOTO", "true"), Ok(()));
+    }
+
+    #[test]
+    fn target_list_survives_a_restart() {
+        let file = std::env::temp_dir().join(format!(
+            "skywatcher-synscan-targets-{}.json",
+            std::process::id()
+        ));
+        let mut device = mount_answering(Command::GoToPreciseRaDec, vec![Some(b"#"), Some(b"!2#")]);
+        device
+            .update_property(
+                "TARGET_LIST",
+                r#"[{"name": "M81", "ra": 148.888, "dec": 69.065},
+                    {"name": "M82", "ra": 148.968, "dec": 69.679}]"#,
+            )
+            .unwrap();
+        assert_eq!(device.update_property("NEXT_TARGET", "true"), Ok(()));
+        assert_eq!(prop(&device, "CURRENT_TARGET"), "M81,148.888,69.065");
+
+        // Refused by the hand controller, the list stays on M81
+        assert_eq!(
+            device.update_property("NEXT_TARGET", "true"),
+            Err(DeviceActions::InvalidValue)
+        );
+        assert_eq!(prop(&device, "CURRENT_TARGET"), "M81,148.888,69.065");
+
+        let mut restarted = MountDevice::networked(device.get_address(), 300, 1).unwrap();
+        assert_eq!(prop(&restarted, "CURRENT_TARGET"), "M81,148.888,69.065");
+        // The goto to M81 was left in flight, until the polls see the
+        // mount stopped
+        assert_eq!(prop(&restarted, "CAUTIOUS_MODE"), "true");
+        restarted.fetch_props();
+        restarted.fetch_props();
+        assert_eq!(restarted.update_property("NEXT_TARGET", "true"), Ok(()));
+        assert_eq!(prop(&restarted, "CURRENT_TARGET"), "M82,148.968,69.679");
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn target_triggers_ignore_false() {
+        // A single goto is answered, a second one would time out
+        let mut device = mount_answering(Command::GoToPreciseRaDec, vec![Some(b"#"), None]);
+        device
+            .update_property(
+                "TARGET_LIST",
+                r#"[{"name": "M81", "ra": 148.888, "dec": 69.065}]"#,
+            )
+            .unwrap();
+        for name in ["NEXT_TARGET", "PREV_TARGET", "CLEAR_TARGET_LIST"] {
+            assert_eq!(device.update_property(name, "false"), Ok(()));
+        }
+        assert_eq!(prop(&device, "CURRENT_TARGET"), "");
+
+        // Nothing was sent and the list is still there
+        assert_eq!(device.update_property("NEXT_TARGET", "true"), Ok(()));
+        assert_eq!(prop(&device, "CURRENT_TARGET"), "M81,148.888,69.065");
+        assert_eq!(device.update_property("CLEAR_TARGET_LIST", "false"), Ok(()));
+        assert_eq!(prop(&device, "CURRENT_TARGET"), "M81,148.888,69.065");
+    }
+
+    #[test]
+    fn restart_in_the_middle_of_a_goto() {
+        let file = std::env::temp_dir().join(format!(
+            "skywatcher-synscan-crash-{}.json",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&file);
+        let mount = SimulatedMount::default().with_slew_time(Duration::from_millis(500));
+        let mut device =
+            MountDevice::with_simulator_in_flight(mount, 1, Some(file.clone())).unwrap();
+        device.update_property("ENABLE_LIMITS", "false").unwrap();
+        device.update_property("TARGET_RA", "10").unwrap();
+        device.update_property("TARGET_DEC", "40").unwrap();
+        device.update_property("DO_GOTO", "true").unwrap();
+
+        // The driver dies with the mount slewing, the next one finds
+        // the same mount still on its way
+        let mount = match device.port {
+            MountPort::Simulated(mount) => mount,
+            _ => unreachable!(),
+        };
+        let mut device =
+            MountDevice::with_simulator_in_flight(mount, 1, Some(file.clone())).unwrap();
+        let recovered = prop(&device, "RECOVERED_FROM_CRASH");
+        assert!(
+            recovered.starts_with("GOTO to RA: 10 DEC: 40 started at "),
+            "{}",
+            recovered
+        );
+        assert_eq!(prop(&device, "CAUTIOUS_MODE"), "true");
+        device.update_property("ENABLE_LIMITS", "false").unwrap();
+        device.update_property("TARGET_RA", "20").unwrap();
+        device.update_property("TARGET_DEC", "30").unwrap();
+        for (name, value) in [("DO_GOTO", "true"), ("PARK", "true"), ("SLEW_RA", "+5")] {
+            assert_eq!(
+                device.update_property(name, value),
+                Err(DeviceActions::InvalidValue),
+                "{}",
+                name
+            );
+        }
+        device.fetch_props();
+        assert_eq!(prop(&device, "GOTO_IN_PROGRESS"), "true");
+        assert_eq!(prop(&device, "CAUTIOUS_MODE"), "true");
+        // Stopping is always allowed
+        assert_eq!(device.update_property("SLEW_RA", "0"), Ok(()));
+
+        // Over once the mount was seen still for long enough
+        thread::sleep(Duration::from_millis(550));
+        device.fetch_props();
+        device.fetch_props();
+        assert_eq!(prop(&device, "CAUTIOUS_MODE"), "true");
+        device.fetch_props();
+        assert_eq!(prop(&device, "CAUTIOUS_MODE"), "false");
+        assert_eq!(prop(&device, "RECOVERED_FROM_CRASH"), recovered);
+        assert!(load_in_flight(&file).is_empty());
+
+        // Gotos seen to the end leave nothing for the next run
+        assert_eq!(device.update_property("DO_GOTO", "true"), Ok(()));
+        assert_eq!(load_in_flight(&file).len(), 1);
+        device.fetch_props();
+        thread::sleep(Duration::from_millis(550));
+        device.fetch_props();
+        assert!(load_in_flight(&file).is_empty());
+        let mount = match device.port {
+            MountPort::Simulated(mount) => mount,
+            _ => unreachable!(),
+        };
+        let device = MountDevice::with_simulator_in_flight(mount, 1, Some(file.clone())).unwrap();
+        assert_eq!(prop(&device, "CAUTIOUS_MODE"), "false");
+        assert_eq!(prop(&device, "RECOVERED_FROM_CRASH"), "");
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn daylight_saving_time_goes_to_the_mount() {
+        let mut device = mount_answering(Command::GetTime, Vec::new());
+        assert_eq!(prop(&device, "DST"), "false");
+        device.update_property("UTC_OFFSET", "2").unwrap();
+        device.update_property("DST", "true").unwrap();
+        assert_eq!(device.update_property("SYNC_TIME_NOW", "true"), Ok(()));
+
+        // Read back from the mount by the next driver run
+        let restarted = MountDevice::networked(device.get_address(), 300, 1).unwrap();
+        assert_eq!(prop(&restarted, "UTC_OFFSET"), "2");
+        assert_eq!(prop(&restarted, "DST"), "true");
+    }
+
+    #[test]
+    fn syncs_stay_on_the_sky() {
+        let mut device = MountDevice::simulated(1).unwrap();
+        for value in ["360,10", "-5,10", "83.82,91", "83.82,-90.5"] {
+            assert_eq!(
+                device.update_property("SYNC_COORDINATES", value),
+                Err(DeviceActions::InvalidValue),
+                "{}",
+                value
+            );
+        }
+        assert_eq!(
+            device.update_property("SYNC_COORDINATES", "83.82,-5.39"),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn raw_commands_are_kept_with_their_answer() {
+        std::env::set_var("LS_ENABLE_RAW", "true");
+        let mut device = MountDevice::simulated(1).unwrap();
+        device.update_property("RAW_COMMAND", "5 6").unwrap();
+        assert_eq!(prop(&device, "RAW_COMMAND"), "56");
+        // Version 4.37.7 as "042507#"
+        assert_eq!(prop(&device, "RAW_RESPONSE"), "30 34 32 35 30 37 23");
+
+        assert_eq!(
+            device.update_property("RAW_COMMAND", "5"),
+            Err(DeviceActions::InvalidValue)
+        );
+        assert_eq!(prop(&device, "RAW_COMMAND"), "56");
+    }
+
+    #[test]
+    fn binary_answers_show_up_in_the_last_error() {
+        let device = mount_answering(Command::GetLocation, vec![None; 4]);
+        assert!(prop(&device, "LAST_ERROR").starts_with("Timeout on GetLocation"));
+
+        // Not having the GPS accessory is no error
+        let device = MountDevice::simulated(1).unwrap();
+        assert_eq!(prop(&device, "LAST_ERROR"), "");
+    }
+
+    #[test]
+    fn alt_az_gotos_land_where_asked() {
+        let mount = SimulatedMount::default().with_slew_time(Duration::from_millis(50));
+        let mut device = MountDevice::with_simulator(mount, 1).unwrap();
+        let alt_az = |device: &mut MountDevice| {
+            thread::sleep(Duration::from_millis(80));
+            device.fetch_props();
+            let value = |name| prop(device, name).parse::<f64>().unwrap();
+            (value("ALT"), value("AZ"))
+        };
+
+        assert_eq!(device.goto_precise_alt_az(120.0, 30.0), Ok(()));
+        let (alt, az) = alt_az(&mut device);
+        assert!(
+            (alt - 30.0).abs() < 0.01 && (az - 120.0).abs() < 0.01,
+            "{} {}",
+            alt,
+            az
+        );
+
+        // 16 bits are still within a hundredth of a degree
+        assert_eq!(device.goto_alt_az(200.0, 40.0), Ok(()));
+        let (alt, az) = alt_az(&mut device);
+        assert!(
+            (alt - 40.0).abs() < 0.01 && (az - 200.0).abs() < 0.01,
+            "{} {}",
+            alt,
+            az
+        );
+
+        assert_eq!(
+            device.goto_precise_alt_az(120.0, -5.0),
+            Err(DeviceActions::InvalidValue)
+        );
+        assert_eq!(
+            device.goto_alt_az(120.0, 95.0),
+            Err(DeviceActions::InvalidValue)
+        );
+    }
+
+    #[test]
+    fn guide_rates_are_kept_by_the_motors() {
+        let mut device = mount_answering(Command::GetTime, Vec::new());
+        assert_eq!(prop(&device, "GUIDE_RATE_RA"), "50");
+        assert_eq!(device.update_property("GUIDE_RATE_RA", "75"), Ok(()));
+        assert_eq!(prop(&device, "GUIDE_RATE_RA"), "75");
+        assert_eq!(prop(&device, "GUIDE_RATE_DEC"), "50");
+        assert_eq!(
+            device.update_property("GUIDE_RATE_DEC", "300"),
+            Err(DeviceActions::InvalidValue)
+        );
+
+        let restarted = MountDevice::networked(device.get_address(), 300, 1).unwrap();
+        assert_eq!(prop(&restarted, "GUIDE_RATE_RA"), "75");
+        assert_eq!(prop(&restarted, "GUIDE_RATE_DEC"), "50");
+    }
+
+    #[test]
+    fn target_coordinates_start_the_goto() {
+        let mount = SimulatedMount::default().with_slew_time(Duration::from_millis(200));
+        let mut device = MountDevice::with_simulator(mount, 1).unwrap();
+        device
+            .update_property("TRACKING_MODE", &TrackingMode::Eq.to_string())
+            .unwrap();
+        // Nothing to go to yet
+        assert_eq!(
+            device.update_property("DO_GOTO", "true"),
+            Err(DeviceActions::InvalidValue)
+        );
+        device.update_property("TARGET_RA", "83.82").unwrap();
+        device.update_property("TARGET_DEC", "95").unwrap();
+        assert_eq!(
+            device.update_property("DO_GOTO", "true"),
+            Err(DeviceActions::InvalidValue)
+        );
+
+        device.update_property("TARGET_DEC", "60").unwrap();
+        assert_eq!(device.update_property("DO_GOTO", "false"), Ok(()));
+        device.fetch_props();
+        assert_eq!(prop(&device, "GOTO_IN_PROGRESS"), "false");
+
+        assert_eq!(device.update_property("DO_GOTO", "true"), Ok(()));
+        device.fetch_props();
+        assert_eq!(prop(&device, "GOTO_IN_PROGRESS"), "true");
+        thread::sleep(Duration::from_millis(250));
+        device.fetch_props();
+        assert_eq!(prop(&device, "GOTO_IN_PROGRESS"), "false");
+        let value = |name| prop(&device, name).parse::<f64>().unwrap();
+        assert!((value("RA") - 83.82).abs() < 0.01, "{}", value("RA"));
+        assert!((value("DEC") - 60.0).abs() < 0.01, "{}", value("DEC"));
+    }
+
+    #[test]
+    fn old_firmwares_get_the_plain_commands() {
+        // Never answers the precise commands
+        let mount = SimulatedMount::default()
+            .with_slew_time(Duration::from_millis(200))
+            .with_version((2, 1, 0));
+        let mut device = MountDevice::with_simulator(mount, 1).unwrap();
+        device
+            .update_property("TRACKING_MODE", &TrackingMode::Eq.to_string())
+            .unwrap();
+        assert_eq!(
+            device.update_property("SYNC_COORDINATES", "83.82,-5.39"),
+            Ok(())
+        );
+
+        device.update_property("TARGET_RA", "100").unwrap();
+        device.update_property("TARGET_DEC", "60").unwrap();
+        assert_eq!(device.update_property("DO_GOTO", "true"), Ok(()));
+        thread::sleep(Duration::from_millis(250));
+        device.fetch_props();
+        let value = |name| prop(&device, name).parse::<f64>().unwrap();
+        assert!((value("RA") - 100.0).abs() < 0.01, "{}", value("RA"));
+        assert!((value("DEC") - 60.0).abs() < 0.01, "{}", value("DEC"));
+
+        assert_eq!(device.update_property("GO_HOME", "true"), Ok(()));
+        thread::sleep(Duration::from_millis(250));
+        device.fetch_props();
+        assert_eq!(device.update_property("PARK", "true"), Ok(()));
+    }
+
+    #[test]
+    fn lost_answers_are_asked_again() {
+        // Every datagram of the first query is lost
+        let lost = || vec![None; UDP_RESENDS as usize + 1];
+        let address = scripted_adapter(Command::GetPreciseRaDec, lost(), Duration::ZERO);
+        let device = MountDevice::networked(&address, 300, 1).unwrap();
+        assert!(prop(&device, "LAST_ERROR").starts_with("Timeout on GetPreciseRaDec"));
+
+        let address = scripted_adapter(Command::GetPreciseRaDec, lost(), Duration::ZERO);
+        let device = MountDevice::networked(&address, 300, 2).unwrap();
+        assert_eq!(prop(&device, "LAST_ERROR"), "");
+        assert_ne!(prop(&device, "RA"), "");
+    }
+
+    #[test]
+    fn gotos_wait_longer_for_their_answer() {
+        // Twice the 300 ms the port was opened with
+        let address = scripted_adapter(
+            Command::GoToPreciseRaDec,
+            vec![Some(b"#")],
+            Duration::from_millis(600),
+        );
+        let mut device = MountDevice::networked(&address, 300, 1).unwrap();
+        device.update_property("TARGET_RA", "10.0").unwrap();
+        device.update_property("TARGET_DEC", "80.0").unwrap();
+        assert_eq!(device.update_property("DO_GOTO", "true"), Ok(()));
+        assert_eq!(device.port.timeout(), Duration::from_millis(300));
+
+        // Past the 500 ms of the position queries
+        let address = scripted_adapter(
+            Command::GetPierSide,
+            vec![Some(b"W#")],
+            Duration::from_millis(600),
+        );
+        let device = MountDevice::networked(&address, 2000, 1).unwrap();
+        assert!(prop(&device, "LAST_ERROR").starts_with("Timeout on GetPierSide"));
+    }
+}
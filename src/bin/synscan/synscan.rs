@@ -1,61 +1,136 @@
 use astrotools::AstroSerialDevice;
-use hex::FromHex;
 use lightspeed_astro::devices::actions::DeviceActions;
 use lightspeed_astro::props::Permission;
 use lightspeed_astro::props::Property;
 use log::{debug, error, info, warn};
-#[cfg(windows)]
-use serialport::COMPort;
-#[cfg(unix)]
-use serialport::TTYPort;
-use serialport::{available_ports, SerialPortType, UsbPortInfo};
+use serialport::{ClearBuffer, UsbPortInfo};
+use skywatcher_rs::actor::{self, DeviceHandle};
+use skywatcher_rs::cable_wrap::{cable_wrap_limit_from, is_alt_az, CableWrap};
+use skywatcher_rs::connection::{Connection, DEFAULT_OFFLINE_AFTER};
+use skywatcher_rs::discovery::PortSource;
+use skywatcher_rs::goto_check::{GotoCheck, GOTO_ERROR_WARNING_ARCSEC};
+use skywatcher_rs::inflight::{
+    load_in_flight, save_in_flight, InFlight, Operation, Recovery, DEFAULT_INFLIGHT_FILE,
+};
+use skywatcher_rs::metrics::{CommandStats, Outcome};
+use skywatcher_rs::network::UdpTransport;
+use skywatcher_rs::props::{check_writable, ls_props, update_all, CustomProp};
+use skywatcher_rs::serial::{
+    response_to_string, retry, send_all, ReadError, SerialLink, SerialTransport, TransportError,
+    DEFAULT_RETRY_ATTEMPTS, RETRY_BACKOFF,
+};
+use skywatcher_rs::shared::{device_id, DeviceView};
+use skywatcher_rs::shutdown;
+use skywatcher_rs::simulator::{SimulatedMount, SIMULATOR_ADDRESS};
+use skywatcher_rs::slew_limits::{
+    alt_az_to_ra_dec, hour_angle_hours, local_sidereal_time_deg, ra_dec_to_alt_az, SlewLimits,
+    DEFAULT_MAX_HOUR_ANGLE_HOURS, DEFAULT_MIN_ALTITUDE_DEG,
+};
+use skywatcher_rs::synscan::{
+    build_frame, decode_location, encode_location, get_guide_rate_frame, hex_dump, home_ra_dec,
+    motor_version_frame, parse_alignment, parse_firmware_version, parse_goto_in_progress,
+    parse_hex_command, parse_model, parse_motor_version, parse_pier_side, parse_slew_value,
+    parse_version_response, passthrough_frame, pier_side_from_dec_axis, response_error,
+    rotation_fraction_to_degrees, set_guide_rate_frame, slew_frame, Axis, Command, Direction,
+    Feature, GotoProgress, GotoWatch, LastError, MountTime, PierSide, PositionAge, StatusTracker,
+    ALIGNMENT_REFRESH_CYCLES, DEFAULT_STALE_POLLS, GPS_DEVICE, IDLE_POLL_INTERVAL,
+    LAST_ERROR_QUIET_PERIOD, MAX_SLEW_RATE, PARK_TIMEOUT, SLEWING_POLL_INTERVAL, UNKNOWN_MODEL,
+    UNKNOWN_VERSION,
+};
+use skywatcher_rs::targets::{
+    load_target_lists, save_target_list, TargetList, DEFAULT_TARGET_LIST_FILE,
+};
+use skywatcher_rs::traffic::TrafficLog;
 use skywatcher_rs::{
-    degrees_to_precise_revolutions, degrees_to_revolutions, str_24bits_to_u32, TrackingMode,
+    enum_kind, format_goto_payload, format_precise_goto_payload, is_truthy, parse_alt_az_response,
+    parse_coordinate_pair, parse_goto_target, parse_precise_alt_az_response,
+    parse_precise_ra_dec_response, parse_ra_dec_pair, parse_ra_dec_response,
+    parse_tracking_mode_response, poll_interval_from, signed_degrees, str_24bits_to_u32,
+    TrackingMode,
 };
 use std::fmt::UpperHex;
-use std::io::{Read, Write};
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
-use std::time::Duration;
-use universe::transform::{dec_to_deg, ra_to_deg};
-use universe::{Declination, RightAscension};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
 
-const TRACKING_OFF: &str = "Off";
-const TRACKING_ALT_AZ: &str = "AltAz";
-const TRACKING_EQUATORIAL: &str = "Equatorial";
-const TRACKING_PEC: &str = "PEC";
-
-enum Command {
-    Echo = 0x4b,
-    GetRaDec = 0x45,
-    GetPreciseRaDec = 0x65,
-    GetAltAz = 0x5a,
-    GetPreciseAltAz = 0x7a,
-    GoToRaDec = 0x52,
-    GoToPreciseRaDec = 0x72,
-    GoToAltAz = 0x42,
-    GoToPreciseAltAz = 0x62,
-    GetTrackingMode = 0x74,
-    SetTrackingMode = 0x54,
-    GetVersion = 0x56,
-    GetModel = 0x6d,
-    GetAlignment = 0x4a,
+/// Where the frames go, a hand controller, a Wi-Fi adapter or the
+/// built-in simulator
+pub enum MountPort {
+    /// Local or behind a TCP bridge
+    Serial(SerialLink),
+    Udp(UdpTransport),
+    Simulated(SimulatedMount),
 }
 
-pub struct CustomProp {
-    name: String,
-    value: Arc<RwLock<String>>,
-    kind: String,
-    permission: Permission,
+impl MountPort {
+    pub fn timeout(&self) -> Duration {
+        match self {
+            MountPort::Serial(p) => p.timeout(),
+            MountPort::Udp(u) => u.timeout(),
+            MountPort::Simulated(s) => s.timeout(),
+        }
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+        match self {
+            MountPort::Serial(p) => p.set_timeout(timeout),
+            MountPort::Udp(u) => Ok(u.set_timeout(timeout)?),
+            MountPort::Simulated(s) => {
+                s.set_timeout(timeout);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn clear(&mut self, buffer: ClearBuffer) -> serialport::Result<()> {
+        match self {
+            MountPort::Serial(p) => p.clear(buffer),
+            MountPort::Udp(u) => Ok(u.clear()?),
+            MountPort::Simulated(s) => {
+                s.clear();
+                Ok(())
+            }
+        }
+    }
 }
 
-impl CustomProp {
-    fn to_ls_prop(&self) -> Property {
-        Property {
-            name: self.name.to_string(),
-            value: self.value.read().unwrap().to_string(),
-            kind: self.kind.to_string(),
-            permission: self.permission as i32,
+impl Read for MountPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            MountPort::Serial(p) => p.read(buf),
+            MountPort::Udp(u) => u.read(buf),
+            MountPort::Simulated(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for MountPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            MountPort::Serial(p) => p.write(buf),
+            MountPort::Udp(u) => u.write(buf),
+            MountPort::Simulated(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            MountPort::Serial(p) => p.flush(),
+            MountPort::Udp(u) => u.flush(),
+            MountPort::Simulated(s) => s.flush(),
+        }
+    }
+}
+
+impl SerialTransport for MountPort {
+    fn exchange(&mut self, frame: &[u8], terminator: u8) -> Result<Vec<u8>, TransportError> {
+        match self {
+            MountPort::Serial(p) => p.exchange(frame, terminator),
+            MountPort::Udp(u) => u.exchange(frame, terminator),
+            MountPort::Simulated(s) => s.exchange(frame, terminator),
         }
     }
 }
@@ -63,469 +138,2577 @@ impl CustomProp {
 pub struct MountDevice {
     id: Uuid,
     name: String,
+    /// What follows the model in the name, the adapter serial number
+    name_suffix: String,
     properties: Vec<CustomProp>,
     static_properties: Vec<Property>,
+    /// Every property with its value as of the last poll or update,
+    /// what get_properties hands out
+    exported: Vec<Property>,
+    view: DeviceView,
     address: String,
     pub baud: u32,
-    #[cfg(unix)]
-    pub port: TTYPort,
-    #[cfg(windows)]
-    pub port: COMPort,
+    pub port: MountPort,
     track_mode: Arc<RwLock<String>>,
     aligned: Arc<RwLock<String>>,
+    targets: TargetList,
+    /// Where the target list is kept across restarts, LS_TARGET_LIST_FILE
+    target_list_file: PathBuf,
+    current_target: Arc<RwLock<String>>,
+    cable_wrap: CableWrap,
+    az_cable_wrap: Arc<RwLock<String>>,
+    /// Rotation the unwind started from, while azimuth is slewed back
+    unwinding: Option<f64>,
+    /// Whether the mount was last seen set up Alt-Az, only then the
+    /// cable wrap is looked after
+    alt_az: bool,
+    goto_check: Option<GotoCheck>,
+    goto_error: Arc<RwLock<String>>,
+    goto_in_progress: Arc<RwLock<String>>,
+    latitude: Arc<RwLock<String>>,
+    longitude: Arc<RwLock<String>>,
+    mount_time: Arc<RwLock<String>>,
+    utc_offset: Arc<RwLock<String>>,
+    /// Whether daylight saving time is on, sent along with the time
+    dst: Arc<RwLock<String>>,
+    guide_rate_ra: Arc<RwLock<String>>,
+    guide_rate_dec: Arc<RwLock<String>>,
+    fetch_cycles: u32,
+    firmware: String,
+    firmware_version: Option<(u8, u8, u8)>,
+    model: String,
+    dec_axis: Option<f64>,
+    pier_side: Arc<RwLock<String>>,
+    target_ra: Arc<RwLock<String>>,
+    target_dec: Arc<RwLock<String>>,
+    ra: Arc<RwLock<String>>,
+    dec: Arc<RwLock<String>>,
+    alt: Arc<RwLock<String>>,
+    az: Arc<RwLock<String>>,
+    parked: Arc<RwLock<String>>,
+    park_position: Arc<RwLock<String>>,
+    tracking_before_park: Option<String>,
+    /// Slew to the park position, tracking stops once it's over
+    parking: Option<GotoWatch>,
+    gps_present: bool,
+    gps_linked: Arc<RwLock<String>>,
+    gps_lat: Arc<RwLock<String>>,
+    gps_lon: Arc<RwLock<String>>,
+    retry_attempts: u32,
+    min_altitude: Arc<RwLock<String>>,
+    max_hour_angle: Arc<RwLock<String>>,
+    limits_enabled: Arc<RwLock<String>>,
+    status: Arc<RwLock<String>>,
+    status_tracker: StatusTracker,
+    stats: CommandStats,
+    traffic: TrafficLog,
+    /// Polling interval while idle, from LS_POLL_INTERVAL_MS
+    idle_poll_interval: Duration,
+    raw_enabled: bool,
+    raw_command: Arc<RwLock<String>>,
+    raw_response: Arc<RwLock<String>>,
+    position_age: PositionAge,
+    position_stale: Arc<RwLock<String>>,
+    /// Goto started by goto_home, tracking stops once it's over
+    going_home: Option<GotoWatch>,
+    /// Where operations in flight are kept across restarts,
+    /// LS_INFLIGHT_FILE, None when nothing outlives the driver
+    in_flight_file: Option<PathBuf>,
+    /// Motion started here, kept in the in-flight file until it's over
+    in_flight: Option<InFlight>,
+    /// Follows the goto of `in_flight`, unwinds are over once the
+    /// polls see the cable unwound
+    in_flight_watch: Option<GotoWatch>,
+    /// Cautious mode of a restart that found an operation in flight,
+    /// motions are refused until the mount is seen stopped
+    recovery: Option<Recovery>,
+    recovered_from_crash: Arc<RwLock<String>>,
+    cautious_mode: Arc<RwLock<String>>,
+    last_error: LastError,
+    last_error_message: Arc<RwLock<String>>,
+    connection: Connection,
 }
 
-impl AstroSerialDevice for MountDevice {
-    fn new(name: &str, address: &str, baud: u32, timeout_ms: u64) -> Option<Self> {
-        let builder = serialport::new(address, baud).timeout(Duration::from_millis(timeout_ms));
-
-        if let Ok(port_) = builder.open_native() {
-            let mut dev = Self {
-                id: Uuid::new_v4(),
-                name: name.to_owned(),
-                properties: Vec::new(),
-                static_properties: Vec::new(),
-                address: address.to_owned(),
+impl MountDevice {
+    /// Same as `new` with the number of attempts for commands that can
+    /// be safely repeated when the answer gets lost
+    pub fn with_retries(
+        name: &str,
+        address: &str,
+        baud: u32,
+        timeout_ms: u64,
+        retry_attempts: u32,
+    ) -> Option<Self> {
+        match SerialLink::open(address, baud, Duration::from_millis(timeout_ms)) {
+            Ok(port) => Self::with_port(
+                name,
+                address,
                 baud,
-                port: port_,
-                track_mode: Arc::new(RwLock::new(String::from("Off"))),
-                aligned: Arc::new(RwLock::new(String::from("false"))),
-            };
-
-            if let Err(e) = dev.send_command(Command::Echo as i32, Some("x".to_string())) {
-                debug!("Cannot connect to mount after command: {}", e as i32);
-                return None;
+                MountPort::Serial(port),
+                retry_attempts,
+                Some(in_flight_file()),
+            ),
+            Err(e) => {
+                debug!("Cannot open {}: {}", address, e);
+                None
             }
-
-            dev.init_device();
-            dev.fetch_props();
-            Some(dev)
-        } else {
-            debug!("Cannot connect to mount - unknonw");
-            None
         }
     }
 
-    fn fetch_props(&mut self) {
-        info!("Fetching actual state");
-        self.get_tracking_mode();
+    /// A mount behind a Wi-Fi adapter, `address` being its host and
+    /// UDP port
+    pub fn networked(address: &str, timeout_ms: u64, retry_attempts: u32) -> Option<Self> {
+        match UdpTransport::connect(address, Duration::from_millis(timeout_ms)) {
+            Ok(udp) => Self::with_port(
+                &format!("-{}", address),
+                address,
+                0,
+                MountPort::Udp(udp),
+                retry_attempts,
+                Some(in_flight_file()),
+            ),
+            Err(e) => {
+                debug!("Cannot reach {}: {}", address, e);
+                None
+            }
+        }
     }
 
-    fn get_id(&self) -> Uuid {
-        self.id
+    /// The built-in simulated mount, for trying clients out and for tests
+    /// without any hardware
+    pub fn simulated(retry_attempts: u32) -> Option<Self> {
+        Self::with_simulator(SimulatedMount::default(), retry_attempts)
     }
 
-    fn get_address(&self) -> &String {
-        &self.address
+    /// Same as `simulated` with a simulator set up by the caller. The
+    /// simulator doesn't outlive the driver, operations in flight are
+    /// not kept.
+    pub fn with_simulator(mount: SimulatedMount, retry_attempts: u32) -> Option<Self> {
+        Self::with_simulator_in_flight(mount, retry_attempts, None)
     }
 
-    fn get_name(&self) -> &String {
-        &self.name
+    /// Same as `with_simulator` keeping the operations in flight in
+    /// `in_flight_file`, for a device created again over the same
+    /// simulator to find them
+    pub fn with_simulator_in_flight(
+        mount: SimulatedMount,
+        retry_attempts: u32,
+        in_flight_file: Option<PathBuf>,
+    ) -> Option<Self> {
+        Self::with_port(
+            "-Simulator",
+            SIMULATOR_ADDRESS,
+            0,
+            MountPort::Simulated(mount),
+            retry_attempts,
+            in_flight_file,
+        )
     }
 
-    fn get_properties(&self) -> &Vec<Property> {
-        todo!()
+    /// Sets the mount on the other end of `port` up, None when it
+    /// doesn't answer
+    fn with_port(
+        name: &str,
+        address: &str,
+        baud: u32,
+        port: MountPort,
+        retry_attempts: u32,
+        in_flight_file: Option<PathBuf>,
+    ) -> Option<Self> {
+        let mut dev = Self {
+            id: device_id(None, address),
+            name: name.to_owned(),
+            name_suffix: name.to_owned(),
+            properties: Vec::new(),
+            static_properties: Vec::new(),
+            exported: Vec::new(),
+            view: DeviceView::default(),
+            address: address.to_owned(),
+            baud,
+            port,
+            track_mode: Arc::new(RwLock::new(String::from("Off"))),
+            aligned: Arc::new(RwLock::new(String::from("false"))),
+            targets: TargetList::default(),
+            target_list_file: std::env::var("LS_TARGET_LIST_FILE")
+                .map(PathBuf::from)
+                .unwrap_or_else(|_| PathBuf::from(DEFAULT_TARGET_LIST_FILE)),
+            current_target: Arc::new(RwLock::new(String::new())),
+            cable_wrap: CableWrap::new(cable_wrap_limit_from(
+                std::env::var("LS_CABLE_WRAP_LIMIT_DEG").ok().as_deref(),
+            )),
+            az_cable_wrap: Arc::new(RwLock::new(String::from("0"))),
+            unwinding: None,
+            alt_az: false,
+            goto_check: None,
+            goto_error: Arc::new(RwLock::new(String::new())),
+            goto_in_progress: Arc::new(RwLock::new(String::from("false"))),
+            latitude: Arc::new(RwLock::new(String::new())),
+            longitude: Arc::new(RwLock::new(String::new())),
+            mount_time: Arc::new(RwLock::new(String::new())),
+            utc_offset: Arc::new(RwLock::new(String::from("0"))),
+            dst: Arc::new(RwLock::new(String::from("false"))),
+            guide_rate_ra: Arc::new(RwLock::new(String::new())),
+            guide_rate_dec: Arc::new(RwLock::new(String::new())),
+            fetch_cycles: 0,
+            firmware: String::from(UNKNOWN_VERSION),
+            firmware_version: None,
+            model: String::from(UNKNOWN_MODEL),
+            dec_axis: None,
+            pier_side: Arc::new(RwLock::new(PierSide::Unknown.to_string())),
+            target_ra: Arc::new(RwLock::new(String::new())),
+            target_dec: Arc::new(RwLock::new(String::new())),
+            ra: Arc::new(RwLock::new(String::new())),
+            dec: Arc::new(RwLock::new(String::new())),
+            alt: Arc::new(RwLock::new(String::new())),
+            az: Arc::new(RwLock::new(String::new())),
+            parked: Arc::new(RwLock::new(String::from("false"))),
+            park_position: Arc::new(RwLock::new(String::from("0,0"))),
+            tracking_before_park: None,
+            parking: None,
+            gps_present: false,
+            gps_linked: Arc::new(RwLock::new(String::from("false"))),
+            gps_lat: Arc::new(RwLock::new(String::new())),
+            gps_lon: Arc::new(RwLock::new(String::new())),
+            retry_attempts,
+            min_altitude: Arc::new(RwLock::new(DEFAULT_MIN_ALTITUDE_DEG.to_string())),
+            max_hour_angle: Arc::new(RwLock::new(DEFAULT_MAX_HOUR_ANGLE_HOURS.to_string())),
+            limits_enabled: Arc::new(RwLock::new(String::from("true"))),
+            status: Arc::new(RwLock::new(String::new())),
+            status_tracker: StatusTracker::default(),
+            stats: CommandStats::default(),
+            traffic: TrafficLog::default(),
+            idle_poll_interval: poll_interval_from(
+                std::env::var("LS_POLL_INTERVAL_MS").ok().as_deref(),
+                IDLE_POLL_INTERVAL,
+            ),
+            // Debugging only, production setups don't expose it
+            raw_enabled: std::env::var("LS_ENABLE_RAW")
+                .map(|v| is_truthy(&v))
+                .unwrap_or(false),
+            raw_command: Arc::new(RwLock::new(String::new())),
+            raw_response: Arc::new(RwLock::new(String::new())),
+            position_age: PositionAge::new(
+                std::env::var("LS_STALE_POLLS")
+                    .ok()
+                    .and_then(|v| v.parse::<u32>().ok())
+                    .unwrap_or(DEFAULT_STALE_POLLS),
+            ),
+            position_stale: Arc::new(RwLock::new(String::from("true"))),
+            going_home: None,
+            in_flight_file,
+            in_flight: None,
+            in_flight_watch: None,
+            recovery: None,
+            recovered_from_crash: Arc::new(RwLock::new(String::new())),
+            cautious_mode: Arc::new(RwLock::new(String::from("false"))),
+            last_error: LastError::new(
+                std::env::var("LS_LAST_ERROR_QUIET_SECS")
+                    .ok()
+                    .and_then(|v| v.parse::<u64>().ok())
+                    .map(Duration::from_secs)
+                    .unwrap_or(LAST_ERROR_QUIET_PERIOD),
+            ),
+            last_error_message: Arc::new(RwLock::new(String::new())),
+            connection: Connection::new(
+                std::env::var("LS_OFFLINE_AFTER_FAILURES")
+                    .ok()
+                    .and_then(|v| v.parse::<u32>().ok())
+                    .unwrap_or(DEFAULT_OFFLINE_AFTER),
+            ),
+        };
+
+        if let Err(e) = dev.send_synscan_command(Command::Echo, Some(b"x")) {
+            debug!("Cannot connect to mount after command: {}", e as i32);
+            return None;
+        }
+
+        dev.restore_target_list();
+        dev.init_device();
+        dev.restore_in_flight();
+        dev.fetch_props();
+        Some(dev)
     }
 
-    fn send_command<T>(&mut self, comm: T, val: Option<String>) -> Result<String, DeviceActions>
-    where
-        T: UpperHex,
-    {
-        // First convert the command into an hex STRING
-        let mut hex_command = format!("{:X}", comm);
+    /// What clients can read without locking the device
+    pub fn view(&self) -> DeviceView {
+        self.view.clone()
+    }
 
-        if let Some(value) = val {
-            hex_command += hex::encode(value).as_str();
-        }
-        debug!("Hex command: {:?}", &hex_command);
-        // Cast the hex string to a sequence of bytes
-        let command: Vec<u8> = Vec::from_hex(hex_command).expect("Invalid Hex String");
-        debug!("Sent RAW command: {:?}", &command);
+    pub fn is_online(&self) -> bool {
+        self.connection.is_online()
+    }
 
-        match self.port.write(&command) {
-            Ok(_) => {
-                debug!("Sent command: {}", std::str::from_utf8(&command).unwrap());
-                let mut final_buf: Vec<u8> = Vec::new();
-                debug!("Receiving data");
-
-                loop {
-                    let mut read_buf = [0; 1];
-
-                    match self.port.read(read_buf.as_mut_slice()) {
-                        Ok(_) => {
-                            let byte = read_buf[0];
-                            //debug!("Read byte: {}", byte);
-                            final_buf.push(byte);
-
-                            if byte == 0x23 as u8 {
-                                break;
-                            }
-                        }
-                        Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => {
-                            error!("Timeout");
-                            return Err(DeviceActions::Timeout);
-                        }
-                        Err(e) => error!("Unknown error occurred {:?}", e),
-                    }
-                }
-                debug!("RAW RESPONSE: {:?}", &final_buf);
-                // Use this to check if the response is OK (=) or there is an error (!)
-                let response = String::from_utf8(final_buf).unwrap();
-                debug!("RESPONSE: {}", response);
-                Ok(response)
-            }
-            Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => Err(DeviceActions::Timeout),
-            Err(e) => {
-                error!("{:?}", e);
-                Err(DeviceActions::ComError)
-            }
-        }
+    /// How long the fetch task waits before the next reconnection attempt
+    pub fn reconnect_delay(&self) -> Duration {
+        self.connection.reconnect_delay()
     }
 
-    fn update_property(&mut self, name: &str, value: &str) -> Result<(), DeviceActions> {
-        info!("Synscan updating property {} with {}", name, value);
-        if let Some(prop_idx) = self.find_property_index(name) {
-            let r_prop = self.properties.get(prop_idx).unwrap();
+    /// Ties the id to the USB serial number of the adapter, when it has
+    /// one, rather than to the port path
+    pub fn with_serial_number(mut self, serial_number: Option<&str>) -> Self {
+        self.id = device_id(serial_number, &self.address);
+        self
+    }
 
-            match r_prop.permission {
-                Permission::ReadOnly => Err(DeviceActions::CannotUpdateReadOnlyProperty),
-                _ => self.update_property_remote(name, value),
-            }
-        } else {
-            Err(DeviceActions::UnknownProperty)
-        }
+    /// Tells whether the adapter is the one of this mount while it is
+    /// disconnected, it may come back on another path
+    pub fn waits_for(&self, info: Option<&UsbPortInfo>) -> bool {
+        self.view
+            .waits_for(info.and_then(|i| i.serial_number.as_deref()))
     }
 
-    fn update_property_remote(&mut self, name: &str, value: &str) -> Result<(), DeviceActions> {
-        match name {
-            "TRACKING_MODE" => self.set_tracking_mode(value),
-            _ => Err(DeviceActions::UnknownProperty),
-        }
+    fn export_props(&mut self) {
+        self.exported = ls_props(&self.properties, &self.static_properties);
     }
-    fn find_property_index(&self, name: &str) -> Option<usize> {
-        let mut index = 256;
 
-        for (idx, prop) in self.properties.iter().enumerate() {
-            if prop.name == name {
-                index = idx;
-                break;
-            }
-        }
-        if index == 256 {
-            None
-        } else {
-            Some(index)
-        }
+    /// Lets the drivers see where the mount is without calling it
+    fn publish_address(&self) {
+        let serial = self.name_suffix.trim_start_matches('-');
+        self.view.set_address(&self.address, Some(serial));
     }
-}
 
-pub trait SynScanMount {
-    fn init_device(&mut self);
-    fn echo(&mut self, val: String);
-    fn get_ra_dec_position(&mut self) -> String;
-    fn get_precise_ra_dec_position(&mut self) -> String;
-    fn get_alt_az_position(&mut self) -> String;
-    fn get_precise_alt_az_position(&mut self) -> String;
-    fn goto_ra_dec(&mut self, ra_degrees: f32, dec_degrees: f32);
-    fn goto_precise_ra_dec(&mut self, ra_degrees: f64, dec_degrees: f64);
-    fn goto_alt_az(&mut self, degrees: f32);
-    fn goto_precise_alt_az(&mut self, degrees: f32);
-    fn get_tracking_mode(&mut self);
-    fn set_tracking_mode(&mut self, mode: &str) -> Result<(), DeviceActions>;
-    fn init_props(&mut self);
-    fn get_ls_props(&self) -> Vec<Property>;
-    fn get_version(&mut self) -> String;
-    fn get_model(&mut self) -> String;
-    fn is_aligned(&mut self);
-}
+    /// Reopens the port of a disconnected mount, on the same path or
+    /// wherever the adapter with the same serial number shows up now,
+    /// and sets the mount up again
+    pub fn reconnect(&mut self, source: &PortSource) -> bool {
+        // Wi-Fi adapters have nothing to reopen, they only have to answer.
+        // TCP bridges are connected again like local ports are reopened.
+        if let MountPort::Serial(_) = self.port {
+            let address = source
+                .ports()
+                .into_iter()
+                .find(|(_, info)| self.waits_for(info.as_ref()))
+                .map_or_else(|| self.address.clone(), |(port, _)| port);
 
-impl SynScanMount for MountDevice {
-    fn get_ls_props(&self) -> Vec<Property> {
-        let mut ls_props = Vec::with_capacity(self.properties.len() + self.static_properties.len());
-        for p in &self.properties {
-            info!("Transforming into LS PROP: {}", p.name);
-            ls_props.push(p.to_ls_prop());
-        }
-        for p in &self.static_properties {
-            ls_props.push(p.clone());
+            let timeout = self.port.timeout();
+            let port = match SerialLink::open(&address, self.baud, timeout) {
+                Ok(p) => p,
+                Err(e) => {
+                    debug!("Cannot reopen {}: {}", address, e);
+                    self.connection.reconnect_failed();
+                    return false;
+                }
+            };
+            self.port = MountPort::Serial(port);
+            self.address = address;
+            self.publish_address();
         }
 
-        ls_props
+        if let Err(e) = self.exchange(&build_frame(Command::Echo, Some(b"x"))) {
+            debug!("No answer from {} yet: {:?}", self.address, e);
+            self.connection.reconnect_failed();
+            return false;
+        }
+        info!("Mount back on {}", self.address);
+        self.connection.reconnected();
+        self.init_device();
+        true
     }
-    fn init_device(&mut self) {
-        self.get_ra_dec_position();
-        self.get_precise_ra_dec_position();
-        self.get_alt_az_position();
-        self.get_precise_alt_az_position();
-        self.get_version();
-        self.init_props();
-        // let ra = RightAscension::new(17, 41, 56.35);
-        // let dec = Declination::new(72, 8, 55.86);
-        // let ra_deg = ra_to_deg(&ra);
-        // let dec_deg = dec_to_deg(&dec);
 
-        // info!("RA degrees: {} DEC degrees: {}", ra_deg, dec_deg);
+    /// Picks up the target list left by a previous run, where it was
+    /// in the list included
+    fn restore_target_list(&mut self) {
+        if let Some(list) = load_target_lists(&self.target_list_file).remove(&self.address) {
+            info!("Restored a target list with {} entries", list.len());
+            self.targets = list;
+            self.show_current_target();
+        }
+    }
 
-        // self.goto_precise_ra_dec(ra_deg, dec_deg);
+    fn save_target_list(&self) {
+        if let Err(e) = save_target_list(&self.target_list_file, &self.address, &self.targets) {
+            warn!(
+                "Cannot save the target list to {:?}: {}",
+                self.target_list_file, e
+            );
+        }
     }
 
-    /// Useful for debugging or to check communication
-    /// with the mount
-    fn echo(&mut self, val: String) {
-        let _version = match self.send_command(Command::Echo as i32, Some(val)) {
-            Ok(v) => v,
-            Err(_) => "UNKNONW".to_string(),
+    /// Goes into the cautious mode when the previous run left an
+    /// operation in flight, the mount may still be moving on its own
+    fn restore_in_flight(&mut self) {
+        let path = match &self.in_flight_file {
+            Some(p) => p,
+            None => return,
         };
+        if let Some(operation) = load_in_flight(path).remove(&self.address) {
+            warn!(
+                "Recovered from a crash during the {}, motions are refused until the mount is seen stopped",
+                operation
+            );
+            *self.recovered_from_crash.write().unwrap() = operation.to_string();
+            *self.cautious_mode.write().unwrap() = String::from("true");
+            self.recovery = Some(Recovery::new(operation));
+        }
     }
 
-    fn get_ra_dec_position(&mut self) -> String {
-        match self.send_command(Command::GetRaDec as i32, None) {
-            Ok(p) => p,
-            Err(_) => String::from("UNKNOWN"),
+    fn save_in_flight(&self) {
+        let path = match &self.in_flight_file {
+            Some(p) => p,
+            None => return,
+        };
+        if let Err(e) = save_in_flight(path, &self.address, self.in_flight.as_ref()) {
+            warn!("Cannot save the operation in flight to {:?}: {}", path, e);
         }
     }
 
-    fn get_precise_ra_dec_position(&mut self) -> String {
-        match self.send_command(Command::GetPreciseRaDec as i32, None) {
-            Ok(p) => p,
-            Err(_) => String::from("UNKNOWN"),
+    /// Runs `start` with the operation kept in the in-flight file, for
+    /// a restarted driver to know the mount may still be moving. The
+    /// record goes before the motion does, when the motion doesn't
+    /// start the one it replaced is put back.
+    fn start_in_flight<F>(
+        &mut self,
+        operation: Operation,
+        target: String,
+        start: F,
+    ) -> Result<(), DeviceActions>
+    where
+        F: FnOnce(&mut Self) -> Result<(), DeviceActions>,
+    {
+        self.check_not_recovering(&format!("{} to {}", operation, target))?;
+        let previous = self.in_flight.replace(InFlight {
+            operation,
+            target,
+            started: unix_now_secs() as u64,
+        });
+        self.save_in_flight();
+
+        let result = start(self);
+        match result {
+            // Unwinds are slews, the mount never reports them in progress
+            Ok(_) if operation != Operation::Unwind => {
+                self.in_flight_watch = Some(GotoWatch::new(Instant::now(), PARK_TIMEOUT));
+            }
+            Ok(_) => {}
+            Err(_) => {
+                self.in_flight = previous;
+                self.save_in_flight();
+            }
         }
+        result
     }
 
-    fn get_alt_az_position(&mut self) -> String {
-        match self.send_command(Command::GetAltAz as i32, None) {
-            Ok(p) => p,
-            Err(_) => String::from("UNKNOWN"),
+    /// Forgets the operation in flight, the mount is done with it
+    fn end_in_flight(&mut self) {
+        self.in_flight_watch = None;
+        if self.in_flight.take().is_some() {
+            self.save_in_flight();
         }
     }
 
-    fn get_precise_alt_az_position(&mut self) -> String {
-        match self.send_command(Command::GetPreciseAltAz as i32, None) {
-            Ok(p) => p,
-            Err(_) => String::from("UNKNOWN"),
+    /// Refuses `what` in the cautious mode
+    fn check_not_recovering(&self, what: &str) -> Result<(), DeviceActions> {
+        match &self.recovery {
+            Some(recovery) => {
+                error!(
+                    "{} refused, the mount may still be moving after the {}",
+                    what,
+                    recovery.operation()
+                );
+                Err(DeviceActions::InvalidValue)
+            }
+            None => Ok(()),
         }
     }
 
-    fn goto_ra_dec(&mut self, ra_degrees: f32, dec_degrees: f32) {
-        let dec_revolutions = degrees_to_revolutions(dec_degrees);
-        let ra_revolutions = degrees_to_revolutions(ra_degrees);
-        debug!("DEC rev calculated: {}", dec_revolutions);
-        debug!("RA rev calculated: {}", ra_revolutions);
-
-        let payload = format!(
-            "{},{}",
-            format!("{:04X}", ra_revolutions),
-            format!("{:04X}", dec_revolutions)
-        );
-        debug!("GOTO payload: {}", &payload);
-        self.send_command(Command::GoToRaDec as i32, Some(payload));
-    }
-    fn goto_precise_ra_dec(&mut self, ra_degrees: f64, dec_degrees: f64) {
-        let dec_revolutions = degrees_to_precise_revolutions(dec_degrees);
-        let ra_revolutions = degrees_to_precise_revolutions(ra_degrees);
-        debug!("DEC rev calculated: {}", dec_revolutions);
-        debug!("RA rev calculated: {}", ra_revolutions);
-
-        let payload = format!(
-            "{},{}",
-            format!("{:8X}", ra_revolutions << 8),
-            format!("{:8X}", dec_revolutions << 8),
-        );
-        debug!("precise GOTO payload: {}", &payload);
-        self.send_command(Command::GoToPreciseRaDec as i32, Some(payload));
+    /// RA/DEC and Az/Alt as last polled
+    fn polled_position(&self) -> Option<((f64, f64), (f64, f64))> {
+        let read = |value: &Arc<RwLock<String>>| value.read().unwrap().parse::<f64>().ok();
+        Some((
+            (read(&self.ra)?, read(&self.dec)?),
+            (read(&self.az)?, read(&self.alt)?),
+        ))
     }
 
-    fn goto_alt_az(&mut self, degrees: f32) {}
-    fn goto_precise_alt_az(&mut self, degrees: f32) {}
+    /// Ends the operation in flight once its goto is over, and the
+    /// cautious mode once the polls show the mount stopped. `polled`
+    /// tells whether the position was just read.
+    fn update_in_flight(&mut self, slewing: bool, polled: bool) {
+        let progress = self
+            .in_flight_watch
+            .as_mut()
+            .map(|watch| watch.update(slewing, Instant::now()));
+        if let Some(GotoProgress::Done | GotoProgress::TimedOut) = progress {
+            self.end_in_flight();
+        }
 
-    fn get_tracking_mode(&mut self) {
-        let new_tm = match self.send_command(Command::GetTrackingMode as i32, None) {
-            Ok(t) => match t.as_str() {
-                "\0#" => TRACKING_OFF.to_string(),
-                "\u{1}#" => TRACKING_ALT_AZ.to_string(),
-                "\u{2}#" => TRACKING_EQUATORIAL.to_string(),
-                "\u{3}#" => TRACKING_PEC.to_string(),
-                _ => String::from("UNKNOWN"),
-            },
-            Err(_) => {
-                error!("Couldn't read actual tracking mode of the mount");
-                String::from("-1")
-            }
+        let position = if polled { self.polled_position() } else { None };
+        let stopped = match self.recovery.as_mut() {
+            Some(recovery) => recovery.update(slewing, position),
+            None => return,
         };
+        if stopped {
+            info!("Mount seen stopped, motions allowed again");
+            self.recovery = None;
+            *self.cautious_mode.write().unwrap() = String::from("false");
+            // Nothing started meanwhile, this drops the previous run's
+            self.save_in_flight();
+        }
+    }
 
-        let mut tm = self.track_mode.write().unwrap();
+    fn show_current_target(&self) {
+        let mut current = self.current_target.write().unwrap();
+        current.clear();
+        if let Some(target) = self.targets.current() {
+            current.push_str(&format!("{},{},{}", target.name, target.ra, target.dec));
+        }
+    }
 
-        if tm.to_string() != new_tm {
-            info!("GET => Updating track mode");
-            tm.clear();
-            tm.push_str(&new_tm.to_owned());
-            info!("GET => Updating track mode");
+    /// Keeps LAST_ERROR up to date with the outcome of `what`
+    fn note_result<T>(&mut self, what: &str, result: &Result<T, DeviceActions>) {
+        let now = Instant::now();
+        match result {
+            Ok(_) => self.last_error.clear_if_quiet(now),
+            Err(e) => self.last_error.record(what, e, unix_now_secs() as u64, now),
         }
+        *self.last_error_message.write().unwrap() = self.last_error.message().to_owned();
     }
 
-    fn set_tracking_mode(&mut self, mode: &str) -> Result<(), DeviceActions> {
-        let mode_code = match mode {
-            "Off" => "\0",
-            "AltAz" => "\u{1}",
-            "Equatorial" => "\u{2}",
-            "PEC" => "\u{3}",
-            _ => {
-                error!("Tracking mode: {} not supported", mode);
-                "UNKNOWN"
-            }
-        };
-        warn!("CODE: {:?}", mode_code.as_bytes());
+    /// Tells whether the hand controller firmware has the feature,
+    /// unknown firmwares are assumed not to
+    fn supports(&self, feature: Feature) -> bool {
+        self.firmware_version
+            .is_some_and(|v| feature.supported_by(v))
+    }
 
-        if mode_code == "UNKNOWN" {
-            return Err(DeviceActions::InvalidValue);
+    /// Reads RA/DEC and Alt/Az, with the plain commands on firmwares
+    /// without the precise ones. Tells whether both were read.
+    fn poll_position(&mut self) -> bool {
+        if self.supports(Feature::PreciseCoordinates) {
+            let ra_dec = parse_precise_ra_dec_response(&self.get_precise_ra_dec_position());
+            let alt_az = parse_precise_alt_az_response(&self.get_precise_alt_az_position());
+            ra_dec.is_ok() && alt_az.is_ok()
+        } else {
+            let ra_dec = parse_ra_dec_response(&self.get_ra_dec_position());
+            let alt_az = parse_alt_az_response(&self.get_alt_az_position());
+            ra_dec.is_ok() && alt_az.is_ok()
         }
+    }
 
-        let old_tm = self.track_mode.read().unwrap().to_string().clone();
-
-        if mode != old_tm {
-            info!("SET => Updating track mode");
-            match self.send_command(Command::SetTrackingMode as i32, Some(mode_code.to_string())) {
+    /// Finishes a park once the slew to the park position is over
+    fn update_parking(&mut self, slewing: bool) {
+        let progress = match self.parking.as_mut() {
+            Some(watch) => watch.update(slewing, Instant::now()),
+            None => return,
+        };
+        match progress {
+            GotoProgress::Slewing => return,
+            GotoProgress::Done => match self.set_tracking_mode(&TrackingMode::Off.to_string()) {
                 Ok(_) => {
-                    info!("SET => Updated value track mode");
-                    {
-                        let mut tm = self.track_mode.write().unwrap();
-                        tm.clear();
-                        tm.push_str(&mode.to_owned());
-                    }
-                    return Ok(());
+                    *self.parked.write().unwrap() = String::from("true");
+                    info!("Mount parked");
                 }
                 Err(e) => {
-                    info!("SET => Not updated value track mode, COM error");
-                    return Err(e);
+                    error!("Cannot stop tracking at the park position: {:?}", e);
+                    self.tracking_before_park = None;
                 }
+            },
+            GotoProgress::TimedOut => {
+                error!("The mount didn't reach the park position in time");
+                self.tracking_before_park = None;
             }
         }
-        info!("SET => Not updated track mode, same value");
-        Ok(())
+        self.parking = None;
     }
 
-    fn get_version(&mut self) -> String {
-        let version = match self.send_command(Command::GetVersion as i32, None) {
-            Ok(v) => v,
-            Err(e) => {
-                error!(
-                    "Could not read the version from the hand controller: {:?}",
-                    e
-                );
-                "000000".to_string()
-            }
-        };
-        debug!("raw version: {}", version);
-        let major = u8::from_str_radix(&version[0..2], 16).unwrap();
-        let minor = u8::from_str_radix(&version[2..4], 16).unwrap();
-        let patch = u8::from_str_radix(&version[4..6], 16).unwrap();
-        format!("{}.{}.{}", major, minor, patch)
+    /// Forgets a park whose slew was stopped
+    fn cancel_parking(&mut self) {
+        if self.parking.take().is_some() {
+            warn!("Park interrupted");
+            self.tracking_before_park = None;
+        }
     }
 
-    fn get_model(&mut self) -> String {
-        let raw_model = match self.send_command(Command::GetModel as i32, None) {
-            Ok(m) => {
-                info!("Model: {:?}", &m.as_bytes());
-                m
+    /// Derives STATUS from the latest polled values, logging each change
+    fn update_status(&mut self, slewing: bool) {
+        // Tracking stops once the goto started by goto_home is over, not
+        // before the mount reported it in progress
+        let home = self
+            .going_home
+            .as_mut()
+            .map(|watch| watch.update(slewing, Instant::now()));
+        match home {
+            Some(GotoProgress::Done) => {
+                self.going_home = None;
+                match self.set_tracking_mode(&TrackingMode::Off.to_string()) {
+                    Ok(_) => info!("Mount reached the home position"),
+                    Err(e) => error!("Cannot stop tracking at the home position: {:?}", e),
+                }
             }
-            Err(e) => {
-                error!("Could not read the mount model: {:?}", e);
-                "UNKNOWN".to_string()
+            Some(GotoProgress::TimedOut) => {
+                self.going_home = None;
+                error!("The mount didn't reach the home position in time");
             }
-        };
+            Some(GotoProgress::Slewing) | None => {}
+        }
+        let tracking = [TrackingMode::AltAz, TrackingMode::Eq, TrackingMode::Pec]
+            .iter()
+            .any(|mode| *self.track_mode.read().unwrap() == mode.to_string());
+        let parked = is_truthy(&self.parked.read().unwrap());
 
-        match u8::from_str_radix(&raw_model[0..1], 16).unwrap() {
-            0 => String::from("EQ6"),
-            1 => String::from("HEQ5"),
-            2 => String::from("EQ5"),
-            3 => String::from("EQ3"),
-            4 => String::from("EQ8"),
-            5 => String::from("AZ-EQ6"),
-            6 => String::from("AZ-EQ5"),
-            128..=143 => String::from("AZ"),
-            144..=159 => String::from("DOB"),
-            _ => String::from("AllView"),
+        if let Some(status) = self.status_tracker.update(slewing, tracking, parked) {
+            info!("Mount status changed to {}", status);
         }
+        *self.status.write().unwrap() = self.status_tracker.status().to_string();
     }
 
-    fn is_aligned(&mut self) {
-        let raw_value = match self.send_command(Command::GetAlignment as i32, None) {
-            Ok(m) => {
-                info!("Aligned: {:?}", &m);
-                m
-            }
-            Err(e) => {
-                error!("Could not read the mount alignment: {:?}", e);
-                "UNKNOWN".to_string()
-            }
+    /// Site latitude and longitude, None while either is unknown
+    fn site(&self) -> Option<(f64, f64)> {
+        let lat = self.latitude.read().unwrap().parse::<f64>().ok()?;
+        let lon = self.longitude.read().unwrap().parse::<f64>().ok()?;
+        Some((lat, lon))
+    }
+
+    fn slew_limits(&self) -> SlewLimits {
+        let read = |value: &Arc<RwLock<String>>, default: f64| {
+            value.read().unwrap().parse::<f64>().unwrap_or(default)
         };
+        SlewLimits {
+            enabled: is_truthy(&self.limits_enabled.read().unwrap()),
+            min_altitude_deg: read(&self.min_altitude, DEFAULT_MIN_ALTITUDE_DEG),
+            max_hour_angle_hours: read(&self.max_hour_angle, DEFAULT_MAX_HOUR_ANGLE_HOURS),
+        }
+    }
 
-        let status = match &raw_value.chars().next() {
-            Some(v) => match *v as i32 {
-                1 => "true",
-                _ => "false",
-            },
-            _ => {
-                error!("Cannot read alignment value");
-                "false"
+    /// Refuses gotos to `target` when it would end up below the minimum
+    /// altitude or past the maximum hour angle, `position` gives its
+    /// altitude and hour angle from the site latitude and the local
+    /// sidereal time. Without a site nothing can be checked, gotos are
+    /// refused until it is set or the limits are turned off.
+    fn check_slew_limits<F>(&self, target: &str, position: F) -> Result<(), DeviceActions>
+    where
+        F: FnOnce(f64, f64) -> (f64, f64),
+    {
+        let limits = self.slew_limits();
+        if !limits.enabled {
+            return Ok(());
+        }
+        let (lat, lon) = match self.site() {
+            Some(site) => site,
+            None => {
+                error!(
+                    "GOTO to {} refused, set the site location or turn the slew limits off",
+                    target
+                );
+                return Err(DeviceActions::InvalidValue);
             }
         };
+        let (alt, hour_angle) = position(lat, local_sidereal_time_deg(unix_now_secs(), lon));
 
-        let aligned = self.aligned.read().unwrap().clone();
-
-        if status != aligned.to_string() {
-            {
-                let mut a = self.aligned.write().unwrap();
-                a.clear();
-                a.push_str(&status);
-            }
+        if limits.allows_position(alt, hour_angle) {
+            Ok(())
+        } else {
+            error!(
+                "GOTO to {} refused, target at altitude {:.1} and hour angle {:.2}h is past the limits ({} degrees, {}h)",
+                target, alt, hour_angle, limits.min_altitude_deg, limits.max_hour_angle_hours
+            );
+            Err(DeviceActions::InvalidValue)
         }
     }
 
-    fn init_props(&mut self) {
-        let version = self.get_version();
-        //self.name = self.get_model() + &self.name;
-        self.is_aligned();
-        // Build the version prop, always immutable
-        self.static_properties.push(Property {
-            name: String::from("SYNSCAN_VERSION"),
-            kind: String::from("string"),
-            value: version,
-            permission: Permission::ReadOnly as i32,
-        });
+    /// Azimuth of RA/DEC from the site as of now, None when the site
+    /// location is unknown
+    fn target_azimuth(&self, ra_deg: f64, dec_deg: f64) -> Option<f64> {
+        let (lat, lon) = self.site()?;
+        let lst = local_sidereal_time_deg(unix_now_secs(), lon);
+        Some(ra_dec_to_alt_az(ra_deg, dec_deg, lat, lst).1)
+    }
 
-        self.properties.push(CustomProp {
-            name: String::from("TRACKING_MODE"),
-            kind: String::from("integer"),
-            permission: Permission::ReadWrite,
-            value: self.track_mode.clone(),
-        });
+    /// Follows the model and the tracking mode, the rotation counted
+    /// so far means nothing once the mount is set up the other way
+    fn update_mount_mode(&mut self) {
+        let tracking = self.track_mode.read().unwrap().parse::<TrackingMode>().ok();
+        let alt_az = match is_alt_az(&self.model, tracking) {
+            Some(alt_az) => alt_az,
+            None => return,
+        };
+        if alt_az != self.alt_az {
+            info!(
+                "Mount set up {}",
+                if alt_az { "Alt-Az" } else { "equatorial" }
+            );
+            self.alt_az = alt_az;
+            self.unwinding = None;
+            self.reset_cable_wrap();
+        }
+    }
 
-        self.properties.push(CustomProp {
-            name: String::from("ALIGNED"),
-            kind: String::from("boolean"),
-            permission: Permission::ReadOnly,
-            value: self.aligned.clone(),
-        })
+    /// Refuses gotos to `az` that would wrap the cable further past
+    /// the limit, equatorial mounts have no azimuth to wrap
+    fn check_cable_wrap(&self, az: f64) -> Result<(), DeviceActions> {
+        if !self.alt_az || self.cable_wrap.allows_goto(az) {
+            return Ok(());
+        }
+        error!(
+            "GOTO to AZ: {:.1} refused, the azimuth already rotated {:.1} degrees, unwind the cable first",
+            az,
+            self.cable_wrap.accumulated()
+        );
+        Err(DeviceActions::InvalidValue)
     }
-}
 
-pub fn look_for_devices() -> Vec<(String, UsbPortInfo)> {
-    let ports = available_ports().unwrap();
-    let mut devices = Vec::new();
+    /// Refuses RA/DEC gotos past the slew limits or the cable wrap
+    fn check_ra_dec_goto(&self, ra_deg: f64, dec_deg: f64) -> Result<(), DeviceActions> {
+        self.check_slew_limits(&format!("RA: {} DEC: {}", ra_deg, dec_deg), |lat, lst| {
+            let (alt, _) = ra_dec_to_alt_az(ra_deg, dec_deg, lat, lst);
+            (alt, hour_angle_hours(ra_deg, lst))
+        })?;
+        self.check_ra_dec_cable_wrap(ra_deg, dec_deg)
+    }
 
-    for port in ports {
-        if let SerialPortType::UsbPort(info) = port.port_type {
-            if info.vid == 0x067b && info.pid == 0x2303 {
-                devices.push((port.port_name, info));
-            }
+    fn check_ra_dec_cable_wrap(&self, ra_deg: f64, dec_deg: f64) -> Result<(), DeviceActions> {
+        match self.target_azimuth(ra_deg, dec_deg) {
+            Some(az) => self.check_cable_wrap(az),
+            None => Ok(()),
         }
     }
 
-    match devices.len() {
-        0 => warn!("No Sky-Watcher mount found"),
-        n => info!("Found {} Sky-Watcher mount(s)", n),
+    /// Refuses Alt/Az gotos outside the horizon and the zenith, past
+    /// the cable wrap or, when `limited`, past the slew limits
+    fn check_alt_az_goto(
+        &self,
+        az_deg: f64,
+        alt_deg: f64,
+        limited: bool,
+    ) -> Result<(), DeviceActions> {
+        if !(0.0..=90.0).contains(&alt_deg) {
+            error!("Refusing GOTO to altitude {}", alt_deg);
+            return Err(DeviceActions::InvalidValue);
+        }
+        if limited {
+            self.check_slew_limits(&format!("AZ: {} ALT: {}", az_deg, alt_deg), |lat, lst| {
+                let (ra, _) = alt_az_to_ra_dec(alt_deg, az_deg, lat, lst);
+                (alt_deg, hour_angle_hours(ra, lst))
+            })?;
+        }
+        self.check_cable_wrap(az_deg)
     }
 
-    devices
+    /// RA/DEC goto without the slew limits, precise and left to the
+    /// landing check on firmwares that have the precise commands
+    fn start_ra_dec_goto(&mut self, ra_deg: f64, dec_deg: f64) -> Result<(), DeviceActions> {
+        if !self.supports(Feature::PreciseCoordinates) {
+            let payload = format_goto_payload(ra_deg as f32, dec_deg as f32);
+            debug!("GOTO payload: {}", &payload);
+            return self.expect_ack(Command::GoToRaDec, Some(&payload));
+        }
+        let payload = format_precise_goto_payload(ra_deg, dec_deg);
+        debug!("precise GOTO payload: {}", &payload);
+        self.expect_ack(Command::GoToPreciseRaDec, Some(&payload))?;
+        self.goto_check = Some(GotoCheck::new(ra_deg, dec_deg));
+        Ok(())
+    }
+
+    /// Alt/Az goto without the slew limits, precise on firmwares that
+    /// have the precise commands
+    fn start_alt_az_goto(&mut self, az_deg: f64, alt_deg: f64) -> Result<(), DeviceActions> {
+        if !self.supports(Feature::PreciseCoordinates) {
+            let payload = format_goto_payload(az_deg as f32, alt_deg as f32);
+            debug!("Alt/Az GOTO payload: {}", &payload);
+            return self.expect_ack(Command::GoToAltAz, Some(&payload));
+        }
+        let payload = format_precise_goto_payload(az_deg, alt_deg);
+        debug!("precise Alt/Az GOTO payload: {}", &payload);
+        self.expect_ack(Command::GoToPreciseAltAz, Some(&payload))
+    }
+
+    /// Sends a command the mount only answers with '#' once it took it,
+    /// any other answer is reported as a ComError
+    fn expect_ack(&mut self, cmd: Command, payload: Option<&str>) -> Result<(), DeviceActions> {
+        match self
+            .send_synscan_command(cmd, payload.map(str::as_bytes))?
+            .as_str()
+        {
+            "#" => Ok(()),
+            r => {
+                error!("Unexpected response to {:?}: {:?}", cmd, r);
+                Err(DeviceActions::ComError)
+            }
+        }
+    }
+
+    /// Sends `cmd` with its payload as it is and reads the answer up to
+    /// the '#' terminator, under the command's own timeout
+    fn send_synscan_command(
+        &mut self,
+        cmd: Command,
+        payload: Option<&[u8]>,
+    ) -> Result<String, DeviceActions> {
+        let command = build_frame(cmd, payload);
+
+        // Each command has its own deadline, the port one comes back afterwards
+        let default_timeout = self.port.timeout();
+        if let Some(timeout) = cmd.read_timeout() {
+            if let Err(e) = self.port.set_timeout(timeout) {
+                warn!("Cannot set the {:?} timeout: {:?}", cmd, e);
+            }
+        }
+        let result = self.send_command_attempts(cmd, &command);
+        if let Err(e) = self.port.set_timeout(default_timeout) {
+            warn!("Cannot restore the port timeout: {:?}", e);
+        }
+        self.note_result(&format!("{:?}", cmd), &result);
+        result
+    }
+
+    /// Sends a command frame, retrying on timeout when the command
+    /// can be safely repeated
+    fn send_command_attempts(
+        &mut self,
+        cmd: Command,
+        command: &[u8],
+    ) -> Result<String, DeviceActions> {
+        if !cmd.is_idempotent() {
+            return self.send_raw(command);
+        }
+        let attempts = self.retry_attempts;
+        retry(
+            attempts,
+            RETRY_BACKOFF,
+            |e| matches!(e, DeviceActions::Timeout),
+            |attempt| {
+                if attempt > 1 {
+                    debug!("{:?} attempt {}/{}", cmd, attempt, attempts);
+                }
+                self.send_raw(command)
+            },
+        )
+    }
+
+    /// Writes the given bytes as they are and reads the answer up to
+    /// the '#' terminator, used directly for binary passthrough frames
+    fn send_raw(&mut self, command: &[u8]) -> Result<String, DeviceActions> {
+        let final_buf = self.send_raw_bytes(command)?;
+        if let Some(code) = response_error(&final_buf) {
+            error!(
+                "The hand controller rejected the command, error code {:?}",
+                code
+            );
+            return Err(DeviceActions::InvalidValue);
+        }
+        // Garbage on the line must not take the fetch task down
+        let response = response_to_string(final_buf).map_err(|e| {
+            error!("Corrupted response: {:?}", e);
+            DeviceActions::ComError
+        })?;
+        debug!("RESPONSE: {}", response);
+        Ok(response)
+    }
+
+    /// Same as `send_raw` without interpreting the answer in any way
+    fn send_raw_bytes(&mut self, command: &[u8]) -> Result<Vec<u8>, DeviceActions> {
+        let result = self.exchange(command);
+        if self.connection.record(result.is_ok()) {
+            error!("{} stopped answering, marked as disconnected", self.address);
+        }
+        result
+    }
+
+    /// Writes the command and reads the answer up to the '#' terminator
+    fn exchange(&mut self, command: &[u8]) -> Result<Vec<u8>, DeviceActions> {
+        debug!("Sent RAW command: {:?}", command);
+
+        debug!("Sending command: {}", String::from_utf8_lossy(command));
+        let start = Instant::now();
+        let answer = self.port.exchange(command, b'#');
+        let elapsed = start.elapsed();
+        self.stats.record(Outcome::from(&answer), elapsed);
+        self.traffic.record(
+            command.first().and_then(|c| Command::try_from(*c).ok()),
+            command,
+            answer.as_deref(),
+            elapsed,
+        );
+        let final_buf = answer.map_err(|e| {
+            error!("No valid response: {:?}", e);
+            e.device_action()
+        })?;
+        debug!("RAW RESPONSE: {:?}", &final_buf);
+        Ok(final_buf)
+    }
+
+    /// Sends a client supplied hex encoded command as it is, the
+    /// command is kept in RAW_COMMAND and the answer in RAW_RESPONSE,
+    /// both as hex dumps
+    fn send_raw_command(&mut self, hex_command: &str) -> Result<(), DeviceActions> {
+        let command = parse_hex_command(hex_command).map_err(|e| {
+            error!("Invalid RAW command {:?}: {:?}", hex_command, e);
+            DeviceActions::InvalidValue
+        })?;
+        warn!("Sending RAW command {}", hex_dump(&command));
+        *self.raw_command.write().unwrap() = hex_dump(&command);
+        // No stale answer next to the new command
+        self.raw_response.write().unwrap().clear();
+        let response = self.send_raw_bytes(&command)?;
+        *self.raw_response.write().unwrap() = hex_dump(&response);
+        Ok(())
+    }
+
+    /// Writes the given bytes and reads back exactly `len` bytes, for
+    /// binary answers where '#' can show up before the terminator
+    fn send_fixed(&mut self, command: &[u8], len: usize) -> Result<Vec<u8>, DeviceActions> {
+        let result = self.exchange_fixed(command, len);
+        let what = match command.first().map(|c| Command::try_from(*c)) {
+            Some(Ok(cmd)) => format!("{:?}", cmd),
+            _ => hex_dump(command),
+        };
+        self.note_result(&what, &result);
+        result
+    }
+
+    fn exchange_fixed(&mut self, command: &[u8], len: usize) -> Result<Vec<u8>, DeviceActions> {
+        debug!("Sent RAW command: {:?}", command);
+
+        let start = Instant::now();
+        if let Err(e) = self.port.write(command) {
+            error!("{:?}", e);
+            return match e.kind() {
+                std::io::ErrorKind::TimedOut => {
+                    self.stats.record(Outcome::TimedOut, start.elapsed());
+                    Err(DeviceActions::Timeout)
+                }
+                _ => {
+                    self.stats.record(Outcome::Failed, start.elapsed());
+                    Err(DeviceActions::ComError)
+                }
+            };
+        }
+
+        let mut response = vec![0; len];
+        let read = self.port.read_exact(&mut response);
+        let elapsed = start.elapsed();
+        self.traffic.record(
+            command.first().and_then(|c| Command::try_from(*c).ok()),
+            command,
+            read.as_ref().map(|_| response.as_slice()),
+            elapsed,
+        );
+        match read {
+            Ok(_) => {
+                self.stats.record(Outcome::Answered, elapsed);
+                debug!("RAW RESPONSE: {:?}", &response);
+                Ok(response)
+            }
+            Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => {
+                self.stats.record(Outcome::TimedOut, elapsed);
+                error!("Timeout");
+                Err(DeviceActions::Timeout)
+            }
+            Err(e) => {
+                self.stats.record(Outcome::Failed, elapsed);
+                error!("{:?}", e);
+                Err(DeviceActions::ComError)
+            }
+        }
+    }
+}
+
+impl MountDevice {
+    /// Sends a passthrough frame and returns the `response_len` bytes
+    /// the device answered with, without the trailing '#'
+    fn send_passthrough(
+        &mut self,
+        frame: &[u8],
+        response_len: usize,
+    ) -> Result<Vec<u8>, DeviceActions> {
+        let result = self.exchange_passthrough(frame, response_len);
+        self.note_result(&format!("passthrough {}", hex_dump(frame)), &result);
+        result
+    }
+
+    fn exchange_passthrough(
+        &mut self,
+        frame: &[u8],
+        response_len: usize,
+    ) -> Result<Vec<u8>, DeviceActions> {
+        let mut resp = self.exchange_fixed(frame, response_len + 1)?;
+
+        if resp.pop() != Some(b'#') {
+            error!("Unterminated passthrough response {:?}", resp);
+            self.stats.record_invalid_answer();
+            return Err(DeviceActions::ComError);
+        }
+        Ok(resp)
+    }
+
+    /// Looks for the GPS accessory, mounts without one are not in error
+    /// so LAST_ERROR is left alone
+    fn probe_gps(&mut self) -> bool {
+        self.exchange_passthrough(&passthrough_frame(GPS_DEVICE, 55, &[], 1), 1)
+            .is_ok()
+    }
+
+    /// Refreshes the GPS properties, only when the module was found
+    fn update_gps(&mut self) {
+        if !self.gps_present {
+            return;
+        }
+
+        let linked = self.is_gps_linked().unwrap_or(false);
+        *self.gps_linked.write().unwrap() = linked.to_string();
+        if !linked {
+            return;
+        }
+
+        if let Ok(lat) = self.gps_latitude() {
+            *self.gps_lat.write().unwrap() = format!("{:.6}", lat);
+        }
+        if let Ok(lon) = self.gps_longitude() {
+            *self.gps_lon.write().unwrap() = format!("{:.6}", lon);
+        }
+    }
+}
+
+impl AstroSerialDevice for MountDevice {
+    fn new(name: &str, address: &str, baud: u32, timeout_ms: u64) -> Option<Self> {
+        Self::with_retries(name, address, baud, timeout_ms, DEFAULT_RETRY_ATTEMPTS)
+    }
+
+    fn fetch_props(&mut self) {
+        info!("Fetching actual state");
+        self.get_tracking_mode();
+        let slewing = self.is_goto_in_progress();
+        *self.goto_in_progress.write().unwrap() = slewing.to_string();
+        if let Some(check) = self.goto_check.as_mut() {
+            check.slewing(slewing);
+        }
+        self.update_parking(slewing);
+        let polled = self.poll_position();
+        self.update_in_flight(slewing, polled);
+        let stale = self
+            .position_age
+            .is_stale(Instant::now(), self.poll_interval());
+        *self.position_stale.write().unwrap() = stale.to_string();
+        // Alignment rarely changes, no need to ask every time
+        self.fetch_cycles = self.fetch_cycles.wrapping_add(1);
+        if self.fetch_cycles % ALIGNMENT_REFRESH_CYCLES == 0 {
+            self.is_aligned();
+        }
+        let pier_side = self.get_pier_side();
+        *self.pier_side.write().unwrap() = pier_side.to_string();
+        self.update_gps();
+        self.update_status(slewing);
+        self.stats.publish();
+        self.export_props();
+    }
+
+    fn get_id(&self) -> Uuid {
+        self.id
+    }
+
+    fn get_address(&self) -> &String {
+        &self.address
+    }
+
+    fn get_name(&self) -> &String {
+        &self.name
+    }
+
+    fn get_properties(&self) -> &Vec<Property> {
+        &self.exported
+    }
+
+    fn send_command<T>(&mut self, comm: T, val: Option<String>) -> Result<String, DeviceActions>
+    where
+        T: UpperHex,
+    {
+        // First convert the command into a known SynScan command
+        let cmd = match u8::from_str_radix(&format!("{:X}", comm), 16)
+            .ok()
+            .and_then(|c| Command::try_from(c).ok())
+        {
+            Some(c) => c,
+            None => {
+                error!("Unknown SynScan command {:X}", comm);
+                return Err(DeviceActions::InvalidValue);
+            }
+        };
+        self.send_synscan_command(cmd, val.as_ref().map(String::as_bytes))
+    }
+
+    fn update_property(&mut self, name: &str, value: &str) -> Result<(), DeviceActions> {
+        info!("Synscan updating property {} with {}", name, value);
+        // Nothing to wait for, the port is gone
+        if !self.connection.is_online() {
+            return Err(DeviceActions::ComError);
+        }
+        let result = check_writable(&self.properties, name)
+            .and_then(|_| self.update_property_remote(name, value));
+        self.note_result(name, &result);
+        self.export_props();
+        result
+    }
+
+    fn update_property_remote(&mut self, name: &str, value: &str) -> Result<(), DeviceActions> {
+        match name {
+            "TRACKING_MODE" => self.set_tracking_mode(value),
+            "TARGET_LIST" => self.load_target_list(value),
+            "NEXT_TARGET" if is_truthy(value) => self.goto_target(self.targets.next_index()),
+            "PREV_TARGET" if is_truthy(value) => self.goto_target(self.targets.prev_index()),
+            "NEXT_TARGET" | "PREV_TARGET" => Ok(()),
+            "GOTO_TARGET_INDEX" => match value.parse::<usize>() {
+                Ok(idx) => self.goto_target(Some(idx)),
+                Err(_) => Err(DeviceActions::InvalidValue),
+            },
+            "SYNC_COORDINATES" => match parse_ra_dec_pair(value) {
+                Ok((ra, dec)) => self.sync_ra_dec(ra, dec),
+                Err(_) => Err(DeviceActions::InvalidValue),
+            },
+            "SITE_LATITUDE" | "SITE_LONGITUDE" => {
+                let deg = value
+                    .parse::<f64>()
+                    .map_err(|_| DeviceActions::InvalidValue)?;
+                let (lat, lon) = self.get_location()?;
+                if name == "SITE_LATITUDE" {
+                    self.set_location(deg, lon)
+                } else {
+                    self.set_location(lat, deg)
+                }
+            }
+            "UTC_OFFSET" => match value.parse::<i8>() {
+                Ok(offset) if (-12..=14).contains(&offset) => {
+                    *self.utc_offset.write().unwrap() = offset.to_string();
+                    Ok(())
+                }
+                _ => Err(DeviceActions::InvalidValue),
+            },
+            "DST" => {
+                *self.dst.write().unwrap() = is_truthy(value).to_string();
+                Ok(())
+            }
+            "SYNC_TIME_NOW" if is_truthy(value) => self.sync_time_now(),
+            "SYNC_TIME_NOW" => Ok(()),
+            "MIN_ALTITUDE" => match value.trim().parse::<f64>() {
+                Ok(deg) if (-90.0..=90.0).contains(&deg) => {
+                    *self.min_altitude.write().unwrap() = deg.to_string();
+                    Ok(())
+                }
+                _ => Err(DeviceActions::InvalidValue),
+            },
+            "MAX_HOUR_ANGLE" => match value.trim().parse::<f64>() {
+                Ok(hours) if (0.0..=12.0).contains(&hours) => {
+                    *self.max_hour_angle.write().unwrap() = hours.to_string();
+                    Ok(())
+                }
+                _ => Err(DeviceActions::InvalidValue),
+            },
+            "ENABLE_LIMITS" => {
+                *self.limits_enabled.write().unwrap() = is_truthy(value).to_string();
+                Ok(())
+            }
+            "GUIDE_RATE_RA" | "GUIDE_RATE_DEC" => {
+                let axis = if name == "GUIDE_RATE_RA" {
+                    Axis::Ra
+                } else {
+                    Axis::Dec
+                };
+                match value.parse::<u8>() {
+                    Ok(percent) => self.set_guide_rate(axis, percent),
+                    Err(_) => Err(DeviceActions::InvalidValue),
+                }
+            }
+            "PARK" if is_truthy(value) => self.park(),
+            "UNPARK" if is_truthy(value) => self.unpark(),
+            "PARK" | "UNPARK" => Ok(()),
+            "GO_HOME" if is_truthy(value) => self.goto_home(),
+            "GO_HOME" => Ok(()),
+            "UNWIND_CABLE" if is_truthy(value) => self.unwind_cable(),
+            "UNWIND_CABLE" => Ok(()),
+            "RESET_CABLE_WRAP" if is_truthy(value) => {
+                self.reset_cable_wrap();
+                Ok(())
+            }
+            "RESET_CABLE_WRAP" => Ok(()),
+            "PARK_POSITION" => match parse_coordinate_pair(value) {
+                Ok((_, alt)) if (0.0..=90.0).contains(&alt) => {
+                    *self.park_position.write().unwrap() = value.trim().to_owned();
+                    Ok(())
+                }
+                _ => Err(DeviceActions::InvalidValue),
+            },
+            "TARGET_RA" | "TARGET_DEC" => {
+                if value.trim().parse::<f64>().is_err() {
+                    return Err(DeviceActions::InvalidValue);
+                }
+                let prop = if name == "TARGET_RA" {
+                    &self.target_ra
+                } else {
+                    &self.target_dec
+                };
+                *prop.write().unwrap() = value.trim().to_owned();
+                Ok(())
+            }
+            "DO_GOTO" if is_truthy(value) => {
+                let ra = self.target_ra.read().unwrap().clone();
+                let dec = self.target_dec.read().unwrap().clone();
+                match parse_goto_target(&ra, &dec) {
+                    Ok((ra, dec)) => {
+                        info!("GOTO to RA: {} DEC: {}", ra, dec);
+                        self.goto_precise_ra_dec(ra, dec)
+                    }
+                    Err(e) => {
+                        error!("Invalid GOTO target RA: {:?} DEC: {:?}: {:?}", ra, dec, e);
+                        Err(DeviceActions::InvalidValue)
+                    }
+                }
+            }
+            "DO_GOTO" => Ok(()),
+            "SLEW_RA" | "SLEW_DEC" => {
+                let axis = if name == "SLEW_RA" {
+                    Axis::Ra
+                } else {
+                    Axis::Dec
+                };
+                match parse_slew_value(value) {
+                    Ok((_, 0)) => self.stop_axis(axis),
+                    Ok((direction, rate)) => self.slew_axis(axis, direction, rate),
+                    Err(_) => Err(DeviceActions::InvalidValue),
+                }
+            }
+            "ABORT_MOTION" if is_truthy(value) => self.cancel_goto(),
+            "ABORT_MOTION" => Ok(()),
+            // Clients go through emergency_stop(), this is for batches
+            "EMERGENCY_STOP" if is_truthy(value) => self.emergency_stop(),
+            "EMERGENCY_STOP" => Ok(()),
+            "RAW_COMMAND" if self.raw_enabled => self.send_raw_command(value),
+            "DEBUG_TRAFFIC" => {
+                self.traffic.set_enabled(is_truthy(value));
+                Ok(())
+            }
+            "CLEAR_TARGET_LIST" if is_truthy(value) => {
+                self.targets.clear();
+                self.current_target.write().unwrap().clear();
+                self.save_target_list();
+                Ok(())
+            }
+            "CLEAR_TARGET_LIST" => Ok(()),
+            _ => Err(DeviceActions::UnknownProperty),
+        }
+    }
+    fn find_property_index(&self, name: &str) -> Option<usize> {
+        self.properties.iter().position(|p| p.name == name)
+    }
+}
+
+pub trait SynScanMount {
+    fn init_device(&mut self);
+    fn echo(&mut self, val: String);
+    fn get_ra_dec_position(&mut self) -> String;
+    fn get_precise_ra_dec_position(&mut self) -> String;
+    fn get_alt_az_position(&mut self) -> String;
+    fn get_precise_alt_az_position(&mut self) -> String;
+    fn goto_ra_dec(&mut self, ra_degrees: f32, dec_degrees: f32) -> Result<(), DeviceActions>;
+    fn goto_precise_ra_dec(
+        &mut self,
+        ra_degrees: f64,
+        dec_degrees: f64,
+    ) -> Result<(), DeviceActions>;
+    fn goto_alt_az(&mut self, azimuth_deg: f32, altitude_deg: f32) -> Result<(), DeviceActions>;
+    fn goto_precise_alt_az(
+        &mut self,
+        azimuth_deg: f64,
+        altitude_deg: f64,
+    ) -> Result<(), DeviceActions>;
+    fn get_tracking_mode(&mut self);
+    fn set_tracking_mode(&mut self, mode: &str) -> Result<(), DeviceActions>;
+    fn init_props(&mut self);
+    fn get_version(&mut self) -> Result<String, DeviceActions>;
+    fn get_model(&mut self) -> String;
+    fn is_aligned(&mut self);
+    fn load_target_list(&mut self, json: &str) -> Result<(), DeviceActions>;
+    fn update_cable_wrap(&mut self, az: f64);
+    fn unwind_cable(&mut self) -> Result<(), DeviceActions>;
+    fn reset_cable_wrap(&mut self);
+    fn verify_goto(&mut self, ra: f64, dec: f64);
+    fn goto_target(&mut self, index: Option<usize>) -> Result<(), DeviceActions>;
+    fn cancel_goto(&mut self) -> Result<(), DeviceActions>;
+    fn emergency_stop(&mut self) -> Result<(), DeviceActions>;
+    fn is_goto_in_progress(&mut self) -> bool;
+    fn poll_interval(&self) -> Duration;
+    fn sync_ra_dec(&mut self, ra_deg: f64, dec_deg: f64) -> Result<(), DeviceActions>;
+    fn slew_axis(
+        &mut self,
+        axis: Axis,
+        direction: Direction,
+        rate: u8,
+    ) -> Result<(), DeviceActions>;
+    fn stop_axis(&mut self, axis: Axis) -> Result<(), DeviceActions>;
+    fn get_location(&mut self) -> Result<(f64, f64), DeviceActions>;
+    fn set_location(&mut self, lat_deg: f64, lon_deg: f64) -> Result<(), DeviceActions>;
+    fn get_time(&mut self) -> Result<MountTime, DeviceActions>;
+    fn set_time(&mut self, time: MountTime) -> Result<(), DeviceActions>;
+    fn sync_time_now(&mut self) -> Result<(), DeviceActions>;
+    fn get_guide_rate(&mut self, axis: Axis) -> Result<u8, DeviceActions>;
+    fn get_motor_versions(&mut self) -> (Option<String>, Option<String>);
+    fn get_pier_side(&mut self) -> PierSide;
+    fn park(&mut self) -> Result<(), DeviceActions>;
+    fn goto_home(&mut self) -> Result<(), DeviceActions>;
+    fn unpark(&mut self) -> Result<(), DeviceActions>;
+    fn set_guide_rate(&mut self, axis: Axis, percent: u8) -> Result<(), DeviceActions>;
+}
+
+impl SynScanMount for MountDevice {
+    fn init_device(&mut self) {
+        // The firmware decides which commands can be used afterwards
+        match self.get_version() {
+            Ok(v) => {
+                self.firmware_version = parse_firmware_version(&v).ok();
+                self.firmware = v;
+            }
+            Err(e) => error!(
+                "Could not read the version from the hand controller: {:?}",
+                e
+            ),
+        }
+        if !self.supports(Feature::PreciseCoordinates) {
+            match self.firmware_version {
+                Some(_) => warn!(
+                    "Firmware {} has no precise coordinates, using the plain ones",
+                    self.firmware
+                ),
+                None => warn!("Firmware version unknown, falling back to the plain coordinates"),
+            }
+        }
+        self.poll_position();
+        // The name only carries the serial number so far
+        self.model = self.get_model();
+        self.name = format!("{}{}", self.model, self.name_suffix);
+        self.update_mount_mode();
+        if let Err(e) = self.get_location() {
+            warn!("Cannot read the observer location: {:?}", e);
+        }
+        match self.get_time() {
+            Ok(t) => {
+                *self.utc_offset.write().unwrap() = t.gmt_offset.to_string();
+                *self.dst.write().unwrap() = t.dst.to_string();
+            }
+            Err(e) => warn!("Cannot read the mount time: {:?}", e),
+        }
+        for axis in [Axis::Ra, Axis::Dec] {
+            if let Err(e) = self.get_guide_rate(axis) {
+                warn!("Cannot read the {:?} guide rate: {:?}", axis, e);
+            }
+        }
+        // Mounts without the GPS accessory only cost one timeout here,
+        // the module is not queried anymore afterwards
+        self.gps_present = self.probe_gps();
+        if self.gps_present {
+            info!("GPS module found");
+            if let Ok(t) = self.gps_time() {
+                info!("GPS time: {}", t);
+            }
+        }
+        self.update_gps();
+        self.init_props();
+        self.view
+            .publish(&self.name, &self.properties, &self.static_properties);
+        self.export_props();
+        self.publish_address();
+    }
+
+    /// Useful for debugging or to check communication
+    /// with the mount
+    fn echo(&mut self, val: String) {
+        let _version = match self.send_synscan_command(Command::Echo, Some(val.as_bytes())) {
+            Ok(v) => v,
+            Err(_) => "UNKNONW".to_string(),
+        };
+    }
+
+    fn get_ra_dec_position(&mut self) -> String {
+        match self.send_synscan_command(Command::GetRaDec, None) {
+            Ok(p) => {
+                match parse_ra_dec_response(&p) {
+                    Ok((ra, dec)) => {
+                        let (ra, dec) = (ra as f64, dec as f64);
+                        info!("RA: {} DEC: {}", ra, dec);
+                        *self.ra.write().unwrap() = format!("{:.6}", ra);
+                        *self.dec.write().unwrap() = format!("{:.6}", signed_degrees(dec));
+                        self.dec_axis = Some(dec);
+                        self.position_age.record_success(Instant::now());
+                        self.verify_goto(ra, dec);
+                    }
+                    Err(e) => warn!("Cannot parse RA/DEC response {:?}: {:?}", p, e),
+                }
+                p
+            }
+            Err(_) => String::from("UNKNOWN"),
+        }
+    }
+
+    fn get_precise_ra_dec_position(&mut self) -> String {
+        match self.send_synscan_command(Command::GetPreciseRaDec, None) {
+            Ok(p) => {
+                match parse_precise_ra_dec_response(&p) {
+                    Ok((ra, dec)) => {
+                        info!("RA: {} DEC: {}", ra, dec);
+                        *self.ra.write().unwrap() = format!("{:.6}", ra);
+                        *self.dec.write().unwrap() = format!("{:.6}", signed_degrees(dec));
+                        self.dec_axis = Some(dec);
+                        self.position_age.record_success(Instant::now());
+                        self.verify_goto(ra, dec);
+                    }
+                    Err(e) => warn!("Cannot parse precise RA/DEC response {:?}: {:?}", p, e),
+                }
+                p
+            }
+            Err(_) => String::from("UNKNOWN"),
+        }
+    }
+
+    fn get_alt_az_position(&mut self) -> String {
+        match self.send_synscan_command(Command::GetAltAz, None) {
+            Ok(p) => {
+                match parse_alt_az_response(&p) {
+                    Ok((az, alt)) => {
+                        info!("AZ: {} ALT: {}", az, alt);
+                        *self.az.write().unwrap() = format!("{:.6}", az);
+                        *self.alt.write().unwrap() = format!("{:.6}", alt);
+                        self.update_cable_wrap(az);
+                    }
+                    Err(e) => warn!("Cannot parse Alt/Az response {:?}: {:?}", p, e),
+                }
+                p
+            }
+            Err(_) => String::from("UNKNOWN"),
+        }
+    }
+
+    fn get_precise_alt_az_position(&mut self) -> String {
+        match self.send_synscan_command(Command::GetPreciseAltAz, None) {
+            Ok(p) => {
+                match parse_precise_alt_az_response(&p) {
+                    Ok((az, alt)) => {
+                        info!("AZ: {} ALT: {}", az, alt);
+                        *self.az.write().unwrap() = format!("{:.6}", az);
+                        *self.alt.write().unwrap() = format!("{:.6}", alt);
+                        self.update_cable_wrap(az);
+                    }
+                    Err(e) => warn!("Cannot parse precise Alt/Az response {:?}: {:?}", p, e),
+                }
+                p
+            }
+            Err(_) => String::from("UNKNOWN"),
+        }
+    }
+
+    fn goto_ra_dec(&mut self, ra_degrees: f32, dec_degrees: f32) -> Result<(), DeviceActions> {
+        self.check_ra_dec_goto(ra_degrees as f64, dec_degrees as f64)?;
+        let payload = format_goto_payload(ra_degrees, dec_degrees);
+        debug!("GOTO payload: {}", &payload);
+        let target = format!("RA: {} DEC: {}", ra_degrees, dec_degrees);
+        self.start_in_flight(Operation::Goto, target, |dev| {
+            dev.expect_ack(Command::GoToRaDec, Some(&payload))
+        })
+    }
+
+    fn goto_precise_ra_dec(
+        &mut self,
+        ra_degrees: f64,
+        dec_degrees: f64,
+    ) -> Result<(), DeviceActions> {
+        self.check_ra_dec_goto(ra_degrees, dec_degrees)?;
+        let target = format!("RA: {} DEC: {}", ra_degrees, dec_degrees);
+        self.start_in_flight(Operation::Goto, target, |dev| {
+            dev.start_ra_dec_goto(ra_degrees, dec_degrees)
+        })
+    }
+
+    /// Slews to the given azimuth and altitude, targets below the
+    /// horizon, past the zenith or the slew limits are refused
+    fn goto_alt_az(&mut self, azimuth_deg: f32, altitude_deg: f32) -> Result<(), DeviceActions> {
+        self.check_alt_az_goto(azimuth_deg as f64, altitude_deg as f64, true)?;
+        let payload = format_goto_payload(azimuth_deg, altitude_deg);
+        debug!("Alt/Az GOTO payload: {}", &payload);
+        let target = format!("AZ: {} ALT: {}", azimuth_deg, altitude_deg);
+        self.start_in_flight(Operation::Goto, target, |dev| {
+            dev.expect_ack(Command::GoToAltAz, Some(&payload))
+        })
+    }
+
+    fn goto_precise_alt_az(
+        &mut self,
+        azimuth_deg: f64,
+        altitude_deg: f64,
+    ) -> Result<(), DeviceActions> {
+        self.check_alt_az_goto(azimuth_deg, altitude_deg, true)?;
+        let target = format!("AZ: {} ALT: {}", azimuth_deg, altitude_deg);
+        self.start_in_flight(Operation::Goto, target, |dev| {
+            dev.start_alt_az_goto(azimuth_deg, altitude_deg)
+        })
+    }
+
+    fn get_tracking_mode(&mut self) {
+        let new_tm = match self.send_synscan_command(Command::GetTrackingMode, None) {
+            Ok(t) => match parse_tracking_mode_response(&t) {
+                Ok(mode) => mode.to_string(),
+                Err(_) => String::from("UNKNOWN"),
+            },
+            Err(_) => {
+                error!("Couldn't read actual tracking mode of the mount");
+                String::from("-1")
+            }
+        };
+
+        let mut tm = self.track_mode.write().unwrap();
+
+        if tm.to_string() != new_tm {
+            info!("GET => Updating track mode");
+            tm.clear();
+            tm.push_str(&new_tm.to_owned());
+            info!("GET => Updating track mode");
+        }
+        drop(tm);
+        self.update_mount_mode();
+    }
+
+    fn set_tracking_mode(&mut self, mode: &str) -> Result<(), DeviceActions> {
+        let requested = match mode.parse::<TrackingMode>() {
+            Ok(m) => m,
+            Err(_) => {
+                error!("Tracking mode: {} not supported", mode);
+                return Err(DeviceActions::InvalidValue);
+            }
+        };
+        let mode_code = requested.to_command_byte();
+        debug!("CODE: {:?}", mode_code);
+
+        let current = self.track_mode.read().unwrap().parse::<TrackingMode>().ok();
+
+        if current != Some(requested) {
+            info!("SET => Updating track mode");
+            match self.send_synscan_command(Command::SetTrackingMode, Some(&[mode_code])) {
+                Ok(_) => {
+                    // Some firmwares acknowledge modes they then ignore
+                    let actual = self
+                        .send_synscan_command(Command::GetTrackingMode, None)
+                        .ok()
+                        .and_then(|r| parse_tracking_mode_response(&r).ok());
+                    let (stored, result) = match actual {
+                        Some(actual) if actual != requested => {
+                            error!(
+                                "SET => Mount ignored track mode {}, still {}",
+                                requested, actual
+                            );
+                            (actual, Err(DeviceActions::InvalidValue))
+                        }
+                        Some(_) => {
+                            info!("SET => Updated value track mode");
+                            (requested, Ok(()))
+                        }
+                        None => {
+                            warn!("SET => Cannot read back the track mode");
+                            (requested, Ok(()))
+                        }
+                    };
+                    *self.track_mode.write().unwrap() = stored.to_string();
+                    self.update_mount_mode();
+                    return result;
+                }
+                Err(e) => {
+                    info!("SET => Not updated value track mode, COM error");
+                    return Err(e);
+                }
+            }
+        }
+        info!("SET => Not updated track mode, same value");
+        Ok(())
+    }
+
+    fn get_version(&mut self) -> Result<String, DeviceActions> {
+        let version = self.send_synscan_command(Command::GetVersion, None)?;
+        debug!("raw version: {}", version);
+
+        parse_version_response(&version).map_err(|e| {
+            error!("Cannot parse version response {:?}: {:?}", version, e);
+            DeviceActions::InvalidValue
+        })
+    }
+
+    fn get_model(&mut self) -> String {
+        // The model is a raw byte, possibly above 0x7F, read it as is
+        let model = match self.send_fixed(&[Command::GetModel.as_byte()], 2) {
+            Ok(m) => {
+                info!("Model: {:?}", &m);
+                parse_model(&m)
+            }
+            Err(e) => {
+                error!("Could not read the mount model: {:?}", e);
+                None
+            }
+        };
+
+        model.unwrap_or(UNKNOWN_MODEL).to_string()
+    }
+
+    fn is_aligned(&mut self) {
+        let raw_value = match self.send_synscan_command(Command::GetAlignment, None) {
+            Ok(m) => {
+                info!("Aligned: {:?}", &m);
+                m
+            }
+            Err(e) => {
+                error!("Could not read the mount alignment: {:?}", e);
+                "UNKNOWN".to_string()
+            }
+        };
+
+        let status = match parse_alignment(&raw_value) {
+            Ok(true) => "true",
+            Ok(false) => "false",
+            Err(e) => {
+                error!("Cannot read alignment value {:?}: {:?}", raw_value, e);
+                "false"
+            }
+        };
+
+        let aligned = self.aligned.read().unwrap().clone();
+
+        if status != aligned.to_string() {
+            {
+                let mut a = self.aligned.write().unwrap();
+                a.clear();
+                a.push_str(&status);
+            }
+        }
+    }
+
+    /// Integrates the azimuth reading into the cable wrap tracker
+    /// and warns when the accumulated rotation is past the limit
+    fn update_cable_wrap(&mut self, az: f64) {
+        if !self.alt_az {
+            return;
+        }
+        let accumulated = self.cable_wrap.update(az);
+
+        if let Some(start) = self.unwinding {
+            if self.cable_wrap.unwound_from(start) {
+                self.unwinding = None;
+                self.end_in_flight();
+                match self.stop_axis(Axis::Ra) {
+                    Ok(_) => info!("Cable unwound, azimuth rotated {:.1} degrees", accumulated),
+                    Err(e) => error!("Cannot stop the azimuth axis after the unwind: {:?}", e),
+                }
+            }
+        } else if self.cable_wrap.exceeded() {
+            warn!(
+                "Azimuth rotated {:.1} degrees, the cable may be wrapping, unwind by {:.1} degrees",
+                accumulated,
+                self.cable_wrap.unwind_delta()
+            );
+        }
+
+        let mut wrap = self.az_cable_wrap.write().unwrap();
+        wrap.clear();
+        wrap.push_str(&format!("{:.1}", accumulated));
+    }
+
+    /// Slews the azimuth axis back the way the cable was wrapped, which
+    /// may be the long way round, until the polls see it unwound. The
+    /// azimuth is the RA axis of Alt-Az mounts, positive slews turn it
+    /// clockwise.
+    fn unwind_cable(&mut self) -> Result<(), DeviceActions> {
+        if !self.alt_az {
+            error!("Not an Alt-Az mount, there is no cable wrap to unwind");
+            return Err(DeviceActions::InvalidValue);
+        }
+        let start = self.cable_wrap.accumulated();
+        if self.cable_wrap.unwound_from(start) {
+            info!("Cable not wrapped, nothing to unwind");
+            return Ok(());
+        }
+        let direction = if start > 0.0 {
+            Direction::Negative
+        } else {
+            Direction::Positive
+        };
+        info!(
+            "Unwinding the cable by {:.1} degrees",
+            self.cable_wrap.unwind_delta()
+        );
+        let target = format!("{:.1} degrees", self.cable_wrap.unwind_delta());
+        self.start_in_flight(Operation::Unwind, target, |dev| {
+            dev.slew_axis(Axis::Ra, direction, MAX_SLEW_RATE)
+        })?;
+        self.unwinding = Some(start);
+        Ok(())
+    }
+
+    /// Forgets the rotation, for when the cable is known to be unwound
+    fn reset_cable_wrap(&mut self) {
+        self.cable_wrap.reset();
+        *self.az_cable_wrap.write().unwrap() = String::from("0.0");
+        info!("Cable wrap reset");
+    }
+
+    /// Once the mount settles after a precise goto, records how far
+    /// it landed from the commanded target
+    fn verify_goto(&mut self, ra: f64, dec: f64) {
+        let error = match self.goto_check.as_mut().and_then(|c| c.update(ra, dec)) {
+            Some(e) => e,
+            None => return,
+        };
+        self.goto_check = None;
+
+        if error > GOTO_ERROR_WARNING_ARCSEC {
+            warn!(
+                "GOTO landed {:.1} arcsec away from the target, check the payload encoding or the alignment",
+                error
+            );
+        } else {
+            info!("GOTO landed {:.1} arcsec away from the target", error);
+        }
+
+        let mut goto_error = self.goto_error.write().unwrap();
+        goto_error.clear();
+        goto_error.push_str(&format!("{:.1}", error));
+    }
+
+    fn load_target_list(&mut self, json: &str) -> Result<(), DeviceActions> {
+        match TargetList::from_json(json) {
+            Ok(list) => {
+                info!("Loaded a target list with {} entries", list.len());
+                self.targets = list;
+                self.current_target.write().unwrap().clear();
+                self.save_target_list();
+                Ok(())
+            }
+            Err(e) => {
+                error!("Invalid target list: {:?}", e);
+                Err(DeviceActions::InvalidValue)
+            }
+        }
+    }
+
+    /// Slews to the entry at `index` and makes it the one reported by
+    /// CURRENT_TARGET, the list stays where it was when the goto fails
+    fn goto_target(&mut self, index: Option<usize>) -> Result<(), DeviceActions> {
+        let (index, target) = index
+            .and_then(|i| Some((i, self.targets.get(i)?.clone())))
+            .ok_or(DeviceActions::InvalidValue)?;
+        info!(
+            "Going to target {} RA: {} DEC: {}",
+            target.name, target.ra, target.dec
+        );
+        self.goto_precise_ra_dec(target.ra, target.dec)?;
+
+        self.targets.select(index);
+        self.show_current_target();
+        self.save_target_list();
+        Ok(())
+    }
+
+    /// Stops a slew in progress
+    fn cancel_goto(&mut self) -> Result<(), DeviceActions> {
+        self.expect_ack(Command::CancelGoto, None)?;
+        info!("GOTO cancelled");
+        self.goto_check = None;
+        self.going_home = None;
+        self.end_in_flight();
+        self.cancel_parking();
+        if self.unwinding.take().is_some() {
+            self.stop_axis(Axis::Ra)?;
+        }
+        Ok(())
+    }
+
+    /// Cancels any goto and stops both axes, all the frames are written
+    /// before any answer is read so a command that isn't answered can't
+    /// delay the others
+    fn emergency_stop(&mut self) -> Result<(), DeviceActions> {
+        warn!("Emergency stop");
+        let frames = [
+            build_frame(Command::CancelGoto, None),
+            slew_frame(Axis::Ra, Direction::Positive, 0),
+            slew_frame(Axis::Dec, Direction::Positive, 0),
+        ];
+        let timeout = self.port.timeout();
+        let answers = send_all(&mut self.port, &frames, b'#', timeout);
+
+        self.goto_check = None;
+        self.going_home = None;
+        self.unwinding = None;
+        self.end_in_flight();
+        self.cancel_parking();
+        self.update_status(false);
+
+        let mut result = Ok(());
+        for answer in answers {
+            let error = match answer {
+                Ok(buf) if response_error(&buf).is_none() => continue,
+                Ok(_) => DeviceActions::InvalidValue,
+                Err(ReadError::Timeout) => DeviceActions::Timeout,
+                Err(_) => DeviceActions::ComError,
+            };
+            error!("Emergency stop not acknowledged: {:?}", error);
+            result = Err(error);
+        }
+        if result.is_err() {
+            // Drop late answers so they don't end up in the next one
+            let _ = self.port.clear(ClearBuffer::Input);
+        }
+        result
+    }
+
+    /// Asks the mount whether it is slewing, errors are reported
+    /// as not slewing
+    fn is_goto_in_progress(&mut self) -> bool {
+        match self.send_synscan_command(Command::IsGotoInProgress, None) {
+            Ok(r) => match parse_goto_in_progress(&r) {
+                Ok(slewing) => slewing,
+                Err(e) => {
+                    warn!("Cannot parse GOTO in progress response {:?}: {:?}", r, e);
+                    false
+                }
+            },
+            Err(_) => false,
+        }
+    }
+
+    /// Polls more often while slewing so that clients notice
+    /// quickly when the mount settles
+    fn poll_interval(&self) -> Duration {
+        self.status_tracker
+            .status()
+            .poll_interval(self.idle_poll_interval)
+    }
+
+    /// Tells the mount it is pointing at the given coordinates,
+    /// refused while a goto is in progress
+    fn sync_ra_dec(&mut self, ra_deg: f64, dec_deg: f64) -> Result<(), DeviceActions> {
+        if self.is_goto_in_progress() {
+            error!("Refusing to sync while a GOTO is in progress");
+            return Err(DeviceActions::InvalidValue);
+        }
+
+        if self.supports(Feature::PreciseCoordinates) {
+            let payload = format_precise_goto_payload(ra_deg, dec_deg);
+            debug!("precise sync payload: {}", &payload);
+            self.expect_ack(Command::SyncPreciseRaDec, Some(&payload))?;
+        } else {
+            let payload = format_goto_payload(ra_deg as f32, dec_deg as f32);
+            debug!("sync payload: {}", &payload);
+            self.expect_ack(Command::SyncRaDec, Some(&payload))?;
+        }
+        info!("Synced to RA: {} DEC: {}", ra_deg, dec_deg);
+        self.goto_check = None;
+        self.is_aligned();
+        Ok(())
+    }
+
+    /// Moves an axis at one of the fixed rates (1-9) until stopped
+    fn slew_axis(
+        &mut self,
+        axis: Axis,
+        direction: Direction,
+        rate: u8,
+    ) -> Result<(), DeviceActions> {
+        if rate > 0 {
+            self.check_not_recovering(&format!("Slew of {:?}", axis))?;
+        }
+        let frame = slew_frame(axis, direction, rate);
+        debug!(
+            "Slew {:?} {:?} at rate {}: {:?}",
+            axis, direction, rate, &frame
+        );
+        match self.send_raw(&frame)?.as_str() {
+            "#" => Ok(()),
+            r => {
+                error!("Unexpected response to slew: {:?}", r);
+                Err(DeviceActions::ComError)
+            }
+        }
+    }
+
+    fn stop_axis(&mut self, axis: Axis) -> Result<(), DeviceActions> {
+        self.slew_axis(axis, Direction::Positive, 0)
+    }
+
+    /// Reads the observer location from the hand controller and
+    /// updates the SITE_* properties
+    fn get_location(&mut self) -> Result<(f64, f64), DeviceActions> {
+        let resp = self.send_fixed(&[Command::GetLocation.as_byte()], 9)?;
+        let (lat, lon) = decode_location(&resp).map_err(|e| {
+            warn!("Cannot parse location response {:?}: {:?}", resp, e);
+            DeviceActions::ComError
+        })?;
+        info!("Location LAT: {} LON: {}", lat, lon);
+
+        *self.latitude.write().unwrap() = format!("{:.6}", lat);
+        *self.longitude.write().unwrap() = format!("{:.6}", lon);
+        Ok((lat, lon))
+    }
+
+    /// Sends the observer location and reads it back so that the
+    /// properties show what the mount actually stored
+    fn set_location(&mut self, lat_deg: f64, lon_deg: f64) -> Result<(), DeviceActions> {
+        let payload = encode_location(lat_deg, lon_deg).map_err(|_| {
+            error!("Invalid location LAT: {} LON: {}", lat_deg, lon_deg);
+            DeviceActions::InvalidValue
+        })?;
+        let mut frame = vec![Command::SetLocation.as_byte()];
+        frame.extend_from_slice(&payload);
+
+        match self.send_fixed(&frame, 1)?.as_slice() {
+            b"#" => {
+                self.get_location()?;
+                Ok(())
+            }
+            r => {
+                error!("Unexpected response to set location: {:?}", r);
+                Err(DeviceActions::ComError)
+            }
+        }
+    }
+
+    fn get_time(&mut self) -> Result<MountTime, DeviceActions> {
+        let resp = self.send_fixed(&[Command::GetTime.as_byte()], 9)?;
+        let time = MountTime::decode(&resp).map_err(|e| {
+            warn!("Cannot parse time response {:?}: {:?}", resp, e);
+            DeviceActions::ComError
+        })?;
+
+        *self.mount_time.write().unwrap() = format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            time.year, time.month, time.day, time.hour, time.minute, time.second
+        );
+        Ok(time)
+    }
+
+    fn set_time(&mut self, time: MountTime) -> Result<(), DeviceActions> {
+        let payload = time.encode().map_err(|_| {
+            error!("Invalid mount time {:?}", time);
+            DeviceActions::InvalidValue
+        })?;
+        let mut frame = vec![Command::SetTime.as_byte()];
+        frame.extend_from_slice(&payload);
+
+        match self.send_fixed(&frame, 1)?.as_slice() {
+            b"#" => {
+                info!("Mount time set to {:?}", time);
+                self.get_time()?;
+                Ok(())
+            }
+            r => {
+                error!("Unexpected response to set time: {:?}", r);
+                Err(DeviceActions::ComError)
+            }
+        }
+    }
+
+    /// Pushes the host UTC time, shifted by the UTC_OFFSET property and
+    /// one more hour when DST is on
+    fn sync_time_now(&mut self) -> Result<(), DeviceActions> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| DeviceActions::InvalidValue)?;
+        let offset = self.utc_offset.read().unwrap().parse::<i8>().unwrap_or(0);
+        let dst = is_truthy(&self.dst.read().unwrap());
+
+        self.set_time(MountTime::from_unix(now.as_secs(), offset, dst))
+    }
+
+    /// Reads the autoguide rate of an axis, in percent of sidereal
+    fn get_guide_rate(&mut self, axis: Axis) -> Result<u8, DeviceActions> {
+        let percent = self.send_passthrough(&get_guide_rate_frame(axis), 1)?[0];
+        let prop = match axis {
+            Axis::Ra => &self.guide_rate_ra,
+            Axis::Dec => &self.guide_rate_dec,
+        };
+        *prop.write().unwrap() = percent.to_string();
+        Ok(percent)
+    }
+
+    fn set_guide_rate(&mut self, axis: Axis, percent: u8) -> Result<(), DeviceActions> {
+        let frame = set_guide_rate_frame(axis, percent).map_err(|_| {
+            error!("Guide rate {}% out of range", percent);
+            DeviceActions::InvalidValue
+        })?;
+        self.send_passthrough(&frame, 0)?;
+        info!("{:?} guide rate set to {}%", axis, percent);
+        self.get_guide_rate(axis)?;
+        Ok(())
+    }
+
+    /// Returns the RA and DEC motor controller firmware versions,
+    /// None for a motor that doesn't answer
+    fn get_motor_versions(&mut self) -> (Option<String>, Option<String>) {
+        let ra = self
+            .send_fixed(&motor_version_frame(Axis::Ra), 3)
+            .ok()
+            .and_then(|resp| parse_motor_version(&resp));
+        let dec = self
+            .send_fixed(&motor_version_frame(Axis::Dec), 3)
+            .ok()
+            .and_then(|resp| parse_motor_version(&resp));
+        (ra, dec)
+    }
+
+    /// Asks the hand controller on firmware 4.15 and later, otherwise
+    /// derives it from the last DEC axis reading
+    fn get_pier_side(&mut self) -> PierSide {
+        if self.supports(Feature::PierSide) {
+            match self.send_synscan_command(Command::GetPierSide, None) {
+                Ok(r) => parse_pier_side(&r),
+                Err(_) => PierSide::Unknown,
+            }
+        } else {
+            self.dec_axis
+                .map(pier_side_from_dec_axis)
+                .unwrap_or(PierSide::Unknown)
+        }
+    }
+
+    /// Starts the slew to the park position ("az,alt"), the fetch loop
+    /// stops tracking and sets PARKED once the mount got there
+    fn park(&mut self) -> Result<(), DeviceActions> {
+        let position = self.park_position.read().unwrap().clone();
+        let (az, alt) =
+            parse_coordinate_pair(&position).map_err(|_| DeviceActions::InvalidValue)?;
+
+        info!("Parking at AZ: {} ALT: {}", az, alt);
+        // Parking again keeps the tracking from before the first park
+        let tracking = match &self.tracking_before_park {
+            Some(mode) => mode.clone(),
+            None => self.track_mode.read().unwrap().clone(),
+        };
+        // The park position is the operator's own choice, the slew
+        // limits are not there to keep the mount from parking
+        self.check_alt_az_goto(az, alt, false)?;
+        let target = format!("AZ: {} ALT: {}", az, alt);
+        self.start_in_flight(Operation::Park, target, |dev| {
+            dev.start_alt_az_goto(az, alt)
+        })?;
+        self.tracking_before_park = Some(tracking);
+        self.parking = Some(GotoWatch::new(Instant::now(), PARK_TIMEOUT));
+        Ok(())
+    }
+
+    /// Slews to the home position, tracking is turned off by the
+    /// fetch loop once the goto is over
+    fn goto_home(&mut self) -> Result<(), DeviceActions> {
+        let (lat, lon) = match self.site() {
+            Some(site) => site,
+            None => {
+                error!("Site location unknown, cannot compute the home position");
+                return Err(DeviceActions::InvalidValue);
+            }
+        };
+
+        let (ra, dec) = home_ra_dec(lat, lon, unix_now_secs());
+        info!("Going home to RA: {} DEC: {}", ra, dec);
+        // Home sits six hours from the meridian, it has to stay
+        // reachable whatever the hour angle limit
+        self.check_ra_dec_cable_wrap(ra, dec)?;
+        let target = format!("RA: {} DEC: {}", ra, dec);
+        self.start_in_flight(Operation::Home, target, |dev| {
+            dev.start_ra_dec_goto(ra, dec)
+        })?;
+        // As long as a park may take
+        self.going_home = Some(GotoWatch::new(Instant::now(), PARK_TIMEOUT));
+        Ok(())
+    }
+
+    /// Restores the tracking mode that was active before parking
+    fn unpark(&mut self) -> Result<(), DeviceActions> {
+        if self.parking.is_some() {
+            info!("Park called off");
+            self.cancel_goto()?;
+        }
+        if *self.parked.read().unwrap() != "true" {
+            return Ok(());
+        }
+
+        if let Some(mode) = self.tracking_before_park.take() {
+            self.set_tracking_mode(&mode)?;
+        }
+        *self.parked.write().unwrap() = String::from("false");
+        // The rotation counts from the unpark
+        self.reset_cable_wrap();
+        info!("Mount unparked");
+        Ok(())
+    }
+
+    fn init_props(&mut self) {
+        // Run again after a reconnection
+        self.properties.clear();
+        self.static_properties.clear();
+        let version = self.firmware.clone();
+        self.is_aligned();
+        // Build the version prop, always immutable
+        self.static_properties.push(Property {
+            name: String::from("SYNSCAN_VERSION"),
+            kind: String::from("string"),
+            value: version,
+            permission: Permission::ReadOnly as i32,
+        });
+
+        self.static_properties.push(Property {
+            name: String::from("BAUD_RATE"),
+            kind: String::from("integer"),
+            value: self.baud.to_string(),
+            permission: Permission::ReadOnly as i32,
+        });
+
+        // Clients can poll the driver at the same pace
+        self.static_properties.push(Property {
+            name: String::from("POLL_INTERVAL_MS"),
+            kind: String::from("integer"),
+            value: self.idle_poll_interval.as_millis().to_string(),
+            permission: Permission::ReadOnly as i32,
+        });
+
+        self.static_properties.push(Property {
+            name: String::from("MOUNT_MODEL"),
+            kind: String::from("string"),
+            value: self.model.clone(),
+            permission: Permission::ReadOnly as i32,
+        });
+
+        let (ra_version, dec_version) = self.get_motor_versions();
+        for (name, version) in [
+            ("MC_VERSION_RA", ra_version),
+            ("MC_VERSION_DEC", dec_version),
+        ] {
+            match version {
+                Some(v) => self.static_properties.push(Property {
+                    name: String::from(name),
+                    kind: String::from("string"),
+                    value: v,
+                    permission: Permission::ReadOnly as i32,
+                }),
+                None => warn!("No answer to the motor version query, {} omitted", name),
+            }
+        }
+
+        self.properties.push(CustomProp {
+            name: String::from("RA"),
+            kind: String::from("float"),
+            permission: Permission::ReadOnly,
+            value: self.ra.clone(),
+        });
+
+        self.properties.push(CustomProp {
+            name: String::from("DEC"),
+            kind: String::from("float"),
+            permission: Permission::ReadOnly,
+            value: self.dec.clone(),
+        });
+
+        self.properties.push(CustomProp {
+            name: String::from("TARGET_RA"),
+            kind: String::from("float"),
+            permission: Permission::ReadWrite,
+            value: self.target_ra.clone(),
+        });
+
+        self.properties.push(CustomProp {
+            name: String::from("TARGET_DEC"),
+            kind: String::from("float"),
+            permission: Permission::ReadWrite,
+            value: self.target_dec.clone(),
+        });
+
+        self.properties.push(CustomProp {
+            name: String::from("ALT"),
+            kind: String::from("float"),
+            permission: Permission::ReadOnly,
+            value: self.alt.clone(),
+        });
+
+        self.properties.push(CustomProp {
+            name: String::from("AZ"),
+            kind: String::from("float"),
+            permission: Permission::ReadOnly,
+            value: self.az.clone(),
+        });
+
+        self.properties.push(CustomProp {
+            name: String::from("TRACKING_MODE"),
+            kind: enum_kind(&TrackingMode::ALL),
+            permission: Permission::ReadWrite,
+            value: self.track_mode.clone(),
+        });
+
+        self.properties.push(CustomProp {
+            name: String::from("ALIGNED"),
+            kind: String::from("boolean"),
+            permission: Permission::ReadOnly,
+            value: self.aligned.clone(),
+        });
+
+        self.properties.push(CustomProp {
+            name: String::from("CONNECTED"),
+            kind: String::from("boolean"),
+            permission: Permission::ReadOnly,
+            value: self.connection.connected.clone(),
+        });
+        self.properties.extend(self.stats.props());
+        self.properties.extend(self.traffic.props());
+
+        // Target list handling, manual motion and sync, the write-only entries
+        // act as triggers and never hold a value
+        for (name, kind) in [
+            ("TARGET_LIST", "string"),
+            ("NEXT_TARGET", "boolean"),
+            ("PREV_TARGET", "boolean"),
+            ("GOTO_TARGET_INDEX", "integer"),
+            ("CLEAR_TARGET_LIST", "boolean"),
+            ("ABORT_MOTION", "boolean"),
+            ("EMERGENCY_STOP", "boolean"),
+            ("SYNC_COORDINATES", "string"),
+            ("SLEW_RA", "integer"),
+            ("SLEW_DEC", "integer"),
+            ("SYNC_TIME_NOW", "boolean"),
+            ("DO_GOTO", "boolean"),
+            ("PARK", "boolean"),
+            ("UNPARK", "boolean"),
+            ("GO_HOME", "boolean"),
+            ("UNWIND_CABLE", "boolean"),
+            ("RESET_CABLE_WRAP", "boolean"),
+        ] {
+            self.properties.push(CustomProp {
+                name: String::from(name),
+                kind: String::from(kind),
+                permission: Permission::ReadWrite,
+                value: Arc::new(RwLock::new(String::new())),
+            });
+        }
+
+        self.properties.push(CustomProp {
+            name: String::from("CURRENT_TARGET"),
+            kind: String::from("string"),
+            permission: Permission::ReadOnly,
+            value: self.current_target.clone(),
+        });
+
+        self.properties.push(CustomProp {
+            name: String::from("AZ_CABLE_WRAP_DEG"),
+            kind: String::from("float"),
+            permission: Permission::ReadOnly,
+            value: self.az_cable_wrap.clone(),
+        });
+
+        self.properties.push(CustomProp {
+            name: String::from("GOTO_ERROR_ARCSEC"),
+            kind: String::from("float"),
+            permission: Permission::ReadOnly,
+            value: self.goto_error.clone(),
+        });
+
+        self.properties.push(CustomProp {
+            name: String::from("SITE_LATITUDE"),
+            kind: String::from("float"),
+            permission: Permission::ReadWrite,
+            value: self.latitude.clone(),
+        });
+
+        self.properties.push(CustomProp {
+            name: String::from("SITE_LONGITUDE"),
+            kind: String::from("float"),
+            permission: Permission::ReadWrite,
+            value: self.longitude.clone(),
+        });
+
+        self.properties.push(CustomProp {
+            name: String::from("MIN_ALTITUDE"),
+            kind: String::from("float"),
+            permission: Permission::ReadWrite,
+            value: self.min_altitude.clone(),
+        });
+
+        self.properties.push(CustomProp {
+            name: String::from("MAX_HOUR_ANGLE"),
+            kind: String::from("float"),
+            permission: Permission::ReadWrite,
+            value: self.max_hour_angle.clone(),
+        });
+
+        self.properties.push(CustomProp {
+            name: String::from("ENABLE_LIMITS"),
+            kind: String::from("boolean"),
+            permission: Permission::ReadWrite,
+            value: self.limits_enabled.clone(),
+        });
+
+        self.properties.push(CustomProp {
+            name: String::from("GUIDE_RATE_RA"),
+            kind: String::from("integer"),
+            permission: Permission::ReadWrite,
+            value: self.guide_rate_ra.clone(),
+        });
+
+        self.properties.push(CustomProp {
+            name: String::from("GUIDE_RATE_DEC"),
+            kind: String::from("integer"),
+            permission: Permission::ReadWrite,
+            value: self.guide_rate_dec.clone(),
+        });
+
+        self.properties.push(CustomProp {
+            name: String::from("MOUNT_TIME"),
+            kind: String::from("string"),
+            permission: Permission::ReadOnly,
+            value: self.mount_time.clone(),
+        });
+
+        self.properties.push(CustomProp {
+            name: String::from("UTC_OFFSET"),
+            kind: String::from("integer"),
+            permission: Permission::ReadWrite,
+            value: self.utc_offset.clone(),
+        });
+
+        self.properties.push(CustomProp {
+            name: String::from("DST"),
+            kind: String::from("boolean"),
+            permission: Permission::ReadWrite,
+            value: self.dst.clone(),
+        });
+
+        if self.gps_present {
+            for (name, kind, value) in [
+                ("GPS_LINKED", "boolean", &self.gps_linked),
+                ("GPS_LAT", "float", &self.gps_lat),
+                ("GPS_LON", "float", &self.gps_lon),
+            ] {
+                self.properties.push(CustomProp {
+                    name: String::from(name),
+                    kind: String::from(kind),
+                    permission: Permission::ReadOnly,
+                    value: value.clone(),
+                });
+            }
+        }
+
+        self.properties.push(CustomProp {
+            name: String::from("PARKED"),
+            kind: String::from("boolean"),
+            permission: Permission::ReadOnly,
+            value: self.parked.clone(),
+        });
+
+        self.properties.push(CustomProp {
+            name: String::from("PARK_POSITION"),
+            kind: String::from("string"),
+            permission: Permission::ReadWrite,
+            value: self.park_position.clone(),
+        });
+
+        self.properties.push(CustomProp {
+            name: String::from("PIER_SIDE"),
+            kind: String::from("string"),
+            permission: Permission::ReadOnly,
+            value: self.pier_side.clone(),
+        });
+
+        if self.raw_enabled {
+            self.properties.push(CustomProp {
+                name: String::from("RAW_COMMAND"),
+                kind: String::from("string"),
+                permission: Permission::ReadWrite,
+                value: self.raw_command.clone(),
+            });
+            self.properties.push(CustomProp {
+                name: String::from("RAW_RESPONSE"),
+                kind: String::from("string"),
+                permission: Permission::ReadOnly,
+                value: self.raw_response.clone(),
+            });
+        }
+
+        self.properties.push(CustomProp {
+            name: String::from("POSITION_STALE"),
+            kind: String::from("boolean"),
+            permission: Permission::ReadOnly,
+            value: self.position_stale.clone(),
+        });
+
+        self.properties.push(CustomProp {
+            name: String::from("LAST_ERROR"),
+            kind: String::from("string"),
+            permission: Permission::ReadOnly,
+            value: self.last_error_message.clone(),
+        });
+
+        self.properties.push(CustomProp {
+            name: String::from("STATUS"),
+            kind: String::from("string"),
+            permission: Permission::ReadOnly,
+            value: self.status.clone(),
+        });
+
+        self.properties.push(CustomProp {
+            name: String::from("GOTO_IN_PROGRESS"),
+            kind: String::from("boolean"),
+            permission: Permission::ReadOnly,
+            value: self.goto_in_progress.clone(),
+        });
+
+        // What the previous run left in flight, empty after a clean one
+        self.properties.push(CustomProp {
+            name: String::from("RECOVERED_FROM_CRASH"),
+            kind: String::from("string"),
+            permission: Permission::ReadOnly,
+            value: self.recovered_from_crash.clone(),
+        });
+
+        self.properties.push(CustomProp {
+            name: String::from("CAUTIOUS_MODE"),
+            kind: String::from("boolean"),
+            permission: Permission::ReadOnly,
+            value: self.cautious_mode.clone(),
+        })
+    }
+}
+
+/// Queries for the optional SynScan GPS accessory
+pub trait SynScanGps {
+    fn is_gps_linked(&mut self) -> Result<bool, DeviceActions>;
+    fn gps_latitude(&mut self) -> Result<f64, DeviceActions>;
+    fn gps_longitude(&mut self) -> Result<f64, DeviceActions>;
+    fn gps_time(&mut self) -> Result<String, DeviceActions>;
+}
+
+impl SynScanGps for MountDevice {
+    /// Whether the GPS module has a fix, errors out if there is no module
+    fn is_gps_linked(&mut self) -> Result<bool, DeviceActions> {
+        let resp = self.send_passthrough(&passthrough_frame(GPS_DEVICE, 55, &[], 1), 1)?;
+        Ok(resp[0] > 0)
+    }
+
+    fn gps_latitude(&mut self) -> Result<f64, DeviceActions> {
+        let resp = self.send_passthrough(&passthrough_frame(GPS_DEVICE, 1, &[], 3), 3)?;
+        rotation_fraction_to_degrees(&resp).map_err(|_| DeviceActions::ComError)
+    }
+
+    fn gps_longitude(&mut self) -> Result<f64, DeviceActions> {
+        let resp = self.send_passthrough(&passthrough_frame(GPS_DEVICE, 2, &[], 3), 3)?;
+        rotation_fraction_to_degrees(&resp).map_err(|_| DeviceActions::ComError)
+    }
+
+    /// Returns the GPS time (UTC) as HH:MM:SS
+    fn gps_time(&mut self) -> Result<String, DeviceActions> {
+        let resp = self.send_passthrough(&passthrough_frame(GPS_DEVICE, 51, &[], 3), 3)?;
+        Ok(format!("{:02}:{:02}:{:02}", resp[0], resp[1], resp[2]))
+    }
+}
+
+/// Where operations in flight are kept, LS_INFLIGHT_FILE or the
+/// default file
+fn in_flight_file() -> PathBuf {
+    std::env::var("LS_INFLIGHT_FILE")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(DEFAULT_INFLIGHT_FILE))
+}
+
+/// Host clock as seconds since the unix epoch
+fn unix_now_secs() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or_default()
+}
+
+/// Parses `value` the way update_property_remote does without acting
+/// on it, so a batch is checked in full before anything is sent
+fn check_property_value(name: &str, value: &str) -> Result<(), DeviceActions> {
+    let valid = match name {
+        "TRACKING_MODE" => value.parse::<TrackingMode>().is_ok(),
+        "TARGET_LIST" => TargetList::from_json(value).is_ok(),
+        "GOTO_TARGET_INDEX" => value.parse::<usize>().is_ok(),
+        "SYNC_COORDINATES" => parse_ra_dec_pair(value).is_ok(),
+        "UTC_OFFSET" => value
+            .parse::<i8>()
+            .is_ok_and(|offset| (-12..=14).contains(&offset)),
+        "MIN_ALTITUDE" => value
+            .trim()
+            .parse::<f64>()
+            .is_ok_and(|deg| (-90.0..=90.0).contains(&deg)),
+        "MAX_HOUR_ANGLE" => value
+            .trim()
+            .parse::<f64>()
+            .is_ok_and(|hours| (0.0..=12.0).contains(&hours)),
+        "GUIDE_RATE_RA" | "GUIDE_RATE_DEC" => value.parse::<u8>().is_ok(),
+        "PARK_POSITION" => {
+            parse_coordinate_pair(value).is_ok_and(|(_, alt)| (0.0..=90.0).contains(&alt))
+        }
+        "SLEW_RA" | "SLEW_DEC" => parse_slew_value(value).is_ok(),
+        // The ranges encode_location takes
+        "SITE_LATITUDE" => value
+            .parse::<f64>()
+            .is_ok_and(|deg| (-90.0..=90.0).contains(&deg)),
+        "SITE_LONGITUDE" => value
+            .parse::<f64>()
+            .is_ok_and(|deg| (-180.0..=180.0).contains(&deg)),
+        // The ranges DO_GOTO takes, a bad target is not even stored
+        "TARGET_RA" => value
+            .trim()
+            .parse::<f64>()
+            .is_ok_and(|deg| (0.0..360.0).contains(&deg)),
+        "TARGET_DEC" => value
+            .trim()
+            .parse::<f64>()
+            .is_ok_and(|deg| (-90.0..=90.0).contains(&deg)),
+        _ => true,
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(DeviceActions::InvalidValue)
+    }
+}
+
+impl actor::Device for MountDevice {
+    fn update_property(&mut self, name: &str, value: &str) -> Result<(), DeviceActions> {
+        AstroSerialDevice::update_property(self, name, value)
+    }
+
+    fn update_properties(&mut self, pairs: &[(&str, &str)]) -> Result<(), DeviceActions> {
+        let props = self.properties.clone();
+        update_all(&props, pairs, check_property_value, |name, value| {
+            AstroSerialDevice::update_property(self, name, value)
+        })
+    }
+
+    fn fetch_props(&mut self) {
+        AstroSerialDevice::fetch_props(self)
+    }
+
+    fn ls_props(&self) -> Vec<Property> {
+        ls_props(&self.properties, &self.static_properties)
+    }
+}
+
+/// Stops the mount ahead of the requests waiting for it
+pub async fn emergency_stop(device: &DeviceHandle<MountDevice>) -> Result<(), DeviceActions> {
+    device.urgent(SynScanMount::emergency_stop).await?
+}
+
+impl shutdown::Shutdown for MountDevice {
+    fn stop_tracking(&mut self) -> Result<(), DeviceActions> {
+        self.set_tracking_mode(&TrackingMode::Off.to_string())
+    }
+
+    /// Waits for the mount to get to the park position, nothing else
+    /// needs the device anymore
+    fn park(&mut self) -> Result<(), DeviceActions> {
+        SynScanMount::park(self)?;
+        while self.parking.is_some() {
+            thread::sleep(SLEWING_POLL_INTERVAL);
+            let slewing = self.is_goto_in_progress();
+            self.update_parking(slewing);
+        }
+        if is_truthy(&self.parked.read().unwrap()) {
+            Ok(())
+        } else {
+            Err(DeviceActions::Timeout)
+        }
+    }
+
+    fn close(&mut self) {
+        if let Err(e) = self.port.flush() {
+            warn!("Cannot flush the serial port: {}", e);
+        }
+    }
 }
@@ -0,0 +1,220 @@
+use crate::TrackingMode;
+use log::warn;
+
+/// Default amount of azimuth rotation, in degrees, allowed before
+/// the cable is considered at risk
+pub const DEFAULT_CABLE_WRAP_LIMIT: f64 = 450.0;
+
+/// Rotation, in degrees, left when an unwind is considered over
+pub const UNWOUND_TOLERANCE_DEG: f64 = 5.0;
+
+/// Normalizes an azimuth difference into the (-180, 180] range so that
+/// crossing the 0/360 boundary counts as a small movement
+fn shortest_delta(from: f64, to: f64) -> f64 {
+    let delta = (to - from).rem_euclid(360.0);
+
+    if delta > 180.0 {
+        delta - 360.0
+    } else {
+        delta
+    }
+}
+
+/// Parses LS_CABLE_WRAP_LIMIT_DEG, giving DEFAULT_CABLE_WRAP_LIMIT when
+/// it is missing or not a positive number of degrees
+pub fn cable_wrap_limit_from(value: Option<&str>) -> f64 {
+    let value = match value {
+        Some(v) => v,
+        None => return DEFAULT_CABLE_WRAP_LIMIT,
+    };
+    match value.trim().parse::<f64>() {
+        Ok(deg) if deg > 0.0 => deg,
+        _ => {
+            warn!(
+                "Invalid cable wrap limit {:?}, using {} degrees",
+                value, DEFAULT_CABLE_WRAP_LIMIT
+            );
+            DEFAULT_CABLE_WRAP_LIMIT
+        }
+    }
+}
+
+/// Tells whether the mount turns in azimuth and so can wrap its cable.
+/// The AZ, DOB and AllView models always do, the others only while
+/// tracking in Alt-Az. None when it can't be told, an AZ-EQ mount not
+/// tracking may be set up either way.
+pub fn is_alt_az(model: &str, tracking: Option<TrackingMode>) -> Option<bool> {
+    if matches!(model, "AZ" | "DOB" | "AllView") {
+        return Some(true);
+    }
+    match tracking {
+        Some(TrackingMode::AltAz) => Some(true),
+        Some(TrackingMode::Eq | TrackingMode::Pec) => Some(false),
+        Some(TrackingMode::Off) | None => None,
+    }
+}
+
+/// Keeps track of the signed azimuth rotation accumulated by an Alt-Az
+/// mount, positive values are clockwise (increasing azimuth).
+#[derive(Debug)]
+pub struct CableWrap {
+    accumulated: f64,
+    last_az: Option<f64>,
+    pub limit: f64,
+}
+
+impl Default for CableWrap {
+    fn default() -> Self {
+        Self::new(DEFAULT_CABLE_WRAP_LIMIT)
+    }
+}
+
+impl CableWrap {
+    pub fn new(limit: f64) -> Self {
+        Self {
+            accumulated: 0.0,
+            last_az: None,
+            limit,
+        }
+    }
+
+    /// Forgets the accumulated rotation, to be used when the cable
+    /// is known to be unwound (e.g. at unpark)
+    pub fn reset(&mut self) {
+        self.accumulated = 0.0;
+        self.last_az = None;
+    }
+
+    /// Integrates a new azimuth reading and returns the accumulated rotation
+    pub fn update(&mut self, az: f64) -> f64 {
+        if let Some(last) = self.last_az {
+            self.accumulated += shortest_delta(last, az);
+        }
+        self.last_az = Some(az);
+        self.accumulated
+    }
+
+    pub fn accumulated(&self) -> f64 {
+        self.accumulated
+    }
+
+    pub fn exceeded(&self) -> bool {
+        self.accumulated.abs() > self.limit
+    }
+
+    /// Tells whether a goto to the given azimuth is allowed, a goto
+    /// is refused only if it moves further in the wrapping direction
+    /// past the limit.
+    pub fn allows_goto(&self, target_az: f64) -> bool {
+        let last = match self.last_az {
+            Some(az) => az,
+            None => return true,
+        };
+        let after = self.accumulated + shortest_delta(last, target_az);
+
+        after.abs() <= self.limit || after.abs() < self.accumulated.abs()
+    }
+
+    /// Returns the signed rotation needed to fully unwind the cable,
+    /// which may be longer than the shortest path to the same azimuth
+    pub fn unwind_delta(&self) -> f64 {
+        -self.accumulated
+    }
+
+    /// Tells whether an unwind that started at `start` degrees of
+    /// rotation is over, overshooting past zero ends it as well
+    pub fn unwound_from(&self, start: f64) -> bool {
+        self.accumulated * start.signum() <= UNWOUND_TOLERANCE_DEG
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{cable_wrap_limit_from, is_alt_az, CableWrap, DEFAULT_CABLE_WRAP_LIMIT};
+    use crate::TrackingMode;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn accumulates_across_wrap() {
+        let mut wrap = CableWrap::new(450.0);
+        // Three full clockwise turns, crossing 0/360 each time
+        for _ in 0..3 {
+            for az in [0.0, 90.0, 180.0, 270.0] {
+                wrap.update(az);
+            }
+        }
+        wrap.update(0.0);
+        assert_approx_eq!(wrap.accumulated(), 1080.0);
+        assert!(wrap.exceeded());
+        assert_approx_eq!(wrap.unwind_delta(), -1080.0);
+
+        // Counter clockwise through the boundary
+        let mut wrap = CableWrap::new(450.0);
+        wrap.update(10.0);
+        wrap.update(350.0);
+        wrap.update(200.0);
+        assert_approx_eq!(wrap.accumulated(), -170.0);
+        assert!(!wrap.exceeded());
+    }
+
+    #[test]
+    fn refuses_goto_in_wrapping_direction() {
+        let mut wrap = CableWrap::new(450.0);
+        for az in [0.0, 120.0, 240.0, 0.0, 90.0] {
+            wrap.update(az);
+        }
+        assert_approx_eq!(wrap.accumulated(), 450.0);
+        assert!(!wrap.allows_goto(100.0));
+        assert!(wrap.allows_goto(80.0));
+        assert!(wrap.allows_goto(90.0));
+    }
+
+    #[test]
+    fn reset_forgets_rotation() {
+        let mut wrap = CableWrap::default();
+        wrap.update(0.0);
+        wrap.update(170.0);
+        wrap.reset();
+        wrap.update(300.0);
+        assert_approx_eq!(wrap.accumulated(), 0.0);
+        assert!(wrap.allows_goto(10.0));
+    }
+
+    #[test]
+    fn unwinding_ends_near_zero() {
+        let mut wrap = CableWrap::default();
+        for az in [0.0, 170.0, 340.0, 150.0] {
+            wrap.update(az);
+        }
+        let start = wrap.accumulated();
+        for az in [0.0, 200.0, 30.0] {
+            wrap.update(az);
+            assert!(!wrap.unwound_from(start));
+        }
+        wrap.update(357.0);
+        assert!(wrap.unwound_from(start));
+        // Past zero the other way
+        wrap.update(340.0);
+        assert!(wrap.unwound_from(start));
+    }
+
+    #[test]
+    fn only_alt_az_mounts_wrap() {
+        assert_eq!(is_alt_az("DOB", None), Some(true));
+        assert_eq!(is_alt_az("AZ", Some(TrackingMode::Eq)), Some(true));
+        assert_eq!(is_alt_az("AZ-EQ6", Some(TrackingMode::AltAz)), Some(true));
+        assert_eq!(is_alt_az("AZ-EQ6", Some(TrackingMode::Eq)), Some(false));
+        assert_eq!(is_alt_az("EQ6", Some(TrackingMode::Pec)), Some(false));
+        assert_eq!(is_alt_az("AZ-EQ5", Some(TrackingMode::Off)), None);
+        assert_eq!(is_alt_az("Unknown", None), None);
+    }
+
+    #[test]
+    fn limit_from_env() {
+        assert_approx_eq!(cable_wrap_limit_from(None), DEFAULT_CABLE_WRAP_LIMIT);
+        assert_approx_eq!(cable_wrap_limit_from(Some(" 540 ")), 540.0);
+        for value in ["0", "-90", "lots"] {
+            assert_approx_eq!(cable_wrap_limit_from(Some(value)), DEFAULT_CABLE_WRAP_LIMIT);
+        }
+    }
+}
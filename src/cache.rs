@@ -0,0 +1,96 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Default time to live for cached responses
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_millis(100);
+
+/// Keeps a single value around for a short amount of time so that
+/// bursts of identical requests don't rebuild it every time.
+#[derive(Debug)]
+pub struct TtlCache<T> {
+    ttl: Duration,
+    entry: Mutex<Option<(Instant, T)>>,
+    builds: AtomicUsize,
+}
+
+impl<T: Clone> Default for TtlCache<T> {
+    fn default() -> Self {
+        Self::new(DEFAULT_CACHE_TTL)
+    }
+}
+
+impl<T: Clone> TtlCache<T> {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entry: Mutex::new(None),
+            builds: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the cached value if still fresh, otherwise builds
+    /// a new one with `build` and caches it
+    pub fn get_or_build<F>(&self, build: F) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        let mut entry = self.entry.lock().unwrap();
+
+        if let Some((created, value)) = entry.as_ref() {
+            if created.elapsed() < self.ttl {
+                return value.clone();
+            }
+        }
+
+        let value = build();
+        self.builds.fetch_add(1, Ordering::Relaxed);
+        *entry = Some((Instant::now(), value.clone()));
+        value
+    }
+
+    /// Drops the cached value, the next request will rebuild it
+    pub fn invalidate(&self) {
+        self.entry.lock().unwrap().take();
+    }
+
+    /// Number of times the value has been built
+    pub fn builds(&self) -> usize {
+        self.builds.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TtlCache;
+    use std::time::Duration;
+
+    #[test]
+    fn burst_is_served_from_cache() {
+        let cache = TtlCache::new(Duration::from_secs(60));
+        for _ in 0..10 {
+            assert_eq!(cache.get_or_build(|| String::from("devices")), "devices");
+        }
+        assert_eq!(cache.builds(), 1);
+    }
+
+    #[test]
+    fn invalidation_rebuilds() {
+        let cache = TtlCache::new(Duration::from_secs(60));
+        assert_eq!(cache.get_or_build(|| String::from("Off")), "Off");
+        cache.invalidate();
+        assert_eq!(
+            cache.get_or_build(|| String::from("Equatorial")),
+            "Equatorial"
+        );
+        assert_eq!(cache.builds(), 2);
+    }
+
+    #[test]
+    fn expired_entry_rebuilds() {
+        let cache = TtlCache::new(Duration::ZERO);
+        cache.get_or_build(|| 1);
+        assert_eq!(cache.get_or_build(|| 2), 2);
+        assert_eq!(cache.builds(), 2);
+    }
+}
@@ -0,0 +1,120 @@
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Consecutive failed exchanges after which a mount is taken for
+/// disconnected
+pub const DEFAULT_OFFLINE_AFTER: u32 = 3;
+/// Wait before the first reconnection attempt, doubled after every
+/// failed one up to MAX_RECONNECT_BACKOFF
+pub const RECONNECT_BACKOFF: Duration = Duration::from_secs(1);
+pub const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Tells from the outcome of the exchanges with a mount whether it is
+/// still connected, and how long to wait before trying to reconnect it
+#[derive(Debug)]
+pub struct Connection {
+    failures: u32,
+    offline_after: u32,
+    online: bool,
+    backoff: Duration,
+    /// Shared with the CONNECTED property
+    pub connected: Arc<RwLock<String>>,
+}
+
+impl Connection {
+    pub fn new(offline_after: u32) -> Self {
+        Self {
+            failures: 0,
+            offline_after: offline_after.max(1),
+            online: true,
+            backoff: RECONNECT_BACKOFF,
+            connected: Arc::new(RwLock::new(String::from("true"))),
+        }
+    }
+
+    pub fn is_online(&self) -> bool {
+        self.online
+    }
+
+    /// Records the outcome of an exchange, returns true when it took
+    /// the mount offline. Once offline only `reconnected` brings it back
+    pub fn record(&mut self, ok: bool) -> bool {
+        if !self.online {
+            return false;
+        }
+        if ok {
+            self.failures = 0;
+            return false;
+        }
+        self.failures += 1;
+        if self.failures < self.offline_after {
+            return false;
+        }
+        self.online = false;
+        *self.connected.write().unwrap() = String::from("false");
+        true
+    }
+
+    /// How long to wait before the next reconnection attempt
+    pub fn reconnect_delay(&self) -> Duration {
+        self.backoff
+    }
+
+    pub fn reconnect_failed(&mut self) {
+        self.backoff = (self.backoff * 2).min(MAX_RECONNECT_BACKOFF);
+    }
+
+    pub fn reconnected(&mut self) {
+        self.failures = 0;
+        self.online = true;
+        self.backoff = RECONNECT_BACKOFF;
+        *self.connected.write().unwrap() = String::from("true");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Connection, MAX_RECONNECT_BACKOFF, RECONNECT_BACKOFF};
+
+    #[test]
+    fn offline_after_consecutive_failures() {
+        let mut conn = Connection::new(3);
+        assert!(conn.is_online());
+
+        // A success in between starts the count again
+        assert!(!conn.record(false));
+        assert!(!conn.record(false));
+        assert!(!conn.record(true));
+        assert!(!conn.record(false));
+        assert!(!conn.record(false));
+        assert!(conn.is_online());
+        assert_eq!(*conn.connected.read().unwrap(), "true");
+
+        assert!(conn.record(false));
+        assert!(!conn.is_online());
+        assert_eq!(*conn.connected.read().unwrap(), "false");
+        // Reported once, and a stray success doesn't bring it back
+        assert!(!conn.record(false));
+        assert!(!conn.record(true));
+        assert!(!conn.is_online());
+    }
+
+    #[test]
+    fn reconnection() {
+        let mut conn = Connection::new(1);
+        assert!(conn.record(false));
+        assert_eq!(conn.reconnect_delay(), RECONNECT_BACKOFF);
+
+        conn.reconnect_failed();
+        assert_eq!(conn.reconnect_delay(), RECONNECT_BACKOFF * 2);
+        for _ in 0..10 {
+            conn.reconnect_failed();
+        }
+        assert_eq!(conn.reconnect_delay(), MAX_RECONNECT_BACKOFF);
+
+        conn.reconnected();
+        assert!(conn.is_online());
+        assert_eq!(*conn.connected.read().unwrap(), "true");
+        assert_eq!(conn.reconnect_delay(), RECONNECT_BACKOFF);
+    }
+}
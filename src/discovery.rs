@@ -0,0 +1,356 @@
+use crate::network::{tcp_address, SYNSCAN_UDP_PORT};
+use log::{debug, error, info, warn};
+use serialport::{available_ports, SerialPortType, UsbPortInfo};
+use std::fs;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+
+/// USB serial bridges found in EQDIR cables and hand controllers, by
+/// VID and PID
+pub const KNOWN_BRIDGES: &[(u16, u16, &str)] = &[
+    (0x067b, 0x2303, "Prolific PL2303"),
+    (0x0403, 0x6001, "FTDI FT232"),
+    (0x1a86, 0x7523, "WCH CH340"),
+    (0x10c4, 0xea60, "Silicon Labs CP2102"),
+];
+
+/// Parses a "vid:pid" pair written in hex, e.g. "1a86:7523"
+pub fn parse_usb_id(value: &str) -> Option<(u16, u16)> {
+    let (vid, pid) = value.trim().split_once(':')?;
+    let vid = u16::from_str_radix(vid.trim().trim_start_matches("0x"), 16).ok()?;
+    let pid = u16::from_str_radix(pid.trim().trim_start_matches("0x"), 16).ok()?;
+    Some((vid, pid))
+}
+
+/// Parses a comma separated list of "vid:pid" pairs, invalid ones are
+/// skipped with a warning
+pub fn parse_usb_ids(value: &str) -> Vec<(u16, u16)> {
+    value
+        .split(',')
+        .filter(|id| !id.trim().is_empty())
+        .filter_map(|id| {
+            let parsed = parse_usb_id(id);
+            if parsed.is_none() {
+                warn!("Ignoring invalid USB id {:?}, expected vid:pid", id);
+            }
+            parsed
+        })
+        .collect()
+}
+
+/// Bridges a mount may be connected through: the known ones plus the
+/// ones listed in LS_EXTRA_USB_IDS
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Bridges {
+    extra: Vec<(u16, u16)>,
+}
+
+impl Bridges {
+    pub fn new(extra: Vec<(u16, u16)>) -> Self {
+        Self { extra }
+    }
+
+    /// Name of the bridge with the given ids, None for anything else
+    pub fn name(&self, vid: u16, pid: u16) -> Option<&'static str> {
+        KNOWN_BRIDGES
+            .iter()
+            .find(|(v, p, _)| *v == vid && *p == pid)
+            .map(|(_, _, name)| *name)
+            .or_else(|| {
+                self.extra
+                    .contains(&(vid, pid))
+                    .then_some("LS_EXTRA_USB_IDS")
+            })
+    }
+
+    /// USB serial ports behind one of the bridges, without logging
+    /// anything so it can be called at every hotplug scan. Whatever is
+    /// on the other end still has to be probed.
+    pub fn ports(&self) -> Vec<(String, UsbPortInfo)> {
+        let ports = match available_ports() {
+            Ok(p) => p,
+            Err(e) => {
+                error!("Cannot list the serial ports: {}", e);
+                return Vec::new();
+            }
+        };
+        let mut devices = Vec::new();
+
+        for port in ports {
+            if let SerialPortType::UsbPort(info) = port.port_type {
+                if self.name(info.vid, info.pid).is_some() {
+                    devices.push((port.port_name, info));
+                }
+            }
+        }
+        devices
+    }
+}
+
+/// Parses a comma separated list of port paths
+pub fn parse_port_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Parses the LS_NETWORK_MOUNTS list of Wi-Fi adapters, "host:port" or
+/// just "host" for SYNSCAN_UDP_PORT
+pub fn parse_network_mounts(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|m| !m.is_empty())
+        .map(|m| match m.parse::<IpAddr>() {
+            Ok(ip) => SocketAddr::new(ip, SYNSCAN_UDP_PORT).to_string(),
+            Err(_) if m.contains(':') => String::from(m),
+            Err(_) => format!("{}:{}", m, SYNSCAN_UDP_PORT),
+        })
+        .collect()
+}
+
+/// Drops the paths leading to a port already in the list, e.g. a
+/// /dev/serial/by-id link next to the device it points to
+pub fn unique_ports(paths: Vec<String>) -> Vec<String> {
+    let mut seen = Vec::new();
+    let mut unique = Vec::new();
+    for path in paths {
+        let target = fs::canonicalize(&path).unwrap_or_else(|_| PathBuf::from(&path));
+        if !seen.contains(&target) {
+            seen.push(target);
+            unique.push(path);
+        }
+    }
+    unique
+}
+
+/// A serial port a mount may be on, with the adapter details when it
+/// was found on the USB bus
+pub type FoundPort = (String, Option<UsbPortInfo>);
+
+/// Where the drivers look for mounts
+#[derive(Clone, Debug, PartialEq)]
+pub enum PortSource {
+    /// USB adapters behind one of the bridges
+    Usb(Bridges),
+    /// Paths given with --serial-port or LS_SERIAL_PORT, for UARTs and
+    /// adapters discovery doesn't know, or tcp://host:port for a port
+    /// behind a TCP bridge
+    Forced(Vec<String>),
+}
+
+impl Default for PortSource {
+    fn default() -> Self {
+        PortSource::Usb(Bridges::default())
+    }
+}
+
+impl PortSource {
+    /// The --serial-port `flag` wins over LS_SERIAL_PORT, without
+    /// either of them the USB bus is scanned
+    pub fn from_config<E>(flag: Option<&str>, env: E) -> Self
+    where
+        E: Fn(&str) -> Option<String>,
+    {
+        let forced = flag
+            .map(String::from)
+            .or_else(|| env("LS_SERIAL_PORT"))
+            .map(|v| unique_ports(parse_port_list(&v)))
+            .unwrap_or_default();
+        if !forced.is_empty() {
+            return PortSource::Forced(forced);
+        }
+        let extra = env("LS_EXTRA_USB_IDS")
+            .map(|v| parse_usb_ids(&v))
+            .unwrap_or_default();
+        PortSource::Usb(Bridges::new(extra))
+    }
+
+    /// Ports to try, without logging anything so it can be called at
+    /// every hotplug scan. Forced ports that don't exist are left out
+    /// until they show up, TCP bridges are always tried.
+    pub fn ports(&self) -> Vec<FoundPort> {
+        match self {
+            PortSource::Usb(bridges) => bridges
+                .ports()
+                .into_iter()
+                .map(|(port, info)| (port, Some(info)))
+                .collect(),
+            PortSource::Forced(paths) => paths
+                .iter()
+                .filter(|p| tcp_address(p).is_some() || Path::new(p).exists())
+                .map(|p| (p.clone(), None))
+                .collect(),
+        }
+    }
+}
+
+pub fn look_for_devices(source: &PortSource) -> Vec<FoundPort> {
+    let devices = source.ports();
+    match source {
+        PortSource::Usb(bridges) => {
+            match devices.len() {
+                0 => warn!("No Sky-Watcher mount found"),
+                n => info!("Found {} Sky-Watcher mount(s)", n),
+            }
+            for (port, info) in &devices {
+                if let Some(info) = info {
+                    debug!(
+                        "{} is a {}",
+                        port,
+                        bridges.name(info.vid, info.pid).unwrap_or_default()
+                    );
+                }
+            }
+        }
+        PortSource::Forced(paths) => {
+            for path in paths {
+                if !devices.iter().any(|(p, _)| p == path) {
+                    warn!("Serial port {} not found", path);
+                }
+            }
+        }
+    }
+
+    devices
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        parse_network_mounts, parse_port_list, parse_usb_id, parse_usb_ids, unique_ports, Bridges,
+        PortSource,
+    };
+    use std::fs;
+
+    #[test]
+    fn known_bridges() {
+        let bridges = Bridges::default();
+        assert_eq!(bridges.name(0x067b, 0x2303), Some("Prolific PL2303"));
+        assert_eq!(bridges.name(0x0403, 0x6001), Some("FTDI FT232"));
+        assert_eq!(bridges.name(0x1a86, 0x7523), Some("WCH CH340"));
+        assert_eq!(bridges.name(0x10c4, 0xea60), Some("Silicon Labs CP2102"));
+        assert_eq!(bridges.name(0x2341, 0x0043), None);
+
+        let bridges = Bridges::new(vec![(0x2341, 0x0043)]);
+        assert_eq!(bridges.name(0x2341, 0x0043), Some("LS_EXTRA_USB_IDS"));
+        assert_eq!(bridges.name(0x067b, 0x2303), Some("Prolific PL2303"));
+    }
+
+    #[test]
+    fn usb_ids() {
+        assert_eq!(parse_usb_id("1a86:7523"), Some((0x1a86, 0x7523)));
+        assert_eq!(parse_usb_id(" 0x10C4:0xEA60 "), Some((0x10c4, 0xea60)));
+        assert_eq!(parse_usb_id("1a86"), None);
+        assert_eq!(parse_usb_id("1a86:"), None);
+        assert_eq!(parse_usb_id("12345:7523"), None);
+        assert_eq!(parse_usb_id("zz:7523"), None);
+
+        assert_eq!(
+            parse_usb_ids("1a86:7523,10c4:ea60"),
+            vec![(0x1a86, 0x7523), (0x10c4, 0xea60)]
+        );
+        assert_eq!(
+            parse_usb_ids("1a86:7523, nope ,,10c4:ea60,"),
+            vec![(0x1a86, 0x7523), (0x10c4, 0xea60)]
+        );
+        assert!(parse_usb_ids("").is_empty());
+    }
+
+    #[test]
+    fn port_lists() {
+        assert_eq!(parse_port_list("/dev/ttyUSB0"), vec!["/dev/ttyUSB0"]);
+        assert_eq!(
+            parse_port_list(" /dev/ttyAMA0 ,, /dev/serial/by-id/usb-FTDI_A1-if00-port0,"),
+            vec!["/dev/ttyAMA0", "/dev/serial/by-id/usb-FTDI_A1-if00-port0"]
+        );
+        assert!(parse_port_list(" , ").is_empty());
+    }
+
+    #[test]
+    fn network_mounts() {
+        assert_eq!(
+            parse_network_mounts("192.168.4.1:11880, 10.0.0.5,mount.local, ::1 ,[fe80::1]:9999,"),
+            vec![
+                "192.168.4.1:11880",
+                "10.0.0.5:11880",
+                "mount.local:11880",
+                "[::1]:11880",
+                "[fe80::1]:9999"
+            ]
+        );
+        assert!(parse_network_mounts("").is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_to_the_same_port_are_dropped() {
+        let dir = std::env::temp_dir().join(format!("skywatcher-ports-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let port = dir.join("ttyUSB0");
+        let link = dir.join("usb-Prolific_A1-if00-port0");
+        fs::write(&port, "").unwrap();
+        std::os::unix::fs::symlink(&port, &link).unwrap();
+
+        let port = port.to_str().unwrap().to_owned();
+        let link = link.to_str().unwrap().to_owned();
+        assert_eq!(
+            unique_ports(vec![
+                link.clone(),
+                port.clone(),
+                String::from("/dev/ttyAMA0")
+            ]),
+            vec![link.clone(), String::from("/dev/ttyAMA0")]
+        );
+
+        // A forced port shows up once it exists
+        let source = PortSource::Forced(vec![
+            link.clone(),
+            String::from("/nonexistent/ttyS9"),
+            String::from("tcp://observatory:4001"),
+        ]);
+        assert_eq!(
+            source.ports(),
+            vec![(link, None), (String::from("tcp://observatory:4001"), None)]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn forced_ports_over_discovery() {
+        let env = |name: &str| match name {
+            "LS_SERIAL_PORT" => Some(String::from("/dev/ttyUSB0,/dev/ttyUSB1")),
+            "LS_EXTRA_USB_IDS" => Some(String::from("2341:0043")),
+            _ => None,
+        };
+        assert_eq!(
+            PortSource::from_config(Some("/dev/ttyAMA0"), env),
+            PortSource::Forced(vec![String::from("/dev/ttyAMA0")])
+        );
+        assert_eq!(
+            PortSource::from_config(None, env),
+            PortSource::Forced(vec![
+                String::from("/dev/ttyUSB0"),
+                String::from("/dev/ttyUSB1")
+            ])
+        );
+
+        let usb_only = |name: &str| match name {
+            "LS_SERIAL_PORT" => Some(String::from(" ")),
+            "LS_EXTRA_USB_IDS" => Some(String::from("2341:0043")),
+            _ => None,
+        };
+        assert_eq!(
+            PortSource::from_config(None, usb_only),
+            PortSource::Usb(Bridges::new(vec![(0x2341, 0x0043)]))
+        );
+        assert_eq!(
+            PortSource::from_config(None, |_| None),
+            PortSource::default()
+        );
+    }
+}
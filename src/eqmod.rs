@@ -0,0 +1,1810 @@
+use crate::serial::SerialTransport;
+use crate::{step_period_for_rate, str_24bits_to_u24, u32_to_str_24bits, ParseError};
+use crate::{LUNAR_RATE, SIDEREAL_RATE, SOLAR_RATE};
+use lightspeed_astro::devices::actions::DeviceActions;
+use log::{debug, warn};
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Axes of a motor controller, the value is the digit sent on the wire.
+/// Aux is the third (e.g. field rotation) axis only some controllers have
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Axis {
+    Ra = 1,
+    Dec = 2,
+    Aux = 3,
+}
+
+impl Axis {
+    fn as_byte(&self) -> u8 {
+        b'0' + *self as u8
+    }
+}
+
+/// Operations understood by the motor controller, the value is the
+/// character sent on the wire
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EqModOp {
+    Init = 0x46,
+    GetAxisPosition = 0x6a,
+    SetAxisPosition = 0x45,
+    GetAxisStatus = 0x66,
+    InquireGridPerRevolution = 0x61,
+    MotorBoardVersion = 0x65,
+    InquireTimerInterruptFreq = 0x62,
+    InquireHighSpeedRatio = 0x67,
+    InquireAuxEncoder = 0x64,
+    ExtendedInquiry = 0x71,
+    ExtendedSetting = 0x57,
+    SetMotionMode = 0x47,
+    SetStepPeriod = 0x49,
+    StartMotion = 0x4a,
+    StopMotion = 0x4b,
+    InstantStop = 0x4c,
+    SetSnapPort = 0x4f,
+    SetPolarLed = 0x56,
+    SetGotoTarget = 0x53,
+    SetGotoTargetIncrement = 0x48,
+    SetBreakPointIncrement = 0x4d,
+}
+
+impl TryFrom<u8> for EqModOp {
+    type Error = ParseError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        let op = match value {
+            0x46 => EqModOp::Init,
+            0x6a => EqModOp::GetAxisPosition,
+            0x45 => EqModOp::SetAxisPosition,
+            0x66 => EqModOp::GetAxisStatus,
+            0x61 => EqModOp::InquireGridPerRevolution,
+            0x65 => EqModOp::MotorBoardVersion,
+            0x62 => EqModOp::InquireTimerInterruptFreq,
+            0x67 => EqModOp::InquireHighSpeedRatio,
+            0x64 => EqModOp::InquireAuxEncoder,
+            0x71 => EqModOp::ExtendedInquiry,
+            0x57 => EqModOp::ExtendedSetting,
+            0x47 => EqModOp::SetMotionMode,
+            0x49 => EqModOp::SetStepPeriod,
+            0x4a => EqModOp::StartMotion,
+            0x4b => EqModOp::StopMotion,
+            0x4c => EqModOp::InstantStop,
+            0x4f => EqModOp::SetSnapPort,
+            0x56 => EqModOp::SetPolarLed,
+            0x53 => EqModOp::SetGotoTarget,
+            0x48 => EqModOp::SetGotoTargetIncrement,
+            0x4d => EqModOp::SetBreakPointIncrement,
+            _ => return Err(ParseError::UnknownValue),
+        };
+        Ok(op)
+    }
+}
+
+impl EqModOp {
+    /// Every operation, in the order of the enum
+    pub const ALL: [EqModOp; 21] = [
+        EqModOp::Init,
+        EqModOp::GetAxisPosition,
+        EqModOp::SetAxisPosition,
+        EqModOp::GetAxisStatus,
+        EqModOp::InquireGridPerRevolution,
+        EqModOp::MotorBoardVersion,
+        EqModOp::InquireTimerInterruptFreq,
+        EqModOp::InquireHighSpeedRatio,
+        EqModOp::InquireAuxEncoder,
+        EqModOp::ExtendedInquiry,
+        EqModOp::ExtendedSetting,
+        EqModOp::SetMotionMode,
+        EqModOp::SetStepPeriod,
+        EqModOp::StartMotion,
+        EqModOp::StopMotion,
+        EqModOp::InstantStop,
+        EqModOp::SetSnapPort,
+        EqModOp::SetPolarLed,
+        EqModOp::SetGotoTarget,
+        EqModOp::SetGotoTargetIncrement,
+        EqModOp::SetBreakPointIncrement,
+    ];
+
+    /// Whether sending the frame twice does no harm, the inquiries
+    pub fn is_idempotent(&self) -> bool {
+        matches!(
+            self,
+            EqModOp::GetAxisPosition
+                | EqModOp::GetAxisStatus
+                | EqModOp::InquireGridPerRevolution
+                | EqModOp::MotorBoardVersion
+                | EqModOp::InquireTimerInterruptFreq
+                | EqModOp::InquireHighSpeedRatio
+                | EqModOp::InquireAuxEncoder
+                | EqModOp::ExtendedInquiry
+        )
+    }
+}
+
+/// Rotation direction of an axis
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    Forward,
+    Backward,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Speed {
+    Low,
+    High,
+}
+
+/// Goto moves to a target position, tracking (slew) runs at the
+/// step period until stopped
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MotionKind {
+    Goto,
+    Tracking,
+}
+
+/// Motion mode set with SetMotionMode (:G) before starting an axis
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MotionMode {
+    pub kind: MotionKind,
+    pub speed: Speed,
+    pub direction: Direction,
+}
+
+impl MotionMode {
+    /// Low speed forward tracking, as used for sidereal tracking
+    pub const TRACKING: MotionMode = MotionMode {
+        kind: MotionKind::Tracking,
+        speed: Speed::Low,
+        direction: Direction::Forward,
+    };
+
+    /// The two payload digits: the first one is 0 for a high speed
+    /// goto, 1 for low speed tracking, 2 for a low speed goto and 3 for
+    /// high speed tracking, the second one has the direction in its
+    /// lowest bit (northern hemisphere)
+    pub fn payload(&self) -> String {
+        let mode = match (self.kind, self.speed) {
+            (MotionKind::Goto, Speed::High) => 0,
+            (MotionKind::Tracking, Speed::Low) => 1,
+            (MotionKind::Goto, Speed::Low) => 2,
+            (MotionKind::Tracking, Speed::High) => 3,
+        };
+        let direction = match self.direction {
+            Direction::Forward => 0,
+            Direction::Backward => 1,
+        };
+        format!("{}{}", mode, direction)
+    }
+}
+
+/// Arcseconds in a radian
+const ARCSEC_PER_RAD: f64 = 206264.806;
+
+/// Fastest custom tracking rate accepted, in radians per second
+pub const MAX_CUSTOM_TRACK_RATE: f64 = 16.0 * SIDEREAL_RATE;
+
+/// Converts a custom tracking rate in arcseconds per second to radians
+/// per second, None when it is not a finite rate, in either direction,
+/// up to MAX_CUSTOM_TRACK_RATE
+pub fn custom_rate_rad_per_sec(arcsec_per_sec: f64) -> Option<f64> {
+    let rate = arcsec_per_sec / ARCSEC_PER_RAD;
+    (rate.is_finite() && rate.abs() <= MAX_CUSTOM_TRACK_RATE).then_some(rate)
+}
+
+/// Rates the RA axis can track at
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TrackingRate {
+    Sidereal,
+    Lunar,
+    Solar,
+    /// User given rate in arcseconds per second (sun, moon, comets)
+    Custom(f64),
+}
+
+impl TrackingRate {
+    pub const ALL: [TrackingRate; 3] = [
+        TrackingRate::Sidereal,
+        TrackingRate::Lunar,
+        TrackingRate::Solar,
+    ];
+
+    /// Custom rate in arcseconds per second, None when it isn't a
+    /// positive rate up to MAX_CUSTOM_TRACK_RATE
+    pub fn custom(arcsec_per_sec: f64) -> Option<Self> {
+        custom_rate_rad_per_sec(arcsec_per_sec)
+            .filter(|r| *r > 0.0)
+            .map(|_| TrackingRate::Custom(arcsec_per_sec))
+    }
+
+    /// Angular rate in radians per second
+    pub fn rad_per_sec(&self) -> f64 {
+        match self {
+            TrackingRate::Sidereal => SIDEREAL_RATE,
+            TrackingRate::Lunar => LUNAR_RATE,
+            TrackingRate::Solar => SOLAR_RATE,
+            TrackingRate::Custom(arcsec_per_sec) => arcsec_per_sec / ARCSEC_PER_RAD,
+        }
+    }
+}
+
+impl fmt::Display for TrackingRate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            TrackingRate::Sidereal => "Sidereal",
+            TrackingRate::Lunar => "Lunar",
+            TrackingRate::Solar => "Solar",
+            TrackingRate::Custom(_) => "Custom",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for TrackingRate {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "sidereal" => Ok(TrackingRate::Sidereal),
+            "lunar" => Ok(TrackingRate::Lunar),
+            "solar" => Ok(TrackingRate::Solar),
+            _ => Err(ParseError::UnknownValue),
+        }
+    }
+}
+
+/// Operations, with their payload, starting tracking at `rate` on the
+/// RA axis. When `current` shows the axis already tracking forward at
+/// low speed only the step period is changed, on the fly, otherwise the
+/// axis is stopped first as the motion mode can only be changed while
+/// it stands still
+pub fn start_tracking_commands(
+    rate: TrackingRate,
+    steps_per_rev: u32,
+    timer_freq: u32,
+    current: Option<AxisStatus>,
+) -> Vec<(EqModOp, Option<String>)> {
+    let period = step_period_for_rate(rate.rad_per_sec(), steps_per_rev, timer_freq);
+    if current.is_some_and(|s| s.is_moving_in(MotionMode::TRACKING)) {
+        return vec![(EqModOp::SetStepPeriod, Some(u32_to_str_24bits(period)))];
+    }
+    vec![
+        (EqModOp::StopMotion, None),
+        (EqModOp::SetMotionMode, Some(MotionMode::TRACKING.payload())),
+        (EqModOp::SetStepPeriod, Some(u32_to_str_24bits(period))),
+        (EqModOp::StartMotion, None),
+    ]
+}
+
+/// Slews at or above this rate, in radians per second, are run at high
+/// speed
+pub const HIGH_SPEED_MIN_RATE: f64 = 128.0 * SIDEREAL_RATE;
+
+/// Motion mode and step period moving an axis at `rate_rad_per_sec`,
+/// negative rates move it backwards. At high speed the motor moves
+/// `high_speed_ratio` steps per timer period so the period is scaled up
+/// accordingly
+pub fn slew_motion(
+    rate_rad_per_sec: f64,
+    steps_per_rev: u32,
+    timer_freq: u32,
+    high_speed_ratio: u16,
+) -> (MotionMode, u32) {
+    let high_speed = rate_rad_per_sec.abs() >= HIGH_SPEED_MIN_RATE && high_speed_ratio > 0;
+    let (speed, rate) = if high_speed {
+        (Speed::High, rate_rad_per_sec / high_speed_ratio as f64)
+    } else {
+        (Speed::Low, rate_rad_per_sec)
+    };
+    let mode = MotionMode {
+        kind: MotionKind::Tracking,
+        speed,
+        direction: if rate_rad_per_sec < 0.0 {
+            Direction::Backward
+        } else {
+            Direction::Forward
+        },
+    };
+    (mode, step_period_for_rate(rate, steps_per_rev, timer_freq))
+}
+
+/// Rate offset applied by guide pulses, as a fraction of the sidereal rate
+pub const DEFAULT_GUIDE_RATE: f64 = 0.5;
+
+/// A guide pulse as written by clients, the signed duration in
+/// milliseconds, e.g. "+350" or "-200"
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GuidePulse {
+    pub direction: Direction,
+    pub duration_ms: u32,
+}
+
+impl GuidePulse {
+    /// Offset, in radians per second, added to the axis rate while the
+    /// pulse lasts
+    pub fn rate_offset(&self, rate_fraction: f64) -> f64 {
+        let offset = rate_fraction * SIDEREAL_RATE;
+        match self.direction {
+            Direction::Forward => offset,
+            Direction::Backward => -offset,
+        }
+    }
+}
+
+impl FromStr for GuidePulse {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (direction, duration) = match s.strip_prefix('-') {
+            Some(d) => (Direction::Backward, d),
+            None => (Direction::Forward, s.strip_prefix('+').unwrap_or(s)),
+        };
+        match duration.parse::<u32>() {
+            Ok(duration_ms) if duration_ms > 0 => Ok(Self {
+                direction,
+                duration_ms,
+            }),
+            _ => Err(ParseError::UnknownValue),
+        }
+    }
+}
+
+/// A guide pulse that started and is due to end
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RunningPulse {
+    pub end: Instant,
+    /// Only the step period of the tracking axis was changed
+    pub period_only: bool,
+}
+
+#[derive(Debug, Default)]
+struct GuideLane {
+    running: Option<RunningPulse>,
+    waiting: VecDeque<GuidePulse>,
+}
+
+/// Keeps guide pulses on the same axis from interleaving: a pulse
+/// written while another runs on its axis waits for it to end, pulses
+/// on different axes still run together. Only keeps the time, the
+/// device starts and ends the pulses.
+#[derive(Debug, Default)]
+pub struct GuideQueue {
+    lanes: [GuideLane; 3],
+}
+
+impl GuideQueue {
+    fn lane(&mut self, axis: Axis) -> &mut GuideLane {
+        &mut self.lanes[axis as usize - 1]
+    }
+
+    pub fn push(&mut self, axis: Axis, pulse: GuidePulse) {
+        self.lane(axis).waiting.push_back(pulse);
+    }
+
+    /// Takes the next pulse of an axis with none running, the device
+    /// then starts it and reports it with `started`
+    pub fn take_next(&mut self) -> Option<(Axis, GuidePulse)> {
+        [Axis::Ra, Axis::Dec, Axis::Aux]
+            .into_iter()
+            .find_map(|axis| {
+                let lane = self.lane(axis);
+                match lane.running {
+                    Some(_) => None,
+                    None => lane.waiting.pop_front().map(|pulse| (axis, pulse)),
+                }
+            })
+    }
+
+    pub fn started(&mut self, axis: Axis, pulse: RunningPulse) {
+        self.lane(axis).running = Some(pulse);
+    }
+
+    /// Takes the running pulses over at `now`, for the device to end
+    pub fn take_ended(&mut self, now: Instant) -> Vec<(Axis, RunningPulse)> {
+        [Axis::Ra, Axis::Dec, Axis::Aux]
+            .into_iter()
+            .filter_map(|axis| {
+                let lane = self.lane(axis);
+                match lane.running {
+                    Some(pulse) if pulse.end <= now => {
+                        lane.running = None;
+                        Some((axis, pulse))
+                    }
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    /// When the first running pulse ends
+    pub fn next_end(&self) -> Option<Instant> {
+        self.lanes
+            .iter()
+            .filter_map(|lane| lane.running.map(|p| p.end))
+            .min()
+    }
+
+    /// Forgets every pulse, for when the axes were stopped
+    pub fn clear(&mut self) {
+        self.lanes = Default::default();
+    }
+}
+
+/// Gotos shorter than this are run at low speed, the high speed ramp
+/// would overshoot them
+pub const LOW_SPEED_GOTO_MAX_DEG: f64 = 5.0;
+
+/// Steps before the target where a high speed goto starts slowing down
+pub const DEFAULT_BREAK_STEPS: u32 = 3500;
+
+/// How often the mount state is polled when LS_POLL_INTERVAL_MS is not set
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Operations, with their payload, moving an axis from `current_steps`
+/// to `target_steps` (raw axis positions). None when the axis is running:
+/// the motion mode cannot be changed while moving so it must be stopped
+/// first. High speed gotos switch to low speed `break_steps` before the
+/// target
+pub fn goto_commands(
+    status: &AxisStatus,
+    current_steps: u32,
+    target_steps: u32,
+    steps_per_rev: u32,
+    break_steps: u32,
+) -> Option<Vec<(EqModOp, Option<String>)>> {
+    if status.running {
+        return None;
+    }
+
+    let delta = target_steps as i64 - current_steps as i64;
+    let distance_deg = delta.unsigned_abs() as f64 / steps_per_rev as f64 * 360.0;
+    let mode = MotionMode {
+        kind: MotionKind::Goto,
+        speed: if distance_deg > LOW_SPEED_GOTO_MAX_DEG {
+            Speed::High
+        } else {
+            Speed::Low
+        },
+        direction: if delta < 0 {
+            Direction::Backward
+        } else {
+            Direction::Forward
+        },
+    };
+
+    let mut commands = vec![
+        (EqModOp::SetMotionMode, Some(mode.payload())),
+        (
+            EqModOp::SetGotoTarget,
+            Some(u32_to_str_24bits(target_steps)),
+        ),
+    ];
+    if mode.speed == Speed::High {
+        let break_steps = break_steps.min(delta.unsigned_abs() as u32);
+        commands.push((
+            EqModOp::SetBreakPointIncrement,
+            Some(u32_to_str_24bits(break_steps)),
+        ));
+    }
+    commands.push((EqModOp::StartMotion, None));
+    Some(commands)
+}
+
+/// Backlash of an axis and the direction it was last driven in, the
+/// dead zone only has to be taken up when the direction reverses
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Backlash {
+    pub steps: u32,
+    last_direction: Option<Direction>,
+}
+
+impl Backlash {
+    /// Steps to add before moving in `direction`, non zero only when it
+    /// reverses the last known direction
+    pub fn compensation(&mut self, direction: Direction) -> u32 {
+        let steps = match self.last_direction {
+            Some(last) if last != direction => self.steps,
+            _ => 0,
+        };
+        self.last_direction = Some(direction);
+        steps
+    }
+
+    /// Records a move in `direction` that takes the backlash up by
+    /// itself, like tracking or a goto
+    pub fn set_direction(&mut self, direction: Direction) {
+        self.last_direction = Some(direction);
+    }
+
+    /// Forgets the last direction, e.g. after the axis is initialized
+    pub fn reset(&mut self) {
+        self.last_direction = None;
+    }
+}
+
+/// Operations, with their payload, taking up `steps` of backlash with a
+/// low speed relative goto in `direction`
+pub fn backlash_commands(direction: Direction, steps: u32) -> Vec<(EqModOp, Option<String>)> {
+    let mode = MotionMode {
+        kind: MotionKind::Goto,
+        speed: Speed::Low,
+        direction,
+    };
+    vec![
+        (EqModOp::SetMotionMode, Some(mode.payload())),
+        (
+            EqModOp::SetGotoTargetIncrement,
+            Some(u32_to_str_24bits(steps)),
+        ),
+        (EqModOp::StartMotion, None),
+    ]
+}
+
+/// Speed of an axis during a goto
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SlewPhase {
+    Stopped,
+    Slow,
+    Fast,
+}
+
+impl SlewPhase {
+    pub fn of(status: &AxisStatus) -> Self {
+        if !status.is_slewing() {
+            SlewPhase::Stopped
+        } else if status.high_speed {
+            SlewPhase::Fast
+        } else {
+            SlewPhase::Slow
+        }
+    }
+}
+
+/// Follows both axes through a goto from the polled statuses, an axis
+/// whose status couldn't be read keeps its last phase
+#[derive(Debug, Default)]
+pub struct SlewMonitor {
+    ra: Option<SlewPhase>,
+    dec: Option<SlewPhase>,
+}
+
+impl SlewMonitor {
+    /// Records the latest statuses and tells whether any axis is still
+    /// slewing
+    pub fn update(&mut self, ra: Option<AxisStatus>, dec: Option<AxisStatus>) -> bool {
+        for (axis, status, phase) in [
+            (Axis::Ra, ra, &mut self.ra),
+            (Axis::Dec, dec, &mut self.dec),
+        ] {
+            if let Some(s) = status {
+                let new = SlewPhase::of(&s);
+                if *phase != Some(new) {
+                    debug!("{:?} axis slew phase: {:?}", axis, new);
+                    *phase = Some(new);
+                }
+            }
+        }
+        self.is_slewing()
+    }
+
+    /// Forgets both phases, as after an emergency stop
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn is_slewing(&self) -> bool {
+        [self.ra, self.dec]
+            .iter()
+            .any(|p| p.is_some_and(|p| p != SlewPhase::Stopped))
+    }
+}
+
+/// Flags of an axis as answered to the axis status (:f) inquiry, three
+/// hex digits: motion mode, running state and init state
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AxisStatus {
+    /// Slew (tracking) motion mode, goto otherwise
+    pub tracking_mode: bool,
+    pub backwards: bool,
+    pub high_speed: bool,
+    pub running: bool,
+    pub blocked: bool,
+    pub initialized: bool,
+}
+
+impl AxisStatus {
+    /// Decodes the answer to the axis status inquiry, e.g. "101"
+    pub fn from_response(status: &str) -> Result<Self, ParseError> {
+        if status.len() != 3 {
+            return Err(ParseError::InvalidLength);
+        }
+        let digit = |i: usize| {
+            u8::from_str_radix(&status[i..i + 1], 16).map_err(|_| ParseError::InvalidHex)
+        };
+        let (mode, state, init) = (digit(0)?, digit(1)?, digit(2)?);
+
+        Ok(Self {
+            tracking_mode: mode & 0x1 != 0,
+            backwards: mode & 0x2 != 0,
+            high_speed: mode & 0x4 != 0,
+            running: state & 0x1 != 0,
+            blocked: state & 0x2 != 0,
+            initialized: init & 0x1 != 0,
+        })
+    }
+
+    /// Running a goto, as opposed to tracking or standing still
+    pub fn is_slewing(&self) -> bool {
+        self.running && !self.tracking_mode
+    }
+
+    /// Running in `mode`, only its step period can change on the fly
+    pub fn is_moving_in(&self, mode: MotionMode) -> bool {
+        self.running
+            && self.tracking_mode == (mode.kind == MotionKind::Tracking)
+            && self.high_speed == (mode.speed == Speed::High)
+            && self.backwards == (mode.direction == Direction::Backward)
+    }
+}
+
+impl fmt::Display for AxisStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = if !self.initialized {
+            "NotInitialized"
+        } else if self.blocked {
+            "Blocked"
+        } else if !self.running {
+            "Stopped"
+        } else if self.tracking_mode {
+            "Tracking"
+        } else {
+            "Goto"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Payload of the extended inquiry (:q) asking for the supported features
+pub const FEATURES_INQUIRY: &str = "010000";
+
+/// Optional features of a motor board
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Capability {
+    Encoder,
+    Ppec,
+    HomeIndexer,
+    AzEq,
+    PolarLed,
+    SnapPort,
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Capability::Encoder => "auxiliary encoder",
+            Capability::Ppec => "PPEC",
+            Capability::HomeIndexer => "home indexer",
+            Capability::AzEq => "AZ/EQ mode",
+            Capability::PolarLed => "polar scope LED",
+            Capability::SnapPort => "SNAP port",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Features advertised by the motor board in the answer to the
+/// extended inquiry, boards not knowing it support none of them
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MountCapabilities {
+    pub has_encoder: bool,
+    pub has_ppec: bool,
+    pub has_home_indexer: bool,
+    pub is_az_eq: bool,
+    pub in_ppec_training: bool,
+    pub in_ppec: bool,
+    pub has_polar_led: bool,
+    pub has_snap_port: bool,
+}
+
+impl MountCapabilities {
+    /// Decodes the 24 bits feature bitmap, e.g. "0B1000"
+    pub fn from_response(response: &str) -> Result<Self, ParseError> {
+        if response.len() != 6 {
+            return Err(ParseError::InvalidLength);
+        }
+        let bits = str_24bits_to_u24(response.to_owned()).ok_or(ParseError::InvalidHex)?;
+
+        Ok(Self {
+            has_encoder: bits & 0x000001 != 0,
+            has_ppec: bits & 0x000002 != 0,
+            has_home_indexer: bits & 0x000004 != 0,
+            is_az_eq: bits & 0x000008 != 0,
+            in_ppec_training: bits & 0x000010 != 0,
+            in_ppec: bits & 0x000020 != 0,
+            has_polar_led: bits & 0x001000 != 0,
+            has_snap_port: bits & 0x002000 != 0,
+        })
+    }
+
+    /// State of the periodic error correction, as shown to clients
+    pub fn ppec_status(&self) -> &'static str {
+        if self.in_ppec_training {
+            "Training"
+        } else if self.in_ppec {
+            "Active"
+        } else {
+            "Idle"
+        }
+    }
+
+    pub fn supports(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::Encoder => self.has_encoder,
+            Capability::Ppec => self.has_ppec,
+            Capability::HomeIndexer => self.has_home_indexer,
+            Capability::AzEq => self.is_az_eq,
+            Capability::PolarLed => self.has_polar_led,
+            Capability::SnapPort => self.has_snap_port,
+        }
+    }
+
+    /// Rejects commands for features the mount doesn't advertise so
+    /// they are never sent
+    pub fn require(&self, capability: Capability) -> Result<(), DeviceActions> {
+        if self.supports(capability) {
+            Ok(())
+        } else {
+            warn!("The mount has no {}", capability);
+            Err(DeviceActions::InvalidValue)
+        }
+    }
+}
+
+/// Axis digit and payload of the SetSnapPort (:O) command switching the
+/// given SNAP port (1 or 2) on or off, rejected when the mount has no
+/// SNAP port
+pub fn snap_port_command(
+    caps: &MountCapabilities,
+    port: u8,
+    on: bool,
+) -> Result<(Axis, String), DeviceActions> {
+    caps.require(Capability::SnapPort)?;
+    let axis = match port {
+        1 => Axis::Ra,
+        2 => Axis::Dec,
+        _ => return Err(DeviceActions::InvalidValue),
+    };
+    Ok((axis, String::from(if on { "1" } else { "0" })))
+}
+
+/// PPEC subcommands of the extended setting (:W) command
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PpecCommand {
+    StartTraining = 0x00,
+    StopTraining = 0x01,
+    Enable = 0x02,
+    Disable = 0x03,
+}
+
+/// Payload of the extended setting (:W) command for a PPEC subcommand,
+/// sent to the RA axis. Rejected when the mount has no PPEC and, as the
+/// recorded error would be meaningless otherwise, when training is asked
+/// while not tracking at sidereal rate
+pub fn ppec_payload(
+    caps: &MountCapabilities,
+    cmd: PpecCommand,
+    tracking: Option<TrackingRate>,
+) -> Result<String, DeviceActions> {
+    caps.require(Capability::Ppec)?;
+    if cmd == PpecCommand::StartTraining && tracking != Some(TrackingRate::Sidereal) {
+        warn!("PPEC training needs sidereal tracking to be running");
+        return Err(DeviceActions::InvalidValue);
+    }
+    Ok(u32_to_str_24bits(cmd as u32))
+}
+
+/// Payload of the extended setting (:W) command turning the auxiliary
+/// encoder of an axis on or off, rejected when the mount has none
+pub fn aux_encoder_payload(
+    caps: &MountCapabilities,
+    enabled: bool,
+) -> Result<String, DeviceActions> {
+    caps.require(Capability::Encoder)?;
+    Ok(u32_to_str_24bits(if enabled { 0x04 } else { 0x05 }))
+}
+
+/// Parses a 24 bits answer with the low byte first, e.g. "00B289" for a
+/// grid per revolution of 9024000. Error answers ("!0") are rejected
+pub fn parse_24bits_response(response: &str) -> Result<u32, ParseError> {
+    if response.len() != 6 {
+        return Err(ParseError::InvalidLength);
+    }
+    str_24bits_to_u24(response.to_owned()).ok_or(ParseError::InvalidHex)
+}
+
+/// Parses the answer to the auxiliary encoder inquiry (:d), a 24 bits
+/// count with the low byte first like the stepper position
+pub fn parse_encoder_position(response: &str) -> Result<u32, ParseError> {
+    parse_24bits_response(response)
+}
+
+/// Parses the answer to the motor board version inquiry (:e), e.g.
+/// "C84B01", into a dotted version like "200.75.1"
+pub fn parse_motor_board_version(response: &str) -> Result<String, ParseError> {
+    if response.len() != 6 || !response.is_ascii() {
+        return Err(ParseError::InvalidLength);
+    }
+    let part =
+        |i: usize| u8::from_str_radix(&response[i..i + 2], 16).map_err(|_| ParseError::InvalidHex);
+    Ok(format!("{}.{}.{}", part(0)?, part(2)?, part(4)?))
+}
+
+/// Parses a polar scope LED brightness as written by clients, 0 (off)
+/// to 255
+pub fn parse_polar_led_brightness(value: &str) -> Result<u8, DeviceActions> {
+    value
+        .trim()
+        .parse::<u8>()
+        .map_err(|_| DeviceActions::InvalidValue)
+}
+
+/// Payload of the SetPolarLed (:V) command, sent to the RA axis,
+/// rejected when the mount has no polar scope LED
+pub fn polar_led_payload(
+    caps: &MountCapabilities,
+    brightness: u8,
+) -> Result<String, DeviceActions> {
+    caps.require(Capability::PolarLed)?;
+    Ok(format!("{:02X}", brightness))
+}
+
+/// Errors answered by the motor controller as "!<code>"
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ControllerError {
+    UnknownCommand,
+    CommandLength,
+    MotorNotStopped,
+    InvalidCharacter,
+    NotInitialized,
+    DriverSleeping,
+    PecTrainingRunning,
+    NoValidPecData,
+    /// Code not documented, or an answer that is neither "=" nor "!"
+    Other(u8),
+}
+
+impl ControllerError {
+    pub fn from_code(code: u8) -> Self {
+        match code {
+            b'0' => ControllerError::UnknownCommand,
+            b'1' => ControllerError::CommandLength,
+            b'2' => ControllerError::MotorNotStopped,
+            b'3' => ControllerError::InvalidCharacter,
+            b'4' => ControllerError::NotInitialized,
+            b'5' => ControllerError::DriverSleeping,
+            b'7' => ControllerError::PecTrainingRunning,
+            b'8' => ControllerError::NoValidPecData,
+            c => ControllerError::Other(c),
+        }
+    }
+
+    /// Outcome reported to clients: malformed commands are a
+    /// communication problem, a moving motor makes the request invalid
+    /// for now and an uninitialized board needs to be set up again
+    pub fn device_action(&self) -> DeviceActions {
+        match self {
+            ControllerError::MotorNotStopped
+            | ControllerError::PecTrainingRunning
+            | ControllerError::NoValidPecData => DeviceActions::InvalidValue,
+            ControllerError::NotInitialized => DeviceActions::CannotConnect,
+            _ => DeviceActions::ComError,
+        }
+    }
+}
+
+impl fmt::Display for ControllerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ControllerError::UnknownCommand => write!(f, "unknown command"),
+            ControllerError::CommandLength => write!(f, "wrong command length"),
+            ControllerError::MotorNotStopped => write!(f, "motor not stopped"),
+            ControllerError::InvalidCharacter => write!(f, "invalid character"),
+            ControllerError::NotInitialized => write!(f, "motor not initialized"),
+            ControllerError::DriverSleeping => write!(f, "driver sleeping"),
+            ControllerError::PecTrainingRunning => write!(f, "PEC training running"),
+            ControllerError::NoValidPecData => write!(f, "no valid PEC data"),
+            ControllerError::Other(c) => write!(f, "unexpected answer {:?}", *c as char),
+        }
+    }
+}
+
+/// Splits an answer read up to the carriage return: "=<payload>" gives
+/// the payload, "!<code>" the decoded error
+pub fn split_response(buf: &[u8]) -> Result<&[u8], ControllerError> {
+    let buf = buf.strip_suffix(b"\r").unwrap_or(buf);
+    match buf.split_first() {
+        Some((b'=', payload)) => Ok(payload),
+        Some((b'!', code)) => Err(ControllerError::from_code(
+            code.first().copied().unwrap_or(0),
+        )),
+        Some((c, _)) => Err(ControllerError::Other(*c)),
+        None => Err(ControllerError::Other(0)),
+    }
+}
+
+/// Tells whether the controller has a third axis by asking for its
+/// position, controllers without it answer with an error or not at all
+pub fn probe_aux_axis<T: SerialTransport>(port: &mut T) -> bool {
+    let mut frame = command(EqModOp::GetAxisPosition, Axis::Aux);
+    frame.push(b'\r');
+    match port.exchange(&frame, b'\r') {
+        Ok(buf) => split_response(&buf).is_ok_and(|p| p.len() == 6),
+        Err(_) => false,
+    }
+}
+
+/// Builds the ":<op><axis>" frame for the given operation and axis,
+/// the payload and the trailing carriage return are added by the caller
+pub fn command(op: EqModOp, axis: Axis) -> Vec<u8> {
+    vec![b':', op as u8, axis.as_byte()]
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        aux_encoder_payload, backlash_commands, command, custom_rate_rad_per_sec, goto_commands,
+        parse_24bits_response, parse_encoder_position, parse_motor_board_version,
+        parse_polar_led_brightness, polar_led_payload, ppec_payload, probe_aux_axis, slew_motion,
+        snap_port_command, split_response, start_tracking_commands, Axis, AxisStatus, Backlash,
+        Capability, ControllerError, Direction, EqModOp, GuidePulse, GuideQueue, MotionKind,
+        MotionMode, MountCapabilities, PpecCommand, RunningPulse, SlewMonitor, SlewPhase, Speed,
+        TrackingRate, DEFAULT_BREAK_STEPS, HIGH_SPEED_MIN_RATE,
+    };
+    use crate::serial::read_response;
+    use crate::serial::test::ScriptedPort;
+    use crate::{
+        degrees_to_eqmod_steps, step_period_for_rate, str_24bits_to_u24, str_to_u16,
+        u32_to_str_24bits, ParseError, SIDEREAL_RATE,
+    };
+    use lightspeed_astro::devices::actions::DeviceActions;
+    use std::io::Cursor;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn frames() {
+        assert_eq!(command(EqModOp::Init, Axis::Ra), b":F1".to_vec());
+        assert_eq!(
+            command(EqModOp::GetAxisPosition, Axis::Dec),
+            b":j2".to_vec()
+        );
+        assert_eq!(command(EqModOp::GetAxisStatus, Axis::Ra), b":f1".to_vec());
+        assert_eq!(
+            command(EqModOp::InquireGridPerRevolution, Axis::Dec),
+            b":a2".to_vec()
+        );
+        assert_eq!(
+            command(EqModOp::MotorBoardVersion, Axis::Ra),
+            b":e1".to_vec()
+        );
+    }
+
+    #[test]
+    fn op_from_byte() {
+        let frame = command(EqModOp::SetGotoTarget, Axis::Dec);
+        assert_eq!(EqModOp::try_from(frame[1]), Ok(EqModOp::SetGotoTarget));
+        assert_eq!(EqModOp::try_from(b'q'), Ok(EqModOp::ExtendedInquiry));
+        assert!(EqModOp::try_from(b'z').is_err());
+    }
+
+    #[test]
+    fn all_lists_every_op() {
+        for byte in 0..=u8::MAX {
+            match EqModOp::try_from(byte) {
+                Ok(op) => assert!(EqModOp::ALL.contains(&op)),
+                Err(_) => assert!(EqModOp::ALL.iter().all(|op| *op as u8 != byte)),
+            }
+        }
+    }
+
+    #[test]
+    fn only_inquiries_are_idempotent() {
+        assert!(EqModOp::GetAxisPosition.is_idempotent());
+        assert!(EqModOp::GetAxisStatus.is_idempotent());
+        assert!(EqModOp::ExtendedInquiry.is_idempotent());
+        assert!(!EqModOp::SetGotoTarget.is_idempotent());
+        assert!(!EqModOp::StartMotion.is_idempotent());
+        assert!(!EqModOp::ExtendedSetting.is_idempotent());
+    }
+
+    #[test]
+    fn set_axis_position_targets_the_right_axis() {
+        assert_eq!(command(EqModOp::SetAxisPosition, Axis::Ra), b":E1".to_vec());
+        assert_eq!(
+            command(EqModOp::SetAxisPosition, Axis::Dec),
+            b":E2".to_vec()
+        );
+        assert_ne!(
+            command(EqModOp::SetAxisPosition, Axis::Ra),
+            command(EqModOp::SetAxisPosition, Axis::Dec)
+        );
+    }
+
+    #[test]
+    fn tracking_frames() {
+        assert_eq!(
+            command(EqModOp::InquireTimerInterruptFreq, Axis::Ra),
+            b":b1".to_vec()
+        );
+        assert_eq!(command(EqModOp::SetMotionMode, Axis::Ra), b":G1".to_vec());
+        assert_eq!(command(EqModOp::SetStepPeriod, Axis::Ra), b":I1".to_vec());
+        assert_eq!(command(EqModOp::StartMotion, Axis::Ra), b":J1".to_vec());
+        assert_eq!(command(EqModOp::StopMotion, Axis::Ra), b":K1".to_vec());
+    }
+
+    #[test]
+    fn axis_status_flags() {
+        let status = AxisStatus::from_response("101").unwrap();
+        assert!(status.tracking_mode && status.initialized);
+        assert!(!status.running && !status.blocked);
+        assert_eq!(status.to_string(), "Stopped");
+
+        assert_eq!(
+            AxisStatus::from_response("111").unwrap().to_string(),
+            "Tracking"
+        );
+        assert_eq!(
+            AxisStatus::from_response("011").unwrap().to_string(),
+            "Goto"
+        );
+        assert_eq!(
+            AxisStatus::from_response("121").unwrap().to_string(),
+            "Blocked"
+        );
+        assert_eq!(
+            AxisStatus::from_response("110").unwrap().to_string(),
+            "NotInitialized"
+        );
+
+        let status = AxisStatus::from_response("611").unwrap();
+        assert!(status.backwards && status.high_speed);
+
+        assert_eq!(
+            AxisStatus::from_response("10"),
+            Err(ParseError::InvalidLength)
+        );
+        assert_eq!(
+            AxisStatus::from_response("1G1"),
+            Err(ParseError::InvalidHex)
+        );
+    }
+
+    #[test]
+    fn known_axis_status_patterns() {
+        // Initialized and idle
+        let idle = AxisStatus::from_response("101").unwrap();
+        assert_eq!(
+            idle,
+            AxisStatus {
+                tracking_mode: true,
+                backwards: false,
+                high_speed: false,
+                running: false,
+                blocked: false,
+                initialized: true,
+            }
+        );
+
+        // Tracking forward at low speed
+        let tracking = AxisStatus::from_response("111").unwrap();
+        assert!(tracking.tracking_mode && tracking.running);
+        assert!(!tracking.backwards && !tracking.high_speed);
+
+        // Fast goto backwards
+        let goto = AxisStatus::from_response("611").unwrap();
+        assert!(!goto.tracking_mode && goto.running);
+        assert!(goto.backwards && goto.high_speed);
+        assert_eq!(goto.to_string(), "Goto");
+
+        // Blocked motor
+        let blocked = AxisStatus::from_response("021").unwrap();
+        assert!(blocked.blocked && !blocked.running);
+        assert_eq!(blocked.to_string(), "Blocked");
+    }
+
+    #[test]
+    fn sidereal_tracking_sequence() {
+        // EQ6: 9024000 steps per revolution, 64935 Hz timer
+        let frames: Vec<Vec<u8>> =
+            start_tracking_commands(TrackingRate::Sidereal, 9_024_000, 64935, None)
+                .into_iter()
+                .map(|(op, payload)| {
+                    let mut frame = command(op, Axis::Ra);
+                    frame.extend(payload.unwrap_or_default().bytes());
+                    frame.push(b'\r');
+                    frame
+                })
+                .collect();
+
+        assert_eq!(
+            frames,
+            vec![
+                b":K1\r".to_vec(),
+                b":G110\r".to_vec(),
+                // 620 timer ticks per step, 24 bits little endian
+                b":I16C0200\r".to_vec(),
+                b":J1\r".to_vec(),
+            ]
+        );
+    }
+
+    #[test]
+    fn custom_tracking_rate() {
+        // 15.041"/s is the sidereal rate
+        let custom = TrackingRate::custom(15.041).unwrap();
+        let sidereal = step_period_for_rate(SIDEREAL_RATE, 9_024_000, 64935);
+        let period = step_period_for_rate(custom.rad_per_sec(), 9_024_000, 64935);
+        assert!(period.abs_diff(sidereal) <= 1);
+        assert_eq!(custom.to_string(), "Custom");
+
+        // Up to 16x sidereal
+        assert!(TrackingRate::custom(16.0 * 15.041).is_some());
+        assert_eq!(TrackingRate::custom(17.0 * 15.041), None);
+        assert_eq!(TrackingRate::custom(0.0), None);
+        assert_eq!(TrackingRate::custom(-15.0), None);
+        assert_eq!(TrackingRate::custom(f64::NAN), None);
+        // Either direction for the DEC axis
+        assert!(custom_rate_rad_per_sec(-100.0).is_some_and(|r| r < 0.0));
+        assert_eq!(custom_rate_rad_per_sec(-300.0), None);
+    }
+
+    #[test]
+    fn tracking_rate_change_on_the_fly() {
+        let status = |s| Some(AxisStatus::from_response(s).unwrap());
+        let solar = TrackingRate::Solar;
+        // Already tracking forward at low speed, only the period changes
+        assert_eq!(
+            start_tracking_commands(solar, 9_024_000, 64935, status("111")),
+            vec![(EqModOp::SetStepPeriod, Some(u32_to_str_24bits(622)))]
+        );
+        // Stopped, running a goto, backwards or at high speed: full sequence
+        for s in ["101", "011", "311", "511"] {
+            assert_eq!(
+                start_tracking_commands(solar, 9_024_000, 64935, status(s)).len(),
+                4
+            );
+        }
+    }
+
+    #[test]
+    fn tracking_rate_names() {
+        for rate in TrackingRate::ALL {
+            assert_eq!(rate.to_string().parse::<TrackingRate>(), Ok(rate));
+        }
+        assert_eq!("lunar".parse::<TrackingRate>(), Ok(TrackingRate::Lunar));
+        assert_eq!("Off".parse::<TrackingRate>(), Err(ParseError::UnknownValue));
+    }
+
+    #[test]
+    fn motion_mode_payloads() {
+        let goto_fast_forward = MotionMode {
+            kind: MotionKind::Goto,
+            speed: Speed::High,
+            direction: Direction::Forward,
+        };
+        let mut frame = command(EqModOp::SetMotionMode, Axis::Dec);
+        frame.extend(goto_fast_forward.payload().bytes());
+        assert_eq!(frame, b":G200".to_vec());
+
+        assert_eq!(MotionMode::TRACKING.payload(), "10");
+        let slow_goto_back = MotionMode {
+            kind: MotionKind::Goto,
+            speed: Speed::Low,
+            direction: Direction::Backward,
+        };
+        assert_eq!(slow_goto_back.payload(), "21");
+        let fast_slew_back = MotionMode {
+            kind: MotionKind::Tracking,
+            speed: Speed::High,
+            direction: Direction::Backward,
+        };
+        assert_eq!(fast_slew_back.payload(), "31");
+    }
+
+    #[test]
+    fn stop_frames() {
+        assert_eq!(command(EqModOp::StopMotion, Axis::Dec), b":K2".to_vec());
+        assert_eq!(command(EqModOp::InstantStop, Axis::Ra), b":L1".to_vec());
+        assert_eq!(command(EqModOp::InstantStop, Axis::Dec), b":L2".to_vec());
+    }
+
+    #[test]
+    fn goto_target_steps() {
+        // EQ6 grid per revolution
+        let spr = 9024000;
+        assert_eq!(degrees_to_eqmod_steps(90.0, spr), 0x800000 + 2256000);
+        assert_eq!(degrees_to_eqmod_steps(-45.0, spr), 0x800000 - 1128000);
+
+        let stopped = AxisStatus::from_response("101").unwrap();
+        let target = degrees_to_eqmod_steps(90.0, spr);
+        let frames: Vec<Vec<u8>> =
+            goto_commands(&stopped, 0x800000, target, spr, DEFAULT_BREAK_STEPS)
+                .unwrap()
+                .into_iter()
+                .map(|(op, payload)| {
+                    let mut frame = command(op, Axis::Dec);
+                    frame.extend(payload.unwrap_or_default().bytes());
+                    frame
+                })
+                .collect();
+        assert_eq!(
+            frames,
+            vec![
+                b":G200".to_vec(),
+                b":S2806CA2".to_vec(),
+                b":M2AC0D00".to_vec(),
+                b":J2".to_vec(),
+            ]
+        );
+    }
+
+    #[test]
+    fn goto_direction_and_speed() {
+        let spr = 9024000;
+        let stopped = AxisStatus::from_response("101").unwrap();
+        let mode =
+            |current, target| goto_commands(&stopped, current, target, spr, 0).unwrap()[0].clone();
+        // One degree back
+        assert_eq!(
+            mode(0x800000, 0x800000 - 25067),
+            (EqModOp::SetMotionMode, Some(String::from("21")))
+        );
+        // Half a turn forward
+        assert_eq!(
+            mode(0x800000, 0x800000 + spr / 2),
+            (EqModOp::SetMotionMode, Some(String::from("00")))
+        );
+    }
+
+    #[test]
+    fn goto_refused_while_running() {
+        let spr = 9024000;
+        let tracking = AxisStatus::from_response("111").unwrap();
+        let goto = AxisStatus::from_response("011").unwrap();
+        assert!(goto_commands(&tracking, 0x800000, 0x900000, spr, 0).is_none());
+        assert!(goto_commands(&goto, 0x800000, 0x900000, spr, 0).is_none());
+        assert!(!tracking.is_slewing());
+        assert!(goto.is_slewing());
+
+        // Accepted once the axis has been stopped
+        let stopped = AxisStatus::from_response("101").unwrap();
+        assert!(goto_commands(&stopped, 0x800000, 0x900000, spr, 0).is_some());
+        assert!(!stopped.is_slewing());
+    }
+
+    #[test]
+    fn eq6_rates() {
+        // Answers of an EQ6 to :a1, :b1 and :g1
+        let spr = str_24bits_to_u24(String::from("00B289")).unwrap();
+        let timer_freq = str_24bits_to_u24(String::from("A7FD00")).unwrap();
+        let ratio = str_to_u16(String::from("10")).unwrap();
+        assert_eq!((spr, timer_freq, ratio), (9024000, 64935, 16));
+
+        let (mode, period) = slew_motion(SIDEREAL_RATE, spr, timer_freq, ratio);
+        assert_eq!(mode, MotionMode::TRACKING);
+        assert_eq!(period, 620);
+
+        let (mode, period) = slew_motion(-HIGH_SPEED_MIN_RATE * 4.0, spr, timer_freq, ratio);
+        assert_eq!(mode.payload(), "31");
+        // 512x sidereal, 16 steps at a time
+        assert_eq!(period, 19);
+
+        // Without a known ratio the slew stays at low speed
+        let (mode, _) = slew_motion(HIGH_SPEED_MIN_RATE, spr, timer_freq, 0);
+        assert_eq!(mode.speed, Speed::Low);
+    }
+
+    #[test]
+    fn capabilities_of_an_eq6r() {
+        // Encoders, PPEC, home indexer and polar LED
+        let caps = MountCapabilities::from_response("071000").unwrap();
+        assert!(caps.has_encoder && caps.has_ppec && caps.has_home_indexer);
+        assert!(caps.has_polar_led);
+        assert!(!caps.is_az_eq && !caps.has_snap_port);
+        assert!(!caps.in_ppec && !caps.in_ppec_training);
+        assert_eq!(caps.require(Capability::Ppec), Ok(()));
+        assert_eq!(
+            caps.require(Capability::SnapPort),
+            Err(DeviceActions::InvalidValue)
+        );
+    }
+
+    #[test]
+    fn capabilities_of_an_az_eq6() {
+        // AZ/EQ with SNAP port, PPEC enabled
+        let caps = MountCapabilities::from_response("2A2000").unwrap();
+        assert!(caps.is_az_eq && caps.has_ppec && caps.in_ppec);
+        assert!(caps.has_snap_port);
+        assert!(!caps.has_encoder && !caps.has_polar_led);
+        assert_eq!(caps.require(Capability::SnapPort), Ok(()));
+        assert_eq!(
+            caps.require(Capability::Encoder),
+            Err(DeviceActions::InvalidValue)
+        );
+
+        assert_eq!(
+            MountCapabilities::from_response("2A20"),
+            Err(ParseError::InvalidLength)
+        );
+        assert_eq!(
+            MountCapabilities::from_response("ZZ2000"),
+            Err(ParseError::InvalidHex)
+        );
+        assert!(!MountCapabilities::default().supports(Capability::AzEq));
+    }
+
+    #[test]
+    fn features_inquiry_frame() {
+        let mut frame = command(EqModOp::ExtendedInquiry, Axis::Ra);
+        frame.extend(super::FEATURES_INQUIRY.bytes());
+        assert_eq!(frame, b":q1010000".to_vec());
+    }
+
+    #[test]
+    fn snap_port_frames() {
+        let caps = MountCapabilities::from_response("002000").unwrap();
+        let frame = |port, on| {
+            let (axis, payload) = snap_port_command(&caps, port, on).unwrap();
+            let mut frame = command(EqModOp::SetSnapPort, axis);
+            frame.extend(payload.bytes());
+            frame
+        };
+        assert_eq!(frame(1, true), b":O11".to_vec());
+        assert_eq!(frame(1, false), b":O10".to_vec());
+        assert_eq!(frame(2, true), b":O21".to_vec());
+        assert_eq!(
+            snap_port_command(&caps, 3, true),
+            Err(DeviceActions::InvalidValue)
+        );
+    }
+
+    #[test]
+    fn snap_port_needs_the_capability() {
+        let caps = MountCapabilities::from_response("071000").unwrap();
+        assert_eq!(
+            snap_port_command(&caps, 1, true),
+            Err(DeviceActions::InvalidValue)
+        );
+        assert_eq!(
+            snap_port_command(&MountCapabilities::default(), 1, false),
+            Err(DeviceActions::InvalidValue)
+        );
+    }
+
+    #[test]
+    fn polar_led_brightness() {
+        assert_eq!(parse_polar_led_brightness("0"), Ok(0));
+        assert_eq!(parse_polar_led_brightness(" 128 "), Ok(128));
+        assert_eq!(parse_polar_led_brightness("255"), Ok(255));
+        for value in ["256", "-1", "", "bright", "12.5"] {
+            assert_eq!(
+                parse_polar_led_brightness(value),
+                Err(DeviceActions::InvalidValue)
+            );
+        }
+
+        let caps = MountCapabilities::from_response("071000").unwrap();
+        let mut frame = command(EqModOp::SetPolarLed, Axis::Ra);
+        frame.extend(polar_led_payload(&caps, 200).unwrap().bytes());
+        assert_eq!(frame, b":V1C8".to_vec());
+        assert_eq!(polar_led_payload(&caps, 5).unwrap(), "05");
+
+        assert_eq!(
+            polar_led_payload(&MountCapabilities::default(), 200),
+            Err(DeviceActions::InvalidValue)
+        );
+    }
+
+    #[test]
+    fn ppec_payloads() {
+        let caps = MountCapabilities::from_response("071000").unwrap();
+        let sidereal = Some(TrackingRate::Sidereal);
+        let payload = |cmd| ppec_payload(&caps, cmd, sidereal).unwrap();
+        assert_eq!(payload(PpecCommand::StartTraining), "000000");
+        assert_eq!(payload(PpecCommand::StopTraining), "010000");
+        assert_eq!(payload(PpecCommand::Enable), "020000");
+        assert_eq!(payload(PpecCommand::Disable), "030000");
+
+        let mut frame = command(EqModOp::ExtendedSetting, Axis::Ra);
+        frame.extend(payload(PpecCommand::Enable).bytes());
+        assert_eq!(frame, b":W1020000".to_vec());
+    }
+
+    #[test]
+    fn ppec_guards() {
+        let caps = MountCapabilities::from_response("071000").unwrap();
+        // Training needs sidereal tracking
+        for tracking in [None, Some(TrackingRate::Lunar), Some(TrackingRate::Solar)] {
+            assert_eq!(
+                ppec_payload(&caps, PpecCommand::StartTraining, tracking),
+                Err(DeviceActions::InvalidValue)
+            );
+        }
+        assert!(ppec_payload(&caps, PpecCommand::StopTraining, None).is_ok());
+        assert!(ppec_payload(&caps, PpecCommand::Disable, None).is_ok());
+
+        // Nothing is sent to mounts without PPEC
+        let no_ppec = MountCapabilities::from_response("002000").unwrap();
+        assert_eq!(
+            ppec_payload(&no_ppec, PpecCommand::Enable, Some(TrackingRate::Sidereal)),
+            Err(DeviceActions::InvalidValue)
+        );
+    }
+
+    #[test]
+    fn ppec_status() {
+        let status = |resp| {
+            MountCapabilities::from_response(resp)
+                .unwrap()
+                .ppec_status()
+        };
+        assert_eq!(status("020000"), "Idle");
+        assert_eq!(status("220000"), "Active");
+        assert_eq!(status("120000"), "Training");
+    }
+
+    #[test]
+    fn aux_encoder_payloads() {
+        let caps = MountCapabilities::from_response("071000").unwrap();
+        let mut frame = command(EqModOp::ExtendedSetting, Axis::Dec);
+        frame.extend(aux_encoder_payload(&caps, true).unwrap().bytes());
+        assert_eq!(frame, b":W2040000".to_vec());
+        assert_eq!(aux_encoder_payload(&caps, false).unwrap(), "050000");
+
+        let no_encoder = MountCapabilities::from_response("2A2000").unwrap();
+        assert_eq!(
+            aux_encoder_payload(&no_encoder, true),
+            Err(DeviceActions::InvalidValue)
+        );
+    }
+
+    #[test]
+    fn encoder_positions() {
+        assert_eq!(
+            command(EqModOp::InquireAuxEncoder, Axis::Ra),
+            b":d1".to_vec()
+        );
+        assert_eq!(parse_encoder_position("000080"), Ok(0x800000));
+        assert_eq!(parse_encoder_position("806CA2"), Ok(0xA26C80));
+        assert_eq!(
+            parse_encoder_position("0080"),
+            Err(ParseError::InvalidLength)
+        );
+        assert_eq!(
+            parse_encoder_position("00G080"),
+            Err(ParseError::InvalidHex)
+        );
+    }
+
+    #[test]
+    fn guide_pulse_values() {
+        assert_eq!(
+            "+350".parse::<GuidePulse>(),
+            Ok(GuidePulse {
+                direction: Direction::Forward,
+                duration_ms: 350
+            })
+        );
+        assert_eq!(
+            " -200 ".parse::<GuidePulse>(),
+            Ok(GuidePulse {
+                direction: Direction::Backward,
+                duration_ms: 200
+            })
+        );
+        assert_eq!(
+            "75".parse::<GuidePulse>().unwrap().direction,
+            Direction::Forward
+        );
+        for value in ["0", "+", "-", "1.5", "north", "--20"] {
+            assert_eq!(value.parse::<GuidePulse>(), Err(ParseError::UnknownValue));
+        }
+
+        let pulse: GuidePulse = "-200".parse().unwrap();
+        assert_eq!(pulse.rate_offset(0.5), -SIDEREAL_RATE / 2.0);
+    }
+
+    fn running(end: Instant) -> RunningPulse {
+        RunningPulse {
+            end,
+            period_only: true,
+        }
+    }
+
+    #[test]
+    fn overlapping_pulses_are_queued() {
+        let mut queue = GuideQueue::default();
+        let (first, second) = ("+350".parse().unwrap(), "-200".parse().unwrap());
+        queue.push(Axis::Ra, first);
+        queue.push(Axis::Ra, second);
+
+        let start = Instant::now();
+        assert_eq!(queue.take_next(), Some((Axis::Ra, first)));
+        let end = start + Duration::from_millis(350);
+        queue.started(Axis::Ra, running(end));
+        // The second one waits for the first to end
+        assert_eq!(queue.take_next(), None);
+        assert_eq!(queue.next_end(), Some(end));
+        assert!(queue.take_ended(start).is_empty());
+
+        assert_eq!(queue.take_ended(end), vec![(Axis::Ra, running(end))]);
+        assert_eq!(queue.take_next(), Some((Axis::Ra, second)));
+        assert_eq!(queue.take_next(), None);
+        assert_eq!(queue.next_end(), None);
+    }
+
+    #[test]
+    fn pulses_on_different_axes_overlap() {
+        let mut queue = GuideQueue::default();
+        queue.push(Axis::Ra, "+350".parse().unwrap());
+        queue.push(Axis::Dec, "+100".parse().unwrap());
+
+        let start = Instant::now();
+        let ra_end = start + Duration::from_millis(350);
+        let dec_end = start + Duration::from_millis(100);
+        assert_eq!(queue.take_next().map(|(axis, _)| axis), Some(Axis::Ra));
+        queue.started(Axis::Ra, running(ra_end));
+        assert_eq!(queue.take_next().map(|(axis, _)| axis), Some(Axis::Dec));
+        queue.started(Axis::Dec, running(dec_end));
+        assert_eq!(queue.next_end(), Some(dec_end));
+
+        assert_eq!(
+            queue.take_ended(dec_end),
+            vec![(Axis::Dec, running(dec_end))]
+        );
+        assert_eq!(queue.next_end(), Some(ra_end));
+
+        queue.push(Axis::Ra, "+350".parse().unwrap());
+        queue.clear();
+        assert_eq!(queue.next_end(), None);
+        assert_eq!(queue.take_next(), None);
+    }
+
+    #[test]
+    fn motor_board_version() {
+        assert_eq!(
+            parse_motor_board_version("C84B01"),
+            Ok(String::from("200.75.1"))
+        );
+        assert_eq!(
+            parse_motor_board_version("020402"),
+            Ok(String::from("2.4.2"))
+        );
+        assert_eq!(
+            parse_motor_board_version("!0"),
+            Err(ParseError::InvalidLength)
+        );
+        assert_eq!(
+            parse_motor_board_version("!00000"),
+            Err(ParseError::InvalidHex)
+        );
+    }
+
+    #[test]
+    fn grid_per_revolution() {
+        assert_eq!(parse_24bits_response("00B289"), Ok(9024000));
+        assert_eq!(parse_24bits_response("C84B01"), Ok(0x014BC8));
+        assert_eq!(parse_24bits_response("!2"), Err(ParseError::InvalidLength));
+        assert_eq!(parse_24bits_response("!20000"), Err(ParseError::InvalidHex));
+    }
+
+    #[test]
+    fn controller_answers() {
+        let answer = |wire: &'static [u8]| {
+            let mut port = Cursor::new(wire);
+            let buf = read_response(&mut port, b'\r', 16, Duration::from_millis(20)).unwrap();
+            split_response(&buf).map(|p| p.to_vec())
+        };
+
+        assert_eq!(answer(b"=806CA2\r"), Ok(b"806CA2".to_vec()));
+        assert_eq!(answer(b"=\r"), Ok(Vec::new()));
+        assert_eq!(answer(b"!0\r"), Err(ControllerError::UnknownCommand));
+        assert_eq!(answer(b"!1\r"), Err(ControllerError::CommandLength));
+        assert_eq!(answer(b"!2\r"), Err(ControllerError::MotorNotStopped));
+        assert_eq!(answer(b"!3\r"), Err(ControllerError::InvalidCharacter));
+        assert_eq!(answer(b"!4\r"), Err(ControllerError::NotInitialized));
+        assert_eq!(answer(b"!5\r"), Err(ControllerError::DriverSleeping));
+        assert_eq!(answer(b"!7\r"), Err(ControllerError::PecTrainingRunning));
+        assert_eq!(answer(b"!8\r"), Err(ControllerError::NoValidPecData));
+        assert_eq!(answer(b"!9\r"), Err(ControllerError::Other(b'9')));
+        assert_eq!(answer(b"?12\r"), Err(ControllerError::Other(b'?')));
+    }
+
+    #[test]
+    fn controller_errors_to_device_actions() {
+        assert_eq!(
+            ControllerError::MotorNotStopped.device_action(),
+            DeviceActions::InvalidValue
+        );
+        assert_eq!(
+            ControllerError::NotInitialized.device_action(),
+            DeviceActions::CannotConnect
+        );
+        for e in [
+            ControllerError::UnknownCommand,
+            ControllerError::CommandLength,
+            ControllerError::InvalidCharacter,
+            ControllerError::DriverSleeping,
+            ControllerError::Other(b'?'),
+        ] {
+            assert_eq!(e.device_action(), DeviceActions::ComError);
+        }
+        assert_eq!(
+            ControllerError::MotorNotStopped.to_string(),
+            "motor not stopped"
+        );
+    }
+
+    #[test]
+    fn short_gotos_have_no_break_point() {
+        let spr = 9024000;
+        let stopped = AxisStatus::from_response("101").unwrap();
+        // One degree, low speed all the way
+        let ops: Vec<EqModOp> = goto_commands(&stopped, 0x800000, 0x800000 + 25067, spr, 3500)
+            .unwrap()
+            .into_iter()
+            .map(|(op, _)| op)
+            .collect();
+        assert_eq!(
+            ops,
+            [
+                EqModOp::SetMotionMode,
+                EqModOp::SetGotoTarget,
+                EqModOp::StartMotion
+            ]
+        );
+
+        // The break point never lies behind the starting position
+        let commands = goto_commands(&stopped, 0x800000, 0x900000, spr, 0x200000).unwrap();
+        assert_eq!(
+            commands[2],
+            (
+                EqModOp::SetBreakPointIncrement,
+                Some(String::from("000010"))
+            )
+        );
+    }
+
+    #[test]
+    fn slew_phases() {
+        let status = |s| Some(AxisStatus::from_response(s).unwrap());
+        let mut monitor = SlewMonitor::default();
+        assert!(!monitor.is_slewing());
+
+        // RA running fast, DEC already slowing down
+        assert!(monitor.update(status("411"), status("011")));
+        assert_eq!(SlewPhase::of(&status("411").unwrap()), SlewPhase::Fast);
+        // RA slowing down, DEC status unreadable
+        assert!(monitor.update(status("011"), None));
+        // RA stopped, DEC still slewing as last seen
+        assert!(monitor.update(status("001"), None));
+        // Both stopped
+        assert!(!monitor.update(status("001"), status("201")));
+
+        // Tracking isn't slewing
+        assert!(!monitor.update(status("111"), status("001")));
+
+        // An emergency stop forgets the goto in flight
+        assert!(monitor.update(status("411"), None));
+        monitor.reset();
+        assert!(!monitor.is_slewing());
+    }
+
+    #[test]
+    fn backlash_on_reversal() {
+        let mut dec = Backlash {
+            steps: 200,
+            ..Default::default()
+        };
+        // Unknown direction after init
+        assert_eq!(dec.compensation(Direction::Forward), 0);
+        assert_eq!(dec.compensation(Direction::Backward), 200);
+        // Never twice in the same direction
+        assert_eq!(dec.compensation(Direction::Backward), 0);
+        assert_eq!(dec.compensation(Direction::Backward), 0);
+        assert_eq!(dec.compensation(Direction::Forward), 200);
+
+        dec.reset();
+        assert_eq!(dec.compensation(Direction::Backward), 0);
+
+        // Tracking leaves the gears engaged forward
+        dec.set_direction(Direction::Forward);
+        assert_eq!(dec.compensation(Direction::Forward), 0);
+        assert_eq!(dec.compensation(Direction::Backward), 200);
+    }
+
+    #[test]
+    fn moving_in_a_mode() {
+        let tracking = AxisStatus::from_response("111").unwrap();
+        assert!(tracking.is_moving_in(MotionMode::TRACKING));
+        let backwards = MotionMode {
+            direction: Direction::Backward,
+            ..MotionMode::TRACKING
+        };
+        assert!(!tracking.is_moving_in(backwards));
+        assert!(AxisStatus::from_response("311")
+            .unwrap()
+            .is_moving_in(backwards));
+        // Stopped, or at high speed
+        assert!(!AxisStatus::from_response("101")
+            .unwrap()
+            .is_moving_in(MotionMode::TRACKING));
+        assert!(!AxisStatus::from_response("511")
+            .unwrap()
+            .is_moving_in(MotionMode::TRACKING));
+    }
+
+    #[test]
+    fn zero_backlash() {
+        let mut ra = Backlash::default();
+        assert_eq!(ra.compensation(Direction::Forward), 0);
+        assert_eq!(ra.compensation(Direction::Backward), 0);
+        assert_eq!(ra.compensation(Direction::Forward), 0);
+    }
+
+    #[test]
+    fn backlash_frames() {
+        let frames: Vec<Vec<u8>> = backlash_commands(Direction::Backward, 200)
+            .into_iter()
+            .map(|(op, payload)| {
+                let mut frame = command(op, Axis::Dec);
+                frame.extend(payload.unwrap_or_default().bytes());
+                frame
+            })
+            .collect();
+        assert_eq!(
+            frames,
+            vec![b":G221".to_vec(), b":H2C80000".to_vec(), b":J2".to_vec()]
+        );
+    }
+
+    #[test]
+    fn aux_axis_frames() {
+        assert_eq!(
+            command(EqModOp::GetAxisPosition, Axis::Aux),
+            b":j3".to_vec()
+        );
+        assert_eq!(command(EqModOp::StopMotion, Axis::Aux), b":K3".to_vec());
+        // The two main axes are untouched
+        assert_eq!(command(EqModOp::StopMotion, Axis::Ra), b":K1".to_vec());
+        assert_eq!(command(EqModOp::StopMotion, Axis::Dec), b":K2".to_vec());
+    }
+
+    #[test]
+    fn aux_axis_present() {
+        let mut port = ScriptedPort {
+            script: vec![(b":j3\r", b"=000080\r")],
+            pending: Vec::new(),
+        };
+        assert!(probe_aux_axis(&mut port));
+    }
+
+    #[test]
+    fn aux_axis_absent() {
+        // Rejected by the controller
+        let mut port = ScriptedPort {
+            script: vec![(b":j3\r", b"!0\r")],
+            pending: Vec::new(),
+        };
+        assert!(!probe_aux_axis(&mut port));
+
+        // Not answered
+        let mut port = ScriptedPort {
+            script: vec![],
+            pending: Vec::new(),
+        };
+        assert!(!probe_aux_axis(&mut port));
+    }
+}
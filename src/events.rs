@@ -0,0 +1,199 @@
+use lightspeed_astro::props::Property;
+use log::warn;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast::{self, error::RecvError};
+use uuid::Uuid;
+
+/// Changes kept for each subscriber, a subscriber that falls further
+/// behind loses the oldest ones
+pub const EVENT_QUEUE_SIZE: usize = 256;
+
+/// A property of a device that took a new value
+#[derive(Clone, Debug, PartialEq)]
+pub struct PropertyEvent {
+    pub device_id: String,
+    pub property_name: String,
+    pub value: String,
+    /// Milliseconds since the Unix epoch
+    pub timestamp: u64,
+}
+
+/// Property changes of one device, for clients that would rather be
+/// told than poll GetDevices. Publishing never waits for subscribers,
+/// the polling loop goes on whatever they do.
+#[derive(Debug)]
+pub struct PropertyEvents {
+    device_id: String,
+    sender: broadcast::Sender<PropertyEvent>,
+    /// Values as of the last publish, in the order of the properties
+    last: Mutex<Vec<(String, String)>>,
+}
+
+impl PropertyEvents {
+    pub fn new(device_id: Uuid) -> Self {
+        Self::with_capacity(device_id, EVENT_QUEUE_SIZE)
+    }
+
+    pub fn with_capacity(device_id: Uuid, capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self {
+            device_id: device_id.to_string(),
+            sender,
+            last: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Sends the properties whose value changed since the last call
+    pub fn publish(&self, props: &[Property]) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        let mut last = self.last.lock().unwrap();
+        for prop in props {
+            match last.iter_mut().find(|(name, _)| *name == prop.name) {
+                Some((_, value)) if *value == prop.value => continue,
+                Some((_, value)) => *value = prop.value.clone(),
+                None => last.push((prop.name.clone(), prop.value.clone())),
+            }
+            // Only fails when nobody is subscribed
+            let _ = self.sender.send(PropertyEvent {
+                device_id: self.device_id.clone(),
+                property_name: prop.name.clone(),
+                value: prop.value.clone(),
+                timestamp,
+            });
+        }
+    }
+
+    /// Every property as last published, then the changes from there on
+    pub fn subscribe(&self) -> Subscription {
+        // Under the lock no change can fall between the two
+        let last = self.last.lock().unwrap();
+        let receiver = self.sender.subscribe();
+        let snapshot = last
+            .iter()
+            .map(|(name, value)| PropertyEvent {
+                device_id: self.device_id.clone(),
+                property_name: name.clone(),
+                value: value.clone(),
+                timestamp: 0,
+            })
+            .collect();
+        Subscription { snapshot, receiver }
+    }
+}
+
+/// Events of a device for one subscriber
+#[derive(Debug)]
+pub struct Subscription {
+    snapshot: VecDeque<PropertyEvent>,
+    receiver: broadcast::Receiver<PropertyEvent>,
+}
+
+impl Subscription {
+    /// The next event, None once the device is gone. Snapshot events
+    /// have a zero timestamp.
+    pub async fn next(&mut self) -> Option<PropertyEvent> {
+        if let Some(event) = self.snapshot.pop_front() {
+            return Some(event);
+        }
+        loop {
+            match self.receiver.recv().await {
+                Ok(event) => return Some(event),
+                Err(RecvError::Lagged(n)) => warn!("Subscriber too slow, {} changes dropped", n),
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PropertyEvent, PropertyEvents};
+    use lightspeed_astro::props::Property;
+    use uuid::Uuid;
+
+    fn props(values: &[(&str, &str)]) -> Vec<Property> {
+        values
+            .iter()
+            .map(|(name, value)| Property {
+                name: name.to_string(),
+                value: value.to_string(),
+                kind: String::from("string"),
+                permission: 0,
+            })
+            .collect()
+    }
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    fn change(event: Option<PropertyEvent>) -> (String, String) {
+        let event = event.unwrap();
+        (event.property_name, event.value)
+    }
+
+    #[test]
+    fn snapshot_then_changes() {
+        let id = Uuid::new_v4();
+        let events = PropertyEvents::new(id);
+        events.publish(&props(&[("TRACKING_MODE", "Off"), ("RA", "10.0")]));
+
+        let mut first = events.subscribe();
+        let mut second = events.subscribe();
+        events.publish(&props(&[("TRACKING_MODE", "Eq"), ("RA", "10.0")]));
+
+        runtime().block_on(async {
+            for subscription in [&mut first, &mut second] {
+                let snapshot = subscription.next().await.unwrap();
+                assert_eq!(snapshot.device_id, id.to_string());
+                assert_eq!(snapshot.timestamp, 0);
+                assert_eq!(
+                    (snapshot.property_name, snapshot.value),
+                    (String::from("TRACKING_MODE"), String::from("Off"))
+                );
+                assert_eq!(
+                    change(subscription.next().await),
+                    (String::from("RA"), String::from("10.0"))
+                );
+                // RA didn't change, only the tracking mode is sent
+                let event = subscription.next().await.unwrap();
+                assert!(event.timestamp > 0);
+                assert_eq!(
+                    (event.property_name, event.value),
+                    (String::from("TRACKING_MODE"), String::from("Eq"))
+                );
+            }
+        });
+    }
+
+    #[test]
+    fn slow_subscribers_lose_the_oldest_changes() {
+        let events = PropertyEvents::with_capacity(Uuid::new_v4(), 2);
+        let mut slow = events.subscribe();
+        for ra in ["1", "2", "3", "4", "5"] {
+            events.publish(&props(&[("RA", ra)]));
+        }
+        runtime().block_on(async {
+            assert_eq!(
+                change(slow.next().await),
+                (String::from("RA"), String::from("4"))
+            );
+            assert_eq!(
+                change(slow.next().await),
+                (String::from("RA"), String::from("5"))
+            );
+        });
+
+        // Gone with the device
+        drop(events);
+        assert_eq!(runtime().block_on(slow.next()), None);
+    }
+}
@@ -0,0 +1,105 @@
+use crate::angular_separation_deg;
+
+/// Two consecutive positions closer than this are considered settled
+pub const SETTLE_TOLERANCE_ARCSEC: f64 = 5.0;
+/// Errors above this are likely due to encoding or alignment problems
+pub const GOTO_ERROR_WARNING_ARCSEC: f64 = 300.0;
+
+/// Compares the position the mount settles on after a goto with the
+/// commanded target. Positions only count once the goto was reported
+/// in progress and then over, the mount stands still right before it
+/// starts slewing too.
+#[derive(Debug)]
+pub struct GotoCheck {
+    target: (f64, f64),
+    last: Option<(f64, f64)>,
+    /// Goto reported in progress so far, and in the last poll
+    seen_slewing: bool,
+    slewing: bool,
+}
+
+impl GotoCheck {
+    pub fn new(target_ra: f64, target_dec: f64) -> Self {
+        Self {
+            target: (target_ra, target_dec),
+            last: None,
+            seen_slewing: false,
+            slewing: false,
+        }
+    }
+
+    /// Feeds a goto-in-progress poll
+    pub fn slewing(&mut self, in_progress: bool) {
+        self.slewing = in_progress;
+        if in_progress {
+            self.seen_slewing = true;
+            self.last = None;
+        }
+    }
+
+    /// Feeds a polled position, once two consecutive positions after the
+    /// goto agree the mount is considered settled and the error in
+    /// arcseconds between the settled position and the target is returned
+    pub fn update(&mut self, ra: f64, dec: f64) -> Option<f64> {
+        if !self.seen_slewing || self.slewing {
+            return None;
+        }
+        let settled = match self.last {
+            Some((last_ra, last_dec)) => {
+                angular_separation_deg(last_ra, last_dec, ra, dec) * 3600.0
+                    < SETTLE_TOLERANCE_ARCSEC
+            }
+            None => false,
+        };
+        self.last = Some((ra, dec));
+
+        if settled {
+            Some(angular_separation_deg(self.target.0, self.target.1, ra, dec) * 3600.0)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{GotoCheck, GOTO_ERROR_WARNING_ARCSEC};
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn settles_on_target() {
+        let mut check = GotoCheck::new(83.822, -5.391);
+        check.slewing(true);
+        assert_eq!(check.update(60.0, 10.0), None);
+        check.slewing(false);
+        assert_eq!(check.update(80.0, -4.0), None);
+        assert_eq!(check.update(83.822, -5.391), None);
+        assert_approx_eq!(check.update(83.822, -5.391).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn detects_encoding_offset() {
+        // A goto landing one degree off in DEC, as an encoding bug would do
+        let mut check = GotoCheck::new(83.822, -5.391);
+        check.slewing(true);
+        check.slewing(false);
+        check.update(83.822, -4.391);
+        let error = check.update(83.822, -4.391).unwrap();
+        assert_approx_eq!(error, 3600.0, 1e-6);
+        assert!(error > GOTO_ERROR_WARNING_ARCSEC);
+    }
+
+    #[test]
+    fn waits_for_the_goto_to_start() {
+        let mut check = GotoCheck::new(83.822, -5.391);
+        // Still where the goto started from, not reported in progress yet
+        assert_eq!(check.update(10.0, 10.0), None);
+        assert_eq!(check.update(10.0, 10.0), None);
+        check.slewing(true);
+        assert_eq!(check.update(50.0, 0.0), None);
+        assert_eq!(check.update(50.0, 0.0), None);
+        check.slewing(false);
+        assert_eq!(check.update(83.822, -5.391), None);
+        assert_approx_eq!(check.update(83.822, -5.391).unwrap(), 0.0);
+    }
+}
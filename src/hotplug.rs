@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// How often the serial ports are scanned for newly plugged in mounts
+pub const DEFAULT_RESCAN_INTERVAL: Duration = Duration::from_secs(5);
+/// Wait before trying again a port that couldn't be opened, doubled at
+/// every further failure up to MAX_FAILED_PORT_BACKOFF
+pub const FAILED_PORT_BACKOFF: Duration = Duration::from_secs(10);
+pub const MAX_FAILED_PORT_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Decides which of the scanned ports are worth opening, remembering
+/// the ones that failed so they aren't retried at every scan
+#[derive(Debug, Default)]
+pub struct PortScanner {
+    /// Backoff and time of the next attempt by port name
+    failed: HashMap<String, (Duration, Instant)>,
+}
+
+impl PortScanner {
+    /// Ports among `found` that aren't `registered` and aren't waiting
+    /// for a retry. Failed ports that are gone are forgotten, a mount
+    /// plugged back in is tried right away
+    pub fn new_ports(
+        &mut self,
+        found: &[String],
+        registered: &[String],
+        now: Instant,
+    ) -> Vec<String> {
+        self.failed.retain(|port, _| found.contains(port));
+        found
+            .iter()
+            .filter(|port| !registered.contains(port))
+            .filter(|port| self.failed.get(*port).is_none_or(|(_, next)| now >= *next))
+            .cloned()
+            .collect()
+    }
+
+    /// Records that `port` couldn't be opened
+    pub fn failed(&mut self, port: &str, now: Instant) {
+        let backoff = match self.failed.get(port) {
+            Some((previous, _)) => (*previous * 2).min(MAX_FAILED_PORT_BACKOFF),
+            None => FAILED_PORT_BACKOFF,
+        };
+        self.failed
+            .insert(port.to_owned(), (backoff, now + backoff));
+    }
+
+    /// Records that `port` was opened, it is registered from now on
+    pub fn opened(&mut self, port: &str) {
+        self.failed.remove(port);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PortScanner, FAILED_PORT_BACKOFF, MAX_FAILED_PORT_BACKOFF};
+    use std::time::Instant;
+
+    fn ports(p: &[&str]) -> Vec<String> {
+        p.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn only_unregistered_ports_are_new() {
+        let mut scanner = PortScanner::default();
+        let now = Instant::now();
+        assert!(scanner.new_ports(&[], &[], now).is_empty());
+
+        // A mount is plugged in next to the one already running
+        let found = ports(&["/dev/ttyUSB0", "/dev/ttyUSB1"]);
+        assert_eq!(
+            scanner.new_ports(&found, &ports(&["/dev/ttyUSB0"]), now),
+            ports(&["/dev/ttyUSB1"])
+        );
+        assert!(scanner.new_ports(&found, &found, now).is_empty());
+    }
+
+    #[test]
+    fn failed_ports_back_off() {
+        let mut scanner = PortScanner::default();
+        let found = ports(&["/dev/ttyUSB0"]);
+        let start = Instant::now();
+
+        scanner.failed("/dev/ttyUSB0", start);
+        assert!(scanner.new_ports(&found, &[], start).is_empty());
+        let retry = start + FAILED_PORT_BACKOFF;
+        assert_eq!(scanner.new_ports(&found, &[], retry), found);
+
+        // Twice as long after the second failure
+        scanner.failed("/dev/ttyUSB0", retry);
+        assert!(scanner
+            .new_ports(&found, &[], retry + FAILED_PORT_BACKOFF)
+            .is_empty());
+        assert_eq!(
+            scanner.new_ports(&found, &[], retry + FAILED_PORT_BACKOFF * 2),
+            found
+        );
+
+        // Never longer than the maximum
+        let mut now = retry;
+        for _ in 0..10 {
+            scanner.failed("/dev/ttyUSB0", now);
+            now += MAX_FAILED_PORT_BACKOFF;
+            assert_eq!(scanner.new_ports(&found, &[], now), found);
+        }
+
+        scanner.opened("/dev/ttyUSB0");
+        assert_eq!(scanner.new_ports(&found, &[], start), found);
+    }
+
+    #[test]
+    fn unplugged_ports_are_forgotten() {
+        let mut scanner = PortScanner::default();
+        let found = ports(&["/dev/ttyUSB0"]);
+        let now = Instant::now();
+
+        scanner.failed("/dev/ttyUSB0", now);
+        assert!(scanner.new_ports(&[], &[], now).is_empty());
+        // Plugged back in, tried without waiting for the backoff
+        assert_eq!(scanner.new_ports(&found, &[], now), found);
+    }
+}
@@ -0,0 +1,254 @@
+use crate::write_file_atomically;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// State file used when LS_INFLIGHT_FILE is not set
+pub const DEFAULT_INFLIGHT_FILE: &str = "skywatcher-inflight.json";
+
+/// Polls in a row the mount has to be seen standing still before a
+/// recovery is over
+pub const RECOVERY_STILL_POLLS: u32 = 2;
+
+/// Movement between two polls, in degrees, still taken as standing
+/// still, the sky drifting by over a few polls or the tracking
+pub const RECOVERY_TOLERANCE_DEG: f64 = 0.05;
+
+/// Motions the mount carries on with by itself once they are started
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum Operation {
+    Goto,
+    Park,
+    Home,
+    Unwind,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Operation::Goto => "GOTO",
+            Operation::Park => "park",
+            Operation::Home => "GOTO home",
+            Operation::Unwind => "cable unwind",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Motion the driver started and didn't see the end of yet, persisted
+/// so a restarted driver knows the mount may still be moving
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct InFlight {
+    pub operation: Operation,
+    /// Where the mount was sent, as logged when it was
+    pub target: String,
+    /// Seconds since the Unix epoch
+    pub started: u64,
+}
+
+impl fmt::Display for InFlight {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} to {} started at {}",
+            self.operation, self.target, self.started
+        )
+    }
+}
+
+/// Reads the operations in flight, by device, a missing or unreadable
+/// file gives none at all
+pub fn load_in_flight(path: &Path) -> HashMap<String, InFlight> {
+    let content = match fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(_) => return HashMap::new(),
+    };
+
+    serde_json::from_str(&content).unwrap_or_else(|e| {
+        warn!(
+            "Ignoring invalid in-flight operation file {:?}: {}",
+            path, e
+        );
+        HashMap::new()
+    })
+}
+
+/// Held while the state file is read, changed and written back, the
+/// devices of a driver share it
+static SAVE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Stores the operation in flight of a device, None once it is over,
+/// leaving the other devices alone
+pub fn save_in_flight(path: &Path, device: &str, operation: Option<&InFlight>) -> io::Result<()> {
+    let _guard = SAVE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut operations = load_in_flight(path);
+    match operation {
+        Some(op) => operations.insert(device.to_owned(), op.clone()),
+        None => operations.remove(device),
+    };
+    let content = serde_json::to_string_pretty(&operations)?;
+    write_file_atomically(path, &content)
+}
+
+/// Cautious mode of a driver that found an operation in flight when it
+/// started, the mount may still be moving on its own. It is over once
+/// the mount reports no goto and neither RA/DEC nor Alt/Az moved for
+/// RECOVERY_STILL_POLLS polls in a row, one of them stays put whether
+/// the mount tracks or not.
+#[derive(Clone, Debug)]
+pub struct Recovery {
+    operation: InFlight,
+    /// RA/DEC and Az/Alt of the previous poll
+    last: Option<((f64, f64), (f64, f64))>,
+    still_polls: u32,
+}
+
+impl Recovery {
+    pub fn new(operation: InFlight) -> Self {
+        Self {
+            operation,
+            last: None,
+            still_polls: 0,
+        }
+    }
+
+    /// The operation the previous run left in flight
+    pub fn operation(&self) -> &InFlight {
+        &self.operation
+    }
+
+    /// Feeds a poll, `position` being RA/DEC and Az/Alt in degrees or
+    /// None when they couldn't be read. Tells whether the mount is
+    /// verifiably stopped.
+    pub fn update(&mut self, slewing: bool, position: Option<((f64, f64), (f64, f64))>) -> bool {
+        let still = match (self.last, position) {
+            (Some(last), Some(now)) => !slewing && (!moved(last.0, now.0) || !moved(last.1, now.1)),
+            _ => false,
+        };
+        self.still_polls = if still { self.still_polls + 1 } else { 0 };
+        self.last = position;
+        self.still_polls >= RECOVERY_STILL_POLLS
+    }
+}
+
+/// Whether either angle of the pair moved past RECOVERY_TOLERANCE_DEG,
+/// the first one wrapping at 360 degrees
+fn moved(from: (f64, f64), to: (f64, f64)) -> bool {
+    let first = ((to.0 - from.0 + 540.0).rem_euclid(360.0) - 180.0).abs();
+    first > RECOVERY_TOLERANCE_DEG || (to.1 - from.1).abs() > RECOVERY_TOLERANCE_DEG
+}
+
+#[cfg(test)]
+mod test {
+    use super::{load_in_flight, save_in_flight, InFlight, Operation, Recovery};
+    use std::fs;
+    use std::path::PathBuf;
+    use std::thread;
+
+    fn state_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("skywatcher-{}-{}.json", name, std::process::id()))
+    }
+
+    fn goto() -> InFlight {
+        InFlight {
+            operation: Operation::Goto,
+            target: String::from("RA: 10 DEC: 20"),
+            started: 1_700_000_000,
+        }
+    }
+
+    #[test]
+    fn state_file_round_trip() {
+        let path = state_file("inflight-round-trip");
+        let _ = fs::remove_file(&path);
+        assert!(load_in_flight(&path).is_empty());
+
+        let park = InFlight {
+            operation: Operation::Park,
+            target: String::from("AZ: 0 ALT: 0"),
+            started: 1_700_000_100,
+        };
+        save_in_flight(&path, "/dev/ttyUSB0", Some(&goto())).unwrap();
+        save_in_flight(&path, "/dev/ttyUSB1", Some(&park)).unwrap();
+
+        let operations = load_in_flight(&path);
+        assert_eq!(operations.len(), 2);
+        assert_eq!(operations["/dev/ttyUSB0"], goto());
+        assert_eq!(operations["/dev/ttyUSB1"], park);
+
+        // Over for one mount, still going for the other
+        save_in_flight(&path, "/dev/ttyUSB0", None).unwrap();
+        let operations = load_in_flight(&path);
+        assert_eq!(operations.len(), 1);
+        assert_eq!(operations["/dev/ttyUSB1"], park);
+
+        fs::write(&path, "not json").unwrap();
+        assert!(load_in_flight(&path).is_empty());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn devices_save_at_the_same_time() {
+        let path = state_file("inflight-threads");
+        let _ = fs::remove_file(&path);
+        let threads: Vec<_> = (0..8)
+            .map(|i| {
+                let path = path.clone();
+                thread::spawn(move || {
+                    save_in_flight(&path, &format!("/dev/ttyUSB{}", i), Some(&goto())).unwrap()
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert_eq!(load_in_flight(&path).len(), 8);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn describes_the_operation() {
+        assert_eq!(
+            goto().to_string(),
+            "GOTO to RA: 10 DEC: 20 started at 1700000000"
+        );
+    }
+
+    #[test]
+    fn recovery_waits_for_the_mount_to_stand_still() {
+        let mut recovery = Recovery::new(goto());
+        let at = |ra: f64, alt: f64| Some(((ra, 20.0), (180.0, alt)));
+
+        // Still slewing
+        assert!(!recovery.update(true, at(10.0, 30.0)));
+        assert!(!recovery.update(true, at(30.0, 40.0)));
+        // Not reported as slewing, yet moving
+        assert!(!recovery.update(false, at(50.0, 50.0)));
+        assert!(!recovery.update(false, at(50.0, 50.0)));
+        // A lost poll starts over
+        assert!(!recovery.update(false, None));
+        assert!(!recovery.update(false, at(50.0, 50.0)));
+        assert!(!recovery.update(false, at(50.0, 50.0)));
+        assert!(recovery.update(false, at(50.0, 50.0)));
+    }
+
+    #[test]
+    fn tracking_or_drifting_mounts_stand_still() {
+        // Tracking, RA/DEC stay put while Alt/Az follow the sky
+        let mut recovery = Recovery::new(goto());
+        assert!(!recovery.update(false, Some(((10.0, 20.0), (180.0, 30.0)))));
+        assert!(!recovery.update(false, Some(((10.0, 20.0), (180.1, 30.1)))));
+        assert!(recovery.update(false, Some(((10.0, 20.0), (180.2, 30.2)))));
+
+        // Not tracking, RA drifts across 0 while Alt/Az stay put
+        let mut recovery = Recovery::new(goto());
+        assert!(!recovery.update(false, Some(((359.9, 20.0), (180.0, 30.0)))));
+        assert!(!recovery.update(false, Some(((0.0, 20.0), (180.0, 30.0)))));
+        assert!(recovery.update(false, Some(((0.1, 20.0), (180.0, 30.0)))));
+    }
+}
@@ -1,4 +1,37 @@
-use log::error;
+use log::{error, warn};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+pub mod actor;
+pub mod cable_wrap;
+pub mod cache;
+pub mod connection;
+pub mod discovery;
+pub mod eqmod;
+pub mod events;
+pub mod goto_check;
+pub mod hotplug;
+pub mod inflight;
+pub mod metrics;
+pub mod motor_simulator;
+pub mod network;
+pub mod park;
+pub mod periodic_error;
+pub mod props;
+pub mod protocol_doc;
+pub mod serial;
+pub mod server;
+pub mod shared;
+pub mod shutdown;
+pub mod simulator;
+pub mod slew_limits;
+pub mod synscan;
+pub mod targets;
+pub mod traffic;
 
 /// Takes a string representation of a 24 bits number like "032723"
 /// and returns the "bytes" in reverse order, of course dealing with
@@ -13,6 +46,26 @@ pub fn str_24bits_to_u32(input: String) -> Option<u32> {
     }
 }
 
+/// Same as `str_24bits_to_u32` but the 24 bits number ends up in the
+/// low bytes, "032723" returns 0x232703 instead of 0x23270300
+pub fn str_24bits_to_u24(input: String) -> Option<u32> {
+    str_24bits_to_u32(input).map(|num| num >> 8)
+}
+
+/// Inverse of `str_24bits_to_u24`, takes a 24 bits number like 0x232703
+/// and returns its 6 characters representation with the low byte first,
+/// in this case "032723". It doesn't round-trip with `str_24bits_to_u32`,
+/// which swaps all four bytes and leaves the number in the high ones,
+/// 0x23270300 here.
+pub fn u32_to_str_24bits(val: u32) -> String {
+    format!(
+        "{:02X}{:02X}{:02X}",
+        val & 0xFF,
+        (val >> 8) & 0xFF,
+        (val >> 16) & 0xFF
+    )
+}
+
 pub fn str_to_u16(input: String) -> Option<u16> {
     if let Ok(num) = u16::from_str_radix(&input, 16) {
         Some(num)
@@ -32,21 +85,295 @@ pub fn str_to_u32(input: String) -> Option<u32> {
 }
 
 pub fn revolutions_to_degrees(rev: u16) -> f32 {
-    rev as f32 / 65_536 as f32 * 360 as f32
+    rev as f32 / 65_536.0 * 360.0
 }
 
-pub fn degrees_to_revolutions(deg: f32) -> i16 {
-    ((deg / 360 as f32) * 65_536 as f32) as i16
+/// Negative angles wrap around a full revolution, e.g. -20 degrees
+/// becomes 340 degrees
+pub fn degrees_to_revolutions(deg: f32) -> u16 {
+    ((deg / 360.0) * 65_536.0) as i32 as u16
 }
 
 pub fn precise_revolutions_to_degrees(rev: u32) -> f32 {
-    rev as f32 / 16_777_216 as f32 * 360 as f32
+    rev as f32 / 16_777_216.0 * 360.0
+}
+
+/// Same as `precise_revolutions_to_degrees` but keeps the result in f64,
+/// f32 has not enough significant digits to tell apart adjacent steps
+/// of a 24 bits revolution
+pub fn precise_revolutions_to_degrees_f64(rev: u32) -> f64 {
+    rev as f64 / 16_777_216_f64 * 360.0
 }
 
 pub fn degrees_to_precise_revolutions(deg: f64) -> i32 {
-    ((deg / 360.0) * 16_777_216_f64) as i32
+    ((deg / 360.0) * 16_777_216_f64).round() as i32
+}
+
+/// Maps an angle in 0..360 degrees to -180..180, e.g. a declination
+/// of 350 degrees as reported by the mount is -10 degrees
+pub fn signed_degrees(deg: f64) -> f64 {
+    if deg > 180.0 {
+        deg - 360.0
+    } else {
+        deg
+    }
+}
+
+/// EQMod axis position representing the home (zero degrees) position
+pub const EQMOD_HOME_POSITION: u32 = 0x800000;
+
+/// Converts a raw EQMod axis position into degrees from home, values
+/// below the 0x800000 offset are negative. Positions further than a
+/// full revolution from home wrap around.
+///
+/// Panics when `steps_per_rev` is 0, the grid of a board that didn't
+/// answer the inquiry, callers check for it first.
+pub fn eqmod_steps_to_degrees(raw: u32, steps_per_rev: u32) -> f64 {
+    let steps = (raw as i64 - EQMOD_HOME_POSITION as i64) % steps_per_rev as i64;
+    steps as f64 / steps_per_rev as f64 * 360.0
+}
+
+/// Converts degrees from home into a raw EQMod axis position, angles
+/// beyond a full revolution wrap around
+pub fn degrees_to_eqmod_steps(deg: f64, steps_per_rev: u32) -> u32 {
+    let steps = ((deg % 360.0) / 360.0 * steps_per_rev as f64).round() as i64;
+    ((EQMOD_HOME_POSITION as i64 + steps) as u32) & 0xFFFFFF
+}
+
+/// Sidereal tracking rate in radians per second
+pub const SIDEREAL_RATE: f64 = std::f64::consts::TAU / 86164.0905;
+/// Lunar tracking rate (14.511415 arcsec/s) in radians per second
+pub const LUNAR_RATE: f64 = 14.511415 / 206264.806;
+/// Solar tracking rate (15 arcsec/s) in radians per second
+pub const SOLAR_RATE: f64 = std::f64::consts::TAU / 86400.0;
+
+/// Returns the step period (timer ticks between two steps) to be sent
+/// to the motor controller to move an axis at the given rate,
+/// the value is clamped to the 24 bits accepted by the controller
+pub fn step_period_for_rate(rate_rad_per_sec: f64, steps_per_rev: u32, timer_freq: u32) -> u32 {
+    let steps_per_sec = rate_rad_per_sec.abs() * steps_per_rev as f64 / std::f64::consts::TAU;
+    let period = (timer_freq as f64 / steps_per_sec).round();
+    period.min(0xFFFFFF as f64) as u32
+}
+
+/// Returns the angular distance in degrees between two points on the
+/// sky given as RA/DEC (or Az/Alt) in degrees
+pub fn angular_separation_deg(ra1: f64, dec1: f64, ra2: f64, dec2: f64) -> f64 {
+    let (ra1, dec1, ra2, dec2) = (
+        ra1.to_radians(),
+        dec1.to_radians(),
+        ra2.to_radians(),
+        dec2.to_radians(),
+    );
+    // Haversine formula, well conditioned for small distances
+    let h = ((dec2 - dec1) / 2.0).sin().powi(2)
+        + dec1.cos() * dec2.cos() * ((ra2 - ra1) / 2.0).sin().powi(2);
+    (2.0 * h.sqrt().min(1.0).asin()).to_degrees()
+}
+
+/// Builds the "RRRRRR00,DDDDDD00" payload for the precise GOTO
+/// commands, negative angles wrap around a full revolution
+pub fn format_precise_goto_payload(ra_deg: f64, dec_deg: f64) -> String {
+    let ra = degrees_to_precise_revolutions(ra_deg) as u32 & 0xFFFFFF;
+    let dec = degrees_to_precise_revolutions(dec_deg) as u32 & 0xFFFFFF;
+
+    format!("{:06X}00,{:06X}00", ra, dec)
+}
+
+/// Builds the "RRRR,DDDD" payload for the non-precise GOTO commands
+pub fn format_goto_payload(ra_deg: f32, dec_deg: f32) -> String {
+    let ra = degrees_to_revolutions(ra_deg);
+    let dec = degrees_to_revolutions(dec_deg);
+
+    format!("{:04X},{:04X}", ra, dec)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    InvalidLength,
+    MissingSeparator,
+    InvalidHex,
+    UnknownValue,
+}
+
+fn is_hex(s: &str) -> bool {
+    s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Parses the answer to a precise RA/DEC (or Alt/Az) query, which
+/// looks like "XXXXXX00,YYYYYY00#", and returns both values in degrees.
+/// The trailing "#" is optional.
+pub fn parse_precise_ra_dec_response(resp: &str) -> Result<(f64, f64), ParseError> {
+    let resp = resp.strip_suffix('#').unwrap_or(resp);
+
+    if resp.len() != 17 {
+        return Err(ParseError::InvalidLength);
+    }
+
+    let (ra, dec) = resp.split_once(',').ok_or(ParseError::MissingSeparator)?;
+
+    if ra.len() != 8 || dec.len() != 8 {
+        return Err(ParseError::MissingSeparator);
+    }
+    // from_str_radix would take a sign as well
+    if !is_hex(ra) || !is_hex(dec) {
+        return Err(ParseError::InvalidHex);
+    }
+
+    // Only the 24 most significant bits carry the position
+    let ra = str_to_u32(ra.to_string()).ok_or(ParseError::InvalidHex)? >> 8;
+    let dec = str_to_u32(dec.to_string()).ok_or(ParseError::InvalidHex)? >> 8;
+
+    Ok((
+        precise_revolutions_to_degrees_f64(ra),
+        precise_revolutions_to_degrees_f64(dec),
+    ))
+}
+
+/// Parses the answer to a RA/DEC (or Alt/Az) query, which looks
+/// like "XXXX,YYYY#", and returns both values in degrees.
+/// The trailing "#" is optional.
+pub fn parse_ra_dec_response(resp: &str) -> Result<(f32, f32), ParseError> {
+    let resp = resp.strip_suffix('#').unwrap_or(resp);
+
+    if resp.len() != 9 {
+        return Err(ParseError::InvalidLength);
+    }
+
+    let (ra, dec) = resp.split_once(',').ok_or(ParseError::MissingSeparator)?;
+
+    if ra.len() != 4 || dec.len() != 4 {
+        return Err(ParseError::MissingSeparator);
+    }
+    if !is_hex(ra) || !is_hex(dec) {
+        return Err(ParseError::InvalidHex);
+    }
+
+    let ra = str_to_u16(ra.to_string()).ok_or(ParseError::InvalidHex)?;
+    let dec = str_to_u16(dec.to_string()).ok_or(ParseError::InvalidHex)?;
+
+    Ok((revolutions_to_degrees(ra), revolutions_to_degrees(dec)))
+}
+
+/// Parses the answer to a precise Alt/Az query into azimuth (0..360)
+/// and altitude (-90..90) degrees, altitudes below the horizon are
+/// encoded by the mount as almost a full revolution
+pub fn parse_precise_alt_az_response(resp: &str) -> Result<(f64, f64), ParseError> {
+    let (az, alt) = parse_precise_ra_dec_response(resp)?;
+    Ok((az, signed_degrees(alt)))
+}
+
+/// Same as `parse_precise_alt_az_response` for the 16 bits Alt/Az query
+pub fn parse_alt_az_response(resp: &str) -> Result<(f64, f64), ParseError> {
+    let (az, alt) = parse_ra_dec_response(resp)?;
+    Ok((az as f64, signed_degrees(alt as f64)))
+}
+
+/// Tells whether an EQMod axis has been initialized (:F) looking at
+/// the answer of the axis status (:f) inquiry, e.g. "101".
+/// The third digit carries the init done flag in its lowest bit.
+pub fn is_axis_initialized(status: &str) -> Result<bool, ParseError> {
+    if status.len() != 3 {
+        return Err(ParseError::InvalidLength);
+    }
+
+    let flags = u8::from_str_radix(&status[2..3], 16).map_err(|_| ParseError::InvalidHex)?;
+    Ok(flags & 0x1 == 0x1)
+}
+
+/// Parses a pair of comma separated coordinates in degrees, e.g.
+/// "83.82,-5.39" as written by clients to coordinate properties
+pub fn parse_coordinate_pair(value: &str) -> Result<(f64, f64), ParseError> {
+    let (first, second) = value.split_once(',').ok_or(ParseError::MissingSeparator)?;
+    let first = first
+        .trim()
+        .parse::<f64>()
+        .map_err(|_| ParseError::UnknownValue)?;
+    let second = second
+        .trim()
+        .parse::<f64>()
+        .map_err(|_| ParseError::UnknownValue)?;
+
+    if !first.is_finite() || !second.is_finite() {
+        return Err(ParseError::UnknownValue);
+    }
+    Ok((first, second))
 }
 
+/// Parses the RA and DEC goto targets, in decimal degrees, as written by
+/// clients. Both must be set, RA within 0..360 and DEC within -90..90.
+pub fn parse_goto_target(ra: &str, dec: &str) -> Result<(f64, f64), ParseError> {
+    if ra.trim().is_empty() || dec.trim().is_empty() {
+        return Err(ParseError::InvalidLength);
+    }
+
+    let ra = ra
+        .trim()
+        .parse::<f64>()
+        .map_err(|_| ParseError::UnknownValue)?;
+    let dec = dec
+        .trim()
+        .parse::<f64>()
+        .map_err(|_| ParseError::UnknownValue)?;
+    check_ra_dec(ra, dec)
+}
+
+/// Parses an "RA,DEC" pair in decimal degrees, e.g. SYNC_COORDINATES,
+/// with the same ranges as the goto targets
+pub fn parse_ra_dec_pair(value: &str) -> Result<(f64, f64), ParseError> {
+    let (ra, dec) = parse_coordinate_pair(value)?;
+    check_ra_dec(ra, dec)
+}
+
+fn check_ra_dec(ra: f64, dec: f64) -> Result<(f64, f64), ParseError> {
+    if !(0.0..360.0).contains(&ra) || !(-90.0..=90.0).contains(&dec) {
+        return Err(ParseError::UnknownValue);
+    }
+    Ok((ra, dec))
+}
+
+/// Tells whether a value written to a trigger property asks
+/// for the action to be performed, e.g. "1", "true" or "on"
+pub fn is_truthy(value: &str) -> bool {
+    matches!(
+        value.trim().to_ascii_lowercase().as_str(),
+        "1" | "true" | "on" | "yes"
+    )
+}
+
+/// Shortest polling interval accepted from LS_POLL_INTERVAL_MS, a mount
+/// polled faster than this has no time left for the other commands
+pub const MIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Parses LS_POLL_INTERVAL_MS, giving `default` when it is missing or
+/// invalid and raising values below MIN_POLL_INTERVAL
+pub fn poll_interval_from(value: Option<&str>, default: Duration) -> Duration {
+    let value = match value {
+        Some(v) => v,
+        None => return default,
+    };
+    match value.trim().parse::<u64>() {
+        Ok(ms) if Duration::from_millis(ms) < MIN_POLL_INTERVAL => {
+            warn!(
+                "Polling interval of {} ms is too small, using {} ms",
+                ms,
+                MIN_POLL_INTERVAL.as_millis()
+            );
+            MIN_POLL_INTERVAL
+        }
+        Ok(ms) => Duration::from_millis(ms),
+        Err(_) => {
+            warn!(
+                "Ignoring invalid polling interval {:?}, using {} ms",
+                value,
+                default.as_millis()
+            );
+            default
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum TrackingMode {
     Off = 0,
     AltAz = 1,
@@ -54,18 +381,141 @@ pub enum TrackingMode {
     Pec = 3,
 }
 
+impl TrackingMode {
+    pub const ALL: [TrackingMode; 4] = [
+        TrackingMode::Off,
+        TrackingMode::AltAz,
+        TrackingMode::Eq,
+        TrackingMode::Pec,
+    ];
+
+    /// Byte to send as payload of the SetTrackingMode command
+    pub fn to_command_byte(&self) -> u8 {
+        *self as u8
+    }
+}
+
+impl TryFrom<u8> for TrackingMode {
+    type Error = ParseError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(TrackingMode::Off),
+            1 => Ok(TrackingMode::AltAz),
+            2 => Ok(TrackingMode::Eq),
+            3 => Ok(TrackingMode::Pec),
+            _ => Err(ParseError::UnknownValue),
+        }
+    }
+}
+
+impl fmt::Display for TrackingMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            TrackingMode::Off => "Off",
+            TrackingMode::AltAz => "AltAz",
+            TrackingMode::Eq => "Equatorial",
+            TrackingMode::Pec => "PEC",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Accepts the mode names, case insensitive, as well as the numeric
+/// codes sent to the mount ("0" to "3")
+impl FromStr for TrackingMode {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Ok(code) = s.parse::<u8>() {
+            return TrackingMode::try_from(code);
+        }
+        match s.to_ascii_lowercase().as_str() {
+            "off" => Ok(TrackingMode::Off),
+            "altaz" => Ok(TrackingMode::AltAz),
+            "equatorial" => Ok(TrackingMode::Eq),
+            "pec" => Ok(TrackingMode::Pec),
+            _ => Err(ParseError::UnknownValue),
+        }
+    }
+}
+
+/// Kind prefix of the properties restricted to a set of values. The
+/// lightspeed Property has no field for them, so they follow the
+/// prefix comma separated, e.g. "enum:Off,AltAz,Equatorial,PEC"
+pub const ENUM_KIND_PREFIX: &str = "enum:";
+
+/// Builds the kind of a property accepting only `values`
+pub fn enum_kind<T: fmt::Display>(values: &[T]) -> String {
+    let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+    format!("{}{}", ENUM_KIND_PREFIX, values.join(","))
+}
+
+/// Returns the allowed values of an enum kind, None for other kinds
+pub fn enum_kind_values(kind: &str) -> Option<Vec<&str>> {
+    kind.strip_prefix(ENUM_KIND_PREFIX)
+        .map(|values| values.split(',').collect())
+}
+
+/// Parses the answer to the GetTrackingMode ('t') query, the mode
+/// byte followed by '#'
+pub fn parse_tracking_mode_response(resp: &str) -> Result<TrackingMode, ParseError> {
+    match resp.as_bytes() {
+        [code, b'#'] => TrackingMode::try_from(*code),
+        _ => Err(ParseError::InvalidLength),
+    }
+}
+
+/// Replaces the content of a state file, written aside first and then
+/// renamed over it so a crash never leaves a truncated file behind
+pub fn write_file_atomically(path: &Path, content: &str) -> io::Result<()> {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}.tmp", std::process::id()));
+    let temp = path.with_file_name(name);
+    fs::write(&temp, content)
+        .and_then(|_| fs::rename(&temp, path))
+        .inspect_err(|_| {
+            let _ = fs::remove_file(&temp);
+        })
+}
+
 #[cfg(test)]
 mod test {
     use crate::{
-        degrees_to_precise_revolutions, degrees_to_revolutions, precise_revolutions_to_degrees,
-        revolutions_to_degrees, str_24bits_to_u32, str_to_u16, str_to_u32,
+        angular_separation_deg, degrees_to_eqmod_steps, degrees_to_precise_revolutions,
+        degrees_to_revolutions, enum_kind, enum_kind_values, eqmod_steps_to_degrees,
+        format_goto_payload, format_precise_goto_payload, is_axis_initialized, is_truthy,
+        parse_alt_az_response, parse_coordinate_pair, parse_goto_target,
+        parse_precise_alt_az_response, parse_precise_ra_dec_response, parse_ra_dec_pair,
+        parse_ra_dec_response, parse_tracking_mode_response, poll_interval_from,
+        precise_revolutions_to_degrees, precise_revolutions_to_degrees_f64, revolutions_to_degrees,
+        signed_degrees, step_period_for_rate, str_24bits_to_u24, str_24bits_to_u32, str_to_u16,
+        str_to_u32, u32_to_str_24bits, ParseError, TrackingMode, LUNAR_RATE, MIN_POLL_INTERVAL,
+        SIDEREAL_RATE, SOLAR_RATE,
     };
     use assert_approx_eq::assert_approx_eq;
+    use std::time::Duration;
     #[test]
     fn test_reverse_str() {
         assert_eq!(str_24bits_to_u32(String::from("c3b2a1")), Some(0xa1b2c300));
     }
 
+    #[test]
+    fn test_24bits_value() {
+        assert_eq!(str_24bits_to_u24(String::from("c3b2a1")), Some(0xa1b2c3));
+        assert_eq!(str_24bits_to_u24(String::from("c3b2g1")), None);
+    }
+
+    #[test]
+    fn test_24bits_round_trip() {
+        assert_eq!(u32_to_str_24bits(0x232703), "032723");
+        assert_eq!(u32_to_str_24bits(0x800000), "000080");
+        for val in [0x000000, 0x800000, 0xFFFFFF] {
+            assert_eq!(str_24bits_to_u24(u32_to_str_24bits(val)), Some(val));
+        }
+    }
+
     #[test]
     fn test_str_to_u16() {
         assert_eq!(str_to_u16(String::from("12CE")), Some(4814));
@@ -85,11 +535,373 @@ mod test {
             26.251938,
             1e-6_f32
         );
+        assert_approx_eq!(
+            precise_revolutions_to_degrees_f64(1_223_429),
+            26.251938,
+            1e-6_f64
+        );
+    }
+
+    #[test]
+    fn precise_rev_to_degrees_keeps_adjacent_steps() {
+        assert_ne!(
+            precise_revolutions_to_degrees_f64(16_000_001),
+            precise_revolutions_to_degrees_f64(16_000_002)
+        );
+        assert_eq!(
+            precise_revolutions_to_degrees(16_000_001),
+            precise_revolutions_to_degrees(16_000_002)
+        );
     }
 
     #[test]
     fn degrees_to_rev() {
         assert_eq!(degrees_to_revolutions(26.4441), 4814);
+        assert_eq!(degrees_to_revolutions(-20.0), 61896);
         assert_eq!(degrees_to_precise_revolutions(26.251938), 1_223_429);
     }
+
+    #[test]
+    fn parse_precise_ra_dec() {
+        let (ra, dec) = parse_precise_ra_dec_response("12AB0500,40000000#").unwrap();
+        assert_approx_eq!(ra, 26.251938, 1e-6_f64);
+        assert_approx_eq!(dec, 90.0, 1e-6_f64);
+    }
+
+    #[test]
+    fn parse_precise_ra_dec_lowercase() {
+        let (ra, _) = parse_precise_ra_dec_response("12ab0500,40000000#").unwrap();
+        assert_approx_eq!(ra, 26.251938, 1e-6_f64);
+    }
+
+    #[test]
+    fn parse_precise_ra_dec_short() {
+        assert_eq!(
+            parse_precise_ra_dec_response("12AB0500,4000#"),
+            Err(ParseError::InvalidLength)
+        );
+    }
+
+    #[test]
+    fn parse_precise_ra_dec_no_comma() {
+        assert_eq!(
+            parse_precise_ra_dec_response("12AB0500;40000000#"),
+            Err(ParseError::MissingSeparator)
+        );
+    }
+
+    #[test]
+    fn parse_ra_dec() {
+        let (ra, dec) = parse_ra_dec_response("34AB,12CE#").unwrap();
+        assert_approx_eq!(ra, 74.0643, 1e-4_f32);
+        assert_approx_eq!(dec, 26.4441, 1e-4_f32);
+    }
+
+    #[test]
+    fn parse_ra_dec_invalid() {
+        assert_eq!(
+            parse_ra_dec_response("34AB12CE#"),
+            Err(ParseError::InvalidLength)
+        );
+        assert_eq!(
+            parse_ra_dec_response("34AB;12CE#"),
+            Err(ParseError::MissingSeparator)
+        );
+        assert_eq!(
+            parse_ra_dec_response("34AG,12CE#"),
+            Err(ParseError::InvalidHex)
+        );
+    }
+
+    #[test]
+    fn precise_goto_payload() {
+        assert_eq!(
+            format_precise_goto_payload(26.251938, 90.0),
+            "12AB0500,40000000"
+        );
+        // Below 16 degrees the RA value needs zero padding
+        assert_eq!(format_precise_goto_payload(10.0, 5.0), "071C7200,038E3900");
+        assert_eq!(format_precise_goto_payload(0.0, 0.0), "00000000,00000000");
+    }
+
+    #[test]
+    fn goto_payload() {
+        assert_eq!(format_goto_payload(45.0, 90.0), "2000,4000");
+        assert_eq!(format_goto_payload(10.0, 5.0), "071C,038E");
+        assert_eq!(format_goto_payload(270.0, 0.0), "C000,0000");
+    }
+
+    #[test]
+    fn goto_payload_negative_dec() {
+        let payload = format_goto_payload(10.0, -20.0);
+        assert_eq!(payload.len(), 9);
+        assert_eq!(payload, "071C,F1C8");
+
+        let payload = format_goto_payload(10.0, -89.9);
+        assert_eq!(payload.len(), 9);
+        assert_eq!(payload, "071C,C013");
+    }
+
+    #[test]
+    fn tracking_mode_round_trip() {
+        for (byte, name) in [(0, "Off"), (1, "AltAz"), (2, "Equatorial"), (3, "PEC")] {
+            let mode = TrackingMode::try_from(byte).unwrap();
+            assert_eq!(mode.to_string(), name);
+            assert_eq!(name.parse::<TrackingMode>().unwrap(), mode);
+            assert_eq!(mode.to_command_byte(), byte);
+        }
+    }
+
+    #[test]
+    fn tracking_mode_unknown() {
+        assert_eq!(TrackingMode::try_from(4), Err(ParseError::UnknownValue));
+        assert_eq!(
+            "Sidereal".parse::<TrackingMode>(),
+            Err(ParseError::UnknownValue)
+        );
+    }
+
+    #[test]
+    fn axis_initialized() {
+        assert_eq!(is_axis_initialized("101"), Ok(true));
+        assert_eq!(is_axis_initialized("113"), Ok(true));
+        assert_eq!(is_axis_initialized("100"), Ok(false));
+        assert_eq!(is_axis_initialized("102"), Ok(false));
+        assert_eq!(is_axis_initialized("10"), Err(ParseError::InvalidLength));
+        assert_eq!(is_axis_initialized("10G"), Err(ParseError::InvalidHex));
+    }
+
+    #[test]
+    fn angular_separation() {
+        assert_approx_eq!(angular_separation_deg(10.0, 20.0, 10.0, 20.0), 0.0);
+        assert_approx_eq!(angular_separation_deg(0.0, 0.0, 90.0, 0.0), 90.0);
+        assert_approx_eq!(angular_separation_deg(0.0, 89.0, 180.0, 89.0), 2.0);
+        assert_approx_eq!(angular_separation_deg(359.5, 0.0, 0.5, 0.0), 1.0);
+    }
+
+    #[test]
+    fn eqmod_steps() {
+        let spr = 9_024_000;
+        assert_eq!(eqmod_steps_to_degrees(0x800000, spr), 0.0);
+        assert!(eqmod_steps_to_degrees(0x7FFFFF, spr) < 0.0);
+        assert_approx_eq!(eqmod_steps_to_degrees(0x7FFFFF, spr), -360.0 / spr as f64);
+        assert_approx_eq!(eqmod_steps_to_degrees(0x800000 + spr / 4, spr), 90.0);
+        // A full revolution away from home is home again
+        assert_approx_eq!(eqmod_steps_to_degrees(0x800000 + spr, spr), 0.0);
+    }
+
+    #[test]
+    fn degrees_to_eqmod() {
+        let spr = 9_024_000;
+        assert_eq!(degrees_to_eqmod_steps(0.0, spr), 0x800000);
+        assert_eq!(degrees_to_eqmod_steps(90.0, spr), 0x800000 + spr / 4);
+        assert_eq!(degrees_to_eqmod_steps(-90.0, spr), 0x800000 - spr / 4);
+        assert_eq!(degrees_to_eqmod_steps(450.0, spr), 0x800000 + spr / 4);
+        assert_approx_eq!(
+            eqmod_steps_to_degrees(degrees_to_eqmod_steps(-10.0, spr), spr),
+            -10.0,
+            1e-4
+        );
+    }
+
+    #[test]
+    fn tracking_step_period() {
+        // EQ6: 9024000 steps per revolution, 64935 Hz timer
+        assert_eq!(step_period_for_rate(SIDEREAL_RATE, 9_024_000, 64935), 620);
+        assert_eq!(step_period_for_rate(SOLAR_RATE, 9_024_000, 64935), 622);
+        assert_eq!(step_period_for_rate(LUNAR_RATE, 9_024_000, 64935), 643);
+        // Direction doesn't change the period
+        assert_eq!(
+            step_period_for_rate(-SIDEREAL_RATE, 9_024_000, 64935),
+            step_period_for_rate(SIDEREAL_RATE, 9_024_000, 64935)
+        );
+        // A stopped axis saturates instead of overflowing
+        assert_eq!(step_period_for_rate(0.0, 9_024_000, 64935), 0xFFFFFF);
+    }
+
+    #[test]
+    fn alt_az_goto_payloads() {
+        // Azimuth goes first, then altitude
+        assert_eq!(format_goto_payload(180.0, 45.0), "8000,2000");
+        assert_eq!(
+            format_precise_goto_payload(180.0, 45.0),
+            "80000000,20000000"
+        );
+    }
+
+    #[test]
+    fn truthy_values() {
+        for v in ["1", "true", "TRUE", "on", "yes", " true "] {
+            assert!(is_truthy(v), "{} should be truthy", v);
+        }
+        for v in ["", "0", "false", "off", "no", "2"] {
+            assert!(!is_truthy(v), "{} should not be truthy", v);
+        }
+    }
+
+    #[test]
+    fn poll_intervals() {
+        let default = Duration::from_secs(1);
+        assert_eq!(poll_interval_from(None, default), default);
+        assert_eq!(
+            poll_interval_from(Some("500"), default),
+            Duration::from_millis(500)
+        );
+        assert_eq!(
+            poll_interval_from(Some(" 2000 "), default),
+            Duration::from_secs(2)
+        );
+        for invalid in ["", "fast", "-200", "1.5"] {
+            assert_eq!(poll_interval_from(Some(invalid), default), default);
+        }
+        for too_small in ["0", "10", "99"] {
+            assert_eq!(
+                poll_interval_from(Some(too_small), default),
+                MIN_POLL_INTERVAL
+            );
+        }
+        assert_eq!(poll_interval_from(Some("100"), default), MIN_POLL_INTERVAL);
+    }
+
+    #[test]
+    fn coordinate_pair() {
+        assert_eq!(parse_coordinate_pair("83.82,-5.39"), Ok((83.82, -5.39)));
+        assert_eq!(parse_coordinate_pair(" 10 , 20 "), Ok((10.0, 20.0)));
+        assert_eq!(
+            parse_coordinate_pair("83.82"),
+            Err(ParseError::MissingSeparator)
+        );
+        assert_eq!(parse_coordinate_pair("a,20"), Err(ParseError::UnknownValue));
+        assert_eq!(
+            parse_coordinate_pair("NaN,20"),
+            Err(ParseError::UnknownValue)
+        );
+    }
+
+    #[test]
+    fn signed_angles() {
+        assert_eq!(signed_degrees(10.0), 10.0);
+        assert_eq!(signed_degrees(180.0), 180.0);
+        assert_eq!(signed_degrees(350.0), -10.0);
+        assert_approx_eq!(signed_degrees(270.5), -89.5);
+    }
+
+    #[test]
+    fn alt_az_below_horizon() {
+        let (az, alt) = parse_precise_alt_az_response("40000000,FE000000#").unwrap();
+        assert_approx_eq!(az, 90.0);
+        assert_approx_eq!(alt, -2.8125);
+
+        let (az, alt) = parse_precise_alt_az_response("C0000000,10000000#").unwrap();
+        assert_approx_eq!(az, 270.0);
+        assert_approx_eq!(alt, 22.5);
+
+        let (az, alt) = parse_alt_az_response("8000,FE00#").unwrap();
+        assert_approx_eq!(az, 180.0);
+        assert_approx_eq!(alt, -2.8125);
+
+        assert!(parse_precise_alt_az_response("FE000000#").is_err());
+    }
+
+    #[test]
+    fn goto_targets() {
+        assert_eq!(parse_goto_target("83.82", "-5.39"), Ok((83.82, -5.39)));
+        assert_eq!(parse_goto_target("0", "90"), Ok((0.0, 90.0)));
+        assert_eq!(
+            parse_goto_target("83.82", "-90.5"),
+            Err(ParseError::UnknownValue)
+        );
+        assert_eq!(
+            parse_goto_target("360", "10"),
+            Err(ParseError::UnknownValue)
+        );
+        assert_eq!(parse_goto_target("", "10"), Err(ParseError::InvalidLength));
+        assert_eq!(
+            parse_goto_target("10", "north"),
+            Err(ParseError::UnknownValue)
+        );
+    }
+
+    #[test]
+    fn ra_dec_pairs() {
+        assert_eq!(parse_ra_dec_pair("83.82,-5.39"), Ok((83.82, -5.39)));
+        assert_eq!(parse_ra_dec_pair("0, 90"), Ok((0.0, 90.0)));
+        assert_eq!(parse_ra_dec_pair("360,10"), Err(ParseError::UnknownValue));
+        assert_eq!(parse_ra_dec_pair("-1,10"), Err(ParseError::UnknownValue));
+        assert_eq!(parse_ra_dec_pair("10,90.5"), Err(ParseError::UnknownValue));
+        assert_eq!(parse_ra_dec_pair("10"), Err(ParseError::MissingSeparator));
+    }
+
+    #[test]
+    fn parse_precise_ra_dec_malformed() {
+        // None of these may panic, they used to be sliced blindly
+        for resp in [
+            "UNKNOWN",
+            "!#",
+            "#",
+            "",
+            "12AB0500,4#",
+            "12AB0500,4000000\u{e9}#",
+            "\u{e9}2AB0500,40000000#",
+            "+2AB0500,40000000#",
+            "12AB0500,-0000000#",
+            "12AB0500;40000000#",
+        ] {
+            assert!(
+                parse_precise_ra_dec_response(resp).is_err(),
+                "{:?} should not parse",
+                resp
+            );
+        }
+    }
+
+    #[test]
+    fn tracking_mode_response() {
+        assert_eq!(parse_tracking_mode_response("\u{2}#"), Ok(TrackingMode::Eq));
+        assert_eq!(
+            parse_tracking_mode_response("\u{4}#"),
+            Err(ParseError::UnknownValue)
+        );
+        assert_eq!(
+            parse_tracking_mode_response("#"),
+            Err(ParseError::InvalidLength)
+        );
+
+        // AltAz acknowledged but the mount still reports Equatorial
+        let requested = "AltAz".parse::<TrackingMode>().unwrap();
+        assert_ne!(parse_tracking_mode_response("\u{2}#"), Ok(requested));
+    }
+
+    #[test]
+    fn tracking_mode_from_input() {
+        assert_eq!("2".parse::<TrackingMode>(), Ok(TrackingMode::Eq));
+        assert_eq!("Equatorial".parse::<TrackingMode>(), Ok(TrackingMode::Eq));
+        assert_eq!("equatorial".parse::<TrackingMode>(), Ok(TrackingMode::Eq));
+        assert_eq!(" altaz ".parse::<TrackingMode>(), Ok(TrackingMode::AltAz));
+        assert_eq!(
+            "banana".parse::<TrackingMode>(),
+            Err(ParseError::UnknownValue)
+        );
+        assert_eq!("4".parse::<TrackingMode>(), Err(ParseError::UnknownValue));
+        assert_eq!(
+            "UNKNOWN".parse::<TrackingMode>(),
+            Err(ParseError::UnknownValue)
+        );
+    }
+
+    #[test]
+    fn tracking_mode_kind() {
+        let kind = enum_kind(&TrackingMode::ALL);
+        assert_eq!(kind, "enum:Off,AltAz,Equatorial,PEC");
+
+        let values = enum_kind_values(&kind).unwrap();
+        assert_eq!(values, vec!["Off", "AltAz", "Equatorial", "PEC"]);
+        // Every advertised value is accepted back
+        for v in values {
+            assert!(v.parse::<TrackingMode>().is_ok());
+        }
+
+        assert_eq!(enum_kind_values("boolean"), None);
+        assert_eq!(enum_kind_values("integer"), None);
+    }
 }
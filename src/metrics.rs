@@ -0,0 +1,233 @@
+use crate::props::CustomProp;
+use crate::serial::{ReadError, TransportError};
+use lightspeed_astro::props::Permission;
+use std::io::ErrorKind;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Number of answered commands the average latency is computed on
+pub const LATENCY_WINDOW: usize = 20;
+
+/// How an exchange with the mount ended
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+    Answered,
+    TimedOut,
+    /// Nothing usable came back: a write error or a garbled answer
+    Failed,
+}
+
+impl From<&TransportError> for Outcome {
+    fn from(e: &TransportError) -> Self {
+        match e {
+            TransportError::Write(ErrorKind::TimedOut)
+            | TransportError::Read(ReadError::Timeout) => Outcome::TimedOut,
+            TransportError::Read(ReadError::Io(ErrorKind::TimedOut)) => Outcome::TimedOut,
+            _ => Outcome::Failed,
+        }
+    }
+}
+
+impl<T> From<&Result<T, TransportError>> for Outcome {
+    fn from(result: &Result<T, TransportError>) -> Self {
+        match result {
+            Ok(_) => Outcome::Answered,
+            Err(e) => e.into(),
+        }
+    }
+}
+
+/// Counts the commands sent to a mount and how they went, so that a
+/// degrading serial link shows up in long unattended sessions. Counting
+/// takes no lock, the STAT_ properties are refreshed by `publish`.
+#[derive(Debug, Default)]
+pub struct CommandStats {
+    commands: AtomicU64,
+    timeouts: AtomicU64,
+    errors: AtomicU64,
+    /// Round trips of the last answered commands, in microseconds
+    latencies_us: [AtomicU64; LATENCY_WINDOW],
+    answered: AtomicU64,
+    /// Shared with the STAT_COMMANDS, STAT_TIMEOUTS, STAT_ERRORS and
+    /// STAT_LATENCY_MS properties
+    values: [Arc<RwLock<String>>; 4],
+}
+
+impl CommandStats {
+    pub fn record(&self, outcome: Outcome, latency: Duration) {
+        self.commands.fetch_add(1, Ordering::Relaxed);
+        match outcome {
+            Outcome::Answered => {
+                let slot = self.answered.fetch_add(1, Ordering::Relaxed) as usize % LATENCY_WINDOW;
+                self.latencies_us[slot].store(latency.as_micros() as u64, Ordering::Relaxed);
+            }
+            Outcome::TimedOut => {
+                self.timeouts.fetch_add(1, Ordering::Relaxed);
+            }
+            Outcome::Failed => {
+                self.errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Counts an error for a command already recorded as answered, when
+    /// the answer turns out not to make sense
+    pub fn record_invalid_answer(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn commands(&self) -> u64 {
+        self.commands.load(Ordering::Relaxed)
+    }
+
+    pub fn timeouts(&self) -> u64 {
+        self.timeouts.load(Ordering::Relaxed)
+    }
+
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    /// Average round trip of the last LATENCY_WINDOW answered commands,
+    /// None before the first answer
+    pub fn average_latency(&self) -> Option<Duration> {
+        let answered = (self.answered.load(Ordering::Relaxed) as usize).min(LATENCY_WINDOW);
+        if answered == 0 {
+            return None;
+        }
+        let total: u64 = self.latencies_us[..answered]
+            .iter()
+            .map(|l| l.load(Ordering::Relaxed))
+            .sum();
+        Some(Duration::from_micros(total / answered as u64))
+    }
+
+    /// Read only properties holding the counters as of the last `publish`
+    pub fn props(&self) -> Vec<CustomProp> {
+        [
+            ("STAT_COMMANDS", "integer"),
+            ("STAT_TIMEOUTS", "integer"),
+            ("STAT_ERRORS", "integer"),
+            ("STAT_LATENCY_MS", "float"),
+        ]
+        .into_iter()
+        .zip(&self.values)
+        .map(|((name, kind), value)| CustomProp {
+            name: String::from(name),
+            value: Arc::clone(value),
+            kind: String::from(kind),
+            permission: Permission::ReadOnly,
+        })
+        .collect()
+    }
+
+    pub fn publish(&self) {
+        let latency = self
+            .average_latency()
+            .map(|l| format!("{:.1}", l.as_secs_f64() * 1000.0))
+            .unwrap_or_default();
+        for (prop, value) in self.values.iter().zip([
+            self.commands().to_string(),
+            self.timeouts().to_string(),
+            self.errors().to_string(),
+            latency,
+        ]) {
+            *prop.write().unwrap() = value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CommandStats, Outcome, LATENCY_WINDOW};
+    use crate::serial::{ReadError, TransportError};
+    use std::io::ErrorKind;
+    use std::time::Duration;
+
+    fn values(stats: &CommandStats) -> Vec<(String, String)> {
+        stats
+            .props()
+            .iter()
+            .map(|p| (p.name.clone(), p.value.read().unwrap().clone()))
+            .collect()
+    }
+
+    #[test]
+    fn outcomes_of_exchanges() {
+        let answered: Result<Vec<u8>, TransportError> = Ok(b"=\r".to_vec());
+        assert_eq!(Outcome::from(&answered), Outcome::Answered);
+        for (e, outcome) in [
+            (TransportError::Read(ReadError::Timeout), Outcome::TimedOut),
+            (
+                TransportError::Write(ErrorKind::TimedOut),
+                Outcome::TimedOut,
+            ),
+            (TransportError::Read(ReadError::TooLong), Outcome::Failed),
+            (
+                TransportError::Read(ReadError::InvalidUtf8),
+                Outcome::Failed,
+            ),
+            (
+                TransportError::Write(ErrorKind::BrokenPipe),
+                Outcome::Failed,
+            ),
+        ] {
+            assert_eq!(Outcome::from(&Err::<Vec<u8>, _>(e)), outcome);
+        }
+    }
+
+    #[test]
+    fn counts_commands() {
+        let stats = CommandStats::default();
+        assert_eq!(stats.average_latency(), None);
+        stats.publish();
+        assert_eq!(
+            values(&stats)[3],
+            (String::from("STAT_LATENCY_MS"), String::new())
+        );
+
+        for ms in [10, 20, 30] {
+            stats.record(Outcome::Answered, Duration::from_millis(ms));
+        }
+        stats.record(Outcome::TimedOut, Duration::from_secs(5));
+        stats.record(Outcome::TimedOut, Duration::from_secs(5));
+        stats.record(Outcome::Failed, Duration::from_millis(1));
+        stats.record_invalid_answer();
+
+        assert_eq!(stats.commands(), 6);
+        assert_eq!(stats.timeouts(), 2);
+        assert_eq!(stats.errors(), 2);
+        // Timeouts don't count towards the latency
+        assert_eq!(stats.average_latency(), Some(Duration::from_millis(20)));
+
+        // Properties only change when published
+        assert_eq!(values(&stats)[0].1, "0");
+        stats.publish();
+        assert_eq!(
+            values(&stats),
+            vec![
+                (String::from("STAT_COMMANDS"), String::from("6")),
+                (String::from("STAT_TIMEOUTS"), String::from("2")),
+                (String::from("STAT_ERRORS"), String::from("2")),
+                (String::from("STAT_LATENCY_MS"), String::from("20.0")),
+            ]
+        );
+    }
+
+    #[test]
+    fn latency_of_the_last_commands() {
+        let stats = CommandStats::default();
+        for _ in 0..LATENCY_WINDOW {
+            stats.record(Outcome::Answered, Duration::from_millis(100));
+        }
+        for _ in 0..LATENCY_WINDOW / 2 {
+            stats.record(Outcome::Answered, Duration::from_millis(20));
+        }
+        assert_eq!(stats.average_latency(), Some(Duration::from_millis(60)));
+        for _ in 0..LATENCY_WINDOW / 2 {
+            stats.record(Outcome::Answered, Duration::from_millis(20));
+        }
+        assert_eq!(stats.average_latency(), Some(Duration::from_millis(20)));
+    }
+}
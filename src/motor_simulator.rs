@@ -0,0 +1,570 @@
+use crate::eqmod::{Axis, Direction, EqModOp, MotionKind, MotionMode, Speed};
+use crate::periodic_error::PeModel;
+use crate::serial::{exchange_frame, SerialTransport, TransportError};
+use crate::{str_24bits_to_u24, u32_to_str_24bits, EQMOD_HOME_POSITION};
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Read, Write};
+use std::time::{Duration, Instant};
+
+/// Time a simulated goto takes, whatever the distance
+pub const SIMULATED_GOTO_TIME: Duration = Duration::from_secs(3);
+/// Version answered by the simulated motor board, 3.39.1
+const SIMULATED_VERSION: &str = "032701";
+/// Steps per revolution of both simulated axes, an EQ6
+const SIMULATED_GRID: u32 = 9024000;
+const SIMULATED_TIMER_FREQ: u32 = 64935;
+const SIMULATED_HIGH_SPEED_RATIO: &str = "10";
+
+/// One axis of the simulated board, positions in steps
+#[derive(Clone, Copy, Debug)]
+struct SimulatedAxis {
+    position: f64,
+    since: Instant,
+    initialized: bool,
+    mode: MotionMode,
+    period: u32,
+    target: u32,
+    running: bool,
+    /// Start time and position of the goto being run
+    goto: Option<(Instant, f64)>,
+}
+
+impl SimulatedAxis {
+    fn new() -> Self {
+        Self {
+            position: EQMOD_HOME_POSITION as f64,
+            since: Instant::now(),
+            initialized: false,
+            mode: MotionMode::TRACKING,
+            period: 0,
+            target: EQMOD_HOME_POSITION,
+            running: false,
+            goto: None,
+        }
+    }
+
+    /// Brings the position up to `now`, ending the goto if it is over
+    fn settle(&mut self, now: Instant, goto_time: Duration) {
+        if let Some((start, from)) = self.goto {
+            let elapsed = now - start;
+            if elapsed >= goto_time {
+                self.position = self.target as f64;
+                self.running = false;
+                self.goto = None;
+            } else {
+                let done = elapsed.as_secs_f64() / goto_time.as_secs_f64();
+                self.position = from + (self.target as f64 - from) * done;
+            }
+        } else if self.running && self.period > 0 {
+            let speed = if self.mode.speed == Speed::High {
+                SIMULATED_HIGH_SPEED_RATIO.parse::<f64>().unwrap_or(1.0)
+            } else {
+                1.0
+            };
+            let steps = SIMULATED_TIMER_FREQ as f64 / self.period as f64
+                * speed
+                * (now - self.since).as_secs_f64();
+            match self.mode.direction {
+                Direction::Forward => self.position += steps,
+                Direction::Backward => self.position -= steps,
+            }
+        }
+        self.since = now;
+    }
+
+    /// The three digits answered to the status inquiry
+    fn status(&self) -> String {
+        let mut mode = 0;
+        if self.mode.kind == MotionKind::Tracking {
+            mode |= 0x1;
+        }
+        if self.mode.direction == Direction::Backward {
+            mode |= 0x2;
+        }
+        if self.mode.speed == Speed::High {
+            mode |= 0x4;
+        }
+        format!(
+            "{:X}{:X}{:X}",
+            mode, self.running as u8, self.initialized as u8
+        )
+    }
+
+    fn steps(&self) -> u32 {
+        self.steps_off_by(0.0)
+    }
+
+    /// Position `offset` steps away from where the axis is
+    fn steps_off_by(&self, offset: f64) -> u32 {
+        (self.position + offset).round().clamp(0.0, 0xFFFFFF as f64) as u32
+    }
+
+    fn is_tracking(&self) -> bool {
+        self.running && self.goto.is_none() && self.mode.kind == MotionKind::Tracking
+    }
+}
+
+/// Inverse of MotionMode::payload
+fn motion_mode(payload: &str) -> Option<MotionMode> {
+    let digits = payload.as_bytes();
+    if digits.len() != 2 {
+        return None;
+    }
+    let (kind, speed) = match digits[0] {
+        b'0' => (MotionKind::Goto, Speed::High),
+        b'1' => (MotionKind::Tracking, Speed::Low),
+        b'2' => (MotionKind::Goto, Speed::Low),
+        b'3' => (MotionKind::Tracking, Speed::High),
+        _ => return None,
+    };
+    let direction = match (digits[1] as char).to_digit(16)? & 0x1 {
+        0 => Direction::Forward,
+        _ => Direction::Backward,
+    };
+    Some(MotionMode {
+        kind,
+        speed,
+        direction,
+    })
+}
+
+/// An EQMod motor board in software, answering the ':' frames of the
+/// RA and DEC axes like the real one. Gotos take SIMULATED_GOTO_TIME,
+/// slews and tracking move the axis at the rate given by the step
+/// period. Every frame received is kept so tests can check what the
+/// driver sent. While tracking, the RA position reported carries the
+/// periodic error of the model, if any.
+#[derive(Debug)]
+pub struct SimulatedMotors {
+    ra: SimulatedAxis,
+    dec: SimulatedAxis,
+    goto_time: Duration,
+    /// Feature bits answered to the extended inquiry
+    features: u32,
+    pe: PeModel,
+    /// When the worm started turning, for its phase
+    worm_start: Instant,
+    frames: Vec<String>,
+    /// Bytes written that don't make a whole frame yet
+    input: Vec<u8>,
+    /// Answers waiting to be read
+    output: VecDeque<u8>,
+    timeout: Duration,
+}
+
+impl Default for SimulatedMotors {
+    fn default() -> Self {
+        Self {
+            ra: SimulatedAxis::new(),
+            dec: SimulatedAxis::new(),
+            goto_time: SIMULATED_GOTO_TIME,
+            features: 0,
+            pe: PeModel::default(),
+            worm_start: Instant::now(),
+            frames: Vec::new(),
+            input: Vec::new(),
+            output: VecDeque::new(),
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl SimulatedMotors {
+    pub fn with_goto_time(mut self, goto_time: Duration) -> Self {
+        self.goto_time = goto_time;
+        self
+    }
+
+    /// Features advertised to the extended inquiry, e.g. 0x001000 for
+    /// a polar scope LED
+    pub fn with_features(mut self, features: u32) -> Self {
+        self.features = features;
+        self
+    }
+
+    /// Adds the periodic error of `pe` to the RA position reported
+    /// while tracking
+    pub fn with_periodic_error(mut self, pe: PeModel) -> Self {
+        self.pe = pe;
+        self
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Only kept to be handed back, answers are ready right away
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Both axes are back home, stopped and not initialized, as when
+    /// the board is turned off and on again
+    pub fn power_cycle(&mut self) {
+        self.ra = SimulatedAxis::new();
+        self.dec = SimulatedAxis::new();
+    }
+
+    /// Drops the answers not read yet
+    pub fn clear(&mut self) {
+        self.output.clear();
+    }
+
+    /// Frames received since the last call, without the carriage return
+    pub fn take_frames(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.frames)
+    }
+
+    /// Raw position of an axis
+    pub fn position(&mut self, axis: Axis) -> Option<u32> {
+        let goto_time = self.goto_time;
+        let axis = self.axis(axis)?;
+        axis.settle(Instant::now(), goto_time);
+        Some(axis.steps())
+    }
+
+    fn axis(&mut self, axis: Axis) -> Option<&mut SimulatedAxis> {
+        match axis {
+            Axis::Ra => Some(&mut self.ra),
+            Axis::Dec => Some(&mut self.dec),
+            Axis::Aux => None,
+        }
+    }
+
+    /// Answer to a whole frame, without the ':' and the carriage
+    /// return: the payload on success, the error code otherwise
+    fn answer(&mut self, frame: &[u8]) -> Result<String, u8> {
+        let (op, axis) = match frame {
+            [op, axis, ..] => (*op, *axis),
+            _ => return Err(b'1'),
+        };
+        let op = EqModOp::try_from(op).map_err(|_| b'0')?;
+        let axis = match axis {
+            b'1' => Axis::Ra,
+            b'2' => Axis::Dec,
+            _ => return Err(b'0'),
+        };
+        let payload = std::str::from_utf8(&frame[2..]).map_err(|_| b'3')?;
+        let value = || str_24bits_to_u24(payload.to_owned()).ok_or(b'3');
+        if op == EqModOp::ExtendedSetting && axis == Axis::Ra {
+            // PPEC training and correction, reported by the extended inquiry
+            match value()? {
+                0x00 => self.features |= 0x000010,
+                0x01 => self.features &= !0x000010,
+                0x02 => self.features |= 0x000020,
+                0x03 => self.features &= !0x000020,
+                _ => {}
+            }
+        }
+        let (goto_time, features) = (self.goto_time, self.features);
+        let state = match axis {
+            Axis::Ra => &mut self.ra,
+            Axis::Dec => &mut self.dec,
+            Axis::Aux => return Err(b'0'),
+        };
+        state.settle(Instant::now(), goto_time);
+
+        let answer = match op {
+            EqModOp::Init => {
+                state.initialized = true;
+                String::new()
+            }
+            EqModOp::MotorBoardVersion => String::from(SIMULATED_VERSION),
+            EqModOp::InquireGridPerRevolution => u32_to_str_24bits(SIMULATED_GRID),
+            EqModOp::InquireTimerInterruptFreq => u32_to_str_24bits(SIMULATED_TIMER_FREQ),
+            EqModOp::InquireHighSpeedRatio => String::from(SIMULATED_HIGH_SPEED_RATIO),
+            EqModOp::ExtendedInquiry => u32_to_str_24bits(features),
+            EqModOp::GetAxisPosition
+                if axis == Axis::Ra && state.is_tracking() && self.pe.is_enabled() =>
+            {
+                let error = self
+                    .pe
+                    .error_arcsec(self.worm_start.elapsed().as_secs_f64());
+                u32_to_str_24bits(state.steps_off_by(error / 1_296_000.0 * SIMULATED_GRID as f64))
+            }
+            EqModOp::GetAxisPosition => u32_to_str_24bits(state.steps()),
+            EqModOp::SetAxisPosition => {
+                state.position = value()? as f64;
+                String::new()
+            }
+            EqModOp::GetAxisStatus => state.status(),
+            EqModOp::SetMotionMode if state.running => return Err(b'2'),
+            EqModOp::SetMotionMode => {
+                state.mode = motion_mode(payload).ok_or(b'3')?;
+                String::new()
+            }
+            EqModOp::SetStepPeriod => {
+                state.period = value()?;
+                String::new()
+            }
+            EqModOp::SetGotoTarget => {
+                state.target = value()?;
+                String::new()
+            }
+            EqModOp::SetGotoTargetIncrement => {
+                let steps = value()?;
+                state.target = match state.mode.direction {
+                    Direction::Forward => state.steps().saturating_add(steps),
+                    Direction::Backward => state.steps().saturating_sub(steps),
+                };
+                String::new()
+            }
+            EqModOp::SetBreakPointIncrement => String::new(),
+            EqModOp::StartMotion if !state.initialized => return Err(b'4'),
+            EqModOp::StartMotion => {
+                if state.mode.kind == MotionKind::Goto {
+                    state.goto = Some((state.since, state.position));
+                }
+                state.running = true;
+                String::new()
+            }
+            EqModOp::StopMotion | EqModOp::InstantStop => {
+                state.running = false;
+                state.goto = None;
+                String::new()
+            }
+            EqModOp::InquireAuxEncoder if features & 0x000001 != 0 => {
+                u32_to_str_24bits(state.steps())
+            }
+            EqModOp::SetPolarLed if features & 0x001000 == 0 => return Err(b'0'),
+            EqModOp::SetSnapPort if features & 0x002000 == 0 => return Err(b'0'),
+            EqModOp::SetPolarLed | EqModOp::SetSnapPort | EqModOp::ExtendedSetting => String::new(),
+            EqModOp::InquireAuxEncoder => return Err(b'0'),
+        };
+        Ok(answer)
+    }
+}
+
+impl Write for SimulatedMotors {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.input.extend_from_slice(buf);
+        while let Some(end) = self.input.iter().position(|b| *b == b'\r') {
+            let frame: Vec<u8> = self.input.drain(..=end).collect();
+            // Anything before the ':' is line noise
+            let start = match frame.iter().position(|b| *b == b':') {
+                Some(start) => start + 1,
+                None => continue,
+            };
+            let frame = &frame[start..frame.len() - 1];
+            self.frames
+                .push(format!(":{}", String::from_utf8_lossy(frame)));
+            let answer = match self.answer(frame) {
+                Ok(payload) => format!("={}\r", payload),
+                Err(code) => format!("!{}\r", code as char),
+            };
+            self.output.extend(answer.into_bytes());
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Read for SimulatedMotors {
+    /// Times out right away when there is nothing to read, waiting would
+    /// not make an answer show up
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.output.is_empty() {
+            return Err(io::Error::new(ErrorKind::TimedOut, "no answer"));
+        }
+        let n = buf.len().min(self.output.len());
+        for (b, answer) in buf.iter_mut().zip(self.output.drain(..n)) {
+            *b = answer;
+        }
+        Ok(n)
+    }
+}
+
+impl SerialTransport for SimulatedMotors {
+    fn exchange(&mut self, frame: &[u8], terminator: u8) -> Result<Vec<u8>, TransportError> {
+        let timeout = self.timeout;
+        let result = exchange_frame(self, frame, terminator, timeout);
+        if result.is_err() {
+            self.clear();
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SimulatedMotors;
+    use crate::eqmod::{
+        backlash_commands, command, goto_commands, parse_24bits_response, probe_aux_axis,
+        split_response, Axis, AxisStatus, Direction, EqModOp, MotionMode,
+    };
+    use crate::periodic_error::{PeComponent, PeModel};
+    use crate::serial::{detect_protocol, Protocol, SerialTransport};
+    use crate::{u32_to_str_24bits, EQMOD_HOME_POSITION};
+    use std::f64::consts::FRAC_PI_2;
+    use std::thread;
+    use std::time::Duration;
+
+    fn send(
+        motors: &mut SimulatedMotors,
+        op: EqModOp,
+        axis: Axis,
+        payload: Option<String>,
+    ) -> Result<String, u8> {
+        let mut frame = command(op, axis);
+        frame.extend(payload.unwrap_or_default().into_bytes());
+        frame.push(b'\r');
+        let answer = motors.exchange(&frame, b'\r').unwrap();
+        match split_response(&answer) {
+            Ok(payload) => Ok(String::from_utf8(payload.to_vec()).unwrap()),
+            Err(_) => Err(answer[1]),
+        }
+    }
+
+    fn status(motors: &mut SimulatedMotors, axis: Axis) -> AxisStatus {
+        AxisStatus::from_response(&send(motors, EqModOp::GetAxisStatus, axis, None).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn detected_as_a_motor_board() {
+        let mut motors = SimulatedMotors::default();
+        assert_eq!(
+            detect_protocol(&mut motors, Duration::from_millis(10)),
+            Protocol::EqMod
+        );
+        assert!(!probe_aux_axis(&mut motors));
+        assert_eq!(motors.take_frames(), vec![":e1", ":j3"]);
+    }
+
+    #[test]
+    fn moves_only_once_initialized() {
+        let mut motors = SimulatedMotors::default();
+        assert!(!status(&mut motors, Axis::Ra).initialized);
+        assert_eq!(
+            send(&mut motors, EqModOp::StartMotion, Axis::Ra, None),
+            Err(b'4')
+        );
+        send(&mut motors, EqModOp::Init, Axis::Ra, None).unwrap();
+        assert!(status(&mut motors, Axis::Ra).initialized);
+        assert_eq!(
+            parse_24bits_response(
+                &send(
+                    &mut motors,
+                    EqModOp::InquireGridPerRevolution,
+                    Axis::Ra,
+                    None
+                )
+                .unwrap()
+            ),
+            Ok(9024000)
+        );
+    }
+
+    #[test]
+    fn gotos_take_a_while() {
+        let mut motors = SimulatedMotors::default().with_goto_time(Duration::from_millis(200));
+        send(&mut motors, EqModOp::Init, Axis::Dec, None).unwrap();
+        let target = EQMOD_HOME_POSITION + 10000;
+        let dec = status(&mut motors, Axis::Dec);
+        for (op, payload) in
+            goto_commands(&dec, EQMOD_HOME_POSITION, target, 9024000, 3500).unwrap()
+        {
+            send(&mut motors, op, Axis::Dec, payload).unwrap();
+        }
+        assert!(status(&mut motors, Axis::Dec).is_slewing());
+        // The motion mode can't change on the way
+        assert_eq!(
+            send(
+                &mut motors,
+                EqModOp::SetMotionMode,
+                Axis::Dec,
+                Some(MotionMode::TRACKING.payload())
+            ),
+            Err(b'2')
+        );
+
+        thread::sleep(Duration::from_millis(250));
+        assert!(!status(&mut motors, Axis::Dec).running);
+        assert_eq!(motors.position(Axis::Dec), Some(target));
+    }
+
+    #[test]
+    fn relative_gotos_and_tracking() {
+        let mut motors = SimulatedMotors::default().with_goto_time(Duration::ZERO);
+        send(&mut motors, EqModOp::Init, Axis::Ra, None).unwrap();
+        for (op, payload) in backlash_commands(Direction::Backward, 50) {
+            send(&mut motors, op, Axis::Ra, payload).unwrap();
+        }
+        assert_eq!(motors.position(Axis::Ra), Some(EQMOD_HOME_POSITION - 50));
+
+        send(
+            &mut motors,
+            EqModOp::SetMotionMode,
+            Axis::Ra,
+            Some(MotionMode::TRACKING.payload()),
+        )
+        .unwrap();
+        send(
+            &mut motors,
+            EqModOp::SetStepPeriod,
+            Axis::Ra,
+            Some(u32_to_str_24bits(10)),
+        )
+        .unwrap();
+        send(&mut motors, EqModOp::StartMotion, Axis::Ra, None).unwrap();
+        let tracking = status(&mut motors, Axis::Ra);
+        assert!(tracking.running && tracking.tracking_mode);
+        thread::sleep(Duration::from_millis(50));
+        send(&mut motors, EqModOp::StopMotion, Axis::Ra, None).unwrap();
+        assert!(motors.position(Axis::Ra).unwrap() > EQMOD_HOME_POSITION);
+    }
+
+    /// RA position reported after tracking starts on motors with `pe`,
+    /// at a rate too slow to move the axis
+    fn tracked_ra(pe: PeModel) -> Vec<u32> {
+        let mut motors = SimulatedMotors::default().with_periodic_error(pe);
+        send(&mut motors, EqModOp::Init, Axis::Ra, None).unwrap();
+        send(
+            &mut motors,
+            EqModOp::SetMotionMode,
+            Axis::Ra,
+            Some(MotionMode::TRACKING.payload()),
+        )
+        .unwrap();
+        send(
+            &mut motors,
+            EqModOp::SetStepPeriod,
+            Axis::Ra,
+            Some(u32_to_str_24bits(0xFFFFFF)),
+        )
+        .unwrap();
+        send(&mut motors, EqModOp::StartMotion, Axis::Ra, None).unwrap();
+        (0..10)
+            .map(|_| {
+                let answer = send(&mut motors, EqModOp::GetAxisPosition, Axis::Ra, None);
+                parse_24bits_response(&answer.unwrap()).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn periodic_error_moves_the_reported_ra() {
+        for steps in tracked_ra(PeModel::default()) {
+            assert_eq!(steps, EQMOD_HOME_POSITION);
+        }
+
+        // 10 arcsec are 69.6 steps of a 9024000 steps revolution
+        let pe = vec![PeComponent {
+            harmonic: 1,
+            amplitude_arcsec: 10.0,
+            phase: FRAC_PI_2,
+        }];
+        for steps in tracked_ra(PeModel::new(pe, 1e9)) {
+            assert_eq!(steps, EQMOD_HOME_POSITION + 70);
+        }
+
+        let walk = || PeModel::default().with_random_walk(42, 1.0);
+        assert_eq!(tracked_ra(walk()), tracked_ra(walk()));
+        assert!(tracked_ra(walk())
+            .iter()
+            .any(|steps| *steps != EQMOD_HOME_POSITION));
+    }
+}
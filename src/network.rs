@@ -0,0 +1,491 @@
+use crate::eqmod::EqModOp;
+use crate::serial::{exchange_frame, ReadError, SerialTransport, TransportError};
+use crate::synscan::Command;
+use log::debug;
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+/// Prefix of the addresses of serial ports behind a TCP bridge, e.g.
+/// "tcp://observatory:4001" for a ser2net or USR-TCP232 adapter
+pub const TCP_SCHEME: &str = "tcp://";
+/// Port the SynScan Wi-Fi adapters listen on
+pub const SYNSCAN_UDP_PORT: u16 = 11880;
+/// Times a lost query is sent again within its timeout
+pub const UDP_RESENDS: u32 = 2;
+/// Largest datagram read, answers are a few bytes long
+const MAX_DATAGRAM_LEN: usize = 512;
+
+/// SynScan or EQMod frames over UDP, one datagram each way. Datagrams get lost,
+/// so queries are sent again when their answer doesn't come back in
+/// time. Commands that move the mount are sent once, the device decides
+/// what to do when they go unanswered.
+#[derive(Debug)]
+pub struct UdpTransport {
+    socket: UdpSocket,
+    peer: SocketAddr,
+    timeout: Duration,
+    /// What is left of the last datagram received
+    pending: VecDeque<u8>,
+}
+
+impl UdpTransport {
+    /// `address` is a host and port, e.g. "192.168.4.1:11880". Nothing is
+    /// sent, UDP has no connection to set up.
+    pub fn connect(address: &str, timeout: Duration) -> io::Result<Self> {
+        let peer = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "no address to send to"))?;
+        let local = if peer.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(peer)?;
+        let mut transport = Self {
+            socket,
+            peer,
+            timeout,
+            pending: VecDeque::new(),
+        };
+        transport.set_timeout(timeout)?;
+        Ok(transport)
+    }
+
+    pub fn peer(&self) -> SocketAddr {
+        self.peer
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.timeout = timeout;
+        self.set_read_timeout(timeout)
+    }
+
+    /// A zero timeout means blocking forever to the socket
+    fn set_read_timeout(&self, timeout: Duration) -> io::Result<()> {
+        self.socket
+            .set_read_timeout(Some(timeout.max(Duration::from_millis(1))))
+    }
+
+    /// Drops the answers not read yet, late ones to an earlier frame
+    /// included so they aren't taken for the answer to the next one
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.pending.clear();
+        self.socket.set_nonblocking(true)?;
+        let mut datagram = [0; MAX_DATAGRAM_LEN];
+        while self.socket.recv(&mut datagram).is_ok() {}
+        self.socket.set_nonblocking(false)
+    }
+}
+
+impl Read for UdpTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            let mut datagram = [0; MAX_DATAGRAM_LEN];
+            let len = match self.socket.recv(&mut datagram) {
+                Ok(len) => len,
+                // Unix reports the read timeout as WouldBlock
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    return Err(io::Error::new(ErrorKind::TimedOut, e));
+                }
+                Err(e) => return Err(e),
+            };
+            self.pending.extend(&datagram[..len]);
+        }
+        let n = buf.len().min(self.pending.len());
+        for (b, answer) in buf.iter_mut().zip(self.pending.drain(..n)) {
+            *b = answer;
+        }
+        Ok(n)
+    }
+}
+
+impl Write for UdpTransport {
+    /// Each write goes out as one datagram
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.socket.send(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SerialTransport for UdpTransport {
+    /// Queries get the timeout split between UDP_RESENDS + 1 attempts
+    fn exchange(&mut self, frame: &[u8], terminator: u8) -> Result<Vec<u8>, TransportError> {
+        let resend = match frame {
+            // EQMod frames are ':', the operation and the axis
+            [b':', op, ..] => EqModOp::try_from(*op).is_ok_and(|op| op.is_idempotent()),
+            [c, ..] => Command::try_from(*c).is_ok_and(|c| c.is_idempotent()),
+            [] => false,
+        };
+        let attempts = if resend { UDP_RESENDS + 1 } else { 1 };
+        let wait = self.timeout / attempts;
+
+        let _ = self.clear();
+        let _ = self.set_read_timeout(wait);
+        let mut attempt = 1;
+        let result = loop {
+            match exchange_frame(self, frame, terminator, wait) {
+                Err(TransportError::Read(ReadError::Timeout)) if attempt < attempts => {
+                    debug!("No answer from {}, sending again", self.peer);
+                    attempt += 1;
+                    // Half an answer is of no use with the next one
+                    self.pending.clear();
+                }
+                result => break result,
+            }
+        };
+        let _ = self.set_read_timeout(self.timeout);
+        result
+    }
+}
+
+/// Host and port of a TCP bridge address, None for a port path
+pub fn tcp_address(address: &str) -> Option<&str> {
+    address.strip_prefix(TCP_SCHEME)
+}
+
+/// Whether the error means the bridge closed or lost the connection
+fn is_disconnect(kind: ErrorKind) -> bool {
+    matches!(
+        kind,
+        ErrorKind::BrokenPipe
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::NotConnected
+            | ErrorKind::UnexpectedEof
+    )
+}
+
+/// A serial port behind a TCP bridge, bytes go through unchanged. A
+/// dropped connection fails the exchange it happened in, the next one
+/// connects again.
+#[derive(Debug)]
+pub struct TcpTransport {
+    /// Host and port, without the scheme
+    address: String,
+    stream: Option<TcpStream>,
+    timeout: Duration,
+}
+
+impl TcpTransport {
+    /// Connects to `address`, a host and port, within `timeout`
+    pub fn connect(address: &str, timeout: Duration) -> io::Result<Self> {
+        let stream = Self::open(address, timeout)?;
+        Ok(Self {
+            address: address.to_owned(),
+            stream: Some(stream),
+            timeout,
+        })
+    }
+
+    fn open(address: &str, timeout: Duration) -> io::Result<TcpStream> {
+        // A zero timeout is an error to the socket
+        let timeout = timeout.max(Duration::from_millis(1));
+        let mut error = io::Error::new(ErrorKind::NotFound, "no address to connect to");
+        for peer in address.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&peer, timeout) {
+                Ok(stream) => {
+                    stream.set_read_timeout(Some(timeout))?;
+                    stream.set_write_timeout(Some(timeout))?;
+                    // Frames are a few bytes, they shouldn't wait for more
+                    stream.set_nodelay(true)?;
+                    return Ok(stream);
+                }
+                Err(e) => error = e,
+            }
+        }
+        Err(error)
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.timeout = timeout;
+        if let Some(stream) = &self.stream {
+            let timeout = Some(timeout.max(Duration::from_millis(1)));
+            stream.set_read_timeout(timeout)?;
+            stream.set_write_timeout(timeout)?;
+        }
+        Ok(())
+    }
+
+    /// Drops the bytes not read yet
+    pub fn clear(&mut self) -> io::Result<()> {
+        let stream = match &mut self.stream {
+            Some(s) => s,
+            None => return Ok(()),
+        };
+        stream.set_nonblocking(true)?;
+        let mut buf = [0; MAX_DATAGRAM_LEN];
+        let closed = loop {
+            match stream.read(&mut buf) {
+                Ok(0) => break true,
+                Ok(_) => continue,
+                Err(e) => break is_disconnect(e.kind()),
+            }
+        };
+        if closed {
+            self.stream = None;
+            return Ok(());
+        }
+        stream.set_nonblocking(false)
+    }
+
+    /// The connection, made again when the last one dropped
+    fn stream(&mut self) -> io::Result<&mut TcpStream> {
+        if self.stream.is_none() {
+            debug!("Connecting to {} again", self.address);
+            self.stream = Some(Self::open(&self.address, self.timeout)?);
+        }
+        Ok(self.stream.as_mut().unwrap())
+    }
+
+    /// Forgets the connection when `result` says it dropped
+    fn check<T>(&mut self, result: io::Result<T>) -> io::Result<T> {
+        if let Err(e) = &result {
+            if is_disconnect(e.kind()) {
+                debug!("Lost the connection to {}: {}", self.address, e);
+                self.stream = None;
+            }
+        }
+        result
+    }
+}
+
+impl Read for TcpTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let result = match self.stream()?.read(buf) {
+            // Nothing more will ever come from a closed connection
+            Ok(0) if !buf.is_empty() => Err(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "the bridge closed the connection",
+            )),
+            // Unix reports the read timeout as WouldBlock
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                return Err(io::Error::new(ErrorKind::TimedOut, e));
+            }
+            result => result,
+        };
+        self.check(result)
+    }
+}
+
+impl Write for TcpTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let result = self.stream()?.write(buf);
+        self.check(result)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let result = self.stream()?.flush();
+        self.check(result)
+    }
+}
+
+impl SerialTransport for TcpTransport {
+    fn exchange(&mut self, frame: &[u8], terminator: u8) -> Result<Vec<u8>, TransportError> {
+        let timeout = self.timeout;
+        let result = exchange_frame(self, frame, terminator, timeout);
+        if let Err(TransportError::Read(_)) = result {
+            // Drop whatever is left so it doesn't end up in the next answer
+            let _ = self.clear();
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{tcp_address, TcpTransport, UdpTransport, UDP_RESENDS};
+    use crate::eqmod::{command, Axis, EqModOp};
+    use crate::serial::{ReadError, SerialTransport, TransportError};
+    use crate::synscan::{build_frame, Command};
+    use std::io::{ErrorKind, Read, Write};
+    use std::net::{TcpListener, UdpSocket};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    /// Answers the frames with `answer`, leaving out the first `drop`
+    /// ones, and counts what it received
+    fn adapter(drop: usize, answer: &'static [u8]) -> (String, Arc<AtomicUsize>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap().to_string();
+        let received = Arc::new(AtomicUsize::new(0));
+        let received_ = Arc::clone(&received);
+        thread::spawn(move || {
+            let mut buf = [0; 64];
+            while let Ok((_, from)) = socket.recv_from(&mut buf) {
+                if received_.fetch_add(1, Ordering::SeqCst) >= drop {
+                    socket.send_to(answer, from).unwrap();
+                }
+            }
+        });
+        (address, received)
+    }
+
+    #[test]
+    fn queries_and_answers() {
+        let (address, received) = adapter(0, b"x#");
+        let mut transport = UdpTransport::connect(&address, Duration::from_millis(500)).unwrap();
+        assert_eq!(transport.peer().to_string(), address);
+        assert_eq!(
+            transport.exchange(&build_frame(Command::Echo, Some(b"x")), b'#'),
+            Ok(b"x#".to_vec())
+        );
+        assert_eq!(
+            transport.exchange(&build_frame(Command::Echo, Some(b"x")), b'#'),
+            Ok(b"x#".to_vec())
+        );
+        assert_eq!(received.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn lost_queries_are_sent_again() {
+        let (address, received) = adapter(UDP_RESENDS as usize, b"1#");
+        let mut transport = UdpTransport::connect(&address, Duration::from_millis(600)).unwrap();
+        assert_eq!(
+            transport.exchange(&build_frame(Command::IsGotoInProgress, None), b'#'),
+            Ok(b"1#".to_vec())
+        );
+        assert_eq!(received.load(Ordering::SeqCst), UDP_RESENDS as usize + 1);
+    }
+
+    #[test]
+    fn gotos_are_sent_once() {
+        let (address, received) = adapter(1, b"#");
+        let mut transport = UdpTransport::connect(&address, Duration::from_millis(200)).unwrap();
+        let goto = build_frame(Command::GoToPreciseRaDec, Some(b"12AB0500,40000000"));
+        assert_eq!(
+            transport.exchange(&goto, b'#'),
+            Err(TransportError::Read(ReadError::Timeout))
+        );
+        assert_eq!(received.load(Ordering::SeqCst), 1);
+
+        // Answered once the adapter is back
+        assert_eq!(transport.exchange(&goto, b'#'), Ok(b"#".to_vec()));
+        assert_eq!(received.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn unresolvable_address() {
+        assert!(UdpTransport::connect("not an address", Duration::from_millis(100)).is_err());
+    }
+
+    #[test]
+    fn tcp_addresses() {
+        assert_eq!(
+            tcp_address("tcp://observatory:4001"),
+            Some("observatory:4001")
+        );
+        assert_eq!(tcp_address("/dev/ttyUSB0"), None);
+    }
+
+    /// Serves one connection per script, answering each frame with the
+    /// next line and closing the connection at a None
+    fn bridge(scripts: Vec<Vec<Option<&'static [u8]>>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            for script in scripts {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut frame = [0; 64];
+                for line in script {
+                    if stream.read(&mut frame).unwrap_or(0) == 0 {
+                        break;
+                    }
+                    match line {
+                        Some(answer) => stream.write_all(answer).unwrap(),
+                        None => break,
+                    }
+                }
+            }
+        });
+        address
+    }
+
+    #[test]
+    fn scripted_bridge() {
+        let address = bridge(vec![vec![Some(b"=020300\r"), Some(b"!0\r=10\r")]]);
+        let mut transport = TcpTransport::connect(&address, Duration::from_millis(500)).unwrap();
+        assert_eq!(
+            transport.exchange(b":e1\r", b'\r'),
+            Ok(b"=020300\r".to_vec())
+        );
+        assert_eq!(transport.exchange(b":f1\r", b'\r'), Ok(b"!0\r".to_vec()));
+        assert!(transport.is_connected());
+    }
+
+    #[test]
+    fn disconnected_mid_command() {
+        let address = bridge(vec![vec![Some(b"x#"), None], vec![Some(b"x#")]]);
+        let mut transport = TcpTransport::connect(&address, Duration::from_millis(500)).unwrap();
+        let echo = build_frame(Command::Echo, Some(b"x"));
+        assert_eq!(transport.exchange(&echo, b'#'), Ok(b"x#".to_vec()));
+
+        // The bridge goes away before answering
+        assert_eq!(
+            transport.exchange(&echo, b'#'),
+            Err(TransportError::Read(ReadError::Io(
+                ErrorKind::UnexpectedEof
+            )))
+        );
+        assert!(!transport.is_connected());
+
+        // The next exchange connects again
+        assert_eq!(transport.exchange(&echo, b'#'), Ok(b"x#".to_vec()));
+        assert!(transport.is_connected());
+    }
+
+    #[test]
+    fn bridge_gone_for_good() {
+        let address = bridge(vec![vec![None]]);
+        let mut transport = TcpTransport::connect(&address, Duration::from_millis(200)).unwrap();
+        let echo = build_frame(Command::Echo, Some(b"x"));
+        assert!(transport.exchange(&echo, b'#').is_err());
+        // The listener is closed, connecting again is refused
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(
+            transport.exchange(&echo, b'#'),
+            Err(TransportError::Write(ErrorKind::ConnectionRefused))
+        );
+        assert!(!transport.is_connected());
+    }
+
+    #[test]
+    fn eqmod_queries_are_sent_again() {
+        let (address, received) = adapter(UDP_RESENDS as usize, b"=000080\r");
+        let mut transport = UdpTransport::connect(&address, Duration::from_millis(600)).unwrap();
+        let mut query = command(EqModOp::GetAxisPosition, Axis::Ra);
+        query.push(b'\r');
+        assert_eq!(transport.exchange(&query, b'\r'), Ok(b"=000080\r".to_vec()));
+        assert_eq!(received.load(Ordering::SeqCst), UDP_RESENDS as usize + 1);
+
+        let (address, received) = adapter(1, b"=\r");
+        let mut transport = UdpTransport::connect(&address, Duration::from_millis(200)).unwrap();
+        let mut goto = command(EqModOp::SetGotoTarget, Axis::Ra);
+        goto.extend_from_slice(b"000080\r");
+        assert_eq!(
+            transport.exchange(&goto, b'\r'),
+            Err(TransportError::Read(ReadError::Timeout))
+        );
+        assert_eq!(received.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,166 @@
+use crate::eqmod::TrackingRate;
+use crate::write_file_atomically;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// State file used when LS_PARK_STATE_FILE is not set
+pub const DEFAULT_PARK_STATE_FILE: &str = "skywatcher-park.json";
+
+/// Axis positions further than this from the parked ones, in steps,
+/// mean the mount was moved while the driver wasn't running
+pub const PARK_TOLERANCE_STEPS: u32 = 100;
+
+/// Park state of a mount, persisted so a restarted driver knows the
+/// mount is parked and where its axes were left
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ParkState {
+    pub parked: bool,
+    /// Tracking rate to restore when unparking, custom rates are kept
+    /// as their value in arcseconds per second
+    pub tracking: Option<String>,
+    pub ra_steps: u32,
+    pub dec_steps: u32,
+}
+
+impl ParkState {
+    /// Marks the mount as parked at the given raw axis positions
+    pub fn park(&mut self, tracking: Option<TrackingRate>, ra_steps: u32, dec_steps: u32) {
+        // Parking twice keeps the tracking of the first park
+        if !self.parked {
+            self.tracking = tracking.map(|r| match r {
+                TrackingRate::Custom(arcsec_per_sec) => arcsec_per_sec.to_string(),
+                r => r.to_string(),
+            });
+        }
+        self.parked = true;
+        self.ra_steps = ra_steps;
+        self.dec_steps = dec_steps;
+    }
+
+    /// Clears the parked flag, returning the tracking rate to restore
+    pub fn unpark(&mut self) -> Option<TrackingRate> {
+        if !self.parked {
+            return None;
+        }
+        self.parked = false;
+        let tracking = self.tracking.take()?;
+        tracking.parse().ok().or_else(|| {
+            let rate = tracking.parse().ok().and_then(TrackingRate::custom);
+            if rate.is_none() {
+                warn!("Cannot restore the tracking rate {:?}", tracking);
+            }
+            rate
+        })
+    }
+
+    /// Tells whether the axes are no longer where the mount was parked
+    pub fn moved(&self, ra_steps: u32, dec_steps: u32) -> bool {
+        self.parked
+            && (self.ra_steps.abs_diff(ra_steps) > PARK_TOLERANCE_STEPS
+                || self.dec_steps.abs_diff(dec_steps) > PARK_TOLERANCE_STEPS)
+    }
+}
+
+/// Reads the park states, by device name, a missing or unreadable file
+/// gives no state at all
+pub fn load_park_states(path: &Path) -> HashMap<String, ParkState> {
+    let content = match fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(_) => return HashMap::new(),
+    };
+
+    serde_json::from_str(&content).unwrap_or_else(|e| {
+        warn!("Ignoring invalid park state file {:?}: {}", path, e);
+        HashMap::new()
+    })
+}
+
+/// Held while the state file is read, changed and written back, the
+/// devices of a driver share it
+static SAVE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Stores the park state of a device, leaving the other devices alone
+pub fn save_park_state(path: &Path, device: &str, state: &ParkState) -> io::Result<()> {
+    let _guard = SAVE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut states = load_park_states(path);
+    states.insert(device.to_owned(), state.clone());
+    let content = serde_json::to_string_pretty(&states)?;
+    write_file_atomically(path, &content)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{load_park_states, save_park_state, ParkState};
+    use crate::eqmod::TrackingRate;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn state_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("skywatcher-{}-{}.json", name, std::process::id()))
+    }
+
+    #[test]
+    fn park_and_unpark() {
+        let mut state = ParkState::default();
+        assert_eq!(state.unpark(), None);
+
+        state.park(Some(TrackingRate::Sidereal), 0x800000, 0x800000);
+        assert!(state.parked);
+        // Parking again doesn't forget what was tracking before
+        state.park(None, 0x800000, 0x800000);
+        assert_eq!(state.tracking, Some(String::from("Sidereal")));
+
+        assert_eq!(state.unpark(), Some(TrackingRate::Sidereal));
+        assert!(!state.parked);
+        assert_eq!(state.unpark(), None);
+
+        state.park(None, 0x800000, 0x800000);
+        assert_eq!(state.unpark(), None);
+
+        // Custom rates come back with their value
+        state.park(TrackingRate::custom(15.0), 0x800000, 0x800000);
+        assert_eq!(state.tracking, Some(String::from("15")));
+        assert_eq!(state.unpark(), TrackingRate::custom(15.0));
+
+        state.tracking = Some(String::from("Custom"));
+        state.parked = true;
+        assert_eq!(state.unpark(), None);
+    }
+
+    #[test]
+    fn moved_while_parked() {
+        let mut state = ParkState::default();
+        assert!(!state.moved(0x900000, 0x800000));
+
+        state.park(None, 0x800000, 0x800000);
+        assert!(!state.moved(0x800000 + 50, 0x800000 - 50));
+        assert!(state.moved(0x900000, 0x800000));
+        assert!(state.moved(0x800000, 0x7F0000));
+    }
+
+    #[test]
+    fn state_file_round_trip() {
+        let path = state_file("park-round-trip");
+        let _ = fs::remove_file(&path);
+        assert!(load_park_states(&path).is_empty());
+
+        let mut eq6 = ParkState::default();
+        eq6.park(Some(TrackingRate::Lunar), 0x800000, 0x812345);
+        save_park_state(&path, "EQ6-r-A1", &eq6).unwrap();
+        save_park_state(&path, "EQ8-B2", &ParkState::default()).unwrap();
+
+        let states = load_park_states(&path);
+        assert_eq!(states.len(), 2);
+        assert_eq!(states["EQ6-r-A1"], eq6);
+        assert_eq!(states["EQ8-B2"], ParkState::default());
+
+        fs::write(&path, "not json").unwrap();
+        assert!(load_park_states(&path).is_empty());
+        fs::remove_file(&path).unwrap();
+    }
+}
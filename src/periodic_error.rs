@@ -0,0 +1,187 @@
+use std::f64::consts::TAU;
+
+/// A single sinusoidal component of the periodic error, `harmonic` is
+/// the number of cycles per worm revolution (1 is the worm period itself)
+#[derive(Clone, Debug, PartialEq)]
+pub struct PeComponent {
+    pub harmonic: u32,
+    pub amplitude_arcsec: f64,
+    pub phase: f64,
+}
+
+/// Returns the periodic error in arcseconds for the given worm phase,
+/// expressed as a fraction of the worm revolution (0.0..1.0).
+/// With no components the error is always 0.
+pub fn periodic_error_arcsec(worm_phase: f64, components: &[PeComponent]) -> f64 {
+    components
+        .iter()
+        .map(|c| c.amplitude_arcsec * (TAU * c.harmonic as f64 * worm_phase + c.phase).sin())
+        .sum()
+}
+
+/// Returns the worm phase (0.0..1.0) after the given amount of seconds
+pub fn worm_phase(elapsed_secs: f64, worm_period_secs: f64) -> f64 {
+    (elapsed_secs / worm_period_secs).rem_euclid(1.0)
+}
+
+/// Deterministic random walk to be added on top of the periodic error,
+/// the same seed always produces the same sequence.
+#[derive(Debug)]
+pub struct RandomWalk {
+    state: u64,
+    step_arcsec: f64,
+    value: f64,
+}
+
+impl RandomWalk {
+    pub fn new(seed: u64, step_arcsec: f64) -> Self {
+        Self {
+            // xorshift gets stuck on 0
+            state: seed.max(1),
+            step_arcsec,
+            value: 0.0,
+        }
+    }
+
+    /// Moves the walk one step up or down and returns the new offset
+    pub fn step(&mut self) -> f64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+
+        if self.state & 1 == 0 {
+            self.value += self.step_arcsec;
+        } else {
+            self.value -= self.step_arcsec;
+        }
+        self.value
+    }
+}
+
+/// Periodic error of the RA worm with an optional random walk on top,
+/// what the simulators add to the RA they report. The default model
+/// has neither, it leaves the positions as they are.
+#[derive(Debug, Default)]
+pub struct PeModel {
+    components: Vec<PeComponent>,
+    worm_period_secs: f64,
+    walk: Option<RandomWalk>,
+}
+
+impl PeModel {
+    pub fn new(components: Vec<PeComponent>, worm_period_secs: f64) -> Self {
+        Self {
+            components,
+            worm_period_secs,
+            walk: None,
+        }
+    }
+
+    /// Adds a random walk of `step_arcsec` steps seeded with `seed`
+    pub fn with_random_walk(mut self, seed: u64, step_arcsec: f64) -> Self {
+        self.walk = Some(RandomWalk::new(seed, step_arcsec));
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        (!self.components.is_empty() && self.worm_period_secs > 0.0) || self.walk.is_some()
+    }
+
+    /// Error in arcseconds once the worm turned for `elapsed_secs`,
+    /// every call moves the random walk one step
+    pub fn error_arcsec(&mut self, elapsed_secs: f64) -> f64 {
+        let pe = if self.worm_period_secs > 0.0 {
+            periodic_error_arcsec(
+                worm_phase(elapsed_secs, self.worm_period_secs),
+                &self.components,
+            )
+        } else {
+            0.0
+        };
+        pe + self.walk.as_mut().map_or(0.0, RandomWalk::step)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{periodic_error_arcsec, worm_phase, PeComponent, PeModel, RandomWalk};
+    use assert_approx_eq::assert_approx_eq;
+    use std::f64::consts::FRAC_PI_2;
+
+    #[test]
+    fn single_component_waveform() {
+        let pe = [PeComponent {
+            harmonic: 1,
+            amplitude_arcsec: 10.0,
+            phase: 0.0,
+        }];
+        assert_approx_eq!(periodic_error_arcsec(0.0, &pe), 0.0);
+        assert_approx_eq!(periodic_error_arcsec(0.25, &pe), 10.0);
+        assert_approx_eq!(periodic_error_arcsec(0.5, &pe), 0.0);
+        assert_approx_eq!(periodic_error_arcsec(0.75, &pe), -10.0);
+    }
+
+    #[test]
+    fn sum_of_components() {
+        let pe = [
+            PeComponent {
+                harmonic: 1,
+                amplitude_arcsec: 10.0,
+                phase: 0.0,
+            },
+            PeComponent {
+                harmonic: 2,
+                amplitude_arcsec: 2.0,
+                phase: FRAC_PI_2,
+            },
+        ];
+        assert_approx_eq!(periodic_error_arcsec(0.0, &pe), 2.0);
+        assert_approx_eq!(periodic_error_arcsec(0.25, &pe), 8.0);
+    }
+
+    #[test]
+    fn disabled_model_has_no_error() {
+        for phase in [0.0, 0.1, 0.5, 0.9] {
+            assert_eq!(periodic_error_arcsec(phase, &[]), 0.0);
+        }
+    }
+
+    #[test]
+    fn phase_wraps_on_worm_period() {
+        assert_approx_eq!(worm_phase(120.0, 480.0), 0.25);
+        assert_approx_eq!(worm_phase(600.0, 480.0), 0.25);
+    }
+
+    #[test]
+    fn random_walk_is_deterministic() {
+        let mut a = RandomWalk::new(42, 0.1);
+        let mut b = RandomWalk::new(42, 0.1);
+        for _ in 0..100 {
+            assert_eq!(a.step(), b.step());
+        }
+    }
+
+    #[test]
+    fn model_adds_the_walk_to_the_waveform() {
+        let mut model = PeModel::default();
+        assert!(!model.is_enabled());
+        assert_eq!(model.error_arcsec(123.0), 0.0);
+
+        let pe = || {
+            vec![PeComponent {
+                harmonic: 1,
+                amplitude_arcsec: 10.0,
+                phase: 0.0,
+            }]
+        };
+        let mut model = PeModel::new(pe(), 480.0);
+        assert!(model.is_enabled());
+        assert_approx_eq!(model.error_arcsec(120.0), 10.0);
+
+        let mut walk = RandomWalk::new(7, 0.5);
+        let mut model = PeModel::new(pe(), 480.0).with_random_walk(7, 0.5);
+        for _ in 0..10 {
+            assert_approx_eq!(model.error_arcsec(360.0), -10.0 + walk.step());
+        }
+    }
+}
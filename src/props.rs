@@ -0,0 +1,414 @@
+use crate::{enum_kind_values, is_truthy};
+use lightspeed_astro::devices::actions::DeviceActions;
+use lightspeed_astro::props::{Permission, Property};
+use log::error;
+use serde::de::{Deserialize, Deserializer, Error, MapAccess, Visitor};
+use serde_json::Value;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+/// Name set_property takes several properties under, the value being a
+/// JSON object of property names to values, e.g.
+/// {"TARGET_RA": "83.82", "TARGET_DEC": "-5.39", "DO_GOTO": "true"}
+pub const BATCH_PROPERTY: &str = "__batch__";
+
+/// A device property whose value is shared with the code keeping it
+/// up to date, turned into a lightspeed Property when requested
+#[derive(Clone)]
+pub struct CustomProp {
+    pub name: String,
+    pub value: Arc<RwLock<String>>,
+    pub kind: String,
+    pub permission: Permission,
+}
+
+impl CustomProp {
+    pub fn to_ls_prop(&self) -> Property {
+        Property {
+            name: self.name.to_string(),
+            value: self.value.read().unwrap().to_string(),
+            kind: self.kind.to_string(),
+            permission: self.permission as i32,
+        }
+    }
+}
+
+/// Properties as sent to lightspeed clients: the shared ones with their
+/// current value followed by the static ones
+pub fn ls_props(props: &[CustomProp], static_props: &[Property]) -> Vec<Property> {
+    props
+        .iter()
+        .map(CustomProp::to_ls_prop)
+        .chain(static_props.iter().cloned())
+        .collect()
+}
+
+/// Checks that a client can write the property called `name`
+pub fn check_writable(props: &[CustomProp], name: &str) -> Result<(), DeviceActions> {
+    match props.iter().find(|p| p.name == name) {
+        Some(p) if p.permission == Permission::ReadOnly => {
+            Err(DeviceActions::CannotUpdateReadOnlyProperty)
+        }
+        Some(_) => Ok(()),
+        None => Err(DeviceActions::UnknownProperty),
+    }
+}
+
+/// Checks that `value` fits the kind of the property called `name`: a
+/// number for "float" and "integer", a yes/no word for "boolean" and
+/// one of the listed values for enums. Strings take anything.
+pub fn check_value(props: &[CustomProp], name: &str, value: &str) -> Result<(), DeviceActions> {
+    let prop = props
+        .iter()
+        .find(|p| p.name == name)
+        .ok_or(DeviceActions::UnknownProperty)?;
+    let value = value.trim();
+    let valid = match prop.kind.as_str() {
+        "float" => value.parse::<f64>().is_ok_and(f64::is_finite),
+        "integer" => value.parse::<i64>().is_ok(),
+        "boolean" => {
+            is_truthy(value)
+                || matches!(
+                    value.to_ascii_lowercase().as_str(),
+                    "0" | "false" | "off" | "no"
+                )
+        }
+        kind => match enum_kind_values(kind) {
+            Some(values) => values.iter().any(|v| v.eq_ignore_ascii_case(value)),
+            None => true,
+        },
+    };
+    if valid {
+        Ok(())
+    } else {
+        error!("Invalid value {:?} for {}", value, name);
+        Err(DeviceActions::InvalidValue)
+    }
+}
+
+/// Property names and values in the order they were written, serde_json
+/// objects would sort them
+struct Batch(Vec<(String, String)>);
+
+impl<'de> Deserialize<'de> for Batch {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct BatchVisitor;
+
+        impl<'de> Visitor<'de> for BatchVisitor {
+            type Value = Batch;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an object of property names to values")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Batch, A::Error> {
+                let mut pairs = Vec::new();
+                while let Some((name, value)) = map.next_entry::<String, Value>()? {
+                    let value = match value {
+                        Value::String(s) => s,
+                        Value::Bool(_) | Value::Number(_) => value.to_string(),
+                        _ => return Err(A::Error::custom(format!("invalid value for {}", name))),
+                    };
+                    pairs.push((name, value));
+                }
+                Ok(Batch(pairs))
+            }
+        }
+
+        deserializer.deserialize_map(BatchVisitor)
+    }
+}
+
+/// Parses the value of a BATCH_PROPERTY update. Numbers and booleans are
+/// taken as they are written, an empty batch is an invalid value like
+/// an empty one.
+pub fn parse_batch(value: &str) -> Result<Vec<(String, String)>, DeviceActions> {
+    match serde_json::from_str::<Batch>(value) {
+        Ok(Batch(pairs)) if !pairs.is_empty() => Ok(pairs),
+        Ok(_) => Err(DeviceActions::InvalidValue),
+        Err(e) => {
+            error!("Invalid batch of properties: {}", e);
+            Err(DeviceActions::InvalidValue)
+        }
+    }
+}
+
+/// Applies the pairs in order with `update`, only once all of them
+/// passed: every property must be writable and every value must fit
+/// the kind of its property and `check`, the device's own parsing of
+/// it. Nothing of an invalid batch reaches the mount. An update that
+/// fails on the mount side ends the batch, what was sent before it
+/// stays done, so a batch should end with the properties that act on
+/// it, like DO_GOTO.
+pub fn update_all<C, F>(
+    props: &[CustomProp],
+    pairs: &[(&str, &str)],
+    mut check: C,
+    mut update: F,
+) -> Result<(), DeviceActions>
+where
+    C: FnMut(&str, &str) -> Result<(), DeviceActions>,
+    F: FnMut(&str, &str) -> Result<(), DeviceActions>,
+{
+    for (name, _) in pairs {
+        check_writable(props, name)?;
+    }
+    for (name, value) in pairs {
+        check_value(props, name, value)?;
+        check(name, value)?;
+    }
+    for (name, value) in pairs {
+        if let Err(e) = update(name, value) {
+            error!("Setting {} failed, the rest of the batch is dropped", name);
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check_value, check_writable, ls_props, parse_batch, update_all, CustomProp};
+    use crate::enum_kind;
+    use lightspeed_astro::devices::actions::DeviceActions;
+    use lightspeed_astro::props::{Permission, Property};
+    use std::sync::{Arc, RwLock};
+
+    fn props() -> Vec<CustomProp> {
+        vec![
+            CustomProp {
+                name: String::from("RA_STATUS"),
+                value: Arc::new(RwLock::new(String::from("101"))),
+                kind: String::from("string"),
+                permission: Permission::ReadOnly,
+            },
+            CustomProp {
+                name: String::from("TRACKING"),
+                value: Arc::new(RwLock::new(String::from("false"))),
+                kind: String::from("boolean"),
+                permission: Permission::ReadWrite,
+            },
+        ]
+    }
+
+    #[test]
+    fn writable_property() {
+        assert_eq!(check_writable(&props(), "TRACKING"), Ok(()));
+    }
+
+    #[test]
+    fn read_only_property_is_rejected() {
+        assert_eq!(
+            check_writable(&props(), "RA_STATUS"),
+            Err(DeviceActions::CannotUpdateReadOnlyProperty)
+        );
+    }
+
+    #[test]
+    fn unknown_property_is_rejected() {
+        assert_eq!(
+            check_writable(&props(), "BANANA"),
+            Err(DeviceActions::UnknownProperty)
+        );
+    }
+
+    #[test]
+    fn shared_value_is_exported() {
+        let props = props();
+        *props[1].value.write().unwrap() = String::from("true");
+        let p = props[1].to_ls_prop();
+        assert_eq!(p.name, "TRACKING");
+        assert_eq!(p.value, "true");
+        assert_eq!(p.permission, Permission::ReadWrite as i32);
+    }
+
+    #[test]
+    fn static_properties_are_exported() {
+        let props = vec![
+            CustomProp {
+                name: String::from("TRACKING_MODE"),
+                value: Arc::new(RwLock::new(String::from("EQ"))),
+                kind: String::from("string"),
+                permission: Permission::ReadWrite,
+            },
+            CustomProp {
+                name: String::from("ALIGNED"),
+                value: Arc::new(RwLock::new(String::from("true"))),
+                kind: String::from("boolean"),
+                permission: Permission::ReadOnly,
+            },
+        ];
+        let static_props = vec![Property {
+            name: String::from("SYNSCAN_VERSION"),
+            value: String::from("04.39.05"),
+            kind: String::from("string"),
+            permission: Permission::ReadOnly as i32,
+        }];
+
+        let exported = ls_props(&props, &static_props);
+        let names: Vec<&str> = exported.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["TRACKING_MODE", "ALIGNED", "SYNSCAN_VERSION"]);
+        assert_eq!(exported[2].value, "04.39.05");
+    }
+
+    #[test]
+    fn batches_keep_their_order() {
+        assert_eq!(
+            parse_batch(r#"{"TARGET_RA": "83.82", "TARGET_DEC": -5.39, "DO_GOTO": true}"#),
+            Ok(vec![
+                (String::from("TARGET_RA"), String::from("83.82")),
+                (String::from("TARGET_DEC"), String::from("-5.39")),
+                (String::from("DO_GOTO"), String::from("true")),
+            ])
+        );
+        for invalid in [
+            "{}",
+            "[]",
+            "TRACKING",
+            r#"{"TRACKING": null}"#,
+            r#"{"A": [1]}"#,
+        ] {
+            assert_eq!(parse_batch(invalid), Err(DeviceActions::InvalidValue));
+        }
+    }
+
+    #[test]
+    fn batch_applied_in_full() {
+        let props = props();
+        let mut applied = Vec::new();
+        let result = update_all(
+            &props,
+            &[("TRACKING", "true")],
+            |_, _| Ok(()),
+            |name, value| {
+                applied.push((name.to_owned(), value.to_owned()));
+                *props[1].value.write().unwrap() = value.to_owned();
+                Ok(())
+            },
+        );
+        assert_eq!(result, Ok(()));
+        assert_eq!(applied.len(), 1);
+        assert_eq!(props[1].value.read().unwrap().as_str(), "true");
+    }
+
+    #[test]
+    fn nothing_applied_when_a_property_cannot_be_written() {
+        let props = props();
+        let mut calls = 0;
+        let result = update_all(
+            &props,
+            &[("TRACKING", "true"), ("RA_STATUS", "0")],
+            |_, _| Ok(()),
+            |_, _| {
+                calls += 1;
+                Ok(())
+            },
+        );
+        assert_eq!(result, Err(DeviceActions::CannotUpdateReadOnlyProperty));
+        assert_eq!(calls, 0);
+    }
+
+    fn mount_props() -> Vec<CustomProp> {
+        [
+            ("TRACKING", enum_kind(&["Sidereal", "Lunar", "Off"])),
+            ("TARGET_DEC", String::from("float")),
+            ("BACKLASH_RA", String::from("integer")),
+            ("PARK", String::from("boolean")),
+            ("GOTO_COORDINATES", String::from("string")),
+        ]
+        .into_iter()
+        .map(|(name, kind)| CustomProp {
+            name: String::from(name),
+            value: Arc::new(RwLock::new(String::new())),
+            kind,
+            permission: Permission::ReadWrite,
+        })
+        .collect()
+    }
+
+    #[test]
+    fn values_fit_their_kind() {
+        let props = mount_props();
+        for (name, value) in [
+            ("TRACKING", "sidereal"),
+            ("TARGET_DEC", " -5.39"),
+            ("BACKLASH_RA", "+12"),
+            ("PARK", "Off"),
+            ("GOTO_COORDINATES", "anything"),
+        ] {
+            assert_eq!(check_value(&props, name, value), Ok(()), "{}", name);
+        }
+        for (name, value) in [
+            ("TRACKING", "Solar"),
+            ("TARGET_DEC", "x"),
+            ("TARGET_DEC", "NaN"),
+            ("BACKLASH_RA", "1.5"),
+            ("PARK", "maybe"),
+        ] {
+            assert_eq!(
+                check_value(&props, name, value),
+                Err(DeviceActions::InvalidValue),
+                "{} {}",
+                name,
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn nothing_is_sent_for_an_invalid_value() {
+        let props = mount_props();
+        let mut sent = Vec::new();
+        let result = update_all(
+            &props,
+            &[("TRACKING", "Sidereal"), ("TARGET_DEC", "x")],
+            |_, _| Ok(()),
+            |name, _| {
+                sent.push(name.to_owned());
+                Ok(())
+            },
+        );
+        assert_eq!(result, Err(DeviceActions::InvalidValue));
+        assert!(sent.is_empty());
+
+        // The device check turns down what the kind lets through
+        let result = update_all(
+            &props,
+            &[("TRACKING", "Sidereal"), ("GOTO_COORDINATES", "north")],
+            |name, _| match name {
+                "GOTO_COORDINATES" => Err(DeviceActions::InvalidValue),
+                _ => Ok(()),
+            },
+            |name, _| {
+                sent.push(name.to_owned());
+                Ok(())
+            },
+        );
+        assert_eq!(result, Err(DeviceActions::InvalidValue));
+        assert!(sent.is_empty());
+    }
+
+    #[test]
+    fn failed_update_ends_the_batch() {
+        let props = mount_props();
+        let mut sent = Vec::new();
+        let result = update_all(
+            &props,
+            &[
+                ("TRACKING", "Sidereal"),
+                ("PARK", "true"),
+                ("TRACKING", "Off"),
+            ],
+            |_, _| Ok(()),
+            |name, _| {
+                sent.push(name.to_owned());
+                match name {
+                    "PARK" => Err(DeviceActions::ComError),
+                    _ => Ok(()),
+                }
+            },
+        );
+        assert_eq!(result, Err(DeviceActions::ComError));
+        assert_eq!(sent, vec!["TRACKING", "PARK"]);
+    }
+}
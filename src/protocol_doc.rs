@@ -0,0 +1,300 @@
+use crate::eqmod::EqModOp;
+use crate::synscan::Command;
+use serde::Serialize;
+
+/// Where the generated documents are checked in, relative to the crate
+pub const PROTOCOL_DOC_DIR: &str = "docs";
+/// Machine readable contract, vendored by client authors
+pub const PROTOCOL_JSON: &str = "protocol.json";
+/// Same content as a markdown table per driver
+pub const PROTOCOL_MARKDOWN: &str = "protocol.md";
+
+/// A command as sent on the wire and the properties sending it
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CommandDoc {
+    pub name: String,
+    /// Character of the command in the frame
+    pub code: String,
+    pub request: &'static str,
+    pub response: &'static str,
+    /// Only idempotent commands are sent again after a lost answer
+    pub idempotent: bool,
+    /// Read deadline in milliseconds, when the command has its own
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    /// Properties whose write sends the command or whose value is read
+    /// with it
+    pub properties: &'static [&'static str],
+}
+
+/// Commands of a driver and how they are framed
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ProtocolDoc {
+    pub driver: &'static str,
+    pub framing: &'static str,
+    pub commands: Vec<CommandDoc>,
+}
+
+/// Request payload, answer and properties of a SynScan command. The
+/// match has no fallback, a new command doesn't build until it is
+/// described here.
+fn synscan_command(cmd: Command) -> (&'static str, &'static str, &'static [&'static str]) {
+    const GOTO: &[&str] = &[
+        "DO_GOTO",
+        "NEXT_TARGET",
+        "PREV_TARGET",
+        "GOTO_TARGET_INDEX",
+        "GO_HOME",
+    ];
+    match cmd {
+        Command::Echo => ("one byte", "the same byte, '#'", &["CONNECTED"]),
+        Command::GetRaDec => ("none", "RRRR,DDDD#", &["RA", "DEC"]),
+        Command::GetPreciseRaDec => ("none", "RRRRRR00,DDDDDD00#", &["RA", "DEC"]),
+        Command::GetAltAz => ("none", "AAAA,BBBB#", &["AZ", "ALT"]),
+        Command::GetPreciseAltAz => ("none", "AAAAAA00,BBBBBB00#", &["AZ", "ALT"]),
+        Command::GoToRaDec => ("RRRR,DDDD", "#", GOTO),
+        Command::GoToPreciseRaDec => ("RRRRRR00,DDDDDD00", "#", GOTO),
+        Command::GoToAltAz => ("AAAA,BBBB", "#", &["PARK"]),
+        Command::GoToPreciseAltAz => ("AAAAAA00,BBBBBB00", "#", &["PARK"]),
+        Command::GetTrackingMode => ("none", "mode byte, '#'", &["TRACKING_MODE"]),
+        Command::SetTrackingMode => ("mode byte", "#", &["TRACKING_MODE", "PARK", "UNPARK"]),
+        Command::GetVersion => ("none", "VVvvrr#, hex digits", &["SYNSCAN_VERSION"]),
+        Command::GetModel => ("none", "model byte, '#'", &["MOUNT_MODEL"]),
+        Command::GetAlignment => ("none", "0 or 1, '#'", &["ALIGNED"]),
+        Command::CancelGoto => ("none", "#", &["ABORT_MOTION", "EMERGENCY_STOP", "UNPARK"]),
+        Command::IsGotoInProgress => ("none", "'0' or '1', '#'", &["GOTO_IN_PROGRESS"]),
+        Command::SyncRaDec => ("RRRR,DDDD", "#", &["SYNC_COORDINATES"]),
+        Command::SyncPreciseRaDec => ("RRRRRR00,DDDDDD00", "#", &["SYNC_COORDINATES"]),
+        Command::GetLocation => ("none", "ABCDEFGH#", &["SITE_LATITUDE", "SITE_LONGITUDE"]),
+        Command::SetLocation => (
+            "ABCDEFGH, latitude d m s N/S then longitude d m s E/W",
+            "#",
+            &["SITE_LATITUDE", "SITE_LONGITUDE"],
+        ),
+        Command::GetTime => ("none", "QRSTUVWX#", &["MOUNT_TIME", "UTC_OFFSET", "DST"]),
+        Command::SetTime => (
+            "QRSTUVWX, hour min sec month day year offset dst",
+            "#",
+            &["SYNC_TIME_NOW"],
+        ),
+        Command::GetPierSide => ("none", "E# or W#", &["PIER_SIDE"]),
+    }
+}
+
+/// Payload, answer and properties of an EQMod operation, exhaustive
+/// like synscan_command
+fn eqmod_op(op: EqModOp) -> (&'static str, &'static str, &'static [&'static str]) {
+    const GOTO: &[&str] = &["GOTO_COORDINATES", "PARK"];
+    match op {
+        EqModOp::Init => ("none", "=", &["FORCE_REINIT"]),
+        EqModOp::GetAxisPosition => (
+            "none",
+            "=XXXXXX, 24 bits low byte first, offset by 0x800000",
+            &["RA_POSITION", "DEC_POSITION", "AUX_POSITION"],
+        ),
+        EqModOp::SetAxisPosition => (
+            "XXXXXX, 24 bits low byte first, offset by 0x800000",
+            "=",
+            &["RA_AXIS_POSITION", "DEC_AXIS_POSITION"],
+        ),
+        EqModOp::GetAxisStatus => (
+            "none",
+            "=ABC, mode, running and init flags",
+            &["RA_STATUS", "DEC_STATUS", "SLEWING"],
+        ),
+        EqModOp::InquireGridPerRevolution => (
+            "none",
+            "=XXXXXX, steps per revolution",
+            &["RA_GRID_PER_REV", "DEC_GRID_PER_REV"],
+        ),
+        EqModOp::MotorBoardVersion => ("none", "=XXXXXX", &["MOTOR_BOARD_VERSION"]),
+        EqModOp::InquireTimerInterruptFreq => ("none", "=XXXXXX", &["TIMER_FREQ"]),
+        EqModOp::InquireHighSpeedRatio => (
+            "none",
+            "=XX",
+            &["RA_HIGH_SPEED_RATIO", "DEC_HIGH_SPEED_RATIO"],
+        ),
+        EqModOp::InquireAuxEncoder => ("none", "=XXXXXX", &["ENCODER_RA", "ENCODER_DEC"]),
+        EqModOp::ExtendedInquiry => (
+            "XXXXXX, inquiry code",
+            "=XXXXXX",
+            &[
+                "HAS_PPEC",
+                "HAS_ENCODER",
+                "HAS_SNAP_PORT",
+                "IS_AZEQ",
+                "PPEC_STATUS",
+            ],
+        ),
+        EqModOp::ExtendedSetting => (
+            "XXXXXX, setting code",
+            "=",
+            &["PPEC", "PPEC_TRAINING", "ENCODERS_ENABLED"],
+        ),
+        EqModOp::SetMotionMode => (
+            "DB, mode and direction digits",
+            "=",
+            &[
+                "GOTO_COORDINATES",
+                "PARK",
+                "UNPARK",
+                "TRACKING",
+                "CUSTOM_TRACK_RATE_RA",
+                "CUSTOM_TRACK_RATE_DEC",
+                "AUX_MOVE",
+            ],
+        ),
+        EqModOp::SetStepPeriod => (
+            "XXXXXX, timer ticks per step",
+            "=",
+            &[
+                "UNPARK",
+                "TRACKING",
+                "CUSTOM_TRACK_RATE_RA",
+                "CUSTOM_TRACK_RATE_DEC",
+                "AUX_MOVE",
+                "PULSE_GUIDE_RA",
+                "PULSE_GUIDE_DEC",
+            ],
+        ),
+        EqModOp::StartMotion => (
+            "none",
+            "=",
+            &[
+                "GOTO_COORDINATES",
+                "PARK",
+                "UNPARK",
+                "TRACKING",
+                "CUSTOM_TRACK_RATE_RA",
+                "CUSTOM_TRACK_RATE_DEC",
+                "AUX_MOVE",
+            ],
+        ),
+        EqModOp::StopMotion => (
+            "none",
+            "=",
+            &[
+                "UNPARK",
+                "TRACKING",
+                "CUSTOM_TRACK_RATE_RA",
+                "CUSTOM_TRACK_RATE_DEC",
+                "AUX_MOVE",
+            ],
+        ),
+        EqModOp::InstantStop => ("none", "=", &["EMERGENCY_STOP"]),
+        EqModOp::SetSnapPort => ("'0' or '1'", "=", &["SNAP_PORT_1"]),
+        EqModOp::SetPolarLed => ("XX, brightness", "=", &["POLAR_LED"]),
+        EqModOp::SetGotoTarget => ("XXXXXX, 24 bits low byte first", "=", GOTO),
+        EqModOp::SetGotoTargetIncrement => ("XXXXXX, steps", "=", GOTO),
+        EqModOp::SetBreakPointIncrement => ("XXXXXX, steps", "=", GOTO),
+    }
+}
+
+/// The SynScan hand controller protocol
+pub fn synscan_doc() -> ProtocolDoc {
+    let commands = Command::ALL
+        .iter()
+        .map(|cmd| {
+            let (request, response, properties) = synscan_command(*cmd);
+            CommandDoc {
+                name: format!("{:?}", cmd),
+                code: char::from(cmd.as_byte()).to_string(),
+                request,
+                response,
+                idempotent: cmd.is_idempotent(),
+                timeout_ms: cmd.read_timeout().map(|t| t.as_millis() as u64),
+                properties,
+            }
+        })
+        .collect();
+    ProtocolDoc {
+        driver: "synscan",
+        framing: "<code><request>, answered with <response>",
+        commands,
+    }
+}
+
+/// The motor controller protocol spoken by the EQMod driver
+pub fn eqmod_doc() -> ProtocolDoc {
+    let commands = EqModOp::ALL
+        .iter()
+        .map(|op| {
+            let (request, response, properties) = eqmod_op(*op);
+            CommandDoc {
+                name: format!("{:?}", op),
+                code: char::from(*op as u8).to_string(),
+                request,
+                response,
+                idempotent: op.is_idempotent(),
+                timeout_ms: None,
+                properties,
+            }
+        })
+        .collect();
+    ProtocolDoc {
+        driver: "eqmod",
+        framing: ":<code><axis><request>\\r, answered with <response>\\r or !<error>\\r",
+        commands,
+    }
+}
+
+/// Content of docs/protocol.json
+pub fn protocol_json() -> String {
+    let docs = [synscan_doc(), eqmod_doc()];
+    // Plain strings and numbers, nothing that can fail to serialize
+    serde_json::to_string_pretty(&docs).unwrap() + "\n"
+}
+
+/// Content of docs/protocol.md
+pub fn protocol_markdown() -> String {
+    let mut md = String::from(
+        "# Serial protocols\n\n\
+         Generated from the command enums by `cargo run --bin protocol-doc`, do not edit.\n",
+    );
+    for doc in [synscan_doc(), eqmod_doc()] {
+        md.push_str(&format!(
+            "\n## {}\n\nFrames: `{}`\n\n\
+             | Command | Code | Request | Response | Idempotent | Properties |\n\
+             |---|---|---|---|---|---|\n",
+            doc.driver, doc.framing
+        ));
+        for cmd in &doc.commands {
+            md.push_str(&format!(
+                "| {} | `{}` | {} | {} | {} | {} |\n",
+                cmd.name,
+                cmd.code,
+                cmd.request,
+                cmd.response,
+                if cmd.idempotent { "yes" } else { "no" },
+                cmd.properties.join(", ")
+            ));
+        }
+    }
+    md
+}
+
+#[cfg(test)]
+mod test {
+    use super::{eqmod_doc, protocol_json, protocol_markdown, synscan_doc};
+    use crate::eqmod::EqModOp;
+    use crate::synscan::Command;
+
+    #[test]
+    fn every_command_is_described() {
+        assert_eq!(synscan_doc().commands.len(), Command::ALL.len());
+        assert_eq!(eqmod_doc().commands.len(), EqModOp::ALL.len());
+        let goto = &synscan_doc().commands[6];
+        assert_eq!(goto.name, "GoToPreciseRaDec");
+        assert_eq!(goto.code, "r");
+        assert_eq!(goto.timeout_ms, Some(5000));
+    }
+
+    #[test]
+    fn checked_in_docs_are_up_to_date() {
+        assert!(
+            include_str!("../docs/protocol.json") == protocol_json()
+                && include_str!("../docs/protocol.md") == protocol_markdown(),
+            "docs/ is out of date, run `cargo run --bin protocol-doc`"
+        );
+    }
+}
@@ -0,0 +1,801 @@
+use crate::eqmod::{command, Axis, EqModOp};
+use crate::network::{tcp_address, TcpTransport};
+use crate::synscan::{build_frame, Command};
+use lightspeed_astro::devices::actions::DeviceActions;
+use log::warn;
+#[cfg(windows)]
+use serialport::COMPort;
+#[cfg(unix)]
+use serialport::TTYPort;
+use serialport::{ClearBuffer, SerialPort};
+use std::io::{self, ErrorKind, Read, Write};
+use std::time::{Duration, Instant};
+
+/// Longest answer expected from a mount, anything longer is garbage
+pub const MAX_RESPONSE_LEN: usize = 64;
+
+/// Default number of attempts for commands that are safe to repeat
+pub const DEFAULT_RETRY_ATTEMPTS: u32 = 3;
+/// Pause before the second attempt, doubled at every further attempt
+pub const RETRY_BACKOFF: Duration = Duration::from_millis(50);
+
+/// How long each protocol probe waits for an answer
+pub const PROBE_TIMEOUT: Duration = Duration::from_millis(300);
+
+/// Rates tried in order when LS_BAUD isn't set: EQDIR cables and older
+/// hand controllers run at 9600, USB hand controllers at 115200
+pub const DEFAULT_BAUD_RATES: &[u32] = &[9600, 115200];
+
+/// Protocol spoken on the other end of a serial port
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Protocol {
+    /// Hand controller, '#' terminated answers
+    SynScan,
+    /// Motor controller connected directly, ':' prefixed commands
+    /// and carriage return terminated answers
+    EqMod,
+    Unknown,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ReadError {
+    Timeout,
+    TooLong,
+    InvalidUtf8,
+    Io(ErrorKind),
+}
+
+/// Reads the answer byte by byte up to and including `terminator`,
+/// giving up once `max_len` bytes came in without it or when
+/// `timeout` has elapsed since the call, whichever comes first
+pub fn read_response<R: Read>(
+    port: &mut R,
+    terminator: u8,
+    max_len: usize,
+    timeout: Duration,
+) -> Result<Vec<u8>, ReadError> {
+    let deadline = Instant::now() + timeout;
+    let mut response = Vec::new();
+
+    loop {
+        if response.len() >= max_len {
+            return Err(ReadError::TooLong);
+        }
+        if Instant::now() >= deadline {
+            return Err(ReadError::Timeout);
+        }
+
+        let mut byte = [0; 1];
+        match port.read(&mut byte) {
+            Ok(0) => continue,
+            Ok(_) => {
+                response.push(byte[0]);
+                if byte[0] == terminator {
+                    return Ok(response);
+                }
+            }
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(ref e) if e.kind() == ErrorKind::TimedOut => return Err(ReadError::Timeout),
+            Err(e) => return Err(ReadError::Io(e.kind())),
+        }
+    }
+}
+
+/// Converts a raw answer into text, bytes that are not valid UTF-8
+/// mean the line is corrupted (e.g. wrong baud rate)
+pub fn response_to_string(response: Vec<u8>) -> Result<String, ReadError> {
+    String::from_utf8(response).map_err(|_| ReadError::InvalidUtf8)
+}
+
+/// Finds out what is connected to the port: first the SynScan echo is
+/// tried, then the EQMod motor board version query
+pub fn detect_protocol<P: Read + Write>(port: &mut P, timeout: Duration) -> Protocol {
+    let echo = build_frame(Command::Echo, Some(b"x"));
+    if port.write_all(&echo).is_ok() {
+        if let Ok(r) = read_response(port, b'#', MAX_RESPONSE_LEN, timeout) {
+            if r == b"x#" {
+                return Protocol::SynScan;
+            }
+        }
+    }
+
+    let mut version = command(EqModOp::MotorBoardVersion, Axis::Ra);
+    version.push(b'\r');
+    if port.write_all(&version).is_err() {
+        return Protocol::Unknown;
+    }
+    // The echo may have left an error answer behind, skip it
+    let deadline = Instant::now() + timeout;
+    while let Ok(r) = read_response(
+        port,
+        b'\r',
+        MAX_RESPONSE_LEN,
+        deadline.saturating_duration_since(Instant::now()),
+    ) {
+        if r.starts_with(b"=") {
+            return Protocol::EqMod;
+        }
+    }
+    Protocol::Unknown
+}
+
+/// Opens the port at `baud` just long enough to probe the protocol
+pub fn probe_port(address: &str, baud: u32) -> Protocol {
+    match SerialLink::open(address, baud, PROBE_TIMEOUT) {
+        Ok(mut port) => detect_protocol(&mut port, PROBE_TIMEOUT),
+        Err(_) => Protocol::Unknown,
+    }
+}
+
+/// Rates to try, only the one from LS_BAUD when it is given and valid
+pub fn baud_rates(value: Option<&str>) -> Vec<u32> {
+    match value.map(|v| v.trim().parse::<u32>()) {
+        Some(Ok(baud)) if baud > 0 => vec![baud],
+        Some(_) => {
+            warn!("Ignoring invalid LS_BAUD {:?}", value.unwrap_or_default());
+            DEFAULT_BAUD_RATES.to_vec()
+        }
+        None => DEFAULT_BAUD_RATES.to_vec(),
+    }
+}
+
+/// Probes the rates in order, stopping at the first one something
+/// answers at
+pub fn detect_baud<F>(rates: &[u32], mut probe: F) -> Option<(u32, Protocol)>
+where
+    F: FnMut(u32) -> Protocol,
+{
+    rates.iter().find_map(|&baud| match probe(baud) {
+        Protocol::Unknown => None,
+        protocol => Some((baud, protocol)),
+    })
+}
+
+/// Writes every frame before reading any answer, then reads one answer
+/// per frame. Meant for stops, where getting all the frames out matters
+/// more than their answers: a frame that isn't answered doesn't keep the
+/// next ones off the line. The results are in the order of the frames,
+/// answers are matched to the frames written by order only so after a
+/// missing one they can't be told apart.
+pub fn send_all<P: Read + Write>(
+    port: &mut P,
+    frames: &[Vec<u8>],
+    terminator: u8,
+    timeout: Duration,
+) -> Vec<Result<Vec<u8>, ReadError>> {
+    let writes: Vec<_> = frames.iter().map(|frame| port.write_all(frame)).collect();
+    let _ = port.flush();
+
+    writes
+        .into_iter()
+        .map(|write| match write {
+            Ok(_) => read_response(port, terminator, MAX_RESPONSE_LEN, timeout),
+            Err(e) => Err(ReadError::Io(e.kind())),
+        })
+        .collect()
+}
+
+/// Why a frame exchange failed
+#[derive(Debug, PartialEq)]
+pub enum TransportError {
+    /// The frame didn't go out
+    Write(ErrorKind),
+    /// No valid answer came back
+    Read(ReadError),
+}
+
+impl TransportError {
+    /// A frame that couldn't be written on a port that timed out and an
+    /// answer that never came in are both reported as timeouts
+    pub fn device_action(&self) -> DeviceActions {
+        match self {
+            TransportError::Write(ErrorKind::TimedOut) | TransportError::Read(_) => {
+                DeviceActions::Timeout
+            }
+            TransportError::Write(_) => DeviceActions::ComError,
+        }
+    }
+}
+
+/// Sends one frame and reads its answer up to `terminator`. The calls
+/// block, the drivers make them on the thread owning the device so the
+/// async runtime never waits for a mount.
+pub trait SerialTransport {
+    fn exchange(&mut self, frame: &[u8], terminator: u8) -> Result<Vec<u8>, TransportError>;
+}
+
+/// Writes `frame` and reads the answer to it within `timeout`
+pub fn exchange_frame<P: Read + Write>(
+    port: &mut P,
+    frame: &[u8],
+    terminator: u8,
+    timeout: Duration,
+) -> Result<Vec<u8>, TransportError> {
+    port.write_all(frame)
+        .and_then(|_| port.flush())
+        .map_err(|e| TransportError::Write(e.kind()))?;
+    read_response(port, terminator, MAX_RESPONSE_LEN, timeout).map_err(TransportError::Read)
+}
+
+/// Serial ports wait for the answer as long as their own timeout
+impl<T: SerialPort> SerialTransport for T {
+    fn exchange(&mut self, frame: &[u8], terminator: u8) -> Result<Vec<u8>, TransportError> {
+        let timeout = self.timeout();
+        let result = exchange_frame(self, frame, terminator, timeout);
+        if let Err(TransportError::Read(_)) = result {
+            // Drop whatever is left so it doesn't end up in the next answer
+            let _ = self.clear(ClearBuffer::Input);
+        }
+        result
+    }
+}
+
+/// The serial line to a mount, on a local port or behind a TCP bridge
+pub enum SerialLink {
+    #[cfg(unix)]
+    Local(TTYPort),
+    #[cfg(windows)]
+    Local(COMPort),
+    Tcp(TcpTransport),
+}
+
+impl SerialLink {
+    /// Opens `address`, a port path or tcp://host:port. The bridge has
+    /// its own rate, `baud` only applies to local ports.
+    pub fn open(address: &str, baud: u32, timeout: Duration) -> io::Result<Self> {
+        match tcp_address(address) {
+            Some(host) => TcpTransport::connect(host, timeout).map(SerialLink::Tcp),
+            None => Ok(SerialLink::Local(
+                serialport::new(address, baud)
+                    .timeout(timeout)
+                    .open_native()?,
+            )),
+        }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        match self {
+            SerialLink::Local(p) => p.timeout(),
+            SerialLink::Tcp(t) => t.timeout(),
+        }
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+        match self {
+            SerialLink::Local(p) => p.set_timeout(timeout),
+            SerialLink::Tcp(t) => Ok(t.set_timeout(timeout)?),
+        }
+    }
+
+    /// Bridges only have an input buffer to drop
+    pub fn clear(&mut self, buffer: ClearBuffer) -> serialport::Result<()> {
+        match self {
+            SerialLink::Local(p) => p.clear(buffer),
+            SerialLink::Tcp(t) => Ok(t.clear()?),
+        }
+    }
+}
+
+impl Read for SerialLink {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            SerialLink::Local(p) => p.read(buf),
+            SerialLink::Tcp(t) => t.read(buf),
+        }
+    }
+}
+
+impl Write for SerialLink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            SerialLink::Local(p) => p.write(buf),
+            SerialLink::Tcp(t) => t.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            SerialLink::Local(p) => p.flush(),
+            SerialLink::Tcp(t) => t.flush(),
+        }
+    }
+}
+
+impl SerialTransport for SerialLink {
+    fn exchange(&mut self, frame: &[u8], terminator: u8) -> Result<Vec<u8>, TransportError> {
+        match self {
+            SerialLink::Local(p) => p.exchange(frame, terminator),
+            SerialLink::Tcp(t) => t.exchange(frame, terminator),
+        }
+    }
+}
+
+/// Runs `op` up to `attempts` times (at least once) while it fails with
+/// an error `retryable` accepts, sleeping `backoff` before the second
+/// attempt and twice as long before each further one.
+/// `op` receives the attempt number, starting from 1.
+pub fn retry<T, E, F, R>(attempts: u32, backoff: Duration, retryable: R, mut op: F) -> Result<T, E>
+where
+    F: FnMut(u32) -> Result<T, E>,
+    R: Fn(&E) -> bool,
+{
+    let mut delay = backoff;
+    let mut attempt = 1;
+
+    loop {
+        match op(attempt) {
+            Err(e) if attempt < attempts && retryable(&e) => {
+                std::thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::{
+        baud_rates, detect_baud, detect_protocol, exchange_frame, read_response,
+        response_to_string, retry, send_all, Protocol, ReadError, SerialTransport, TransportError,
+        MAX_RESPONSE_LEN,
+    };
+    use crate::actor::{Device, DeviceHandle};
+    use lightspeed_astro::devices::actions::DeviceActions;
+    use lightspeed_astro::props::Property;
+    use std::io::{ErrorKind, Read, Write};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    /// Answers with the same byte forever
+    struct Endless(u8);
+
+    impl Read for Endless {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            buf[0] = self.0;
+            Ok(1)
+        }
+    }
+
+    /// Never answers anything
+    struct Silent;
+
+    impl Read for Silent {
+        fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+            Ok(0)
+        }
+    }
+
+    /// Answers each known command with a scripted answer, stays silent
+    /// for anything else
+    pub(crate) struct ScriptedPort {
+        pub(crate) script: Vec<(&'static [u8], &'static [u8])>,
+        pub(crate) pending: Vec<u8>,
+    }
+
+    impl Read for ScriptedPort {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.pending.is_empty() {
+                return Ok(0);
+            }
+            buf[0] = self.pending.remove(0);
+            Ok(1)
+        }
+    }
+
+    impl Write for ScriptedPort {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if let Some((_, answer)) = self.script.iter().find(|(cmd, _)| *cmd == buf) {
+                self.pending.extend_from_slice(answer);
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Keeps everything written to the wrapped port
+    struct Recorder {
+        port: ScriptedPort,
+        written: Vec<u8>,
+    }
+
+    impl Read for Recorder {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.port.read(buf)
+        }
+    }
+
+    impl Write for Recorder {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.written.extend_from_slice(buf);
+            self.port.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn detects_hand_controller() {
+        let mut port = ScriptedPort {
+            script: vec![(b"Kx", b"x#")],
+            pending: Vec::new(),
+        };
+        assert_eq!(
+            detect_protocol(&mut port, Duration::from_millis(20)),
+            Protocol::SynScan
+        );
+    }
+
+    #[test]
+    fn detects_motor_controller() {
+        let mut port = ScriptedPort {
+            script: vec![(b"Kx", b"!0\r"), (b":e1\r", b"=020304\r")],
+            pending: Vec::new(),
+        };
+        assert_eq!(
+            detect_protocol(&mut port, Duration::from_millis(20)),
+            Protocol::EqMod
+        );
+
+        let mut port = ScriptedPort {
+            script: vec![],
+            pending: Vec::new(),
+        };
+        assert_eq!(
+            detect_protocol(&mut port, Duration::from_millis(20)),
+            Protocol::Unknown
+        );
+    }
+
+    #[test]
+    fn reads_up_to_terminator() {
+        let mut port: &[u8] = b"12CE,34AB#extra";
+        assert_eq!(
+            read_response(&mut port, b'#', MAX_RESPONSE_LEN, Duration::from_secs(1)),
+            Ok(b"12CE,34AB#".to_vec())
+        );
+        assert_eq!(port, b"extra");
+    }
+
+    #[test]
+    fn unterminated_garbage_is_bounded() {
+        assert_eq!(
+            read_response(
+                &mut Endless(b'x'),
+                b'#',
+                MAX_RESPONSE_LEN,
+                Duration::from_secs(5)
+            ),
+            Err(ReadError::TooLong)
+        );
+    }
+
+    #[test]
+    fn silent_port_returns_within_deadline() {
+        let start = Instant::now();
+        assert_eq!(
+            read_response(
+                &mut Silent,
+                b'#',
+                MAX_RESPONSE_LEN,
+                Duration::from_millis(50)
+            ),
+            Err(ReadError::Timeout)
+        );
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn retries_a_dropped_response() {
+        let mut calls = 0;
+        let result = retry(
+            3,
+            Duration::ZERO,
+            |e| *e == ReadError::Timeout,
+            |attempt| {
+                calls += 1;
+                match attempt {
+                    1 => Err(ReadError::Timeout),
+                    _ => Ok("042507#"),
+                }
+            },
+        );
+        assert_eq!(result, Ok("042507#"));
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn gives_up_after_the_last_attempt() {
+        let mut calls = 0;
+        let result: Result<(), _> = retry(
+            3,
+            Duration::ZERO,
+            |e| *e == ReadError::Timeout,
+            |_| {
+                calls += 1;
+                Err(ReadError::Timeout)
+            },
+        );
+        assert_eq!(result, Err(ReadError::Timeout));
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn other_errors_are_not_retried() {
+        let mut calls = 0;
+        let result: Result<(), _> = retry(
+            3,
+            Duration::ZERO,
+            |e| *e == ReadError::Timeout,
+            |_| {
+                calls += 1;
+                Err(ReadError::TooLong)
+            },
+        );
+        assert_eq!(result, Err(ReadError::TooLong));
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn corrupted_response_is_an_error() {
+        let mut port: &[u8] = &[0x31, 0xff, 0xfe, b'#'];
+        let response = read_response(&mut port, b'#', MAX_RESPONSE_LEN, Duration::from_secs(1));
+        assert_eq!(
+            response_to_string(response.unwrap()),
+            Err(ReadError::InvalidUtf8)
+        );
+        // Control bytes such as the tracking mode are fine
+        assert_eq!(
+            response_to_string(vec![0x01, b'#']),
+            Ok(String::from("\u{1}#"))
+        );
+    }
+
+    #[test]
+    fn stop_frames_go_out_before_any_answer() {
+        // The RA axis doesn't answer its stop
+        let mut port = Recorder {
+            port: ScriptedPort {
+                script: vec![(b":L2\r", b"=\r"), (b":L3\r", b"=\r")],
+                pending: Vec::new(),
+            },
+            written: Vec::new(),
+        };
+        let frames = [b":L1\r".to_vec(), b":L2\r".to_vec(), b":L3\r".to_vec()];
+        let answers = send_all(&mut port, &frames, b'\r', Duration::from_millis(20));
+
+        assert_eq!(port.written, b":L1\r:L2\r:L3\r".to_vec());
+        assert_eq!(answers.len(), 3);
+        assert_eq!(answers.iter().filter(|a| a.is_ok()).count(), 2);
+        assert_eq!(answers[2], Err(ReadError::Timeout));
+    }
+
+    /// Fails to write one given frame
+    struct RefusingPort {
+        port: Recorder,
+        refused: &'static [u8],
+    }
+
+    impl Read for RefusingPort {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.port.read(buf)
+        }
+    }
+
+    impl Write for RefusingPort {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if buf == self.refused {
+                return Err(ErrorKind::BrokenPipe.into());
+            }
+            self.port.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn failed_writes_keep_their_place() {
+        let mut port = RefusingPort {
+            port: Recorder {
+                port: ScriptedPort {
+                    script: vec![(b":L1\r", b"=\r"), (b":L3\r", b"!0\r")],
+                    pending: Vec::new(),
+                },
+                written: Vec::new(),
+            },
+            refused: b":L2\r",
+        };
+        let frames = [b":L1\r".to_vec(), b":L2\r".to_vec(), b":L3\r".to_vec()];
+        let answers = send_all(&mut port, &frames, b'\r', Duration::from_millis(20));
+
+        assert_eq!(port.port.written, b":L1\r:L3\r".to_vec());
+        assert_eq!(
+            answers,
+            vec![
+                Ok(b"=\r".to_vec()),
+                Err(ReadError::Io(ErrorKind::BrokenPipe)),
+                Ok(b"!0\r".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn silent_hand_controller_still_gets_every_stop() {
+        let mut port = Recorder {
+            port: ScriptedPort {
+                script: vec![],
+                pending: Vec::new(),
+            },
+            written: Vec::new(),
+        };
+        let frames = [
+            vec![0x4d],
+            vec![0x50, 0x02, 0x10, 0x24, 0x00, 0x00, 0x00, 0x00],
+        ];
+        let answers = send_all(&mut port, &frames, b'#', Duration::from_millis(20));
+
+        assert_eq!(port.written, frames.concat());
+        assert!(answers.iter().all(|a| *a == Err(ReadError::Timeout)));
+    }
+
+    /// Answers every frame, taking `delay` to do it
+    struct SlowPort {
+        delay: Duration,
+    }
+
+    impl SerialTransport for SlowPort {
+        fn exchange(&mut self, _frame: &[u8], _terminator: u8) -> Result<Vec<u8>, TransportError> {
+            thread::sleep(self.delay);
+            Ok(b"=\r".to_vec())
+        }
+    }
+
+    impl SerialTransport for ScriptedPort {
+        fn exchange(&mut self, frame: &[u8], terminator: u8) -> Result<Vec<u8>, TransportError> {
+            exchange_frame(self, frame, terminator, Duration::from_millis(20))
+        }
+    }
+
+    /// Polls the axis status over its transport
+    struct Mount<T> {
+        port: T,
+        polls: usize,
+    }
+
+    impl<T: SerialTransport + Send + 'static> Device for Mount<T> {
+        fn update_property(&mut self, _name: &str, _value: &str) -> Result<(), DeviceActions> {
+            Ok(())
+        }
+
+        fn update_properties(&mut self, _pairs: &[(&str, &str)]) -> Result<(), DeviceActions> {
+            Ok(())
+        }
+
+        fn fetch_props(&mut self) {
+            if self.port.exchange(b":f1\r", b'\r').is_ok() {
+                self.polls += 1;
+            }
+        }
+
+        fn ls_props(&self) -> Vec<Property> {
+            Vec::new()
+        }
+    }
+
+    #[test]
+    fn exchanges_a_frame() {
+        let mut port = ScriptedPort {
+            script: vec![(b":e1\r", b"=020402\r")],
+            pending: Vec::new(),
+        };
+        assert_eq!(port.exchange(b":e1\r", b'\r'), Ok(b"=020402\r".to_vec()));
+        let missing = port.exchange(b":X1\r", b'\r').unwrap_err();
+        assert_eq!(missing, TransportError::Read(ReadError::Timeout));
+        assert_eq!(missing.device_action(), DeviceActions::Timeout);
+        assert_eq!(
+            TransportError::Write(ErrorKind::BrokenPipe).device_action(),
+            DeviceActions::ComError
+        );
+    }
+
+    #[test]
+    fn slow_mount_doesnt_hold_up_another() {
+        let fast = DeviceHandle::spawn(
+            "fast",
+            Mount {
+                port: SlowPort {
+                    delay: Duration::from_millis(1),
+                },
+                polls: 0,
+            },
+        );
+        let slow = DeviceHandle::spawn(
+            "slow",
+            Mount {
+                port: SlowPort {
+                    delay: Duration::from_secs(2),
+                },
+                polls: 0,
+            },
+        );
+
+        // A single worker, nothing else can run while it is blocked
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let stuck = tokio::spawn(async move { slow.fetch_props().await });
+            tokio::task::yield_now().await;
+
+            let start = Instant::now();
+            for _ in 0..10 {
+                fast.fetch_props().await.unwrap();
+            }
+            assert!(start.elapsed() < Duration::from_millis(500));
+            assert_eq!(fast.call(|m| m.polls).await, Ok(10));
+            stuck.abort();
+        });
+    }
+
+    /// Hand controller answering only at `rate`
+    fn probe_at(rate: u32, tried: &mut Vec<u32>) -> impl FnMut(u32) -> Protocol + '_ {
+        move |baud| {
+            tried.push(baud);
+            let script: Vec<(&'static [u8], &'static [u8])> = if baud == rate {
+                vec![(b"Kx", b"x#")]
+            } else {
+                Vec::new()
+            };
+            let mut port = ScriptedPort {
+                script,
+                pending: Vec::new(),
+            };
+            detect_protocol(&mut port, Duration::from_millis(5))
+        }
+    }
+
+    #[test]
+    fn baud_falls_back_to_115200() {
+        let mut tried = Vec::new();
+        assert_eq!(
+            detect_baud(&baud_rates(None), probe_at(115200, &mut tried)),
+            Some((115200, Protocol::SynScan))
+        );
+        assert_eq!(tried, vec![9600, 115200]);
+    }
+
+    #[test]
+    fn baud_answering_first_is_kept() {
+        let mut tried = Vec::new();
+        assert_eq!(
+            detect_baud(&baud_rates(None), probe_at(9600, &mut tried)),
+            Some((9600, Protocol::SynScan))
+        );
+        assert_eq!(tried, vec![9600]);
+
+        let mut tried = Vec::new();
+        assert_eq!(
+            detect_baud(&baud_rates(None), probe_at(19200, &mut tried)),
+            None
+        );
+        assert_eq!(tried, vec![9600, 115200]);
+    }
+
+    #[test]
+    fn baud_from_env() {
+        assert_eq!(baud_rates(None), vec![9600, 115200]);
+        assert_eq!(baud_rates(Some("115200")), vec![115200]);
+        assert_eq!(baud_rates(Some(" 9600 ")), vec![9600]);
+        assert_eq!(baud_rates(Some("fast")), vec![9600, 115200]);
+        assert_eq!(baud_rates(Some("0")), vec![9600, 115200]);
+    }
+}
@@ -0,0 +1,189 @@
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, SocketAddr, TcpListener};
+
+/// Host the gRPC server binds to when neither --host nor LS_HOST is set
+pub const DEFAULT_HOST: &str = "127.0.0.1";
+
+#[derive(Debug, PartialEq)]
+pub enum AddressError {
+    MissingValue(String),
+    InvalidHost(String),
+    InvalidPort(String),
+    UnknownArgument(String),
+}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AddressError::MissingValue(flag) => write!(f, "{} needs a value", flag),
+            AddressError::InvalidHost(host) => write!(f, "invalid host {:?}", host),
+            AddressError::InvalidPort(port) => write!(f, "invalid port {:?}", port),
+            AddressError::UnknownArgument(arg) => write!(f, "unknown argument {:?}", arg),
+        }
+    }
+}
+
+impl std::error::Error for AddressError {}
+
+/// Command line flags, each given either as "--port 50052" or as
+/// "--port=50052". They win over the matching LS_* variables.
+#[derive(Debug, Default, PartialEq)]
+pub struct CliArgs {
+    /// --host, LS_HOST
+    pub host: Option<String>,
+    /// --port, LS_PORT
+    pub port: Option<String>,
+    /// --serial-port, LS_SERIAL_PORT
+    pub serial_port: Option<String>,
+}
+
+pub fn parse_args(args: &[String]) -> Result<CliArgs, AddressError> {
+    let mut parsed = CliArgs::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) => (flag, Some(value.to_owned())),
+            None => (arg.as_str(), None),
+        };
+        let slot = match flag {
+            "--host" => &mut parsed.host,
+            "--port" => &mut parsed.port,
+            "--serial-port" => &mut parsed.serial_port,
+            _ => return Err(AddressError::UnknownArgument(arg.clone())),
+        };
+        let value = inline
+            .or_else(|| args.next().cloned())
+            .ok_or_else(|| AddressError::MissingValue(flag.to_owned()))?;
+        *slot = Some(value);
+    }
+    Ok(parsed)
+}
+
+/// Picks the address the gRPC server binds to: --host/--port win over
+/// LS_HOST/LS_PORT, which win over `default`. The host must be an IP
+/// address.
+pub fn bind_address<E>(
+    args: &CliArgs,
+    env: E,
+    default: SocketAddr,
+) -> Result<SocketAddr, AddressError>
+where
+    E: Fn(&str) -> Option<String>,
+{
+    let mut addr = default;
+    if let Some(h) = args.host.clone().or_else(|| env("LS_HOST")) {
+        addr.set_ip(
+            h.trim()
+                .parse::<IpAddr>()
+                .map_err(|_| AddressError::InvalidHost(h.clone()))?,
+        );
+    }
+    if let Some(p) = args.port.clone().or_else(|| env("LS_PORT")) {
+        addr.set_port(
+            p.trim()
+                .parse::<u16>()
+                .map_err(|_| AddressError::InvalidPort(p.clone()))?,
+        );
+    }
+    Ok(addr)
+}
+
+/// Fails when something else already listens on `addr`, so that the
+/// driver doesn't grab the serial ports only to give up afterwards
+pub fn check_address_free(addr: SocketAddr) -> io::Result<()> {
+    TcpListener::bind(addr).map(|_| ())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{bind_address, check_address_free, parse_args, AddressError, CliArgs};
+    use std::net::{SocketAddr, TcpListener};
+
+    fn default() -> SocketAddr {
+        "127.0.0.1:50051".parse().unwrap()
+    }
+
+    fn args(a: &[&str]) -> CliArgs {
+        parse_args(&a.iter().map(|s| s.to_string()).collect::<Vec<_>>()).unwrap()
+    }
+
+    fn env(name: &str) -> Option<String> {
+        match name {
+            "LS_HOST" => Some(String::from("192.168.1.20")),
+            "LS_PORT" => Some(String::from("50060")),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn defaults_without_flags_or_env() {
+        assert_eq!(bind_address(&args(&[]), |_| None, default()), Ok(default()));
+    }
+
+    #[test]
+    fn env_over_default() {
+        assert_eq!(
+            bind_address(&args(&[]), env, default()),
+            Ok("192.168.1.20:50060".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn flags_over_env() {
+        assert_eq!(
+            bind_address(
+                &args(&["--host", "0.0.0.0", "--port=50070"]),
+                env,
+                default()
+            ),
+            Ok("0.0.0.0:50070".parse().unwrap())
+        );
+        // Only the port given, the host still comes from the env
+        assert_eq!(
+            bind_address(&args(&["--port", "50070"]), env, default()),
+            Ok("192.168.1.20:50070".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn serial_port_flag() {
+        let parsed = args(&["--serial-port", "/dev/ttyAMA0", "--port=50070"]);
+        assert_eq!(parsed.serial_port, Some(String::from("/dev/ttyAMA0")));
+        assert_eq!(parsed.port, Some(String::from("50070")));
+        assert_eq!(
+            args(&["--serial-port=/dev/ttyUSB0,/dev/ttyUSB1"]).serial_port,
+            Some(String::from("/dev/ttyUSB0,/dev/ttyUSB1"))
+        );
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        let raw = |a: &[&str]| parse_args(&a.iter().map(|s| s.to_string()).collect::<Vec<_>>());
+        assert_eq!(
+            raw(&["--port"]),
+            Err(AddressError::MissingValue(String::from("--port")))
+        );
+        assert_eq!(
+            bind_address(&args(&["--port", "70000"]), |_| None, default()),
+            Err(AddressError::InvalidPort(String::from("70000")))
+        );
+        assert_eq!(
+            bind_address(&args(&["--host=mount.local"]), |_| None, default()),
+            Err(AddressError::InvalidHost(String::from("mount.local")))
+        );
+        assert_eq!(
+            raw(&["--verbose"]),
+            Err(AddressError::UnknownArgument(String::from("--verbose")))
+        );
+    }
+
+    #[test]
+    fn busy_address_is_detected() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        assert!(check_address_free(addr).is_err());
+        drop(listener);
+        assert!(check_address_free(addr).is_ok());
+    }
+}
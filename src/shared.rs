@@ -0,0 +1,251 @@
+use crate::actor::{Device, DeviceHandle};
+use crate::events::PropertyEvents;
+use crate::props::{ls_props, CustomProp};
+use lightspeed_astro::props::Property;
+use std::sync::{Arc, RwLock};
+use uuid::Uuid;
+
+#[derive(Default)]
+struct ViewState {
+    name: String,
+    address: String,
+    /// USB serial number of the adapter, when it has one
+    adapter_serial: Option<String>,
+    props: Vec<CustomProp>,
+    static_props: Vec<Property>,
+}
+
+/// What clients see of a device, readable without locking the device
+/// itself so that property reads never wait for its serial traffic.
+/// The device publishes its property list whenever it changes, the
+/// values are shared and always current.
+#[derive(Clone, Default)]
+pub struct DeviceView {
+    state: Arc<RwLock<ViewState>>,
+}
+
+impl DeviceView {
+    pub fn publish(&self, name: &str, props: &[CustomProp], static_props: &[Property]) {
+        let mut state = self.state.write().unwrap();
+        state.name = name.to_owned();
+        state.props = props.to_vec();
+        state.static_props = static_props.to_vec();
+    }
+
+    pub fn name(&self) -> String {
+        self.state.read().unwrap().name.clone()
+    }
+
+    /// Records where the device is reached, for the drivers looking for
+    /// new ports without waiting for the device thread
+    pub fn set_address(&self, address: &str, adapter_serial: Option<&str>) {
+        let mut state = self.state.write().unwrap();
+        state.address = address.to_owned();
+        state.adapter_serial = adapter_serial
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::to_owned);
+    }
+
+    pub fn address(&self) -> String {
+        self.state.read().unwrap().address.clone()
+    }
+
+    /// Tells whether the device is disconnected, its CONNECTED property
+    /// false, and the adapter with `serial_number` is its own. The
+    /// adapter may come back on another path.
+    pub fn waits_for(&self, serial_number: Option<&str>) -> bool {
+        let state = self.state.read().unwrap();
+        let offline = state
+            .props
+            .iter()
+            .any(|p| p.name == "CONNECTED" && *p.value.read().unwrap() == "false");
+        offline
+            && state.adapter_serial.is_some()
+            && state.adapter_serial.as_deref() == serial_number
+    }
+
+    /// Properties as sent to lightspeed clients
+    pub fn ls_props(&self) -> Vec<Property> {
+        let state = self.state.read().unwrap();
+        ls_props(&state.props, &state.static_props)
+    }
+}
+
+/// Namespace of the device ids, the v5 UUID of the project URL
+pub const ID_NAMESPACE: Uuid = Uuid::from_u128(0x6ce927465e3c529bb0879e3f9fda608b);
+
+/// Id of the mount behind `port`, the same across restarts as long as
+/// the adapter keeps its USB serial number or, without one, the mount
+/// stays on the same path. Random when there is neither.
+pub fn device_id(serial_number: Option<&str>, port: &str) -> Uuid {
+    match serial_number.map(str::trim).filter(|s| !s.is_empty()) {
+        Some(serial) => Uuid::new_v5(&ID_NAMESPACE, format!("serial:{}", serial).as_bytes()),
+        None if !port.is_empty() => {
+            Uuid::new_v5(&ID_NAMESPACE, format!("port:{}", port).as_bytes())
+        }
+        None => Uuid::new_v4(),
+    }
+}
+
+/// A device as held by a driver: the queue to the thread owning it,
+/// next to what can be read without waiting for that thread
+pub struct SharedDevice<D> {
+    pub id: Uuid,
+    pub view: DeviceView,
+    pub device: DeviceHandle<D>,
+    /// Property changes, for clients subscribing to them
+    pub events: Arc<PropertyEvents>,
+}
+
+impl<D: Device> SharedDevice<D> {
+    pub fn new(id: Uuid, view: DeviceView, device: D) -> Self {
+        let events = PropertyEvents::new(id);
+        events.publish(&view.ls_props());
+        Self {
+            id,
+            device: DeviceHandle::spawn(&view.name(), device),
+            view,
+            events: Arc::new(events),
+        }
+    }
+}
+
+impl<D> SharedDevice<D> {
+    /// Sends the properties that changed since the last call to the
+    /// subscribers, after a poll or a property update
+    pub fn publish_changes(&self) {
+        self.events.publish(&self.view.ls_props());
+    }
+}
+
+impl<D> Clone for SharedDevice<D> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            view: self.view.clone(),
+            device: self.device.clone(),
+            events: Arc::clone(&self.events),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{device_id, DeviceView, SharedDevice, ID_NAMESPACE};
+    use crate::actor::Device;
+    use crate::props::CustomProp;
+    use lightspeed_astro::devices::actions::DeviceActions;
+    use lightspeed_astro::props::{Permission, Property};
+    use std::sync::mpsc;
+    use std::sync::{Arc, RwLock};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use uuid::Uuid;
+
+    struct Mount;
+
+    impl Device for Mount {
+        fn update_property(&mut self, _: &str, _: &str) -> Result<(), DeviceActions> {
+            Ok(())
+        }
+
+        fn update_properties(&mut self, _: &[(&str, &str)]) -> Result<(), DeviceActions> {
+            Ok(())
+        }
+
+        fn fetch_props(&mut self) {}
+
+        fn ls_props(&self) -> Vec<Property> {
+            Vec::new()
+        }
+    }
+
+    #[test]
+    fn props_readable_during_slow_command() {
+        let tracking = Arc::new(RwLock::new(String::from("Off")));
+        let view = DeviceView::default();
+        view.publish(
+            "EQ6-r",
+            &[CustomProp {
+                name: String::from("TRACKING"),
+                value: tracking.clone(),
+                kind: String::from("string"),
+                permission: Permission::ReadWrite,
+            }],
+            &[],
+        );
+        let shared = SharedDevice::new(Uuid::new_v4(), view, Mount);
+
+        // A command timing out keeps the device busy for a while
+        let (started, wait) = mpsc::channel();
+        let device = shared.device.clone();
+        let io = thread::spawn(move || {
+            device.call_blocking(move |_| {
+                started.send(()).unwrap();
+                thread::sleep(Duration::from_millis(500));
+            })
+        });
+        wait.recv().unwrap();
+
+        let start = Instant::now();
+        *tracking.write().unwrap() = String::from("Sidereal");
+        let props = shared.view.ls_props();
+        assert!(start.elapsed() < Duration::from_millis(100));
+        assert_eq!(shared.view.name(), "EQ6-r");
+        assert_eq!(props[0].value, "Sidereal");
+        io.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn stable_device_ids() {
+        assert_eq!(
+            ID_NAMESPACE,
+            Uuid::new_v5(
+                &Uuid::NAMESPACE_URL,
+                b"https://github.com/MattBlack85/skywatcher-rs"
+            )
+        );
+
+        // The adapter may come back on another path after a restart
+        let eq6 = device_id(Some("A10K5XYZ"), "/dev/ttyUSB0");
+        assert_eq!(eq6, device_id(Some("A10K5XYZ"), "/dev/ttyUSB1"));
+        assert_ne!(eq6, device_id(Some("B20L6ABC"), "/dev/ttyUSB0"));
+
+        let uart = device_id(None, "/dev/ttyAMA0");
+        assert_eq!(uart, device_id(Some(" "), "/dev/ttyAMA0"));
+        assert_ne!(uart, device_id(None, "/dev/ttyAMA1"));
+        // A serial number that looks like a path doesn't clash with it
+        assert_ne!(uart, device_id(Some("/dev/ttyAMA0"), "/dev/ttyUSB0"));
+
+        assert_ne!(device_id(None, ""), device_id(None, ""));
+    }
+
+    #[test]
+    fn disconnected_devices_wait_for_their_adapter() {
+        let connected = Arc::new(RwLock::new(String::from("true")));
+        let view = DeviceView::default();
+        view.publish(
+            "AZ-GTi",
+            &[CustomProp {
+                name: String::from("CONNECTED"),
+                value: connected.clone(),
+                kind: String::from("boolean"),
+                permission: Permission::ReadOnly,
+            }],
+            &[],
+        );
+        view.set_address("/dev/ttyUSB0", Some("A10K"));
+        assert_eq!(view.address(), "/dev/ttyUSB0");
+        assert!(!view.waits_for(Some("A10K")));
+
+        *connected.write().unwrap() = String::from("false");
+        assert!(view.waits_for(Some("A10K")));
+        assert!(!view.waits_for(Some("B20K")));
+        assert!(!view.waits_for(None));
+
+        // Without a serial number it can only come back on its path
+        view.set_address("/dev/ttyUSB0", Some(" "));
+        assert!(!view.waits_for(None));
+    }
+}
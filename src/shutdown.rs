@@ -0,0 +1,133 @@
+use crate::is_truthy;
+use lightspeed_astro::devices::actions::DeviceActions;
+use log::{info, warn};
+use std::time::Duration;
+
+/// Longest wait for a mount to be brought to a stop on exit, a park
+/// waits for the slew to be over
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(330);
+
+/// What to do with the mounts when the driver is asked to exit, set
+/// with LS_STOP_TRACKING_ON_EXIT and LS_PARK_ON_EXIT, both off by default
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ShutdownPolicy {
+    pub stop_tracking: bool,
+    pub park: bool,
+}
+
+impl ShutdownPolicy {
+    pub fn from_env() -> Self {
+        let enabled = |name| std::env::var(name).is_ok_and(|v| is_truthy(&v));
+        Self {
+            stop_tracking: enabled("LS_STOP_TRACKING_ON_EXIT"),
+            park: enabled("LS_PARK_ON_EXIT"),
+        }
+    }
+}
+
+/// Steps a driver can take on a mount before the process exits
+pub trait Shutdown {
+    fn stop_tracking(&mut self) -> Result<(), DeviceActions>;
+    /// Parks the mount, tracking stops too
+    fn park(&mut self) -> Result<(), DeviceActions>;
+    /// Flushes whatever is still pending on the serial port
+    fn close(&mut self);
+}
+
+/// Brings device `i` to the state asked by `policy` and closes its
+/// port, the port is closed even when the mount doesn't answer
+pub fn shutdown_device<D: Shutdown>(i: usize, device: &mut D, policy: ShutdownPolicy) {
+    let result = if policy.park {
+        info!("Parking device {} before exiting", i);
+        device.park()
+    } else if policy.stop_tracking {
+        info!("Stopping tracking on device {} before exiting", i);
+        device.stop_tracking()
+    } else {
+        Ok(())
+    };
+    if let Err(e) = result {
+        warn!("Device {} not brought to a stop on exit: {:?}", i, e);
+    }
+    device.close();
+}
+
+#[cfg(test)]
+mod test {
+    use super::{shutdown_device, Shutdown, ShutdownPolicy};
+    use lightspeed_astro::devices::actions::DeviceActions;
+
+    /// Records the steps it is taken through
+    #[derive(Default)]
+    struct Mount {
+        fail: bool,
+        steps: Vec<&'static str>,
+    }
+
+    impl Shutdown for Mount {
+        fn stop_tracking(&mut self) -> Result<(), DeviceActions> {
+            self.steps.push("stop_tracking");
+            if self.fail {
+                return Err(DeviceActions::Timeout);
+            }
+            Ok(())
+        }
+
+        fn park(&mut self) -> Result<(), DeviceActions> {
+            self.steps.push("park");
+            Ok(())
+        }
+
+        fn close(&mut self) {
+            self.steps.push("close");
+        }
+    }
+
+    /// Shuts two mounts down, returning the steps each was taken through
+    fn shutdown(fail_first: bool, policy: ShutdownPolicy) -> Vec<Vec<&'static str>> {
+        let mut mounts = vec![
+            Mount {
+                fail: fail_first,
+                ..Default::default()
+            },
+            Mount::default(),
+        ];
+        for (i, m) in mounts.iter_mut().enumerate() {
+            shutdown_device(i, m, policy);
+        }
+        mounts.into_iter().map(|m| m.steps).collect()
+    }
+
+    #[test]
+    fn ports_are_closed_by_default() {
+        assert_eq!(
+            shutdown(false, ShutdownPolicy::default()),
+            vec![vec!["close"], vec!["close"]]
+        );
+    }
+
+    #[test]
+    fn tracking_stopped_on_exit() {
+        let policy = ShutdownPolicy {
+            stop_tracking: true,
+            park: false,
+        };
+        // The first mount doesn't answer, the second is still handled
+        assert_eq!(
+            shutdown(true, policy),
+            vec![
+                vec!["stop_tracking", "close"],
+                vec!["stop_tracking", "close"]
+            ]
+        );
+    }
+
+    #[test]
+    fn parking_wins_over_stopping() {
+        let policy = ShutdownPolicy {
+            stop_tracking: true,
+            park: true,
+        };
+        assert_eq!(shutdown(false, policy)[0], vec!["park", "close"]);
+    }
+}
@@ -0,0 +1,692 @@
+use crate::periodic_error::PeModel;
+use crate::serial::{exchange_frame, SerialTransport, TransportError};
+use crate::slew_limits::{alt_az_to_ra_dec, local_sidereal_time_deg, ra_dec_to_alt_az};
+use crate::synscan::{decode_location, encode_location, Command, Feature, MountTime};
+use crate::{
+    format_goto_payload, format_precise_goto_payload, is_truthy, parse_precise_ra_dec_response,
+    parse_ra_dec_response, signed_degrees, TrackingMode, SIDEREAL_RATE,
+};
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Read, Write};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Address of the simulated mount, in place of a port path
+pub const SIMULATOR_ADDRESS: &str = "simulator";
+/// Time a simulated goto takes, whatever the distance
+pub const SIMULATED_SLEW_TIME: Duration = Duration::from_secs(3);
+/// Version answered by the simulated hand controller
+const SIMULATED_VERSION: (u8, u8, u8) = (4, 37, 7);
+/// Model code answered by the simulated hand controller, an EQ6
+const SIMULATED_MODEL: u8 = 0;
+/// Firmware version of the simulated motors, 3.39
+const SIMULATED_MOTOR_VERSION: [u8; 2] = [3, 39];
+
+/// Whether the SynScan driver registers the simulated mount, from
+/// LS_SIMULATOR
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SimulatorMode {
+    Off,
+    /// The simulator instead of the mounts on the serial ports
+    On,
+    /// The simulator when no mount is found
+    Auto,
+}
+
+impl SimulatorMode {
+    pub fn from_env(value: Option<&str>) -> Self {
+        match value.map(str::trim) {
+            Some(v) if v.eq_ignore_ascii_case("auto") => SimulatorMode::Auto,
+            Some(v) if is_truthy(v) => SimulatorMode::On,
+            _ => SimulatorMode::Off,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Goto {
+    from: (f64, f64),
+    to: (f64, f64),
+    start: Instant,
+}
+
+/// A SynScan hand controller in software, answering the same frames as
+/// the real one so that the driver runs unchanged on top of it.
+///
+/// While tracking the RA axis follows the sky at the sidereal rate and
+/// the pointing stays put, with tracking off the sky drifts and RA
+/// grows. Gotos take SIMULATED_SLEW_TIME, GPS and unknown frames are
+/// never answered, neither are the precise commands on firmwares
+/// older than the ones that have them. While tracking, the RA reported
+/// carries the periodic error of the model, if any.
+#[derive(Debug)]
+pub struct SimulatedMount {
+    /// RA/DEC in degrees at `since`
+    ra: f64,
+    dec: f64,
+    since: Instant,
+    tracking: TrackingMode,
+    goto: Option<Goto>,
+    slew_time: Duration,
+    latitude: f64,
+    longitude: f64,
+    gmt_offset: i8,
+    dst: bool,
+    guide_rates: [u8; 2],
+    version: (u8, u8, u8),
+    model: u8,
+    pe: PeModel,
+    /// When the worm started turning, for its phase
+    worm_start: Instant,
+    /// Bytes written that don't make a whole frame yet
+    input: Vec<u8>,
+    /// Answers waiting to be read
+    output: VecDeque<u8>,
+    timeout: Duration,
+}
+
+impl Default for SimulatedMount {
+    fn default() -> Self {
+        Self {
+            ra: 0.0,
+            dec: 90.0,
+            since: Instant::now(),
+            tracking: TrackingMode::Off,
+            goto: None,
+            slew_time: SIMULATED_SLEW_TIME,
+            latitude: 51.4769,
+            longitude: 0.0,
+            gmt_offset: 0,
+            dst: false,
+            guide_rates: [50, 50],
+            version: SIMULATED_VERSION,
+            model: SIMULATED_MODEL,
+            pe: PeModel::default(),
+            worm_start: Instant::now(),
+            input: Vec::new(),
+            output: VecDeque::new(),
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl SimulatedMount {
+    pub fn with_slew_time(mut self, slew_time: Duration) -> Self {
+        self.slew_time = slew_time;
+        self
+    }
+
+    /// Answers as the given hand controller firmware
+    pub fn with_version(mut self, version: (u8, u8, u8)) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Answers the model query with `model`, e.g. 128 for an AZ mount
+    pub fn with_model(mut self, model: u8) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Adds the periodic error of `pe` to the RA reported while tracking
+    pub fn with_periodic_error(mut self, pe: PeModel) -> Self {
+        self.pe = pe;
+        self
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Only kept to be handed back, answers are ready right away
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Drops the answers not read yet
+    pub fn clear(&mut self) {
+        self.output.clear();
+    }
+
+    /// RA/DEC in degrees, half way through a goto it is half way
+    /// between where it started and the target
+    pub fn position(&mut self) -> (f64, f64) {
+        let now = Instant::now();
+        self.settle(now);
+        match self.goto {
+            Some(goto) => {
+                let done = (now - goto.start).as_secs_f64() / self.slew_time.as_secs_f64();
+                let ra_diff = (goto.to.0 - goto.from.0 + 540.0).rem_euclid(360.0) - 180.0;
+                (
+                    (goto.from.0 + ra_diff * done).rem_euclid(360.0),
+                    goto.from.1 + (goto.to.1 - goto.from.1) * done,
+                )
+            }
+            None => (self.ra, self.dec),
+        }
+    }
+
+    /// RA/DEC as answered to the position queries, off by the periodic
+    /// error while tracking
+    fn reported_position(&mut self) -> (f64, f64) {
+        let (ra, dec) = self.position();
+        if !self.pe.is_enabled() || self.tracking == TrackingMode::Off || self.goto.is_some() {
+            return (ra, dec);
+        }
+        let error = self
+            .pe
+            .error_arcsec(self.worm_start.elapsed().as_secs_f64());
+        ((ra + error / 3600.0).rem_euclid(360.0), dec)
+    }
+
+    pub fn is_slewing(&mut self) -> bool {
+        self.settle(Instant::now());
+        self.goto.is_some()
+    }
+
+    /// Brings the pointing up to `now`, ending the goto if it is over
+    fn settle(&mut self, now: Instant) {
+        if let Some(goto) = self.goto {
+            let end = goto.start + self.slew_time;
+            if now < end {
+                return;
+            }
+            (self.ra, self.dec) = goto.to;
+            self.since = end;
+            self.goto = None;
+        }
+        if self.tracking == TrackingMode::Off {
+            let drift = SIDEREAL_RATE.to_degrees() * (now - self.since).as_secs_f64();
+            self.ra = (self.ra + drift).rem_euclid(360.0);
+        }
+        self.since = now;
+    }
+
+    fn start_goto(&mut self, ra: f64, dec: f64) {
+        let from = self.position();
+        self.goto = Some(Goto {
+            from,
+            to: (ra.rem_euclid(360.0), signed_degrees(dec)),
+            start: Instant::now(),
+        });
+    }
+
+    fn sync(&mut self, ra: f64, dec: f64) {
+        self.settle(Instant::now());
+        self.goto = None;
+        self.ra = ra.rem_euclid(360.0);
+        self.dec = signed_degrees(dec);
+    }
+
+    fn alt_az(&mut self) -> (f64, f64) {
+        let (ra, dec) = self.position();
+        let lst = local_sidereal_time_deg(unix_now(), self.longitude);
+        ra_dec_to_alt_az(ra, dec, self.latitude, lst)
+    }
+
+    fn start_alt_az_goto(&mut self, alt: f64, az: f64) {
+        let lst = local_sidereal_time_deg(unix_now(), self.longitude);
+        let (ra, dec) = alt_az_to_ra_dec(alt, az, self.latitude, lst);
+        self.start_goto(ra, dec);
+    }
+
+    /// Length of the frame starting with `command`, None for bytes that
+    /// don't start any known frame
+    fn frame_len(command: u8) -> Option<usize> {
+        if command == b'P' {
+            return Some(8);
+        }
+        let len = match Command::try_from(command).ok()? {
+            Command::Echo | Command::SetTrackingMode => 2,
+            Command::GoToPreciseRaDec | Command::GoToPreciseAltAz | Command::SyncPreciseRaDec => 18,
+            Command::GoToRaDec | Command::GoToAltAz | Command::SyncRaDec => 10,
+            Command::SetLocation | Command::SetTime => 9,
+            _ => 1,
+        };
+        Some(len)
+    }
+
+    /// Answer to a whole frame, without the trailing '#', None when the
+    /// hand controller stays silent
+    fn answer(&mut self, frame: &[u8]) -> Option<Vec<u8>> {
+        if frame[0] == b'P' {
+            return self.passthrough(frame);
+        }
+        let payload = std::str::from_utf8(&frame[1..]).unwrap_or_default();
+        let command = Command::try_from(frame[0]).ok()?;
+        let precise = matches!(
+            command,
+            Command::GetPreciseRaDec
+                | Command::GetPreciseAltAz
+                | Command::GoToPreciseRaDec
+                | Command::GoToPreciseAltAz
+                | Command::SyncPreciseRaDec
+        );
+        if precise && !Feature::PreciseCoordinates.supported_by(self.version) {
+            return None;
+        }
+        let answer = match command {
+            Command::Echo => frame[1..].to_vec(),
+            Command::GetPreciseRaDec => {
+                let (ra, dec) = self.reported_position();
+                format_precise_goto_payload(ra, dec).into_bytes()
+            }
+            Command::GetRaDec => {
+                let (ra, dec) = self.reported_position();
+                format_goto_payload(ra as f32, dec as f32).into_bytes()
+            }
+            Command::GetPreciseAltAz => {
+                let (alt, az) = self.alt_az();
+                format_precise_goto_payload(az, alt).into_bytes()
+            }
+            Command::GetAltAz => {
+                let (alt, az) = self.alt_az();
+                format_goto_payload(az as f32, alt as f32).into_bytes()
+            }
+            Command::GoToPreciseRaDec => {
+                let (ra, dec) = parse_precise_ra_dec_response(payload).ok()?;
+                self.start_goto(ra, dec);
+                Vec::new()
+            }
+            Command::GoToRaDec => {
+                let (ra, dec) = parse_ra_dec_response(payload).ok()?;
+                self.start_goto(ra as f64, dec as f64);
+                Vec::new()
+            }
+            Command::SyncPreciseRaDec => {
+                let (ra, dec) = parse_precise_ra_dec_response(payload).ok()?;
+                self.sync(ra, dec);
+                Vec::new()
+            }
+            Command::SyncRaDec => {
+                let (ra, dec) = parse_ra_dec_response(payload).ok()?;
+                self.sync(ra as f64, dec as f64);
+                Vec::new()
+            }
+            // Aimed at where the Alt/Az is when the goto starts
+            Command::GoToPreciseAltAz => {
+                let (az, alt) = parse_precise_ra_dec_response(payload).ok()?;
+                self.start_alt_az_goto(signed_degrees(alt), az);
+                Vec::new()
+            }
+            Command::GoToAltAz => {
+                let (az, alt) = parse_ra_dec_response(payload).ok()?;
+                self.start_alt_az_goto(signed_degrees(alt as f64), az as f64);
+                Vec::new()
+            }
+            Command::GetTrackingMode => vec![self.tracking.to_command_byte()],
+            Command::SetTrackingMode => {
+                let mode = TrackingMode::try_from(frame[1]).ok()?;
+                self.settle(Instant::now());
+                self.tracking = mode;
+                Vec::new()
+            }
+            Command::GetVersion => {
+                let (major, minor, patch) = self.version;
+                format!("{:02X}{:02X}{:02X}", major, minor, patch).into_bytes()
+            }
+            Command::GetModel => vec![self.model],
+            Command::GetAlignment => vec![1],
+            Command::IsGotoInProgress => vec![if self.is_slewing() { b'1' } else { b'0' }],
+            Command::CancelGoto => {
+                let position = self.position();
+                self.sync(position.0, position.1);
+                Vec::new()
+            }
+            Command::GetPierSide => {
+                let lst = local_sidereal_time_deg(unix_now(), self.longitude);
+                let ha = (lst - self.position().0).rem_euclid(360.0);
+                vec![if ha < 180.0 { b'W' } else { b'E' }]
+            }
+            Command::GetLocation => encode_location(self.latitude, self.longitude)
+                .ok()?
+                .to_vec(),
+            Command::SetLocation => {
+                (self.latitude, self.longitude) = decode_location(&frame[1..]).ok()?;
+                Vec::new()
+            }
+            Command::GetTime => MountTime::from_unix(unix_now() as u64, self.gmt_offset, self.dst)
+                .encode()
+                .ok()?
+                .to_vec(),
+            Command::SetTime => {
+                let time = MountTime::decode(&frame[1..]).ok()?;
+                self.gmt_offset = time.gmt_offset;
+                self.dst = time.dst;
+                Vec::new()
+            }
+        };
+        Some(answer)
+    }
+
+    fn passthrough(&mut self, frame: &[u8]) -> Option<Vec<u8>> {
+        let (device, msg_id, response_len) = (frame[2], frame[3], frame[7] as usize);
+        let axis = match device {
+            16 => 0,
+            17 => 1,
+            // No GPS module, nor anything else, on the bus
+            _ => return None,
+        };
+        let answer = match msg_id {
+            254 => SIMULATED_MOTOR_VERSION.to_vec(),
+            71 => vec![self.guide_rates[axis]],
+            70 => {
+                self.guide_rates[axis] = frame[4];
+                Vec::new()
+            }
+            _ => vec![0; response_len],
+        };
+        Some(answer)
+    }
+}
+
+fn unix_now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or_default()
+}
+
+impl Write for SimulatedMount {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.input.extend_from_slice(buf);
+        while let Some(&command) = self.input.first() {
+            let len = match Self::frame_len(command) {
+                Some(len) => len,
+                None => {
+                    self.input.remove(0);
+                    continue;
+                }
+            };
+            if self.input.len() < len {
+                break;
+            }
+            let frame: Vec<u8> = self.input.drain(..len).collect();
+            if let Some(mut answer) = self.answer(&frame) {
+                answer.push(b'#');
+                self.output.extend(answer);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Read for SimulatedMount {
+    /// Times out right away when there is nothing to read, waiting would
+    /// not make an answer show up
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.output.is_empty() {
+            return Err(io::Error::new(ErrorKind::TimedOut, "no answer"));
+        }
+        let n = buf.len().min(self.output.len());
+        for (b, answer) in buf.iter_mut().zip(self.output.drain(..n)) {
+            *b = answer;
+        }
+        Ok(n)
+    }
+}
+
+impl SerialTransport for SimulatedMount {
+    fn exchange(&mut self, frame: &[u8], terminator: u8) -> Result<Vec<u8>, TransportError> {
+        let timeout = self.timeout;
+        let result = exchange_frame(self, frame, terminator, timeout);
+        if result.is_err() {
+            self.clear();
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SimulatedMount, SimulatorMode};
+    use crate::periodic_error::{PeComponent, PeModel};
+    use crate::serial::{ReadError, SerialTransport, TransportError};
+    use crate::synscan::{
+        build_frame, get_guide_rate_frame, motor_version_frame, parse_goto_in_progress,
+        passthrough_frame, set_guide_rate_frame, Axis, Command, GPS_DEVICE,
+    };
+    use crate::{
+        format_goto_payload, format_precise_goto_payload, parse_precise_ra_dec_response,
+        parse_tracking_mode_response, signed_degrees, TrackingMode,
+    };
+    use assert_approx_eq::assert_approx_eq;
+    use std::f64::consts::FRAC_PI_2;
+    use std::thread;
+    use std::time::Duration;
+
+    fn send(mount: &mut SimulatedMount, cmd: Command, payload: Option<&str>) -> String {
+        let answer = mount
+            .exchange(&build_frame(cmd, payload.map(str::as_bytes)), b'#')
+            .unwrap();
+        String::from_utf8(answer).unwrap()
+    }
+
+    fn ra_dec(mount: &mut SimulatedMount) -> (f64, f64) {
+        let (ra, dec) =
+            parse_precise_ra_dec_response(&send(mount, Command::GetPreciseRaDec, None)).unwrap();
+        (ra, signed_degrees(dec))
+    }
+
+    #[test]
+    fn simulator_mode() {
+        assert_eq!(SimulatorMode::from_env(None), SimulatorMode::Off);
+        assert_eq!(SimulatorMode::from_env(Some("0")), SimulatorMode::Off);
+        assert_eq!(SimulatorMode::from_env(Some("1")), SimulatorMode::On);
+        assert_eq!(SimulatorMode::from_env(Some("true")), SimulatorMode::On);
+        assert_eq!(SimulatorMode::from_env(Some(" AUTO ")), SimulatorMode::Auto);
+    }
+
+    #[test]
+    fn canned_answers() {
+        let mut mount = SimulatedMount::default();
+        assert_eq!(send(&mut mount, Command::Echo, Some("x")), "x#");
+        assert_eq!(send(&mut mount, Command::GetVersion, None), "042507#");
+        assert_eq!(
+            mount.exchange(&build_frame(Command::GetModel, None), b'#'),
+            Ok(vec![0, b'#'])
+        );
+        assert_eq!(
+            mount.exchange(&build_frame(Command::GetAlignment, None), b'#'),
+            Ok(vec![1, b'#'])
+        );
+        assert_eq!(
+            mount.exchange(&motor_version_frame(Axis::Ra), b'#'),
+            Ok(vec![3, 39, b'#'])
+        );
+
+        mount
+            .exchange(&set_guide_rate_frame(Axis::Dec, 75).unwrap(), b'#')
+            .unwrap();
+        assert_eq!(
+            mount.exchange(&get_guide_rate_frame(Axis::Dec), b'#'),
+            Ok(vec![75, b'#'])
+        );
+    }
+
+    #[test]
+    fn gps_and_garbage_are_not_answered() {
+        let mut mount = SimulatedMount::default();
+        assert_eq!(
+            mount.exchange(&passthrough_frame(GPS_DEVICE, 55, &[], 1), b'#'),
+            Err(TransportError::Read(ReadError::Timeout))
+        );
+        assert_eq!(
+            mount.exchange(b"~", b'#'),
+            Err(TransportError::Read(ReadError::Timeout))
+        );
+        // Nothing left behind for the next command
+        assert_eq!(send(&mut mount, Command::Echo, Some("y")), "y#");
+    }
+
+    #[test]
+    fn old_firmware_has_no_precise_commands() {
+        let mut mount = SimulatedMount::default().with_version((2, 1, 0));
+        assert_eq!(send(&mut mount, Command::GetVersion, None), "020100#");
+        assert_eq!(
+            mount.exchange(&build_frame(Command::GetPreciseRaDec, None), b'#'),
+            Err(TransportError::Read(ReadError::Timeout))
+        );
+        assert_eq!(
+            send(
+                &mut mount,
+                Command::SyncRaDec,
+                Some(&format_goto_payload(90.0, 45.0))
+            ),
+            "#"
+        );
+        assert_eq!(
+            send(&mut mount, Command::GetRaDec, None),
+            format!("{}#", format_goto_payload(90.0, 45.0))
+        );
+    }
+
+    /// Synced on 83.82/-5.39 and tracking
+    fn tracking_mount(pe: PeModel) -> SimulatedMount {
+        let mut mount = SimulatedMount::default().with_periodic_error(pe);
+        send(
+            &mut mount,
+            Command::SyncPreciseRaDec,
+            Some(&format_precise_goto_payload(83.82, -5.39)),
+        );
+        send(&mut mount, Command::SetTrackingMode, Some("\u{2}"));
+        mount
+    }
+
+    #[test]
+    fn periodic_error_moves_the_reported_ra() {
+        // A worm so slow it stays at the crest of the wave
+        let pe = vec![PeComponent {
+            harmonic: 1,
+            amplitude_arcsec: 10.0,
+            phase: FRAC_PI_2,
+        }];
+        let mut mount = tracking_mount(PeModel::new(pe, 1e9));
+        let (ra, dec) = ra_dec(&mut mount);
+        assert_approx_eq!(ra, 83.82 + 10.0 / 3600.0, 1e-4);
+        assert_approx_eq!(dec, -5.39, 1e-4);
+        // Only what is reported, the pointing is left alone
+        assert_approx_eq!(mount.position().0, 83.82, 1e-4);
+
+        let answers = |mount: &mut SimulatedMount| {
+            (0..20)
+                .map(|_| send(mount, Command::GetPreciseRaDec, None))
+                .collect::<Vec<_>>()
+        };
+        let walk = || PeModel::default().with_random_walk(42, 1.0);
+        assert_eq!(
+            answers(&mut tracking_mount(walk())),
+            answers(&mut tracking_mount(walk()))
+        );
+    }
+
+    #[test]
+    fn disabled_periodic_error_changes_nothing() {
+        for pe in [PeModel::default(), PeModel::new(Vec::new(), 480.0)] {
+            let mut mount = tracking_mount(pe);
+            assert_eq!(
+                send(&mut mount, Command::GetPreciseRaDec, None),
+                format!("{}#", format_precise_goto_payload(83.82, -5.39))
+            );
+            assert_eq!(
+                send(&mut mount, Command::GetRaDec, None),
+                format!("{}#", format_goto_payload(83.82, -5.39))
+            );
+        }
+    }
+
+    #[test]
+    fn tracking_keeps_the_pointing() {
+        let mut mount = SimulatedMount::default();
+        send(
+            &mut mount,
+            Command::SyncPreciseRaDec,
+            Some(&format_precise_goto_payload(83.82, -5.39)),
+        );
+        send(&mut mount, Command::SetTrackingMode, Some("\u{2}"));
+        assert_eq!(
+            parse_tracking_mode_response(&send(&mut mount, Command::GetTrackingMode, None)),
+            Ok(TrackingMode::Eq)
+        );
+        thread::sleep(Duration::from_millis(200));
+        let (ra, dec) = ra_dec(&mut mount);
+        assert_approx_eq!(ra, 83.82, 1e-4);
+        assert_approx_eq!(dec, -5.39, 1e-4);
+
+        // Without tracking the sky drifts by 15"/s
+        send(&mut mount, Command::SetTrackingMode, Some("\u{0}"));
+        thread::sleep(Duration::from_millis(1000));
+        let (ra, _) = ra_dec(&mut mount);
+        assert!(ra - 83.82 > 0.0035 && ra - 83.82 < 0.006, "{}", ra);
+    }
+
+    #[test]
+    fn gotos_take_a_while() {
+        let mut mount = SimulatedMount::default().with_slew_time(Duration::from_millis(300));
+        send(
+            &mut mount,
+            Command::SyncPreciseRaDec,
+            Some(&format_precise_goto_payload(350.0, 10.0)),
+        );
+        send(&mut mount, Command::SetTrackingMode, Some("\u{2}"));
+        send(
+            &mut mount,
+            Command::GoToPreciseRaDec,
+            Some(&format_precise_goto_payload(10.0, 30.0)),
+        );
+        let slewing = |mount: &mut SimulatedMount| {
+            parse_goto_in_progress(&send(mount, Command::IsGotoInProgress, None)).unwrap()
+        };
+        assert!(slewing(&mut mount));
+
+        thread::sleep(Duration::from_millis(150));
+        let (ra, dec) = ra_dec(&mut mount);
+        // Through RA 0, the short way
+        assert!(!(30.0..340.0).contains(&ra), "{}", ra);
+        assert!(dec > 10.0 && dec < 30.0, "{}", dec);
+
+        thread::sleep(Duration::from_millis(200));
+        assert!(!slewing(&mut mount));
+        let (ra, dec) = ra_dec(&mut mount);
+        assert_approx_eq!(ra, 10.0, 1e-4);
+        assert_approx_eq!(dec, 30.0, 1e-4);
+    }
+
+    #[test]
+    fn alt_az_gotos() {
+        let mut mount = SimulatedMount::default().with_slew_time(Duration::from_millis(100));
+        send(&mut mount, Command::SetTrackingMode, Some("\u{2}"));
+        send(
+            &mut mount,
+            Command::GoToPreciseAltAz,
+            Some(&format_precise_goto_payload(120.0, 30.0)),
+        );
+        assert!(mount.is_slewing());
+        thread::sleep(Duration::from_millis(150));
+        let (az, alt) =
+            parse_precise_ra_dec_response(&send(&mut mount, Command::GetPreciseAltAz, None))
+                .unwrap();
+        // The sky moved on a little while tracking
+        assert_approx_eq!(az, 120.0, 0.01);
+        assert_approx_eq!(signed_degrees(alt), 30.0, 0.01);
+    }
+
+    #[test]
+    fn cancelled_goto_stops_on_the_way() {
+        let mut mount = SimulatedMount::default().with_slew_time(Duration::from_secs(10));
+        send(&mut mount, Command::SetTrackingMode, Some("\u{2}"));
+        send(
+            &mut mount,
+            Command::GoToPreciseRaDec,
+            Some(&format_precise_goto_payload(180.0, 0.0)),
+        );
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(send(&mut mount, Command::CancelGoto, None), "#");
+        assert!(!mount.is_slewing());
+        let (_, dec) = ra_dec(&mut mount);
+        assert!(dec < 90.0 && dec > 80.0, "{}", dec);
+    }
+}
@@ -0,0 +1,195 @@
+/// Default lowest altitude a goto is allowed to reach
+pub const DEFAULT_MIN_ALTITUDE_DEG: f64 = -5.0;
+
+/// Default largest hour angle, in hours, a goto is allowed to reach,
+/// twelve hours leaves the whole sky open
+pub const DEFAULT_MAX_HOUR_ANGLE_HOURS: f64 = 12.0;
+
+/// Returns the local sidereal time in degrees for the given UTC unix
+/// time (seconds) and east longitude in degrees
+pub fn local_sidereal_time_deg(unix_secs: f64, lon_deg: f64) -> f64 {
+    let days_since_j2000 = unix_secs / 86400.0 + 2440587.5 - 2451545.0;
+    (280.46061837 + 360.98564736629 * days_since_j2000 + lon_deg).rem_euclid(360.0)
+}
+
+/// Converts RA/DEC in degrees into (altitude, azimuth) in degrees for
+/// an observer at `lat_deg` at the local sidereal time `lst_deg`,
+/// azimuth is measured from north through east
+pub fn ra_dec_to_alt_az(ra_deg: f64, dec_deg: f64, lat_deg: f64, lst_deg: f64) -> (f64, f64) {
+    let ha = (lst_deg - ra_deg).to_radians();
+    let (dec, lat) = (dec_deg.to_radians(), lat_deg.to_radians());
+
+    let sin_alt = dec.sin() * lat.sin() + dec.cos() * lat.cos() * ha.cos();
+    let alt = sin_alt.clamp(-1.0, 1.0).asin();
+    let az =
+        (-ha.sin() * dec.cos()).atan2(dec.sin() * lat.cos() - dec.cos() * lat.sin() * ha.cos());
+
+    (alt.to_degrees(), (az.to_degrees() + 360.0) % 360.0)
+}
+
+/// Converts (altitude, azimuth) in degrees, azimuth from north through
+/// east, into RA/DEC in degrees for an observer at `lat_deg` at the
+/// local sidereal time `lst_deg`
+pub fn alt_az_to_ra_dec(alt_deg: f64, az_deg: f64, lat_deg: f64, lst_deg: f64) -> (f64, f64) {
+    let (alt, az, lat) = (
+        alt_deg.to_radians(),
+        az_deg.to_radians(),
+        lat_deg.to_radians(),
+    );
+
+    let sin_dec = alt.sin() * lat.sin() + alt.cos() * lat.cos() * az.cos();
+    let dec = sin_dec.clamp(-1.0, 1.0).asin();
+    let ha =
+        (-az.sin() * alt.cos()).atan2(alt.sin() * lat.cos() - alt.cos() * lat.sin() * az.cos());
+
+    (
+        (lst_deg - ha.to_degrees()).rem_euclid(360.0),
+        dec.to_degrees(),
+    )
+}
+
+/// Hour angle in hours of `ra_deg` at the local sidereal time
+/// `lst_deg`, between -12 and 12 and positive west of the meridian
+pub fn hour_angle_hours(ra_deg: f64, lst_deg: f64) -> f64 {
+    ((lst_deg - ra_deg + 180.0).rem_euclid(360.0) - 180.0) / 15.0
+}
+
+/// Refuses gotos to targets below a minimum altitude or further than
+/// a maximum hour angle from the meridian
+#[derive(Clone, Debug, PartialEq)]
+pub struct SlewLimits {
+    pub enabled: bool,
+    pub min_altitude_deg: f64,
+    pub max_hour_angle_hours: f64,
+}
+
+impl Default for SlewLimits {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_altitude_deg: DEFAULT_MIN_ALTITUDE_DEG,
+            max_hour_angle_hours: DEFAULT_MAX_HOUR_ANGLE_HOURS,
+        }
+    }
+}
+
+impl SlewLimits {
+    /// Tells whether a goto to a target at the given altitude and hour
+    /// angle is allowed
+    pub fn allows_position(&self, alt_deg: f64, hour_angle_hours: f64) -> bool {
+        !self.enabled
+            || (alt_deg >= self.min_altitude_deg
+                && hour_angle_hours.abs() <= self.max_hour_angle_hours)
+    }
+
+    /// Tells whether a goto to the given RA/DEC is allowed from the
+    /// given site at the given UTC unix time
+    pub fn allows(
+        &self,
+        ra_deg: f64,
+        dec_deg: f64,
+        lat_deg: f64,
+        lon_deg: f64,
+        unix_secs: f64,
+    ) -> bool {
+        let lst = local_sidereal_time_deg(unix_secs, lon_deg);
+        let (alt, _) = ra_dec_to_alt_az(ra_deg, dec_deg, lat_deg, lst);
+        self.allows_position(alt, hour_angle_hours(ra_deg, lst))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        alt_az_to_ra_dec, hour_angle_hours, local_sidereal_time_deg, ra_dec_to_alt_az, SlewLimits,
+    };
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn sidereal_time() {
+        // J2000.0, GMST 280.46 degrees
+        assert_approx_eq!(
+            local_sidereal_time_deg(946728000.0, 0.0),
+            280.46061837,
+            1e-6
+        );
+        assert_approx_eq!(
+            local_sidereal_time_deg(946728000.0, 10.0),
+            290.46061837,
+            1e-6
+        );
+    }
+
+    #[test]
+    fn alt_az_conversion() {
+        // On the meridian the altitude is 90 - |lat - dec|
+        let (alt, az) = ra_dec_to_alt_az(100.0, 20.0, 45.0, 100.0);
+        assert_approx_eq!(alt, 65.0, 1e-9);
+        assert_approx_eq!(az, 180.0, 1e-9);
+
+        // The pole sits at the latitude altitude, due north
+        let (alt, az) = ra_dec_to_alt_az(0.0, 90.0, 45.0, 123.0);
+        assert_approx_eq!(alt, 45.0, 1e-9);
+        assert_approx_eq!(az, 0.0, 1e-9);
+
+        // Six hours east of the meridian on the equator is rising due east
+        let (alt, az) = ra_dec_to_alt_az(190.0, 0.0, 45.0, 100.0);
+        assert_approx_eq!(alt, 0.0, 1e-9);
+        assert_approx_eq!(az, 90.0, 1e-9);
+    }
+
+    #[test]
+    fn alt_az_back_to_ra_dec() {
+        for (ra, dec) in [(83.82, -5.39), (300.0, 60.0), (10.0, 10.0)] {
+            let (alt, az) = ra_dec_to_alt_az(ra, dec, 51.48, 123.0);
+            let (ra_back, dec_back) = alt_az_to_ra_dec(alt, az, 51.48, 123.0);
+            assert_approx_eq!(ra_back, ra, 1e-9);
+            assert_approx_eq!(dec_back, dec, 1e-9);
+        }
+    }
+
+    #[test]
+    fn target_below_horizon_is_refused() {
+        let limits = SlewLimits::default();
+        let lst = local_sidereal_time_deg(946728000.0, 0.0);
+        // Anti-meridian target at DEC -60 from 45N is well below the horizon
+        assert!(!limits.allows(lst + 180.0, -60.0, 45.0, 0.0, 946728000.0));
+    }
+
+    #[test]
+    fn target_just_above_limit_is_allowed() {
+        let limits = SlewLimits {
+            enabled: true,
+            min_altitude_deg: 10.0,
+            ..SlewLimits::default()
+        };
+        let lst = local_sidereal_time_deg(946728000.0, 0.0);
+        // On the meridian at 45N, DEC -34.9 culminates at 10.1 degrees
+        assert!(limits.allows(lst, -34.9, 45.0, 0.0, 946728000.0));
+        assert!(!limits.allows(lst, -35.1, 45.0, 0.0, 946728000.0));
+
+        let disabled = SlewLimits {
+            enabled: false,
+            ..limits
+        };
+        assert!(disabled.allows(lst, -35.1, 45.0, 0.0, 946728000.0));
+    }
+
+    #[test]
+    fn hour_angle_limit() {
+        assert_approx_eq!(hour_angle_hours(100.0, 130.0), 2.0, 1e-9);
+        assert_approx_eq!(hour_angle_hours(350.0, 5.0), 1.0, 1e-9);
+        assert_approx_eq!(hour_angle_hours(5.0, 350.0), -1.0, 1e-9);
+
+        let limits = SlewLimits {
+            max_hour_angle_hours: 3.0,
+            ..SlewLimits::default()
+        };
+        let lst = local_sidereal_time_deg(946728000.0, 0.0);
+        // Near the pole from 45N, high up on either side of the meridian
+        assert!(limits.allows(lst - 40.0, 80.0, 45.0, 0.0, 946728000.0));
+        assert!(!limits.allows(lst - 50.0, 80.0, 45.0, 0.0, 946728000.0));
+        assert!(!limits.allows(lst + 50.0, 80.0, 45.0, 0.0, 946728000.0));
+        assert!(SlewLimits::default().allows(lst - 50.0, 80.0, 45.0, 0.0, 946728000.0));
+    }
+}
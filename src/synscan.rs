@@ -0,0 +1,1442 @@
+use crate::slew_limits::local_sidereal_time_deg;
+use crate::ParseError;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// How often the mount state is polled while idle, unless
+/// LS_POLL_INTERVAL_MS says otherwise
+pub const IDLE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How often the mount state is polled while a goto is in progress
+pub const SLEWING_POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// How often the mount state is polled while parked
+pub const PARKED_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// The alignment status is refreshed once every this many polls
+pub const ALIGNMENT_REFRESH_CYCLES: u32 = 10;
+/// Longest time to wait for the mount to reach the park position
+pub const PARK_TIMEOUT: Duration = Duration::from_secs(300);
+/// Longest time a hand controller takes to report a goto it accepted
+pub const GOTO_START_GRACE: Duration = Duration::from_secs(3);
+/// The position is flagged stale when older than this many polls
+pub const DEFAULT_STALE_POLLS: u32 = 3;
+/// The last error is forgotten after this long without new failures
+pub const LAST_ERROR_QUIET_PERIOD: Duration = Duration::from_secs(60);
+
+/// Commands understood by the SynScan hand controller
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    Echo = 0x4b,
+    GetRaDec = 0x45,
+    GetPreciseRaDec = 0x65,
+    GetAltAz = 0x5a,
+    GetPreciseAltAz = 0x7a,
+    GoToRaDec = 0x52,
+    GoToPreciseRaDec = 0x72,
+    GoToAltAz = 0x42,
+    GoToPreciseAltAz = 0x62,
+    GetTrackingMode = 0x74,
+    SetTrackingMode = 0x54,
+    GetVersion = 0x56,
+    GetModel = 0x6d,
+    GetAlignment = 0x4a,
+    CancelGoto = 0x4d,
+    IsGotoInProgress = 0x4c,
+    SyncRaDec = 0x53,
+    SyncPreciseRaDec = 0x73,
+    GetLocation = 0x77,
+    SetLocation = 0x57,
+    GetTime = 0x68,
+    SetTime = 0x48,
+    GetPierSide = 0x70,
+}
+
+impl Command {
+    /// Every command, in the order of the enum
+    pub const ALL: [Command; 23] = [
+        Command::Echo,
+        Command::GetRaDec,
+        Command::GetPreciseRaDec,
+        Command::GetAltAz,
+        Command::GetPreciseAltAz,
+        Command::GoToRaDec,
+        Command::GoToPreciseRaDec,
+        Command::GoToAltAz,
+        Command::GoToPreciseAltAz,
+        Command::GetTrackingMode,
+        Command::SetTrackingMode,
+        Command::GetVersion,
+        Command::GetModel,
+        Command::GetAlignment,
+        Command::CancelGoto,
+        Command::IsGotoInProgress,
+        Command::SyncRaDec,
+        Command::SyncPreciseRaDec,
+        Command::GetLocation,
+        Command::SetLocation,
+        Command::GetTime,
+        Command::SetTime,
+        Command::GetPierSide,
+    ];
+
+    pub fn as_byte(&self) -> u8 {
+        *self as u8
+    }
+
+    /// Read deadline for the answer to this command, None keeps the
+    /// timeout the port was opened with
+    pub fn read_timeout(&self) -> Option<Duration> {
+        match self {
+            Command::Echo => Some(Duration::from_millis(200)),
+            Command::GetRaDec
+            | Command::GetPreciseRaDec
+            | Command::GetAltAz
+            | Command::GetPreciseAltAz
+            | Command::IsGotoInProgress
+            | Command::GetPierSide => Some(Duration::from_millis(500)),
+            Command::GoToRaDec
+            | Command::GoToPreciseRaDec
+            | Command::GoToAltAz
+            | Command::GoToPreciseAltAz
+            | Command::SyncRaDec
+            | Command::SyncPreciseRaDec
+            | Command::CancelGoto
+            | Command::GetTrackingMode
+            | Command::SetTrackingMode => Some(Duration::from_secs(5)),
+            _ => None,
+        }
+    }
+
+    /// Whether sending the command twice has the same effect as sending
+    /// it once, only those are retried after a lost answer
+    pub fn is_idempotent(&self) -> bool {
+        matches!(
+            self,
+            Command::Echo
+                | Command::GetRaDec
+                | Command::GetPreciseRaDec
+                | Command::GetAltAz
+                | Command::GetPreciseAltAz
+                | Command::GetTrackingMode
+                | Command::GetVersion
+                | Command::GetModel
+                | Command::GetAlignment
+                | Command::IsGotoInProgress
+                | Command::GetLocation
+                | Command::GetTime
+                | Command::GetPierSide
+        )
+    }
+}
+
+impl TryFrom<u8> for Command {
+    type Error = ParseError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        let command = match value {
+            0x4b => Command::Echo,
+            0x45 => Command::GetRaDec,
+            0x65 => Command::GetPreciseRaDec,
+            0x5a => Command::GetAltAz,
+            0x7a => Command::GetPreciseAltAz,
+            0x52 => Command::GoToRaDec,
+            0x72 => Command::GoToPreciseRaDec,
+            0x42 => Command::GoToAltAz,
+            0x62 => Command::GoToPreciseAltAz,
+            0x74 => Command::GetTrackingMode,
+            0x54 => Command::SetTrackingMode,
+            0x56 => Command::GetVersion,
+            0x6d => Command::GetModel,
+            0x4a => Command::GetAlignment,
+            0x4d => Command::CancelGoto,
+            0x4c => Command::IsGotoInProgress,
+            0x53 => Command::SyncRaDec,
+            0x73 => Command::SyncPreciseRaDec,
+            0x77 => Command::GetLocation,
+            0x57 => Command::SetLocation,
+            0x68 => Command::GetTime,
+            0x48 => Command::SetTime,
+            0x70 => Command::GetPierSide,
+            _ => return Err(ParseError::UnknownValue),
+        };
+        Ok(command)
+    }
+}
+
+/// Builds the bytes to write on the wire for a command, the payload
+/// (if any) is sent as is right after the command byte
+pub fn build_frame(cmd: Command, payload: Option<&[u8]>) -> Vec<u8> {
+    let mut frame = vec![cmd.as_byte()];
+
+    if let Some(p) = payload {
+        frame.extend_from_slice(p);
+    }
+    frame
+}
+
+/// Parses the answer to the GOTO in progress ('L') query, "1#" while
+/// the mount is slewing and "0#" otherwise
+pub fn parse_goto_in_progress(resp: &str) -> Result<bool, ParseError> {
+    match resp {
+        "1#" => Ok(true),
+        "0#" => Ok(false),
+        r if r.len() != 2 => Err(ParseError::InvalidLength),
+        _ => Err(ParseError::UnknownValue),
+    }
+}
+
+/// Where a goto followed by a GotoWatch is at
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GotoProgress {
+    Slewing,
+    Done,
+    /// Not over within the time it was given
+    TimedOut,
+}
+
+/// Follows a goto through the goto-in-progress polls. The hand
+/// controller can take a moment to report a goto it accepted, so the
+/// goto is only over once it was seen in progress. One never seen
+/// within GOTO_START_GRACE was too short to be, the mount was there.
+#[derive(Clone, Copy, Debug)]
+pub struct GotoWatch {
+    started: Instant,
+    timeout: Duration,
+    seen_slewing: bool,
+}
+
+impl GotoWatch {
+    pub fn new(now: Instant, timeout: Duration) -> Self {
+        Self {
+            started: now,
+            timeout,
+            seen_slewing: false,
+        }
+    }
+
+    /// Feeds a goto-in-progress poll
+    pub fn update(&mut self, slewing: bool, now: Instant) -> GotoProgress {
+        let elapsed = now.saturating_duration_since(self.started);
+        if slewing {
+            self.seen_slewing = true;
+        } else if self.seen_slewing || elapsed >= GOTO_START_GRACE {
+            return GotoProgress::Done;
+        }
+        if elapsed > self.timeout {
+            GotoProgress::TimedOut
+        } else {
+            GotoProgress::Slewing
+        }
+    }
+}
+
+/// Returns the error code of a "!<code>#" error answer, None for
+/// genuine answers. The code may be empty on some firmwares.
+pub fn response_error(resp: &[u8]) -> Option<String> {
+    let code = resp.strip_prefix(b"!")?;
+    let code = code.strip_suffix(b"#").unwrap_or(code);
+    Some(String::from_utf8_lossy(code).into_owned())
+}
+
+/// Motors reachable through the passthrough ('P') command, the value
+/// is the device id the hand controller forwards the message to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Axis {
+    Ra = 16,
+    Dec = 17,
+}
+
+/// Device id of the GPS accessory on the passthrough bus
+pub const GPS_DEVICE: u8 = 176;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    Positive,
+    Negative,
+}
+
+/// Fastest fixed slew rate accepted by the motors
+pub const MAX_SLEW_RATE: u8 = 9;
+
+/// Builds the 8 bytes passthrough frame forwarding `msg_id` and up to
+/// 3 bytes of `data` to a device, `response_len` is the number of bytes
+/// the device answers with (the trailing '#' excluded)
+pub fn passthrough_frame(device: u8, msg_id: u8, data: &[u8], response_len: u8) -> Vec<u8> {
+    let data = &data[..data.len().min(3)];
+    let mut frame = vec![
+        b'P',
+        1 + data.len() as u8,
+        device,
+        msg_id,
+        0,
+        0,
+        0,
+        response_len,
+    ];
+    frame[4..4 + data.len()].copy_from_slice(data);
+    frame
+}
+
+/// Builds the passthrough frame moving an axis at a fixed rate,
+/// rate 0 stops the axis
+pub fn slew_frame(axis: Axis, direction: Direction, rate: u8) -> Vec<u8> {
+    let msg_id = match direction {
+        Direction::Positive => 36,
+        Direction::Negative => 37,
+    };
+    passthrough_frame(axis as u8, msg_id, &[rate.min(MAX_SLEW_RATE)], 0)
+}
+
+/// Highest autoguide rate, in percent of the sidereal rate
+pub const MAX_GUIDE_RATE: u8 = 99;
+
+/// Builds the passthrough frame setting the autoguide rate of an axis
+pub fn set_guide_rate_frame(axis: Axis, percent: u8) -> Result<Vec<u8>, ParseError> {
+    if percent > MAX_GUIDE_RATE {
+        return Err(ParseError::UnknownValue);
+    }
+    Ok(passthrough_frame(axis as u8, 70, &[percent], 0))
+}
+
+/// Builds the passthrough frame reading the autoguide rate of an axis
+pub fn get_guide_rate_frame(axis: Axis) -> Vec<u8> {
+    passthrough_frame(axis as u8, 71, &[], 1)
+}
+
+/// Builds the passthrough frame reading the firmware version of a motor
+pub fn motor_version_frame(axis: Axis) -> Vec<u8> {
+    passthrough_frame(axis as u8, 254, &[], 2)
+}
+
+/// Formats the answer to the motor version query as "major.minor",
+/// None if the motor didn't answer with exactly two bytes
+pub fn parse_motor_version(resp: &[u8]) -> Option<String> {
+    match resp.strip_suffix(b"#").unwrap_or(resp) {
+        [major, minor] => Some(format!("{}.{}", major, minor)),
+        _ => None,
+    }
+}
+
+/// Converts the 3 bytes fraction of a rotation returned by the GPS
+/// module into degrees, angles past 180 are reported as negative
+pub fn rotation_fraction_to_degrees(bytes: &[u8]) -> Result<f64, ParseError> {
+    let bytes = bytes.strip_suffix(b"#").unwrap_or(bytes);
+    if bytes.len() != 3 {
+        return Err(ParseError::InvalidLength);
+    }
+
+    let fraction = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]) as f64 / 16_777_216.0;
+    let deg = fraction * 360.0;
+    Ok(if deg > 180.0 { deg - 360.0 } else { deg })
+}
+
+/// Parses a signed slew rate as written by clients, e.g. "+5", "-3"
+/// or "0" to stop
+pub fn parse_slew_value(value: &str) -> Result<(Direction, u8), ParseError> {
+    let value = value.trim();
+    let (direction, rate) = match value.strip_prefix('-') {
+        Some(r) => (Direction::Negative, r),
+        None => (
+            Direction::Positive,
+            value.strip_prefix('+').unwrap_or(value),
+        ),
+    };
+
+    match rate.parse::<u8>() {
+        Ok(r) if r <= MAX_SLEW_RATE => Ok((direction, r)),
+        _ => Err(ParseError::UnknownValue),
+    }
+}
+
+/// Splits an angle into whole degrees, minutes and seconds, seconds
+/// are rounded and carried over
+fn to_dms(deg: f64) -> (u8, u8, u8) {
+    let total = (deg.abs() * 3600.0).round() as u32;
+    (
+        (total / 3600) as u8,
+        (total / 60 % 60) as u8,
+        (total % 60) as u8,
+    )
+}
+
+/// Encodes a location as the ABCDEFGH payload of the SetLocation
+/// command: latitude degrees, minutes, seconds, 0 for north 1 for south,
+/// then longitude degrees, minutes, seconds, 0 for east 1 for west
+pub fn encode_location(lat_deg: f64, lon_deg: f64) -> Result<[u8; 8], ParseError> {
+    if !(-90.0..=90.0).contains(&lat_deg) || !(-180.0..=180.0).contains(&lon_deg) {
+        return Err(ParseError::UnknownValue);
+    }
+    let (lat_d, lat_m, lat_s) = to_dms(lat_deg);
+    let (lon_d, lon_m, lon_s) = to_dms(lon_deg);
+
+    Ok([
+        lat_d,
+        lat_m,
+        lat_s,
+        (lat_deg < 0.0) as u8,
+        lon_d,
+        lon_m,
+        lon_s,
+        (lon_deg < 0.0) as u8,
+    ])
+}
+
+/// Decodes the answer to the GetLocation command, with or without
+/// the trailing '#', into (latitude, longitude) in degrees
+pub fn decode_location(resp: &[u8]) -> Result<(f64, f64), ParseError> {
+    let resp = resp.strip_suffix(b"#").unwrap_or(resp);
+    if resp.len() != 8 {
+        return Err(ParseError::InvalidLength);
+    }
+
+    let angle = |d: u8, m: u8, s: u8, negative: u8| {
+        if m >= 60 || s >= 60 || negative > 1 {
+            return Err(ParseError::UnknownValue);
+        }
+        let deg = d as f64 + m as f64 / 60.0 + s as f64 / 3600.0;
+        Ok(if negative == 1 { -deg } else { deg })
+    };
+
+    Ok((
+        angle(resp[0], resp[1], resp[2], resp[3])?,
+        angle(resp[4], resp[5], resp[6], resp[7])?,
+    ))
+}
+
+/// Local date and time as stored by the hand controller, `gmt_offset`
+/// is in hours and `dst` tells whether daylight saving is in effect
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MountTime {
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub month: u8,
+    pub day: u8,
+    pub year: u16,
+    pub gmt_offset: i8,
+    pub dst: bool,
+}
+
+impl MountTime {
+    /// Converts a UTC unix timestamp into the local time for the given
+    /// offset, one more hour is added when daylight saving is on
+    pub fn from_unix(secs: u64, gmt_offset: i8, dst: bool) -> Self {
+        let local = secs as i64 + (gmt_offset as i64 + dst as i64) * 3600;
+        let days = local.div_euclid(86400);
+        let time = local.rem_euclid(86400);
+
+        // Days to civil date, see http://howardhinnant.github.io/date_algorithms.html
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + (month <= 2) as i64;
+
+        Self {
+            hour: (time / 3600) as u8,
+            minute: (time / 60 % 60) as u8,
+            second: (time % 60) as u8,
+            month: month as u8,
+            day: day as u8,
+            year: year as u16,
+            gmt_offset,
+            dst,
+        }
+    }
+
+    /// Encodes the time as the QRSTUVWX payload of the SetTime command,
+    /// negative offsets are sent as 256 + offset
+    pub fn encode(&self) -> Result<[u8; 8], ParseError> {
+        if !(2000..=2255).contains(&self.year) || !(-12..=14).contains(&self.gmt_offset) {
+            return Err(ParseError::UnknownValue);
+        }
+
+        Ok([
+            self.hour,
+            self.minute,
+            self.second,
+            self.month,
+            self.day,
+            (self.year - 2000) as u8,
+            self.gmt_offset as u8,
+            self.dst as u8,
+        ])
+    }
+
+    /// Decodes the answer to the GetTime command, with or without
+    /// the trailing '#'
+    pub fn decode(resp: &[u8]) -> Result<Self, ParseError> {
+        let resp = resp.strip_suffix(b"#").unwrap_or(resp);
+        if resp.len() != 8 {
+            return Err(ParseError::InvalidLength);
+        }
+
+        let time = Self {
+            hour: resp[0],
+            minute: resp[1],
+            second: resp[2],
+            month: resp[3],
+            day: resp[4],
+            year: 2000 + resp[5] as u16,
+            gmt_offset: resp[6] as i8,
+            dst: resp[7] == 1,
+        };
+
+        if time.hour > 23
+            || time.minute > 59
+            || time.second > 59
+            || !(1..=12).contains(&time.month)
+            || !(1..=31).contains(&time.day)
+            || resp[7] > 1
+        {
+            return Err(ParseError::UnknownValue);
+        }
+        Ok(time)
+    }
+}
+
+/// Formats a UTC unix timestamp like "2024-05-01T20:13:02Z"
+pub fn utc_timestamp(secs: u64) -> String {
+    let t = MountTime::from_unix(secs, 0, false);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        t.year, t.month, t.day, t.hour, t.minute, t.second
+    )
+}
+
+/// Remembers the most recent failure so that remote clients can see
+/// it, the message is dropped after a quiet period without failures
+#[derive(Debug)]
+pub struct LastError {
+    message: String,
+    at: Option<Instant>,
+    quiet_period: Duration,
+}
+
+impl LastError {
+    pub fn new(quiet_period: Duration) -> Self {
+        Self {
+            message: String::new(),
+            at: None,
+            quiet_period,
+        }
+    }
+
+    /// Records `error` raised by `what` (a command or a property),
+    /// `unix_secs` is the wall clock time shown in the message
+    pub fn record<E: fmt::Debug>(&mut self, what: &str, error: E, unix_secs: u64, now: Instant) {
+        self.message = format!("{:?} on {} at {}", error, what, utc_timestamp(unix_secs));
+        self.at = Some(now);
+    }
+
+    /// To be called after a success, clears the message once the
+    /// quiet period is over
+    pub fn clear_if_quiet(&mut self, now: Instant) {
+        if let Some(at) = self.at {
+            if now.saturating_duration_since(at) >= self.quiet_period {
+                self.message.clear();
+                self.at = None;
+            }
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+/// Side of the pier the telescope is on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PierSide {
+    East,
+    West,
+    Unknown,
+}
+
+impl fmt::Display for PierSide {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            PierSide::East => "East",
+            PierSide::West => "West",
+            PierSide::Unknown => "Unknown",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Parses the answer to the alignment ('J') query, the flag is
+/// usually the byte 0x01/0x00 but some firmwares answer '1'/'0'
+pub fn parse_alignment(resp: &str) -> Result<bool, ParseError> {
+    match resp.strip_suffix('#').unwrap_or(resp).as_bytes() {
+        [1] | [b'1'] => Ok(true),
+        [0] | [b'0'] => Ok(false),
+        [] => Err(ParseError::InvalidLength),
+        _ => Err(ParseError::UnknownValue),
+    }
+}
+
+/// Name reported for mounts that don't answer the model query
+pub const UNKNOWN_MODEL: &str = "Unknown";
+
+/// Maps the answer to the model ('m') query, a single byte followed
+/// by '#', to the mount model name. None if the mount didn't answer.
+pub fn parse_model(resp: &[u8]) -> Option<&'static str> {
+    let model = match resp.strip_suffix(b"#").unwrap_or(resp) {
+        [code] => match code {
+            0 => "EQ6",
+            1 => "HEQ5",
+            2 => "EQ5",
+            3 => "EQ3",
+            4 => "EQ8",
+            5 => "AZ-EQ6",
+            6 => "AZ-EQ5",
+            128..=143 => "AZ",
+            144..=159 => "DOB",
+            _ => "AllView",
+        },
+        _ => return None,
+    };
+    Some(model)
+}
+
+/// Firmware version reported when the hand controller doesn't answer
+pub const UNKNOWN_VERSION: &str = "unknown";
+
+/// Parses the answer to the version ('V') query, e.g. "042507#",
+/// into a "major.minor.patch" version like "4.37.7"
+pub fn parse_version_response(resp: &str) -> Result<String, ParseError> {
+    let resp = resp.strip_suffix('#').unwrap_or(resp);
+    if resp.len() != 6 || !resp.is_ascii() {
+        return Err(ParseError::InvalidLength);
+    }
+
+    let part =
+        |i: usize| u8::from_str_radix(&resp[i..i + 2], 16).map_err(|_| ParseError::InvalidHex);
+    Ok(format!("{}.{}.{}", part(0)?, part(2)?, part(4)?))
+}
+
+/// Parses a "major.minor.patch" firmware version
+pub fn parse_firmware_version(version: &str) -> Result<(u8, u8, u8), ParseError> {
+    let mut parts = version.trim().split('.').map(|p| p.parse::<u8>());
+
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(major)), Some(Ok(minor)), Some(Ok(patch)), None) => Ok((major, minor, patch)),
+        _ => Err(ParseError::UnknownValue),
+    }
+}
+
+/// Hand controller features that depend on the firmware version
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Feature {
+    /// 24 bit RA/DEC and Alt/Az commands ('e', 'r', 'z', 'b', 's')
+    PreciseCoordinates,
+    /// Pier side ('p') query
+    PierSide,
+}
+
+impl Feature {
+    /// First firmware version implementing the feature
+    pub fn min_firmware(&self) -> (u8, u8, u8) {
+        match self {
+            Feature::PreciseCoordinates => (2, 2, 0),
+            Feature::PierSide => (4, 15, 0),
+        }
+    }
+
+    pub fn supported_by(&self, version: (u8, u8, u8)) -> bool {
+        version >= self.min_firmware()
+    }
+}
+
+/// Tells whether a "major.minor.patch" firmware has the feature,
+/// unparseable versions are treated as not having it
+pub fn firmware_supports(version: &str, feature: Feature) -> bool {
+    parse_firmware_version(version).is_ok_and(|v| feature.supported_by(v))
+}
+
+/// Parses the answer to the pier side query, "E#" or "W#"
+pub fn parse_pier_side(resp: &str) -> PierSide {
+    match resp {
+        "E#" => PierSide::East,
+        "W#" => PierSide::West,
+        _ => PierSide::Unknown,
+    }
+}
+
+/// Derives the pier side from the DEC axis as reported by the precise
+/// RA/DEC query (0..360 degrees) on firmware without the pier side
+/// query: past the pole (90..270) the telescope is on the west side
+pub fn pier_side_from_dec_axis(dec_axis_deg: f64) -> PierSide {
+    if !(0.0..360.0).contains(&dec_axis_deg) {
+        PierSide::Unknown
+    } else if dec_axis_deg > 90.0 && dec_axis_deg < 270.0 {
+        PierSide::West
+    } else {
+        PierSide::East
+    }
+}
+
+/// Decodes a hex string like "56" or "4b 78" into the bytes to send,
+/// whitespace between the bytes is ignored
+pub fn parse_hex_command(hex_command: &str) -> Result<Vec<u8>, ParseError> {
+    let digits: String = hex_command.split_whitespace().collect();
+    if digits.is_empty() || digits.len() % 2 != 0 {
+        return Err(ParseError::InvalidLength);
+    }
+    hex::decode(digits).map_err(|_| ParseError::InvalidHex)
+}
+
+/// Formats bytes as space separated upper case hex, e.g. "30 34 #" is
+/// dumped as "30 34 23"
+pub fn hex_dump(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// RA/DEC of the home position, counterweights down and the telescope
+/// on the celestial pole: DEC at the pole of the site hemisphere and
+/// the RA axis six hours west of the meridian
+pub fn home_ra_dec(lat_deg: f64, lon_deg: f64, unix_secs: f64) -> (f64, f64) {
+    let lst = local_sidereal_time_deg(unix_secs, lon_deg);
+    let dec = if lat_deg >= 0.0 { 90.0 } else { -90.0 };
+    ((lst - 90.0).rem_euclid(360.0), dec)
+}
+
+/// Keeps track of when the position was last read successfully
+#[derive(Debug)]
+pub struct PositionAge {
+    last_success: Option<Instant>,
+    stale_polls: u32,
+}
+
+impl PositionAge {
+    pub fn new(stale_polls: u32) -> Self {
+        Self {
+            last_success: None,
+            stale_polls,
+        }
+    }
+
+    pub fn record_success(&mut self, at: Instant) {
+        self.last_success = Some(at);
+    }
+
+    /// True if the position was never read or is older than
+    /// `stale_polls` polling intervals
+    pub fn is_stale(&self, now: Instant, poll_interval: Duration) -> bool {
+        match self.last_success {
+            Some(at) => now.saturating_duration_since(at) > poll_interval * self.stale_polls,
+            None => true,
+        }
+    }
+}
+
+/// Overall state of the mount, derived from the polled values
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MountStatus {
+    Idle,
+    Slewing,
+    Tracking,
+    Parked,
+}
+
+impl MountStatus {
+    /// A running goto wins over tracking, which wins over parking
+    pub fn derive(slewing: bool, tracking: bool, parked: bool) -> Self {
+        if slewing {
+            MountStatus::Slewing
+        } else if tracking {
+            MountStatus::Tracking
+        } else if parked {
+            MountStatus::Parked
+        } else {
+            MountStatus::Idle
+        }
+    }
+
+    /// How long to wait before polling the mount again, `idle` being
+    /// the configured interval. Slews are never polled less often and
+    /// parked mounts never more often than that.
+    pub fn poll_interval(&self, idle: Duration) -> Duration {
+        match self {
+            MountStatus::Slewing => SLEWING_POLL_INTERVAL.min(idle),
+            MountStatus::Tracking | MountStatus::Idle => idle,
+            MountStatus::Parked => PARKED_POLL_INTERVAL.max(idle),
+        }
+    }
+}
+
+impl fmt::Display for MountStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            MountStatus::Idle => "Idle",
+            MountStatus::Slewing => "Slewing",
+            MountStatus::Tracking => "Tracking",
+            MountStatus::Parked => "Parked",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Remembers the last derived status so that each transition is
+/// reported only once
+#[derive(Debug)]
+pub struct StatusTracker {
+    status: MountStatus,
+}
+
+impl Default for StatusTracker {
+    fn default() -> Self {
+        Self {
+            status: MountStatus::Idle,
+        }
+    }
+}
+
+impl StatusTracker {
+    pub fn status(&self) -> MountStatus {
+        self.status
+    }
+
+    /// Derives the status from the latest polled values, returns the
+    /// new status only when it differs from the previous one
+    pub fn update(&mut self, slewing: bool, tracking: bool, parked: bool) -> Option<MountStatus> {
+        let status = MountStatus::derive(slewing, tracking, parked);
+        if status == self.status {
+            return None;
+        }
+        self.status = status;
+        Some(status)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        build_frame, decode_location, encode_location, firmware_supports, get_guide_rate_frame,
+        hex_dump, home_ra_dec, motor_version_frame, parse_alignment, parse_firmware_version,
+        parse_goto_in_progress, parse_hex_command, parse_model, parse_motor_version,
+        parse_pier_side, parse_slew_value, parse_version_response, passthrough_frame,
+        pier_side_from_dec_axis, response_error, rotation_fraction_to_degrees,
+        set_guide_rate_frame, slew_frame, utc_timestamp, Axis, Command, Direction, Duration,
+        Feature, GotoProgress, GotoWatch, Instant, LastError, MountStatus, MountTime, PierSide,
+        PositionAge, StatusTracker, GPS_DEVICE, IDLE_POLL_INTERVAL,
+    };
+    use crate::ParseError;
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn frames() {
+        assert_eq!(build_frame(Command::Echo, Some(b"x")), vec![0x4b, 0x78]);
+        assert_eq!(build_frame(Command::GetPreciseRaDec, None), vec![0x65]);
+        assert_eq!(
+            build_frame(Command::SetTrackingMode, Some(&[2])),
+            vec![0x54, 0x02]
+        );
+        assert_eq!(
+            build_frame(Command::GoToRaDec, Some(b"12CE,34AB")),
+            b"R12CE,34AB".to_vec()
+        );
+        // Binary payloads go out byte for byte
+        assert_eq!(
+            build_frame(Command::SetLocation, Some(&[51, 28, 37, 0, 0, 0, 180, 1])),
+            vec![0x57, 51, 28, 37, 0, 0, 0, 180, 1]
+        );
+    }
+
+    #[test]
+    fn command_from_byte() {
+        assert_eq!(Command::try_from(0x72), Ok(Command::GoToPreciseRaDec));
+        assert_eq!(Command::GetVersion.as_byte(), 0x56);
+        assert!(Command::try_from(0x00).is_err());
+    }
+
+    #[test]
+    fn all_lists_every_command() {
+        for byte in 0..=u8::MAX {
+            match Command::try_from(byte) {
+                Ok(cmd) => assert!(Command::ALL.contains(&cmd)),
+                Err(_) => assert!(Command::ALL.iter().all(|c| c.as_byte() != byte)),
+            }
+        }
+    }
+
+    #[test]
+    fn cancel_goto_frame() {
+        assert_eq!(build_frame(Command::CancelGoto, None), vec![0x4d]);
+        assert_eq!(Command::try_from(0x4d), Ok(Command::CancelGoto));
+    }
+
+    #[test]
+    fn goto_in_progress_response() {
+        assert_eq!(parse_goto_in_progress("1#"), Ok(true));
+        assert_eq!(parse_goto_in_progress("0#"), Ok(false));
+        assert_eq!(parse_goto_in_progress("2#"), Err(ParseError::UnknownValue));
+        assert_eq!(parse_goto_in_progress("#"), Err(ParseError::InvalidLength));
+        assert_eq!(parse_goto_in_progress(""), Err(ParseError::InvalidLength));
+    }
+
+    #[test]
+    fn sync_frame() {
+        assert_eq!(
+            build_frame(Command::SyncPreciseRaDec, Some(b"80000000,20000000")),
+            b"s80000000,20000000".to_vec()
+        );
+        assert_eq!(Command::try_from(0x53), Ok(Command::SyncRaDec));
+    }
+
+    #[test]
+    fn slew_frames() {
+        assert_eq!(
+            slew_frame(Axis::Ra, Direction::Positive, 5),
+            vec![0x50, 0x02, 0x10, 0x24, 0x05, 0x00, 0x00, 0x00]
+        );
+        assert_eq!(
+            slew_frame(Axis::Dec, Direction::Negative, 3),
+            vec![0x50, 0x02, 0x11, 0x25, 0x03, 0x00, 0x00, 0x00]
+        );
+        assert_eq!(
+            slew_frame(Axis::Dec, Direction::Positive, 0),
+            vec![0x50, 0x02, 0x11, 0x24, 0x00, 0x00, 0x00, 0x00]
+        );
+        assert_eq!(
+            slew_frame(Axis::Ra, Direction::Negative, 20),
+            vec![0x50, 0x02, 0x10, 0x25, 0x09, 0x00, 0x00, 0x00]
+        );
+    }
+
+    #[test]
+    fn slew_values() {
+        assert_eq!(parse_slew_value("+5"), Ok((Direction::Positive, 5)));
+        assert_eq!(parse_slew_value("-3"), Ok((Direction::Negative, 3)));
+        assert_eq!(parse_slew_value("0"), Ok((Direction::Positive, 0)));
+        assert_eq!(parse_slew_value("7"), Ok((Direction::Positive, 7)));
+        assert!(parse_slew_value("+10").is_err());
+        assert!(parse_slew_value("fast").is_err());
+        assert!(parse_slew_value("").is_err());
+    }
+
+    #[test]
+    fn location_encoding() {
+        // 45°30'15" N, 122°40'30" W
+        assert_eq!(
+            encode_location(45.504167, -122.675),
+            Ok([45, 30, 15, 0, 122, 40, 30, 1])
+        );
+        assert_eq!(encode_location(0.0, 0.0), Ok([0, 0, 0, 0, 0, 0, 0, 0]));
+        assert_eq!(
+            encode_location(-33.865, 151.209444),
+            Ok([33, 51, 54, 1, 151, 12, 34, 0])
+        );
+        // 59.9 seconds carry over to the next minute
+        assert_eq!(
+            encode_location(10.0 + 59.9 / 3600.0, 0.0),
+            Ok([10, 1, 0, 0, 0, 0, 0, 0])
+        );
+        assert!(encode_location(91.0, 0.0).is_err());
+        assert!(encode_location(0.0, -181.0).is_err());
+    }
+
+    #[test]
+    fn location_decoding() {
+        let (lat, lon) = decode_location(&[45, 30, 15, 0, 122, 40, 30, 1, b'#']).unwrap();
+        assert_approx_eq!(lat, 45.504167, 1e-6);
+        assert_approx_eq!(lon, -122.675, 1e-6);
+
+        assert_eq!(decode_location(&[0; 8]), Ok((0.0, 0.0)));
+        assert_eq!(
+            decode_location(&[45, 30, 15, 0, 122, 40, 30]),
+            Err(ParseError::InvalidLength)
+        );
+        assert_eq!(
+            decode_location(&[45, 60, 15, 0, 122, 40, 30, 1]),
+            Err(ParseError::UnknownValue)
+        );
+    }
+
+    #[test]
+    fn location_round_trip() {
+        for (lat, lon) in [(0.0, -0.5), (-89.5, 179.25), (51.4778, -0.0015)] {
+            let (dlat, dlon) = decode_location(&encode_location(lat, lon).unwrap()).unwrap();
+            assert_approx_eq!(dlat, lat, 1.0 / 3600.0);
+            assert_approx_eq!(dlon, lon, 1.0 / 3600.0);
+        }
+    }
+
+    #[test]
+    fn time_from_unix() {
+        // 2022-07-14 21:30:05 UTC
+        let t = MountTime::from_unix(1657834205, 0, false);
+        assert_eq!((t.year, t.month, t.day), (2022, 7, 14));
+        assert_eq!((t.hour, t.minute, t.second), (21, 30, 5));
+
+        // UTC-5 with DST is 17:30 on the same day
+        let t = MountTime::from_unix(1657834205, -5, true);
+        assert_eq!((t.day, t.hour), (14, 17));
+
+        // UTC+3 moves to the next day
+        let t = MountTime::from_unix(1657834205, 3, false);
+        assert_eq!((t.month, t.day, t.hour), (7, 15, 0));
+
+        // Leap day
+        let t = MountTime::from_unix(1709208000, 0, false);
+        assert_eq!((t.year, t.month, t.day), (2024, 2, 29));
+    }
+
+    #[test]
+    fn time_encoding() {
+        let t = MountTime::from_unix(1657834205, -5, true);
+        assert_eq!(t.encode(), Ok([17, 30, 5, 7, 14, 22, 251, 1]));
+
+        let t = MountTime::from_unix(1657834205, 2, false);
+        assert_eq!(t.encode(), Ok([23, 30, 5, 7, 14, 22, 2, 0]));
+
+        let t = MountTime::from_unix(0, 0, false);
+        assert!(t.encode().is_err());
+    }
+
+    #[test]
+    fn time_decoding() {
+        let t = MountTime::decode(&[17, 30, 5, 7, 14, 22, 251, 1, b'#']).unwrap();
+        assert_eq!(t, MountTime::from_unix(1657834205, -5, true));
+
+        let t = MountTime::decode(&[23, 30, 5, 7, 14, 22, 2, 0]).unwrap();
+        assert_eq!((t.gmt_offset, t.dst), (2, false));
+
+        assert_eq!(
+            MountTime::decode(&[23, 30, 5, 7, 14, 22, 2]),
+            Err(ParseError::InvalidLength)
+        );
+        assert_eq!(
+            MountTime::decode(&[24, 30, 5, 7, 14, 22, 2, 0]),
+            Err(ParseError::UnknownValue)
+        );
+    }
+
+    #[test]
+    fn passthrough_frames() {
+        // GPS linked query
+        assert_eq!(
+            passthrough_frame(GPS_DEVICE, 55, &[], 1),
+            vec![0x50, 0x01, 0xb0, 0x37, 0x00, 0x00, 0x00, 0x01]
+        );
+        assert_eq!(
+            passthrough_frame(Axis::Dec as u8, 70, &[30], 0),
+            vec![0x50, 0x02, 0x11, 0x46, 0x1e, 0x00, 0x00, 0x00]
+        );
+    }
+
+    #[test]
+    fn gps_rotation_fractions() {
+        assert_eq!(rotation_fraction_to_degrees(&[0, 0, 0, b'#']), Ok(0.0));
+        assert_eq!(rotation_fraction_to_degrees(&[0x20, 0, 0]), Ok(45.0));
+        assert_eq!(rotation_fraction_to_degrees(&[0xe0, 0, 0]), Ok(-45.0));
+        assert_approx_eq!(
+            rotation_fraction_to_degrees(&[0x1c, 0x71, 0xc7]).unwrap(),
+            40.0,
+            1e-4
+        );
+        assert_eq!(
+            rotation_fraction_to_degrees(&[0x20, 0]),
+            Err(ParseError::InvalidLength)
+        );
+    }
+
+    #[test]
+    fn guide_rate_frames() {
+        assert_eq!(
+            set_guide_rate_frame(Axis::Ra, 50),
+            Ok(vec![0x50, 0x02, 0x10, 0x46, 0x32, 0x00, 0x00, 0x00])
+        );
+        assert_eq!(
+            set_guide_rate_frame(Axis::Dec, 99),
+            Ok(vec![0x50, 0x02, 0x11, 0x46, 0x63, 0x00, 0x00, 0x00])
+        );
+        assert_eq!(
+            get_guide_rate_frame(Axis::Dec),
+            vec![0x50, 0x01, 0x11, 0x47, 0x00, 0x00, 0x00, 0x01]
+        );
+        assert_eq!(
+            set_guide_rate_frame(Axis::Ra, 100),
+            Err(ParseError::UnknownValue)
+        );
+    }
+
+    #[test]
+    fn motor_versions() {
+        assert_eq!(
+            motor_version_frame(Axis::Ra),
+            vec![0x50, 0x01, 0x10, 0xfe, 0x00, 0x00, 0x00, 0x02]
+        );
+        assert_eq!(
+            parse_motor_version(&[7, 11, b'#']),
+            Some(String::from("7.11"))
+        );
+        assert_eq!(parse_motor_version(&[2, 4]), Some(String::from("2.4")));
+        // No answer from the motor
+        assert_eq!(parse_motor_version(b"#"), None);
+        assert_eq!(parse_motor_version(&[]), None);
+        assert_eq!(parse_motor_version(&[1, 2, 3]), None);
+    }
+
+    #[test]
+    fn pier_side_query() {
+        assert_eq!(parse_pier_side("E#"), PierSide::East);
+        assert_eq!(parse_pier_side("W#"), PierSide::West);
+        assert_eq!(parse_pier_side("#"), PierSide::Unknown);
+        assert_eq!(PierSide::West.to_string(), "West");
+    }
+
+    #[test]
+    fn pier_side_fallback() {
+        assert_eq!(pier_side_from_dec_axis(45.0), PierSide::East);
+        assert_eq!(pier_side_from_dec_axis(315.0), PierSide::East);
+        assert_eq!(pier_side_from_dec_axis(135.0), PierSide::West);
+        assert_eq!(pier_side_from_dec_axis(-1.0), PierSide::Unknown);
+        assert_eq!(pier_side_from_dec_axis(f64::NAN), PierSide::Unknown);
+    }
+
+    #[test]
+    fn firmware_versions() {
+        assert_eq!(parse_firmware_version("4.39.2"), Ok((4, 39, 2)));
+        assert!(firmware_supports("4.15.0", Feature::PierSide));
+        assert!(firmware_supports("5.1.3", Feature::PierSide));
+        assert!(!firmware_supports("4.14.9", Feature::PierSide));
+        assert!(!firmware_supports("3.37.8", Feature::PierSide));
+        // Unparseable versions fall back to the derivation
+        for v in ["", "0.0", "4.x.1", "4.15.0.1", "UNKNOWN"] {
+            assert!(parse_firmware_version(v).is_err());
+            assert!(!firmware_supports(v, Feature::PierSide));
+        }
+    }
+
+    #[test]
+    fn follows_goto_to_completion() {
+        let start = Instant::now();
+        let mut watch = GotoWatch::new(start, Duration::from_secs(300));
+        let progress: Vec<GotoProgress> = ["1#", "1#", "0#"]
+            .iter()
+            .enumerate()
+            .map(|(i, r)| {
+                let slewing = parse_goto_in_progress(r).unwrap();
+                watch.update(slewing, start + Duration::from_secs(i as u64))
+            })
+            .collect();
+        assert_eq!(
+            progress,
+            [
+                GotoProgress::Slewing,
+                GotoProgress::Slewing,
+                GotoProgress::Done
+            ]
+        );
+    }
+
+    #[test]
+    fn goto_not_reported_yet_is_not_over() {
+        let start = Instant::now();
+        let mut watch = GotoWatch::new(start, Duration::from_secs(300));
+        assert_eq!(watch.update(false, start), GotoProgress::Slewing);
+        assert_eq!(
+            watch.update(false, start + Duration::from_secs(1)),
+            GotoProgress::Slewing
+        );
+        assert_eq!(
+            watch.update(true, start + Duration::from_secs(2)),
+            GotoProgress::Slewing
+        );
+        assert_eq!(
+            watch.update(false, start + Duration::from_secs(3)),
+            GotoProgress::Done
+        );
+
+        // Never reported, the mount was already there
+        let mut watch = GotoWatch::new(start, Duration::from_secs(300));
+        assert_eq!(
+            watch.update(false, start + Duration::from_secs(3)),
+            GotoProgress::Done
+        );
+    }
+
+    #[test]
+    fn gives_up_on_goto() {
+        let start = Instant::now();
+        let mut watch = GotoWatch::new(start, Duration::from_secs(5));
+        assert_eq!(watch.update(true, start), GotoProgress::Slewing);
+        assert_eq!(
+            watch.update(true, start + Duration::from_secs(6)),
+            GotoProgress::TimedOut
+        );
+    }
+
+    #[test]
+    fn mount_models() {
+        assert_eq!(parse_model(&[0, b'#']), Some("EQ6"));
+        assert_eq!(parse_model(&[1]), Some("HEQ5"));
+        assert_eq!(parse_model(&[130, b'#']), Some("AZ"));
+        assert_eq!(parse_model(&[150, b'#']), Some("DOB"));
+        assert_eq!(parse_model(&[200, b'#']), Some("AllView"));
+        // Mount not answering
+        assert_eq!(parse_model(b"#"), None);
+        assert_eq!(parse_model(&[]), None);
+    }
+
+    #[test]
+    fn alignment_flag() {
+        assert_eq!(parse_alignment("\u{1}#"), Ok(true));
+        assert_eq!(parse_alignment("\u{0}#"), Ok(false));
+        assert_eq!(parse_alignment("1#"), Ok(true));
+        assert_eq!(parse_alignment("0#"), Ok(false));
+        assert_eq!(parse_alignment("#"), Err(ParseError::InvalidLength));
+        assert_eq!(parse_alignment("2#"), Err(ParseError::UnknownValue));
+        assert_eq!(parse_alignment("10#"), Err(ParseError::UnknownValue));
+    }
+
+    #[test]
+    fn error_responses() {
+        assert_eq!(response_error(b"!2#"), Some(String::from("2")));
+        assert_eq!(response_error(b"!#"), Some(String::new()));
+        assert_eq!(response_error(b"#"), None);
+        assert_eq!(response_error(b"12AB0500,40000000#"), None);
+        assert_eq!(response_error(b""), None);
+    }
+
+    #[test]
+    fn version_responses() {
+        assert_eq!(
+            parse_version_response("042507#"),
+            Ok(String::from("4.37.7"))
+        );
+        assert_eq!(
+            parse_version_response("030A0F"),
+            Ok(String::from("3.10.15"))
+        );
+        assert_eq!(
+            parse_version_response("0425#"),
+            Err(ParseError::InvalidLength)
+        );
+        assert_eq!(parse_version_response(""), Err(ParseError::InvalidLength));
+        assert_eq!(
+            parse_version_response("04ZZ07#"),
+            Err(ParseError::InvalidHex)
+        );
+        // The parsed version feeds the firmware checks
+        assert!(firmware_supports(
+            &parse_version_response("042507#").unwrap(),
+            Feature::PierSide
+        ));
+    }
+
+    #[test]
+    fn only_queries_are_idempotent() {
+        assert!(Command::Echo.is_idempotent());
+        assert!(Command::GetPreciseRaDec.is_idempotent());
+        assert!(Command::GetVersion.is_idempotent());
+        assert!(!Command::GoToPreciseRaDec.is_idempotent());
+        assert!(!Command::SyncPreciseRaDec.is_idempotent());
+        assert!(!Command::SetTrackingMode.is_idempotent());
+        assert!(!Command::CancelGoto.is_idempotent());
+    }
+
+    #[test]
+    fn command_timeouts() {
+        assert_eq!(
+            Command::Echo.read_timeout(),
+            Some(Duration::from_millis(200))
+        );
+        assert_eq!(
+            Command::GetPreciseRaDec.read_timeout(),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(
+            Command::GoToPreciseRaDec.read_timeout(),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(
+            Command::SetTrackingMode.read_timeout(),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(Command::GetVersion.read_timeout(), None);
+    }
+
+    #[test]
+    fn status_follows_goto_tracking_park() {
+        let mut tracker = StatusTracker::default();
+        assert_eq!(tracker.status(), MountStatus::Idle);
+
+        // (goto in progress answer, tracking, parked) as polled over time
+        let script = [
+            ("1#", false, false),
+            ("1#", false, false),
+            ("0#", true, false),
+            ("0#", true, false),
+            ("0#", false, true),
+            ("0#", false, true),
+        ];
+        let changes: Vec<Option<MountStatus>> = script
+            .iter()
+            .map(|(resp, tracking, parked)| {
+                let slewing = parse_goto_in_progress(resp).unwrap();
+                tracker.update(slewing, *tracking, *parked)
+            })
+            .collect();
+
+        assert_eq!(
+            changes,
+            vec![
+                Some(MountStatus::Slewing),
+                None,
+                Some(MountStatus::Tracking),
+                None,
+                Some(MountStatus::Parked),
+                None,
+            ]
+        );
+        assert_eq!(tracker.status().to_string(), "Parked");
+    }
+
+    #[test]
+    fn slewing_wins_over_tracking() {
+        assert_eq!(MountStatus::derive(true, true, false), MountStatus::Slewing);
+        assert_eq!(
+            MountStatus::derive(false, true, true),
+            MountStatus::Tracking
+        );
+        assert_eq!(MountStatus::derive(false, false, false), MountStatus::Idle);
+    }
+
+    #[test]
+    fn raw_commands() {
+        assert_eq!(parse_hex_command("56"), Ok(vec![0x56]));
+        assert_eq!(parse_hex_command(" 4b 78 "), Ok(vec![0x4b, 0x78]));
+        assert_eq!(parse_hex_command("5"), Err(ParseError::InvalidLength));
+        assert_eq!(parse_hex_command(""), Err(ParseError::InvalidLength));
+        assert_eq!(parse_hex_command("zz"), Err(ParseError::InvalidHex));
+
+        // Version answer, several bytes up to the terminator
+        assert_eq!(hex_dump(b"042507#"), "30 34 32 35 30 37 23");
+        assert_eq!(hex_dump(&[0x00, 0xff]), "00 FF");
+        assert_eq!(hex_dump(&[]), "");
+    }
+
+    #[test]
+    fn position_goes_stale_after_failed_polls() {
+        let interval = Duration::from_secs(1);
+        let start = Instant::now();
+        let mut age = PositionAge::new(2);
+        assert!(age.is_stale(start, interval));
+
+        age.record_success(start);
+        assert!(!age.is_stale(start, interval));
+
+        // Three failed polls, nothing recorded
+        let stale: Vec<bool> = (1..=3)
+            .map(|poll| age.is_stale(start + interval * poll, interval))
+            .collect();
+        assert_eq!(stale, vec![false, false, true]);
+
+        let recovered = start + interval * 4;
+        age.record_success(recovered);
+        assert!(!age.is_stale(recovered, interval));
+    }
+
+    #[test]
+    fn poll_interval_per_status() {
+        let idle = IDLE_POLL_INTERVAL;
+        assert_eq!(
+            MountStatus::Slewing.poll_interval(idle),
+            Duration::from_millis(250)
+        );
+        assert_eq!(
+            MountStatus::Tracking.poll_interval(idle),
+            Duration::from_secs(1)
+        );
+        assert_eq!(
+            MountStatus::Idle.poll_interval(idle),
+            Duration::from_secs(1)
+        );
+        assert_eq!(
+            MountStatus::Parked.poll_interval(idle),
+            Duration::from_secs(5)
+        );
+
+        // A faster configured interval speeds up slews too
+        let fast = Duration::from_millis(100);
+        assert_eq!(MountStatus::Slewing.poll_interval(fast), fast);
+        assert_eq!(MountStatus::Idle.poll_interval(fast), fast);
+        assert_eq!(
+            MountStatus::Parked.poll_interval(fast),
+            Duration::from_secs(5)
+        );
+
+        // A slower one still follows slews closely
+        let slow = Duration::from_secs(10);
+        assert_eq!(
+            MountStatus::Slewing.poll_interval(slow),
+            Duration::from_millis(250)
+        );
+        assert_eq!(MountStatus::Tracking.poll_interval(slow), slow);
+        assert_eq!(MountStatus::Parked.poll_interval(slow), slow);
+    }
+
+    #[test]
+    fn home_position() {
+        // J2000.0 at Greenwich, LST 280.46 degrees
+        let (ra, dec) = home_ra_dec(45.0, 0.0, 946728000.0);
+        assert_approx_eq!(ra, 190.46061837, 1e-6);
+        assert_eq!(dec, 90.0);
+
+        // Six hours later at 90E in the southern hemisphere
+        let (ra, dec) = home_ra_dec(-33.0, 90.0, 946749600.0);
+        assert_approx_eq!(ra, 10.70703021, 1e-6);
+        assert_eq!(dec, -90.0);
+    }
+
+    #[test]
+    fn old_firmware_falls_back_to_plain_coordinates() {
+        let old = parse_version_response("010200").unwrap();
+        assert_eq!(old, "1.2.0");
+        assert!(!firmware_supports(&old, Feature::PreciseCoordinates));
+
+        let recent = parse_version_response("042507").unwrap();
+        assert!(firmware_supports(&recent, Feature::PreciseCoordinates));
+        assert!(Feature::PreciseCoordinates.supported_by((2, 2, 0)));
+        assert!(!Feature::PreciseCoordinates.supported_by((2, 1, 9)));
+    }
+
+    #[test]
+    fn last_error_records_a_timeout() {
+        assert_eq!(utc_timestamp(1714594382), "2024-05-01T20:13:02Z");
+
+        // A silent port times out
+        let mut port: &[u8] = b"";
+        let error = crate::serial::read_response(
+            &mut port,
+            b'#',
+            crate::serial::MAX_RESPONSE_LEN,
+            Duration::from_millis(10),
+        )
+        .unwrap_err();
+
+        let start = Instant::now();
+        let mut last = LastError::new(Duration::from_secs(60));
+        assert_eq!(last.message(), "");
+        last.record("GetPreciseRaDec", error, 1714594382, start);
+        assert_eq!(
+            last.message(),
+            "Timeout on GetPreciseRaDec at 2024-05-01T20:13:02Z"
+        );
+
+        // Successes within the quiet period keep it around
+        last.clear_if_quiet(start + Duration::from_secs(30));
+        assert!(!last.message().is_empty());
+        last.clear_if_quiet(start + Duration::from_secs(60));
+        assert_eq!(last.message(), "");
+    }
+}
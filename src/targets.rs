@@ -0,0 +1,306 @@
+use crate::write_file_atomically;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// File the target lists are kept in when LS_TARGET_LIST_FILE is not set
+pub const DEFAULT_TARGET_LIST_FILE: &str = "skywatcher-targets.json";
+/// Max number of entries accepted in a single target list
+pub const MAX_TARGETS: usize = 256;
+/// Max length of a target name
+pub const MAX_TARGET_NAME_LEN: usize = 64;
+
+#[derive(Debug, PartialEq)]
+pub enum TargetListError {
+    InvalidJson,
+    TooManyTargets,
+    InvalidName,
+    InvalidCoordinates,
+    /// The active entry is past the end of the list
+    InvalidIndex,
+}
+
+/// A single entry of a target list, coordinates are in decimal degrees
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Target {
+    pub name: String,
+    pub ra: f64,
+    pub dec: f64,
+}
+
+impl Target {
+    fn validate(&self) -> Result<(), TargetListError> {
+        if self.name.is_empty() || self.name.len() > MAX_TARGET_NAME_LEN {
+            return Err(TargetListError::InvalidName);
+        }
+
+        if !(0.0..360.0).contains(&self.ra) || !(-90.0..=90.0).contains(&self.dec) {
+            return Err(TargetListError::InvalidCoordinates);
+        }
+
+        Ok(())
+    }
+}
+
+/// A list of targets the mount can be stepped through, keeping
+/// track of the active entry.
+#[derive(Clone, Default, Debug, Deserialize, PartialEq, Serialize)]
+pub struct TargetList {
+    targets: Vec<Target>,
+    index: Option<usize>,
+}
+
+impl TargetList {
+    /// Builds a list from a JSON array like
+    /// `[{"name": "M31", "ra": 10.684, "dec": 41.269}]`
+    pub fn from_json(input: &str) -> Result<Self, TargetListError> {
+        let targets: Vec<Target> =
+            serde_json::from_str(input).map_err(|_| TargetListError::InvalidJson)?;
+        let list = Self {
+            targets,
+            index: None,
+        };
+        list.validate()?;
+        Ok(list)
+    }
+
+    fn validate(&self) -> Result<(), TargetListError> {
+        if self.targets.len() > MAX_TARGETS {
+            return Err(TargetListError::TooManyTargets);
+        }
+
+        for t in &self.targets {
+            t.validate()?;
+        }
+
+        match self.index {
+            Some(i) if i >= self.targets.len() => Err(TargetListError::InvalidIndex),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.targets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.targets.clear();
+        self.index = None;
+    }
+
+    pub fn current(&self) -> Option<&Target> {
+        self.index.and_then(|i| self.targets.get(i))
+    }
+
+    pub fn current_index(&self) -> Option<usize> {
+        self.index
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Target> {
+        self.targets.get(index)
+    }
+
+    /// Index `next_target` moves to, without moving
+    pub fn next_index(&self) -> Option<usize> {
+        if self.targets.is_empty() {
+            return None;
+        }
+
+        match self.index {
+            None => Some(0),
+            Some(i) => Some((i + 1).min(self.targets.len() - 1)),
+        }
+    }
+
+    /// Index `prev_target` moves to, without moving
+    pub fn prev_index(&self) -> Option<usize> {
+        if self.targets.is_empty() {
+            return None;
+        }
+
+        Some(self.index.map_or(0, |i| i.saturating_sub(1)))
+    }
+
+    /// Moves to the next entry, the first call after loading the list
+    /// selects the first entry. Stays on the last entry once reached.
+    pub fn next_target(&mut self) -> Option<&Target> {
+        let index = self.next_index()?;
+        self.select(index)
+    }
+
+    /// Moves to the previous entry, stays on the first entry once reached.
+    pub fn prev_target(&mut self) -> Option<&Target> {
+        let index = self.prev_index()?;
+        self.select(index)
+    }
+
+    pub fn select(&mut self, index: usize) -> Option<&Target> {
+        if index >= self.targets.len() {
+            return None;
+        }
+
+        self.index = Some(index);
+        self.current()
+    }
+}
+
+/// Reads the target lists, by mount address, a missing or unreadable
+/// file gives no list at all
+pub fn load_target_lists(path: &Path) -> HashMap<String, TargetList> {
+    let content = match fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(_) => return HashMap::new(),
+    };
+
+    let mut lists: HashMap<String, TargetList> =
+        serde_json::from_str(&content).unwrap_or_else(|e| {
+            warn!("Ignoring invalid target list file {:?}: {}", path, e);
+            HashMap::new()
+        });
+    // Edited by hand maybe, the lists are checked as when they were loaded
+    lists.retain(|mount, list| match list.validate() {
+        Ok(()) => true,
+        Err(e) => {
+            warn!("Ignoring the target list of {}: {:?}", mount, e);
+            false
+        }
+    });
+    lists
+}
+
+/// Held while the list file is read, changed and written back, the
+/// mounts of a driver share it
+static SAVE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Stores the target list of a mount, leaving the other mounts alone.
+/// An empty list is dropped from the file.
+pub fn save_target_list(path: &Path, mount: &str, list: &TargetList) -> io::Result<()> {
+    let _guard = SAVE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut lists = load_target_lists(path);
+    if list.is_empty() {
+        lists.remove(mount);
+    } else {
+        lists.insert(mount.to_owned(), list.clone());
+    }
+    let content = serde_json::to_string_pretty(&lists)?;
+    write_file_atomically(path, &content)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{load_target_lists, save_target_list, TargetList, TargetListError, MAX_TARGETS};
+    use std::fs;
+
+    const LIST: &str = r#"[
+        {"name": "M31", "ra": 10.684, "dec": 41.269},
+        {"name": "M42", "ra": 83.822, "dec": -5.391},
+        {"name": "M13", "ra": 250.423, "dec": 36.461}
+    ]"#;
+
+    #[test]
+    fn step_through_list() {
+        let mut list = TargetList::from_json(LIST).unwrap();
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.current(), None);
+        assert_eq!(list.next_target().unwrap().name, "M31");
+        assert_eq!(list.next_target().unwrap().name, "M42");
+        assert_eq!(list.next_target().unwrap().name, "M13");
+        assert_eq!(list.next_target().unwrap().name, "M13");
+        assert_eq!(list.prev_target().unwrap().name, "M42");
+        assert_eq!(list.select(0).unwrap().name, "M31");
+        assert_eq!(list.prev_target().unwrap().name, "M31");
+        assert!(list.select(3).is_none());
+        assert_eq!(list.current_index(), Some(0));
+    }
+
+    #[test]
+    fn peek_without_moving() {
+        let mut list = TargetList::from_json(LIST).unwrap();
+        assert_eq!(list.next_index(), Some(0));
+        assert_eq!(list.prev_index(), Some(0));
+        list.select(1);
+        assert_eq!(list.next_index(), Some(2));
+        assert_eq!(list.prev_index(), Some(0));
+        assert_eq!(list.current_index(), Some(1));
+        assert_eq!(list.get(2).unwrap().name, "M13");
+        assert!(TargetList::default().next_index().is_none());
+    }
+
+    #[test]
+    fn saved_lists() {
+        let path =
+            std::env::temp_dir().join(format!("skywatcher-targets-{}.json", std::process::id()));
+        let mut list = TargetList::from_json(LIST).unwrap();
+        list.select(1);
+        save_target_list(&path, "/dev/ttyUSB0", &list).unwrap();
+        save_target_list(&path, "simulator", &TargetList::from_json(LIST).unwrap()).unwrap();
+
+        let mut lists = load_target_lists(&path);
+        assert_eq!(lists.len(), 2);
+        let saved = lists.remove("/dev/ttyUSB0").unwrap();
+        assert_eq!(saved, list);
+        assert_eq!(saved.current().unwrap().name, "M42");
+
+        // An empty list leaves nothing behind
+        save_target_list(&path, "simulator", &TargetList::default()).unwrap();
+        assert!(!load_target_lists(&path).contains_key("simulator"));
+
+        // Nor does a list pointing past its end
+        fs::write(
+            &path,
+            r#"{"simulator": {"targets": [{"name": "M31", "ra": 10.0, "dec": 41.0}], "index": 1}}"#,
+        )
+        .unwrap();
+        assert!(load_target_lists(&path).is_empty());
+        fs::remove_file(&path).unwrap();
+        // A missing file is no list
+        assert!(load_target_lists(&path).is_empty());
+    }
+
+    #[test]
+    fn clear_list() {
+        let mut list = TargetList::from_json(LIST).unwrap();
+        list.next_target();
+        list.clear();
+        assert!(list.is_empty());
+        assert!(list.current().is_none());
+        assert!(list.next_target().is_none());
+    }
+
+    #[test]
+    fn invalid_lists() {
+        assert_eq!(
+            TargetList::from_json("not json").unwrap_err(),
+            TargetListError::InvalidJson
+        );
+        assert_eq!(
+            TargetList::from_json(r#"[{"name": "X", "ra": 360.0, "dec": 0.0}]"#).unwrap_err(),
+            TargetListError::InvalidCoordinates
+        );
+        assert_eq!(
+            TargetList::from_json(r#"[{"name": "X", "ra": 10.0, "dec": -91.0}]"#).unwrap_err(),
+            TargetListError::InvalidCoordinates
+        );
+        assert_eq!(
+            TargetList::from_json(r#"[{"name": "", "ra": 10.0, "dec": 0.0}]"#).unwrap_err(),
+            TargetListError::InvalidName
+        );
+
+        let too_many = format!(
+            "[{}]",
+            vec![r#"{"name": "X", "ra": 1.0, "dec": 1.0}"#; MAX_TARGETS + 1].join(",")
+        );
+        assert_eq!(
+            TargetList::from_json(&too_many).unwrap_err(),
+            TargetListError::TooManyTargets
+        );
+    }
+}
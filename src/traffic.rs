@@ -0,0 +1,188 @@
+use crate::props::CustomProp;
+use crate::synscan::hex_dump;
+use lightspeed_astro::props::Permission;
+use log::info;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Exchanges kept for TRAFFIC_DUMP, the oldest ones are dropped first
+pub const TRAFFIC_CAPACITY: usize = 100;
+
+/// Trace of the frames exchanged with a mount, switched on and off with
+/// the DEBUG_TRAFFIC property. While off it costs a single check per
+/// exchange, nothing is formatted nor stored.
+#[derive(Debug)]
+pub struct TrafficLog {
+    enabled: bool,
+    exchanges: VecDeque<String>,
+    /// Shared with the DEBUG_TRAFFIC property
+    debug_traffic: Arc<RwLock<String>>,
+    /// Shared with the TRAFFIC_DUMP property, one exchange per line
+    dump: Arc<RwLock<String>>,
+}
+
+impl Default for TrafficLog {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            exchanges: VecDeque::new(),
+            debug_traffic: Arc::new(RwLock::new(String::from("false"))),
+            dump: Arc::new(RwLock::new(String::new())),
+        }
+    }
+}
+
+impl TrafficLog {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Switching the trace off keeps what was recorded so far readable
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        *self.debug_traffic.write().unwrap() = enabled.to_string();
+    }
+
+    /// Logs an exchange and keeps it for TRAFFIC_DUMP, `command` being
+    /// the decoded command when it is a known one
+    pub fn record<C, E>(
+        &mut self,
+        command: Option<C>,
+        frame: &[u8],
+        answer: Result<&[u8], E>,
+        elapsed: Duration,
+    ) where
+        C: fmt::Debug,
+        E: fmt::Debug,
+    {
+        if !self.enabled {
+            return;
+        }
+        let command = match command {
+            Some(c) => format!("{:?}", c),
+            None => String::from("Unknown"),
+        };
+        let answer = match answer {
+            Ok(a) => hex_dump(a),
+            Err(e) => format!("{:?}", e),
+        };
+        let exchange = format!(
+            "{} > {} < {} in {} ms",
+            command,
+            hex_dump(frame),
+            answer,
+            elapsed.as_millis()
+        );
+        info!("{}", exchange);
+
+        if self.exchanges.len() == TRAFFIC_CAPACITY {
+            self.exchanges.pop_front();
+        }
+        self.exchanges.push_back(exchange);
+        *self.dump.write().unwrap() = self
+            .exchanges
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+
+    /// The writable DEBUG_TRAFFIC switch and the read only TRAFFIC_DUMP
+    pub fn props(&self) -> Vec<CustomProp> {
+        vec![
+            CustomProp {
+                name: String::from("DEBUG_TRAFFIC"),
+                value: Arc::clone(&self.debug_traffic),
+                kind: String::from("boolean"),
+                permission: Permission::ReadWrite,
+            },
+            CustomProp {
+                name: String::from("TRAFFIC_DUMP"),
+                value: Arc::clone(&self.dump),
+                kind: String::from("string"),
+                permission: Permission::ReadOnly,
+            },
+        ]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TrafficLog, TRAFFIC_CAPACITY};
+    use crate::eqmod::EqModOp;
+    use crate::serial::{ReadError, TransportError};
+    use std::time::Duration;
+
+    fn dump(log: &TrafficLog) -> String {
+        log.props()[1].value.read().unwrap().clone()
+    }
+
+    fn position(log: &mut TrafficLog) {
+        let answer: Result<Vec<u8>, TransportError> = Ok(b"=000080\r".to_vec());
+        log.record(
+            EqModOp::try_from(b'j').ok(),
+            b":j1\r",
+            answer.as_deref(),
+            Duration::from_millis(12),
+        );
+    }
+
+    #[test]
+    fn nothing_recorded_while_disabled() {
+        let mut log = TrafficLog::default();
+        position(&mut log);
+        assert!(log.exchanges.is_empty());
+        assert_eq!(log.exchanges.capacity(), 0);
+        assert_eq!(dump(&log), "");
+        assert_eq!(log.props()[0].value.read().unwrap().as_str(), "false");
+    }
+
+    #[test]
+    fn toggled_mid_session() {
+        let mut log = TrafficLog::default();
+        position(&mut log);
+
+        log.set_enabled(true);
+        assert_eq!(log.props()[0].value.read().unwrap().as_str(), "true");
+        position(&mut log);
+        let timeout: Result<Vec<u8>, TransportError> =
+            Err(TransportError::Read(ReadError::Timeout));
+        log.record(
+            None::<EqModOp>,
+            b":z1\r",
+            timeout.as_deref(),
+            Duration::from_millis(500),
+        );
+        assert_eq!(
+            dump(&log),
+            "GetAxisPosition > 3A 6A 31 0D < 3D 30 30 30 30 38 30 0D in 12 ms\n\
+             Unknown > 3A 7A 31 0D < Read(Timeout) in 500 ms"
+        );
+
+        // What was recorded stays readable once switched off
+        log.set_enabled(false);
+        position(&mut log);
+        assert_eq!(log.exchanges.len(), 2);
+        assert_eq!(dump(&log).lines().count(), 2);
+    }
+
+    #[test]
+    fn keeps_the_last_exchanges() {
+        let mut log = TrafficLog::default();
+        log.set_enabled(true);
+        for i in 0..TRAFFIC_CAPACITY + 5 {
+            log.record(
+                EqModOp::try_from(b'j').ok(),
+                b":j1\r",
+                Ok::<&[u8], ()>(b"=\r"),
+                Duration::from_millis(i as u64),
+            );
+        }
+        assert_eq!(log.exchanges.len(), TRAFFIC_CAPACITY);
+        let dump = dump(&log);
+        assert!(dump.lines().next().unwrap().ends_with("in 5 ms"));
+        assert!(dump.lines().last().unwrap().ends_with("in 104 ms"));
+    }
+}